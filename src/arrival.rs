@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// 用意したブロック間隔を使い切ったときの扱い（`--arrival-exhausted`）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ArrivalExhausted {
+    /// 先頭に戻って繰り返す。
+    #[default]
+    Loop,
+    /// それ以上ブロックを生成しない（シミュレーションはそこで終わる）。
+    Stop,
+}
+
+/// 実測のブロック間隔を順に再生する到着過程。
+///
+/// 指数分布から採掘時間を引く代わりに、ネットワーク全体で k 番目のブロックを前のブロックの
+/// `intervals_ms[k]` 後に生成する。誰が掘るかはハッシュレートに比例して選ぶ。
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeterministicArrival {
+    intervals_ms: Vec<f64>,
+    on_exhausted: ArrivalExhausted,
+    next: usize,
+}
+
+#[derive(Deserialize)]
+struct IntervalRow {
+    interval_ms: f64,
+}
+
+impl DeterministicArrival {
+    pub fn new(intervals_ms: Vec<f64>, on_exhausted: ArrivalExhausted) -> Self {
+        assert!(!intervals_ms.is_empty(), "interval list must not be empty");
+        assert!(
+            intervals_ms.iter().all(|t| t.is_finite() && *t >= 0.0),
+            "intervals must be non-negative"
+        );
+        Self {
+            intervals_ms,
+            on_exhausted,
+            next: 0,
+        }
+    }
+
+    /// `interval_ms` 列を持つ CSV（1 行 1 ブロック）から読み込む。
+    pub fn from_csv<P: AsRef<Path>>(
+        path: P,
+        on_exhausted: ArrivalExhausted,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut intervals_ms = Vec::new();
+        for row in reader.deserialize() {
+            let row: IntervalRow = row?;
+            if !row.interval_ms.is_finite() || row.interval_ms < 0.0 {
+                return Err(
+                    format!("intervals must be non-negative, got {}", row.interval_ms).into(),
+                );
+            }
+            intervals_ms.push(row.interval_ms);
+        }
+        if intervals_ms.is_empty() {
+            return Err("interval CSV has no rows".into());
+        }
+        Ok(Self::new(intervals_ms, on_exhausted))
+    }
+
+    /// 次のブロックまでの間隔（µs）。使い切って `Stop` なら `None`。
+    pub fn next_interval_us(&mut self) -> Option<i64> {
+        if self.next == self.intervals_ms.len() {
+            match self.on_exhausted {
+                ArrivalExhausted::Loop => self.next = 0,
+                ArrivalExhausted::Stop => return None,
+            }
+        }
+        let interval_ms = self.intervals_ms[self.next];
+        self.next += 1;
+        Some((interval_ms * 1000.0).round() as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exhausted_intervals_loop_or_stop() {
+        let intervals = vec![1.0, 2.5];
+        let mut looping = DeterministicArrival::new(intervals.clone(), ArrivalExhausted::Loop);
+        let looped: Vec<_> = (0..5).map(|_| looping.next_interval_us()).collect();
+        assert_eq!(
+            looped,
+            [Some(1000), Some(2500), Some(1000), Some(2500), Some(1000)]
+        );
+
+        let mut stopping = DeterministicArrival::new(intervals, ArrivalExhausted::Stop);
+        let stopped: Vec<_> = (0..3).map(|_| stopping.next_interval_us()).collect();
+        assert_eq!(stopped, [Some(1000), Some(2500), None]);
+    }
+}
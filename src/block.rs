@@ -9,9 +9,12 @@ pub const GENESIS_BLOCK_ID: BlockId = BlockId::new(0);
 pub struct Block {
     height: i64,
     prev_block_id: Option<BlockId>,
-    minter: NodeId,
-    /// timestamp（プロトコル上の壁時計、**ミリ秒**）
-    time: i64,
+    /// 採掘したノード（ジェネシスは `None`）
+    minter: Option<NodeId>,
+    /// 採掘者が申告した timestamp（**ミリ秒**）。難易度調整はこちらを使う。
+    timestamp: i64,
+    /// 実際に採掘が終わった時刻（シミュレータの時計、**ミリ秒**）。正直な採掘者なら `timestamp` と等しい。
+    created_at: i64,
     /// Random number for block selection
     rand: i64,
     id: BlockId,
@@ -23,6 +26,12 @@ pub struct Block {
     pub mining_time: f64,
     /// 少なくとも一度でもネットワーク上へ伝搬がスケジュールされたか（主鎖・指標用）
     announced: bool,
+    /// 二重支払いの対象トランザクション（`set_double_spend_height`）を含むか
+    marked_tx: bool,
+    /// 含むトランザクション数（`set_tx_arrival_rate` を設定しないときは常に 0）
+    tx_count: u64,
+    /// `prev_block_id`（selected parent）以外に参照する親（blockDAG のプロトコルのみ）
+    merge_parents: Vec<BlockId>,
 }
 
 impl Block {
@@ -30,7 +39,7 @@ impl Block {
         height: i64,
         prev_block_id: Option<BlockId>,
         minter: NodeId,
-        time: i64,
+        timestamp: i64,
         rand: i64,
         id: BlockId,
         difficulty: Difficulty,
@@ -41,14 +50,18 @@ impl Block {
         Self {
             height,
             prev_block_id,
-            minter,
-            time,
+            minter: Some(minter),
+            timestamp,
+            created_at: timestamp,
             rand,
             id,
             difficulty,
             cumulative_chain_work,
             mining_time: mining_time_ms,
             announced,
+            marked_tx: false,
+            tx_count: 0,
+            merge_parents: Vec::new(),
         }
     }
 
@@ -57,14 +70,18 @@ impl Block {
         Self {
             height: 0,
             prev_block_id: None,
-            minter: NodeId::dummy(),
-            time: 0,
+            minter: None,
+            timestamp: 0,
+            created_at: 0,
             rand: 0,
             id: GENESIS_BLOCK_ID,
             difficulty,
             cumulative_chain_work: difficulty.chain_work_increment(),
             mining_time: 0.0,
             announced: true,
+            marked_tx: false,
+            tx_count: 0,
+            merge_parents: Vec::new(),
         }
     }
 
@@ -80,18 +97,41 @@ impl Block {
         self.difficulty
     }
 
-    pub fn minter(&self) -> NodeId {
+    pub fn minter(&self) -> Option<NodeId> {
         self.minter
     }
 
-    pub fn time(&self) -> i64 {
-        self.time
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn created_at(&self) -> i64 {
+        self.created_at
+    }
+
+    pub(crate) fn set_created_at(&mut self, created_at: i64) {
+        self.created_at = created_at;
     }
 
     pub fn prev_block_id(&self) -> Option<BlockId> {
         self.prev_block_id
     }
 
+    pub fn merge_parents(&self) -> &[BlockId] {
+        &self.merge_parents
+    }
+
+    pub fn set_merge_parents(&mut self, merge_parents: Vec<BlockId>) {
+        self.merge_parents = merge_parents;
+    }
+
+    /// `prev_block_id` とマージ親をこの順に返す。
+    pub fn parents(&self) -> impl Iterator<Item = BlockId> + '_ {
+        self.prev_block_id
+            .into_iter()
+            .chain(self.merge_parents.iter().copied())
+    }
+
     pub fn rand(&self) -> i64 {
         self.rand
     }
@@ -107,4 +147,20 @@ impl Block {
     pub fn set_announced(&mut self, announced: bool) {
         self.announced = announced;
     }
+
+    pub fn contains_marked_tx(&self) -> bool {
+        self.marked_tx
+    }
+
+    pub fn set_marked_tx(&mut self, marked_tx: bool) {
+        self.marked_tx = marked_tx;
+    }
+
+    pub fn tx_count(&self) -> u64 {
+        self.tx_count
+    }
+
+    pub fn set_tx_count(&mut self, tx_count: u64) {
+        self.tx_count = tx_count;
+    }
 }
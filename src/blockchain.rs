@@ -1,16 +1,19 @@
-use primitive_types::U256;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     Protocol,
     block::{Block, GENESIS_BLOCK_ID},
+    fork_choice::{ForkChoice, break_tie},
+    mining_strategy::TieBreakingRule,
     node::NodeId,
-    types::ChainMetrics,
+    protocol::UncleRewardRule,
+    types::{ChainMetrics, ForkEvent, TreeNode},
 };
-use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, atomic::AtomicUsize};
 
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+/// ブロックの通し番号（生成順）。順序は同着の最終的な決め手にも使う。
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct BlockId(usize);
 
 impl BlockId {
@@ -25,31 +28,199 @@ impl std::fmt::Display for BlockId {
     }
 }
 
+/// 刈り込み（[`Blockchain::prune_stale_forks`]）で `Block` の代わりに残す tombstone。
+/// stale 指標・フォーク深さ・浪費ハッシュレートの集計に必要な情報だけを持つ。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrunedBlock {
+    pub height: i64,
+    pub prev_block_id: Option<BlockId>,
+    pub minter: Option<NodeId>,
+    /// マイニングにかかった時間（ms、`Block::mining_time`）
+    pub mining_time: f64,
+    /// 採掘完了・告知済み（stale 指標の母集団）だったか
+    pub mined: bool,
+}
+
+/// minter ごとの GHOST 報酬（ブロック報酬 1 を単位とする）。
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UncleRewards {
+    /// 自分の孤立ブロックが uncle として取り込まれて得た報酬
+    pub uncle: f64,
+    /// 自分のメインチェーンのブロックが uncle を取り込んで得た報酬
+    pub nephew: f64,
+}
+
+/// `Live` は箱に入れておき、刈り込みで tombstone に置き換えたときにブロック本体のメモリを解放する。
+enum BlockSlot {
+    Live(Box<Block>),
+    Pruned(PrunedBlock),
+}
+
 /// A pool for blocks which maintains a single global instance of the blockchain.
 pub struct Blockchain {
-    blocks: Vec<Block>,
+    /// `BlockId` の添字で引くブロック（刈り込まれたものは tombstone）
+    blocks: Vec<BlockSlot>,
     next_block_id: AtomicUsize,
     /// `BlockGeneration` イベントまで到達したブロック（キューから捨てられた未発火分は含まない）
     generation_completed: HashSet<BlockId>,
+    /// 高さごとのブロック一覧（追加順、刈り込まれたものは除く）
+    blocks_by_height: Vec<Vec<BlockId>>,
+    /// この高さ以下は刈り込み済み
+    pruned_through_height: i64,
+    /// `blocks` のうち `Live` のものの数
+    live_blocks: usize,
+    /// 同じ chainwork の tip が競合したときの選び方。ノードの採用と主鎖の両方が使う。
+    tie_breaking_rule: TieBreakingRule,
+    /// selfish mining の γ。同じ chainwork の honest/攻撃者ブロックが競合したとき、
+    /// honest ノードが攻撃者側を採る確率（`None` なら `tie_breaking_rule` に従う）。
+    gamma: Option<f64>,
+    /// honest でない（攻撃者の）ノード。γ の適用対象を判定する。
+    attackers: HashSet<NodeId>,
+    /// フォーク選択の既定（[`Protocol::prefer`]）に使うプロトコル（`Env::protocol` と共有）
+    protocol: Arc<dyn Protocol>,
+    /// 全ノード共通のフォーク選択。`None` ならプロトコル既定（`protocol.prefer` と `tie_breaking_rule`）。
+    fork_choice: Option<Box<dyn ForkChoice>>,
+    /// ブロックごとの、各ノードが最初に受け取った（採掘者なら採掘した）時刻（μs）。first-seen の同着判定に使う。
+    first_seen: HashMap<BlockId, HashMap<NodeId, i64>>,
 }
 
 impl Blockchain {
-    pub fn new(protocol: &dyn Protocol, total_hashrate: i64) -> Self {
+    pub fn new(protocol: Arc<dyn Protocol>, total_hashrate: i64) -> Self {
+        let genesis = Block::genesis(&*protocol, total_hashrate);
         let mut blockchain = Self {
             blocks: Vec::new(),
             next_block_id: AtomicUsize::new(1),
             generation_completed: HashSet::new(),
+            blocks_by_height: Vec::new(),
+            pruned_through_height: 0,
+            live_blocks: 0,
+            tie_breaking_rule: TieBreakingRule::default(),
+            gamma: None,
+            attackers: HashSet::new(),
+            protocol,
+            fork_choice: None,
+            first_seen: HashMap::new(),
         };
-        blockchain.add_block(Block::genesis(protocol, total_hashrate));
+        blockchain.add_block(genesis);
         blockchain
     }
 
     pub fn add_block(&mut self, block: Block) -> BlockId {
         let id = block.id();
-        self.blocks.push(block);
+        let height = block.height() as usize;
+        if self.blocks_by_height.len() <= height {
+            self.blocks_by_height.resize_with(height + 1, Vec::new);
+        }
+        self.blocks_by_height[height].push(id);
+        self.blocks.push(BlockSlot::Live(Box::new(block)));
+        self.live_blocks += 1;
         id
     }
 
+    /// ノードの採用と [`Self::get_main_chain`] で chainwork が等しい tip が競合したときの選び方。
+    pub fn set_tie_breaking_rule(&mut self, rule: TieBreakingRule) {
+        self.tie_breaking_rule = rule;
+    }
+
+    pub fn tie_breaking_rule(&self) -> TieBreakingRule {
+        self.tie_breaking_rule
+    }
+
+    /// selfish mining の γ を設定する（`None` で無効）。
+    pub fn set_gamma(&mut self, gamma: Option<f64>) {
+        self.gamma = gamma;
+    }
+
+    pub fn gamma(&self) -> Option<f64> {
+        self.gamma
+    }
+
+    /// honest でない戦略で動くノードを設定する。
+    pub fn set_attackers(&mut self, attackers: HashSet<NodeId>) {
+        self.attackers = attackers;
+    }
+
+    pub fn attackers(&self) -> &HashSet<NodeId> {
+        &self.attackers
+    }
+
+    /// `node` が honest でない戦略で動いているか。
+    pub fn is_attacker(&self, node: NodeId) -> bool {
+        self.attackers.contains(&node)
+    }
+
+    /// ノードの採用と [`Self::get_main_chain`] が使うフォーク選択を差し替える（`None` でプロトコル既定）。
+    pub fn set_fork_choice(&mut self, fork_choice: Option<Box<dyn ForkChoice>>) {
+        self.fork_choice = fork_choice;
+    }
+
+    pub fn fork_choice(&self) -> Option<&dyn ForkChoice> {
+        self.fork_choice.as_deref()
+    }
+
+    /// ノード `node` が `block_id` を時刻 `time_us` に受け取った（採掘した）ことを記録する。
+    /// 2 回目以降の受信は無視するので、最初に受け取った時刻が残る。
+    pub fn record_first_seen(&mut self, node: NodeId, block_id: BlockId, time_us: i64) {
+        self.first_seen
+            .entry(block_id)
+            .or_default()
+            .entry(node)
+            .or_insert(time_us);
+    }
+
+    /// ノード `node` が `block_id` を最初に受け取った時刻（μs）。まだ受け取っていなければ `None`。
+    pub fn first_seen(&self, node: NodeId, block_id: BlockId) -> Option<i64> {
+        self.first_seen.get(&block_id)?.get(&node).copied()
+    }
+
+    /// `block_id` をいずれかのノードが最初に受け取った時刻（μs）。
+    pub fn first_seen_by_any(&self, block_id: BlockId) -> Option<i64> {
+        self.first_seen.get(&block_id)?.values().min().copied()
+    }
+
+    /// ノード `node` から見て、`a`（現在の head）と `b`（受け取った tip）のどちらを採るか。
+    /// `node` が `None` なら主鎖としての選択。どちらも刈り込まれていないこと。
+    /// `fork_choice` があればそれに、なければ [`Protocol::prefer`] と `tie_breaking_rule` に従う。
+    /// 2 つ目の値は、プロトコル既定の選択で同等（同着）だったか。
+    pub(crate) fn choose_tip(
+        &self,
+        node: Option<NodeId>,
+        a: BlockId,
+        b: BlockId,
+    ) -> (BlockId, bool) {
+        if let Some(fork_choice) = &self.fork_choice {
+            return (fork_choice.select(self, node, a, b), false);
+        }
+        let (block_a, block_b) = (self.get_block(a).unwrap(), self.get_block(b).unwrap());
+        match self.protocol.prefer(block_a, block_b) {
+            std::cmp::Ordering::Greater => (a, false),
+            std::cmp::Ordering::Less => (b, false),
+            std::cmp::Ordering::Equal if a == b => (a, false),
+            std::cmp::Ordering::Equal => (break_tie(self, node, a, b), true),
+        }
+    }
+
+    /// 主鎖を決めるときの [`Self::choose_tip`]。γ が設定されていて honest と攻撃者の tip が同着なら、
+    /// 各 honest ノードは確率 γ で攻撃者側に付くので、過半数が採る側（γ > 1/2 なら攻撃者側）を選ぶ。
+    fn choose_main_chain_tip(&self, a: BlockId, b: BlockId) -> BlockId {
+        let (chosen, tie) = self.choose_tip(None, a, b);
+        let Some(gamma) = self.gamma.filter(|&g| tie && g != 0.5) else {
+            return chosen;
+        };
+        let is_attacker = |id| {
+            self.get_block(id)
+                .unwrap()
+                .minter()
+                .is_some_and(|m| self.is_attacker(m))
+        };
+        let (attacker, honest) = match (is_attacker(a), is_attacker(b)) {
+            (true, false) => (a, b),
+            (false, true) => (b, a),
+            _ => return chosen,
+        };
+        if gamma > 0.5 { attacker } else { honest }
+    }
+
     /// マイニング完了イベントが処理されたブロックのみマークする（スケジュールのみでイベントが取代されたブロックは含めない）。
     pub fn mark_block_generation_completed(&mut self, block_id: BlockId) {
         self.generation_completed.insert(block_id);
@@ -62,17 +233,71 @@ impl Blockchain {
         }
     }
 
+    /// 刈り込まれたブロックは `None`（tombstone は [`Self::pruned_block`] で引ける）。
     pub fn get_block(&self, id: BlockId) -> Option<&Block> {
-        self.blocks.get(id.0)
+        match self.blocks.get(id.0)? {
+            BlockSlot::Live(block) => Some(block),
+            BlockSlot::Pruned(_) => None,
+        }
     }
 
     pub fn get_block_mut(&mut self, id: BlockId) -> Option<&mut Block> {
-        self.blocks.get_mut(id.0)
+        match self.blocks.get_mut(id.0)? {
+            BlockSlot::Live(block) => Some(block),
+            BlockSlot::Pruned(_) => None,
+        }
+    }
+
+    /// 刈り込まれたブロックの tombstone。
+    pub fn pruned_block(&self, id: BlockId) -> Option<PrunedBlock> {
+        match self.blocks.get(id.0)? {
+            BlockSlot::Live(_) => None,
+            BlockSlot::Pruned(pruned) => Some(*pruned),
+        }
+    }
+
+    /// Get all blocks including orphan blocks (except pruned ones).
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.iter().filter_map(|slot| match slot {
+            BlockSlot::Live(block) => Some(&**block),
+            BlockSlot::Pruned(_) => None,
+        })
+    }
+
+    /// メモリ上に残っている（刈り込まれていない）ブロック数。
+    pub fn live_blocks(&self) -> usize {
+        self.live_blocks
+    }
+
+    /// ブロックの要約。刈り込まれたブロックでも tombstone から引けるので、高さ・親・minter だけが
+    /// 必要なら [`Self::get_block`] の代わりにこちらを使う。存在しない id は `None`。
+    pub fn block_summary(&self, id: BlockId) -> Option<PrunedBlock> {
+        match self.blocks.get(id.0)? {
+            BlockSlot::Live(block) => Some(PrunedBlock {
+                height: block.height(),
+                prev_block_id: block.prev_block_id(),
+                minter: block.minter(),
+                mining_time: block.mining_time,
+                mined: self.generation_completed.contains(&id) && block.is_announced(),
+            }),
+            BlockSlot::Pruned(pruned) => Some(*pruned),
+        }
+    }
+
+    /// ブロックの高さ（刈り込まれたブロックも含む）。
+    pub fn block_height(&self, id: BlockId) -> i64 {
+        self.block_summary(id)
+            .expect("block ids are never removed")
+            .height
     }
 
-    /// Get all blocks including orphan blocks.
-    pub fn blocks(&self) -> &[Block] {
-        &self.blocks
+    /// 高さ `h` の全ブロック（分岐・未完了のものも含む、追加順）。
+    pub fn blocks_at_height(&self, h: i64) -> Vec<BlockId> {
+        usize::try_from(h)
+            .ok()
+            .and_then(|h| self.blocks_by_height.get(h))
+            .cloned()
+            .unwrap_or_default()
     }
 
     /// blockの祖先nブロックを返す　(block_id自身は含まない)
@@ -93,12 +318,59 @@ impl Blockchain {
         blocks
     }
 
+    /// `a` と `b` の最も低い共通祖先。一方が他方の祖先ならその祖先自身、同じブロックならそのブロック。
+    /// 高さの高い方から親をたどって高さを揃え、その後は両方を同時にたどるので、
+    /// 分岐点から 2 つの tip までの距離に比例する時間で済む。刈り込まれたブロックは tombstone でたどる。
+    pub fn common_ancestor(&self, a: BlockId, b: BlockId) -> BlockId {
+        let parent = |id: BlockId| {
+            self.block_summary(id)
+                .and_then(|b| b.prev_block_id)
+                .unwrap_or(GENESIS_BLOCK_ID)
+        };
+        let height = |id: BlockId| self.block_height(id);
+        let (mut a, mut b) = (a, b);
+        while height(a) > height(b) {
+            a = parent(a);
+        }
+        while height(b) > height(a) {
+            b = parent(b);
+        }
+        while a != b {
+            a = parent(a);
+            b = parent(b);
+        }
+        a
+    }
+
+    /// 子を持たない採掘完了済みのブロック（各分岐の先端）を id 順に返す。ジェネシスだけならジェネシス。
+    /// 採掘中（`BlockGeneration` 前）のブロックは子とみなさず、刈り込まれた分岐は含まない。
+    pub fn tips(&self) -> Vec<BlockId> {
+        let children = self.children();
+        self.blocks()
+            .map(|b| b.id())
+            .filter(|&id| self.is_effective_chain_block(id) && !children.contains_key(&id))
+            .collect()
+    }
+
+    /// 採掘完了済みのブロックについて、親 → 子の一覧（id 順）を 1 回の走査で作る。
+    fn children(&self) -> HashMap<BlockId, Vec<BlockId>> {
+        let mut children: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+        for block in self.blocks() {
+            if let Some(prev) = block.prev_block_id()
+                && self.is_effective_chain_block(block.id())
+            {
+                children.entry(prev).or_default().push(block.id());
+            }
+        }
+        children
+    }
+
     pub fn len(&self) -> usize {
         self.blocks.len()
     }
 
     pub fn max_height(&self) -> i64 {
-        self.blocks.iter().map(|b| b.height()).max().unwrap_or(0)
+        self.blocks_by_height.len().saturating_sub(1) as i64
     }
 
     pub fn next_block_id(&self) -> BlockId {
@@ -109,13 +381,7 @@ impl Blockchain {
     }
 
     pub fn last_block(&self) -> Option<&Block> {
-        self.blocks.last()
-    }
-
-    fn cumulative_chain_work(&self, tip_id: BlockId) -> U256 {
-        self.get_block(tip_id)
-            .map(|block| block.cumulative_chain_work())
-            .unwrap_or(U256::zero())
+        self.blocks().last()
     }
 
     /// ジェネシス、または `BlockGeneration` イベントが処理されたブロックのみを「有効」とする。
@@ -139,103 +405,493 @@ impl Blockchain {
         self.get_block(id).is_some_and(|b| b.is_announced())
     }
 
-    /// tip から prev を辿り、ジェネシスまでの経路に未完了ブロックが無ければそのチェーンを返す。
-    fn chain_from_tip_if_fully_effective(
-        &self,
-        tip: BlockId,
-        include_unannounced: bool,
-    ) -> Option<Vec<BlockId>> {
-        let mut rev = Vec::new();
-        let mut cur = tip;
-        loop {
-            if cur == GENESIS_BLOCK_ID {
-                rev.push(cur);
-                break;
-            }
-            if !self.is_main_chain_candidate(cur, include_unannounced) {
-                return None;
-            }
-            rev.push(cur);
-            cur = self.get_block(cur)?.prev_block_id()?;
-        }
-        rev.reverse();
-        Some(rev)
+    /// `BlockGeneration` イベントまで到達したか（ジェネシスは常に `true`）。
+    pub fn is_generation_completed(&self, id: BlockId) -> bool {
+        self.is_effective_chain_block(id)
     }
 
-    fn compute_main_chain(&self, include_unannounced: bool) -> Vec<BlockId> {
-        if self.blocks.is_empty() {
-            return vec![GENESIS_BLOCK_ID];
-        }
-
-        let mut best_weight = self.cumulative_chain_work(GENESIS_BLOCK_ID);
-        let mut best_tips: Vec<BlockId> = vec![GENESIS_BLOCK_ID];
-        for block in &self.blocks {
-            if !self.is_main_chain_candidate(block.id(), include_unannounced) {
+    /// `ancestor` が `descendant` の（マージ親も含めた）過去にあるか。自分自身は含まない。
+    /// 祖先の id は子より小さいので、`ancestor` より小さい id のブロックは辿らない。
+    pub fn is_dag_ancestor(&self, ancestor: BlockId, descendant: BlockId) -> bool {
+        let mut stack = vec![descendant];
+        let mut seen = HashSet::new();
+        while let Some(id) = stack.pop() {
+            let Some(block) = self.get_block(id) else {
                 continue;
-            }
-            let w = self.cumulative_chain_work(block.id());
-            match w.cmp(&best_weight) {
-                std::cmp::Ordering::Greater => {
-                    best_weight = w;
-                    best_tips = vec![block.id()];
+            };
+            for parent in block.parents() {
+                if parent == ancestor {
+                    return true;
+                }
+                if parent > ancestor && seen.insert(parent) {
+                    stack.push(parent);
                 }
-                std::cmp::Ordering::Equal => best_tips.push(block.id()),
-                std::cmp::Ordering::Less => {}
             }
         }
+        false
+    }
 
-        for &tip in &best_tips {
-            if let Some(ch) = self.chain_from_tip_if_fully_effective(tip, include_unannounced) {
-                return ch;
+    /// tip から prev を辿り、ジェネシスまでの経路に未完了ブロックが無いか。
+    fn is_fully_effective(&self, tip: BlockId, include_unannounced: bool) -> bool {
+        let mut cur = tip;
+        while cur != GENESIS_BLOCK_ID {
+            if !self.is_main_chain_candidate(cur, include_unannounced) {
+                return false;
+            }
+            match self.get_block(cur).and_then(|b| b.prev_block_id()) {
+                Some(prev) => cur = prev,
+                None => return false,
             }
         }
+        true
+    }
 
-        // 最大 work の先端がすべて有効な祖先を持たない（異常）とき、次点以降を探索
-        let mut tips: Vec<(U256, BlockId)> = self
-            .blocks
-            .iter()
-            .filter(|b| self.is_main_chain_candidate(b.id(), include_unannounced))
-            .map(|b| (self.cumulative_chain_work(b.id()), b.id()))
+    /// ノードと同じフォーク選択（[`Self::choose_main_chain_tip`]）で候補の tip を追加順に畳み込み、メインチェーンの tip を返す。
+    /// 追加順に畳み込むので、first-seen の同着は先に生成された方になる。
+    fn main_chain_tip(&self, include_unannounced: bool) -> BlockId {
+        let mut tips: Vec<BlockId> = self
+            .blocks()
+            .map(|b| b.id())
+            .filter(|&id| self.is_main_chain_candidate(id, include_unannounced))
             .collect();
-        tips.sort_by(|a, b| b.0.cmp(&a.0));
-        for (_, tip) in tips {
-            if best_tips.contains(&tip) {
-                continue;
-            }
-            if let Some(ch) = self.chain_from_tip_if_fully_effective(tip, include_unannounced) {
-                return ch;
+        // 最良の先端が有効な祖先を持たない（異常）とき、次点以降を探索
+        while let Some(best) = tips
+            .iter()
+            .copied()
+            .reduce(|best, tip| self.choose_main_chain_tip(best, tip))
+        {
+            if self.is_fully_effective(best, include_unannounced) {
+                return best;
             }
+            tips.retain(|&tip| tip != best);
         }
 
-        vec![GENESIS_BLOCK_ID]
+        GENESIS_BLOCK_ID
     }
 
     /// メインチェーン（告知済み・採掘完了ブロックのみ）。シミュレーション中のネットワーク上の最重鎖。
     pub fn get_main_chain(&self) -> Vec<BlockId> {
-        self.compute_main_chain(false)
+        self.main_chain_blocks().map(Block::id).collect()
     }
 
     /// シミュレーション終了後の CSV 等用。採掘完了済みなら未告知（私有）も含めた最重鎖。
     pub fn get_main_chain_for_export(&self) -> Vec<BlockId> {
-        self.compute_main_chain(true)
+        self.main_chain_blocks_for_export().map(Block::id).collect()
+    }
+
+    /// [`Self::get_main_chain`] のブロックをジェネシスから順に返す。
+    pub fn main_chain_blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks_to(self.main_chain_tip(false))
+    }
+
+    /// [`Self::get_main_chain_for_export`] のブロックをジェネシスから順に返す。
+    pub fn main_chain_blocks_for_export(&self) -> impl Iterator<Item = &Block> {
+        self.blocks_to(self.main_chain_tip(true))
+    }
+
+    /// ジェネシスから `tip` までのブロック。`tip` から prev を辿って逆順に並べ直す。
+    fn blocks_to(&self, tip: BlockId) -> impl Iterator<Item = &Block> {
+        // メインチェーンは刈り込まれない。
+        let block = |id| self.get_block(id).expect("main chain block must exist");
+        let mut chain: Vec<&Block> =
+            std::iter::successors(Some(block(tip)), |b| b.prev_block_id().map(block)).collect();
+        chain.reverse();
+        chain.into_iter()
+    }
+
+    /// [`Self::get_main_chain`] からジェネシスを除いたもの（報酬など、採掘されたブロックだけを数える集計用）。
+    pub fn get_main_chain_without_genesis(&self) -> Vec<BlockId> {
+        Self::without_genesis(self.get_main_chain())
+    }
+
+    /// [`Self::get_main_chain_for_export`] からジェネシスを除いたもの。
+    pub fn get_main_chain_for_export_without_genesis(&self) -> Vec<BlockId> {
+        Self::without_genesis(self.get_main_chain_for_export())
+    }
+
+    fn without_genesis(mut chain: Vec<BlockId>) -> Vec<BlockId> {
+        debug_assert_eq!(chain.first(), Some(&GENESIS_BLOCK_ID));
+        chain.split_off(1)
     }
 
     /// 採掘完了済みメインチェーンの先端ブロックの高さ（ジェネシスのみなら 0）
     pub fn main_chain_height(&self) -> i64 {
-        self.get_main_chain()
-            .last()
-            .and_then(|&id| self.get_block(id))
-            .map(|b| b.height())
-            .unwrap_or(0)
+        self.block_height(self.main_chain_tip(false))
     }
 
     /// 終了後レポート用のメインチェーン先端高さ（未告知ブロックを含む）
     pub fn main_chain_height_for_export(&self) -> i64 {
-        self.get_main_chain_for_export()
+        self.block_height(self.main_chain_tip(true))
+    }
+
+    /// エクスポート用メインチェーン上のブロック数を minter ごとに数える（報酬 = 1 ブロック 1 単位）。
+    /// ジェネシスは数えない。
+    ///
+    /// 上に `confirmations` ブロック以上積まれたブロックのみ数える（0 なら tip も含めて全て）。
+    /// `confirmations` がチェーン長を超えると報酬は空になる。
+    /// 高さ `min_height` 未満（burn-in 区間）のブロックも数えない。
+    pub fn main_chain_rewards(&self, confirmations: i64, min_height: i64) -> HashMap<NodeId, u64> {
+        let mut rewards = HashMap::new();
+        for block in self.rewarded_main_chain_blocks(confirmations, min_height) {
+            let minter = block.minter().expect("non-genesis block has a minter");
+            *rewards.entry(minter).or_insert(0) += 1;
+        }
+        rewards
+    }
+
+    /// [`Self::main_chain_rewards`] と同じブロックについて、ブロック数の代わりに難易度（仕事量に比例）を合計する。
+    pub fn main_chain_work_rewards(
+        &self,
+        confirmations: i64,
+        min_height: i64,
+    ) -> HashMap<NodeId, f64> {
+        let mut rewards = HashMap::new();
+        for block in self.rewarded_main_chain_blocks(confirmations, min_height) {
+            let minter = block.minter().expect("non-genesis block has a minter");
+            *rewards.entry(minter).or_insert(0.0) += block.difficulty().as_f64();
+        }
+        rewards
+    }
+
+    fn rewarded_main_chain_blocks(
+        &self,
+        confirmations: i64,
+        min_height: i64,
+    ) -> impl Iterator<Item = &Block> {
+        let tip_height = self.main_chain_height_for_export();
+        self.main_chain_blocks_for_export()
+            .skip(1)
+            .take_while(move |block| tip_height - block.height() >= confirmations)
+            .filter(move |block| block.height() >= min_height)
+    }
+
+    /// エクスポート用メインチェーンの各ブロック（ジェネシスを除く）の (難易度, 採掘時間 ms)。
+    /// 難易度調整の検証用に、難易度が高いほど採掘時間が比例して長くなっているかを見る。
+    pub fn difficulty_vs_mining_time(&self) -> Vec<(f64, i64)> {
+        self.main_chain_blocks_for_export()
+            .skip(1)
+            .map(|b| (b.difficulty().as_f64(), b.mining_time.round() as i64))
+            .collect()
+    }
+
+    /// メインチェーンのブロック間隔（ジェネシスから順、採掘された時刻 `created_at` の差）の lag 次の標本自己相関。
+    ///
+    /// 難易度調整が行き過ぎて振動するプロトコルでは lag 1 が負になる。間隔が `lag` 個以下、
+    /// または分散が 0 のときは 0 を返す。
+    pub fn inter_block_autocorrelation(&self, lag: usize) -> f64 {
+        let times: Vec<f64> = self
+            .main_chain_blocks()
+            .map(|b| b.created_at() as f64)
+            .collect();
+        let intervals: Vec<f64> = times.windows(2).map(|w| w[1] - w[0]).collect();
+        if intervals.len() <= lag {
+            return 0.0;
+        }
+        let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+        let variance: f64 = intervals.iter().map(|x| (x - mean).powi(2)).sum();
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let covariance: f64 = intervals
+            .iter()
+            .zip(&intervals[lag..])
+            .map(|(a, b)| (a - mean) * (b - mean))
+            .sum();
+        covariance / variance
+    }
+
+    /// GHOST の uncle/nephew 報酬を minter ごとに集計する。
+    ///
+    /// 親がエクスポート用メインチェーン上にある孤立ブロック（採掘完了・告知済み）を uncle とし、
+    /// 高さの低い順に、`rule.max_depth` 以内でまだ枠の空いている最初のメインチェーンのブロック（nephew）が
+    /// 取り込むとみなす。nephew は [`Self::main_chain_rewards`] で報酬を得るブロックに限る
+    /// （`confirmations` 以上埋まっていること）。高さ `min_height` 未満の uncle は数えない。
+    pub fn uncle_rewards(
+        &self,
+        rule: UncleRewardRule,
+        confirmations: i64,
+        min_height: i64,
+    ) -> HashMap<NodeId, UncleRewards> {
+        let main_chain = self.get_main_chain_for_export();
+        let main_set: HashSet<_> = main_chain.iter().copied().collect();
+        // nephew はメインチェーン上にしかないので、確認数が負でも tip より先は見ない。
+        let last_rewarded_height =
+            (self.main_chain_height_for_export() - confirmations).min(main_chain.len() as i64 - 1);
+        let mut uncles: Vec<(BlockId, PrunedBlock)> = self
+            .block_entries()
+            .filter(|(id, entry)| {
+                entry.mined
+                    && entry.height >= min_height.max(1)
+                    && !main_set.contains(id)
+                    && entry.prev_block_id.is_some_and(|p| main_set.contains(&p))
+            })
+            .collect();
+        uncles.sort_by_key(|(id, entry)| (entry.height, id.0));
+
+        // メインチェーンの高さ（= 添字）ごとに取り込んだ uncle の数。
+        let mut included = vec![0; main_chain.len()];
+        let mut rewards: HashMap<NodeId, UncleRewards> = HashMap::new();
+        for (_, uncle) in uncles {
+            let Some(nephew_height) = (uncle.height + 1..=uncle.height + rule.max_depth)
+                .take_while(|&h| h <= last_rewarded_height)
+                .find(|&h| included[h as usize] < rule.max_uncles_per_block)
+            else {
+                continue;
+            };
+            included[nephew_height as usize] += 1;
+            let nephew = self
+                .get_block(main_chain[nephew_height as usize])
+                .and_then(|b| b.minter())
+                .expect("main chain is never pruned and only genesis has no minter");
+            if let Some(minter) = uncle.minter {
+                rewards.entry(minter).or_default().uncle +=
+                    rule.uncle_reward(nephew_height - uncle.height);
+            }
+            rewards.entry(nephew).or_default().nephew += rule.nephew_reward();
+        }
+        rewards
+    }
+
+    /// メインチェーン tip から `prune_depth` より深い高さにある分岐ブロックを tombstone に置き換え、
+    /// メモリから落とす。`keep` のブロック（各ノードの戦略やキュー内のイベントが参照するブロック）と
+    /// その祖先、およびメインチェーン（告知済み・エクスポート用の両方）は残す。
+    /// stale 指標の集計に必要な情報は tombstone に残るので、`chain_metrics` の結果は変わらない。
+    ///
+    /// `prune_depth` はあり得る reorg の深さより大きくしておくこと。刈り込んだブロックの id を返す。
+    pub fn prune_stale_forks(&mut self, prune_depth: i64, keep: &HashSet<BlockId>) -> Vec<BlockId> {
+        let export_main = self.get_main_chain_for_export();
+        let tip_height = export_main
             .last()
             .and_then(|&id| self.get_block(id))
-            .map(|b| b.height())
-            .unwrap_or(0)
+            .map_or(0, |b| b.height());
+        let horizon = tip_height - prune_depth;
+        if horizon <= self.pruned_through_height {
+            return Vec::new();
+        }
+
+        let mut live: HashSet<BlockId> = export_main.into_iter().collect();
+        live.extend(self.get_main_chain());
+        for &id in keep {
+            let mut cur = id;
+            while let Some(block) = self.get_block(cur) {
+                if !live.insert(cur) {
+                    break;
+                }
+                match block.prev_block_id() {
+                    Some(prev) => cur = prev,
+                    None => break,
+                }
+            }
+        }
+
+        let mut pruned = Vec::new();
+        for height in (self.pruned_through_height + 1)..=horizon {
+            let Some(ids) = self.blocks_by_height.get_mut(height as usize) else {
+                break;
+            };
+            let (kept, dropped): (Vec<BlockId>, Vec<BlockId>) =
+                ids.iter().partition(|id| live.contains(id));
+            *ids = kept;
+            for id in dropped {
+                let Some(slot) = self.blocks.get_mut(id.0) else {
+                    continue;
+                };
+                let BlockSlot::Live(block) = slot else {
+                    continue;
+                };
+                let mined = self.generation_completed.remove(&id) && block.is_announced();
+                *slot = BlockSlot::Pruned(PrunedBlock {
+                    height: block.height(),
+                    prev_block_id: block.prev_block_id(),
+                    minter: block.minter(),
+                    mining_time: block.mining_time,
+                    mined,
+                });
+                self.live_blocks -= 1;
+                self.first_seen.remove(&id);
+                pruned.push(id);
+            }
+        }
+        self.pruned_through_height = horizon;
+        pruned
+    }
+
+    /// ブロックツリー全体（分岐を含む）を [`TreeNode`] の JSON 配列にする。
+    /// `main_chain` に含まれるブロックに `on_main_chain` を立てる。刈り込まれたブロックは含まない。
+    pub fn to_json_tree(&self, main_chain: &[BlockId]) -> String {
+        let main_set: HashSet<_> = main_chain.iter().copied().collect();
+        let tree: Vec<TreeNode> = self
+            .blocks()
+            .map(|block| TreeNode {
+                id: block.id().0,
+                height: block.height(),
+                prev_id: block.prev_block_id().map(|id| id.0),
+                minter: block.minter().map(NodeId::into_usize),
+                time: block.timestamp(),
+                created_at: block.created_at(),
+                difficulty: block.difficulty().as_f64(),
+                on_main_chain: main_set.contains(&block.id()),
+            })
+            .collect();
+        serde_json::to_string(&tree).expect("block tree is always serializable")
+    }
+
+    /// 全ブロックの要約を id 順に返す（[`Self::block_summary`]）。
+    fn block_entries(&self) -> impl Iterator<Item = (BlockId, PrunedBlock)> + '_ {
+        (0..self.blocks.len()).map(|i| {
+            let id = BlockId::new(i);
+            (id, self.block_summary(id).expect("index is in range"))
+        })
+    }
+
+    /// メインチェーンと競合し続けた最長の分岐（孤立した枝の長さ）を返す。分岐がなければ `None`。
+    /// 採掘完了・告知済みのブロックだけを数える（[`Self::chain_metrics`] と同じ母集団）。
+    pub fn longest_fork(&self) -> Option<ForkEvent> {
+        let main = self.get_main_chain();
+        let main_set: HashSet<_> = main.iter().copied().collect();
+        // 親は子より先に追加されるので、id 順に 1 回走査すれば枝の深さが求まる。
+        let mut depths: HashMap<BlockId, i64> = HashMap::new();
+        let mut deepest: Option<(BlockId, i64)> = None;
+        for (id, entry) in self.block_entries() {
+            if entry.height == 0 || !entry.mined || main_set.contains(&id) {
+                continue;
+            }
+            let Some(prev) = entry.prev_block_id else {
+                continue;
+            };
+            let depth = if main_set.contains(&prev) {
+                1
+            } else if let Some(parent_depth) = depths.get(&prev) {
+                parent_depth + 1
+            } else {
+                continue;
+            };
+            depths.insert(id, depth);
+            if deepest.is_none_or(|(_, best)| depth > best) {
+                deepest = Some((id, depth));
+            }
+        }
+
+        let (tip, depth) = deepest?;
+        let mut nodes = Vec::new();
+        let mut cur = Some(tip);
+        for _ in 0..depth {
+            let entry = self.block_summary(cur.unwrap()).unwrap();
+            nodes.extend(entry.minter.map(NodeId::into_usize));
+            if let Some(main_block) = main.get(entry.height as usize) {
+                let main_minter = self.block_summary(*main_block).and_then(|b| b.minter);
+                nodes.extend(main_minter.map(NodeId::into_usize));
+            }
+            cur = entry.prev_block_id;
+        }
+        nodes.sort();
+        nodes.dedup();
+        let start_height = self.block_summary(tip).unwrap().height - depth + 1;
+        Some(ForkEvent {
+            start_height,
+            depth,
+            nodes,
+        })
+    }
+
+    /// メインチェーン上で `attackers` の採掘したブロックが続いた区間のうち、honest ブロックが
+    /// 後に続いて取り返したものを `(攻撃者ブロック数, 回復時間 ms)` で返す。回復時間は区間最初の
+    /// 攻撃者ブロックから取り返した honest ブロックまでの、採掘された時刻（`created_at`）の差。
+    /// tip まで続いている（まだ取り返していない）区間と、高さ `min_height` 未満で始まる区間は含めない。
+    pub fn catch_up_intervals(
+        &self,
+        attackers: &HashSet<NodeId>,
+        min_height: i64,
+    ) -> Vec<(i64, i64)> {
+        let mut intervals = Vec::new();
+        let mut run: Option<(i64, i64, i64)> = None;
+        for block in self.main_chain_blocks().skip(1) {
+            let adversarial = block.minter().is_some_and(|m| attackers.contains(&m));
+            match (adversarial, run) {
+                (true, Some((start_height, start_ms, len))) => {
+                    run = Some((start_height, start_ms, len + 1));
+                }
+                (true, None) => run = Some((block.height(), block.created_at(), 1)),
+                (false, Some((start_height, start_ms, len))) => {
+                    if start_height >= min_height {
+                        intervals.push((len, block.created_at() - start_ms));
+                    }
+                    run = None;
+                }
+                (false, None) => {}
+            }
+        }
+        intervals
+    }
+
+    /// 孤立した honest ブロックを、分岐点の直後でメインチェーン側に立ったブロックの minter で分類し、
+    /// `(攻撃者の分岐に覆された数, honest 同士の自然な分岐で負けた数)` を返す。
+    /// 母集団は [`Self::orphaned_blocks`] と同じ（採掘済み・高さ `min_height` 以上）。
+    pub fn honest_orphans_by_cause(
+        &self,
+        attackers: &HashSet<NodeId>,
+        min_height: i64,
+    ) -> (u64, u64) {
+        let main = self.get_main_chain();
+        let main_set: HashSet<_> = main.iter().copied().collect();
+        let is_attacker = |minter: Option<NodeId>| minter.is_some_and(|m| attackers.contains(&m));
+        // 分岐上のブロック → 覆したブロックが攻撃者のものか（同じ高さのメインチェーンがなければ `None`）。
+        // 親は子より先に追加されるので、id 順に 1 回走査すれば分岐点の子から引き継げる。
+        let mut displaced_by_attacker: HashMap<BlockId, Option<bool>> = HashMap::new();
+        let (mut by_attacker, mut by_fork) = (0, 0);
+        for (id, entry) in self.block_entries() {
+            if entry.height == 0 || main_set.contains(&id) {
+                continue;
+            }
+            let Some(prev) = entry.prev_block_id else {
+                continue;
+            };
+            let cause = if main_set.contains(&prev) {
+                main.get(entry.height as usize)
+                    .map(|&displacer| is_attacker(self.get_block(displacer).unwrap().minter()))
+            } else {
+                displaced_by_attacker.get(&prev).copied().flatten()
+            };
+            displaced_by_attacker.insert(id, cause);
+            if !entry.mined || entry.height < min_height || is_attacker(entry.minter) {
+                continue;
+            }
+            match cause {
+                Some(true) => by_attacker += 1,
+                Some(false) => by_fork += 1,
+                None => {}
+            }
+        }
+        (by_attacker, by_fork)
+    }
+
+    /// 採掘済みでメインチェーンに乗らなかった（孤立した）ブロックの要約。刈り込み済みのものも含む。
+    /// 「採掘済み」の定義は [`Self::chain_metrics`] と同じで、終了時点でまだ採掘中
+    /// （マイニング完了イベント未発火）のブロックは含めない。高さ `min_height` 未満は除外する。
+    pub fn orphaned_blocks(&self, min_height: i64) -> Vec<PrunedBlock> {
+        let main_set: HashSet<_> = self.get_main_chain().into_iter().collect();
+        self.block_entries()
+            .filter(|(id, entry)| {
+                entry.height > 0
+                    && entry.height >= min_height
+                    && entry.mined
+                    && !main_set.contains(id)
+            })
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    /// minter ごとの孤立ブロック数（採掘済みブロック数 − メインチェーン上のブロック数、[`Self::orphaned_blocks`]）。
+    pub fn orphaned_blocks_by_minter(&self, min_height: i64) -> HashMap<NodeId, u64> {
+        let mut orphans = HashMap::new();
+        for minter in self
+            .orphaned_blocks(min_height)
+            .into_iter()
+            .filter_map(|b| b.minter)
+        {
+            *orphans.entry(minter).or_insert(0) += 1;
+        }
+        orphans
     }
 
     /// ジェネシス以外で、実際にマイニング完了イベントが発火したブロックを「採掘済み」とみなし、
@@ -257,8 +913,13 @@ impl Blockchain {
         let mut honest_main_mined_blocks: u64 = 0;
         let mut attacker_mined_blocks: u64 = 0;
         let mut attacker_main_mined_blocks: u64 = 0;
-        for block in self.blocks() {
-            let height = block.height();
+        for (id, entry) in self.block_entries() {
+            let PrunedBlock {
+                height,
+                minter,
+                mined,
+                ..
+            } = entry;
             if height == 0 {
                 continue;
             }
@@ -268,18 +929,17 @@ impl Blockchain {
             if max_height.is_some_and(|max_h| height > max_h) {
                 continue;
             }
-            if !self.generation_completed.contains(&block.id()) {
-                continue;
-            }
-            if !block.is_announced() {
+            if !mined {
                 continue;
             }
             mined_blocks += 1;
-            let on_main = main_set.contains(&block.id());
+            let on_main = main_set.contains(&id);
             if on_main {
                 main_mined_blocks += 1;
             }
-            if honest_minters.is_some_and(|set| set.contains(&block.minter())) {
+            let is_honest = honest_minters
+                .is_some_and(|set| minter.is_some_and(|m| set.contains(&m)));
+            if is_honest {
                 honest_mined_blocks += 1;
                 if on_main {
                     honest_main_mined_blocks += 1;
@@ -322,7 +982,8 @@ impl Blockchain {
                 if max_height.is_some_and(|max_h| height > max_h) {
                     continue;
                 }
-                private_attack_reorg_success = !honest_set.contains(&block.minter());
+                private_attack_reorg_success =
+                    block.minter().is_some_and(|m| !honest_set.contains(&m));
                 break;
             }
         }
@@ -353,9 +1014,12 @@ mod chain_metrics_tests {
         node::NodeId,
         protocol::{GenesisDifficultyMode, ProtocolType},
     };
+    use primitive_types::U256;
 
-    fn test_protocol() -> Box<dyn Protocol> {
-        ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Fixed)
+    fn test_protocol() -> Arc<dyn Protocol> {
+        ProtocolType::Bitcoin
+            .to_protocol(GenesisDifficultyMode::Fixed)
+            .into()
     }
 
     fn push_block(
@@ -390,10 +1054,189 @@ mod chain_metrics_tests {
         block_id
     }
 
+    #[test]
+    fn tips_are_the_blocks_without_children() {
+        let mut chain = Blockchain::new(test_protocol(), 1);
+        assert_eq!(chain.tips(), [GENESIS_BLOCK_ID]);
+
+        // 0 -> 1 -> 2 -> 3、1 から分岐して 1 -> 4。5 は 3 の上で採掘中（未完了）。
+        let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 0, true);
+        let b2 = push_block(&mut chain, 2, 2, b1, 0, true);
+        let b3 = push_block(&mut chain, 3, 3, b2, 0, true);
+        let b4 = push_block(&mut chain, 4, 2, b1, 1, true);
+        for id in [b1, b2, b3, b4] {
+            chain.mark_block_generation_completed(id);
+        }
+        push_block(&mut chain, 5, 4, b3, 0, false);
+        assert_eq!(chain.tips(), [b3, b4]);
+    }
+
+    #[test]
+    fn common_ancestor_of_siblings_ancestors_and_genesis() {
+        let mut chain = Blockchain::new(test_protocol(), 1);
+        // 0 -> 1 -> 2 -> 3、2 から分岐して 2 -> 4 -> 5
+        let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 0, true);
+        let b2 = push_block(&mut chain, 2, 2, b1, 0, true);
+        let b3 = push_block(&mut chain, 3, 3, b2, 0, true);
+        let b4 = push_block(&mut chain, 4, 3, b2, 1, true);
+        let b5 = push_block(&mut chain, 5, 4, b4, 1, true);
+
+        // 兄弟、高さの違う分岐（どちらの順でも同じ）
+        assert_eq!(chain.common_ancestor(b3, b4), b2);
+        assert_eq!(chain.common_ancestor(b3, b5), b2);
+        assert_eq!(chain.common_ancestor(b5, b3), b2);
+        // 祖先と子孫、同じブロック
+        assert_eq!(chain.common_ancestor(b1, b5), b1);
+        assert_eq!(chain.common_ancestor(b5, b2), b2);
+        assert_eq!(chain.common_ancestor(b3, b3), b3);
+
+        // ジェネシスだけのチェーン、ジェネシスで分かれる分岐
+        let genesis_only = Blockchain::new(test_protocol(), 1);
+        assert_eq!(
+            genesis_only.common_ancestor(GENESIS_BLOCK_ID, GENESIS_BLOCK_ID),
+            GENESIS_BLOCK_ID
+        );
+        let other = push_block(&mut chain, 6, 1, GENESIS_BLOCK_ID, 1, true);
+        assert_eq!(chain.common_ancestor(other, b5), GENESIS_BLOCK_ID);
+        assert_eq!(
+            chain.common_ancestor(GENESIS_BLOCK_ID, b3),
+            GENESIS_BLOCK_ID
+        );
+    }
+
+    #[test]
+    fn uncles_are_included_within_depth_and_per_block_limit() {
+        let mut chain = Blockchain::new(test_protocol(), 1);
+        // メインチェーン: 0 -> 1 -> 2 -> ... -> 9（minter 0）
+        let mut main = vec![GENESIS_BLOCK_ID];
+        for height in 1..=9 {
+            let id = push_block(
+                &mut chain,
+                height as usize,
+                height,
+                main[height as usize - 1],
+                0,
+                true,
+            );
+            main.push(id);
+        }
+        // 高さ 1 の uncle 3 つ（minter 1, 2, 3）と、親がメインチェーンにない高さ 2 のブロック（minter 4）。
+        let u1 = push_block(&mut chain, 10, 1, GENESIS_BLOCK_ID, 1, true);
+        push_block(&mut chain, 11, 1, GENESIS_BLOCK_ID, 2, true);
+        push_block(&mut chain, 12, 1, GENESIS_BLOCK_ID, 3, true);
+        push_block(&mut chain, 13, 2, u1, 4, true);
+        for id in 1..=13 {
+            chain.mark_block_generation_completed(BlockId::new(id));
+        }
+
+        let rule = UncleRewardRule::ETHEREUM;
+        let rewards = chain.uncle_rewards(rule, 0, 0);
+        // 高さ 2 が 2 つ（深さ 1）、3 つ目は高さ 3（深さ 2）に入る。
+        assert_eq!(rewards[&NodeId::new(1)].uncle, rule.uncle_reward(1));
+        assert_eq!(rewards[&NodeId::new(2)].uncle, rule.uncle_reward(1));
+        assert_eq!(rewards[&NodeId::new(3)].uncle, rule.uncle_reward(2));
+        assert!(!rewards.contains_key(&NodeId::new(4)));
+        assert_eq!(rewards[&NodeId::new(0)].nephew, 3.0 * rule.nephew_reward());
+
+        // nephew が確認数を満たさなければ取り込まれない。
+        assert!(chain.uncle_rewards(rule, 8, 0).is_empty());
+    }
+
+    #[test]
+    fn uncle_at_tip_height_has_no_nephew_yet() {
+        let mut chain = Blockchain::new(test_protocol(), 1);
+        let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 0, true);
+        let b2 = push_block(&mut chain, 2, 2, b1, 0, true);
+        // tip と同じ高さの uncle。取り込める nephew はまだない。
+        push_block(&mut chain, 3, 2, b1, 1, true);
+        for id in 1..=3 {
+            chain.mark_block_generation_completed(BlockId::new(id));
+        }
+        assert_eq!(chain.get_main_chain_for_export().last(), Some(&b2));
+
+        let rule = UncleRewardRule::ETHEREUM;
+        for confirmations in [0, -3] {
+            assert!(chain.uncle_rewards(rule, confirmations, 0).is_empty());
+        }
+    }
+
+    #[test]
+    fn longest_fork_reports_deepest_sustained_competition() {
+        let mut chain = Blockchain::new(test_protocol(), 3);
+        // main: genesis -> m1 -> m2 -> m3 -> m4 -> m5（node 0）
+        let mut main = vec![GENESIS_BLOCK_ID];
+        for h in 1..=5 {
+            main.push(push_block(
+                &mut chain,
+                h as usize,
+                h,
+                main[h as usize - 1],
+                0,
+                true,
+            ));
+        }
+        // 高さ 2..=4 で競合した深さ 3 の分岐（node 1）
+        let f2 = push_block(&mut chain, 6, 2, main[1], 1, true);
+        let f3 = push_block(&mut chain, 7, 3, f2, 1, true);
+        let f4 = push_block(&mut chain, 8, 4, f3, 1, true);
+        // 高さ 5 の 1 ブロックだけの分岐（node 2）
+        let g5 = push_block(&mut chain, 9, 5, main[4], 2, true);
+        for id in main.iter().skip(1).chain(&[f2, f3, f4, g5]) {
+            chain.mark_block_generation_completed(*id);
+        }
+        assert_eq!(
+            chain.longest_fork(),
+            Some(ForkEvent {
+                start_height: 2,
+                depth: 3,
+                nodes: vec![0, 1],
+            })
+        );
+
+        // 採掘が完了していない（終了時点で採掘中の）ブロックは数えない。
+        let mut chain = Blockchain::new(test_protocol(), 3);
+        let m1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 0, true);
+        let f1 = push_block(&mut chain, 2, 1, GENESIS_BLOCK_ID, 1, true);
+        chain.mark_block_generation_completed(m1);
+        assert_eq!(chain.longest_fork(), None);
+        chain.mark_block_generation_completed(f1);
+        assert_eq!(chain.longest_fork().map(|f| f.depth), Some(1));
+    }
+
+    #[test]
+    fn honest_orphans_are_attributed_to_the_chain_that_displaced_them() {
+        let mut chain = Blockchain::new(test_protocol(), 3);
+        let attackers: HashSet<NodeId> = [NodeId::new(0)].into_iter().collect();
+        // honest（node 1）が h1 -> h2 と伸ばした後、攻撃者（node 0）が genesis から伸ばした
+        // a1 -> a2 -> a3 を公開して h1, h2 を覆す。
+        let h1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 1, true);
+        let h2 = push_block(&mut chain, 2, 2, h1, 1, true);
+        let a1 = push_block(&mut chain, 3, 1, GENESIS_BLOCK_ID, 0, true);
+        let a2 = push_block(&mut chain, 4, 2, a1, 0, true);
+        let a3 = push_block(&mut chain, 5, 3, a2, 0, true);
+        // その上で honest 同士が高さ 4 で競合し、m4 -> m5 が勝つ。
+        let m4 = push_block(&mut chain, 6, 4, a3, 1, true);
+        let f4 = push_block(&mut chain, 7, 4, a3, 2, true);
+        let m5 = push_block(&mut chain, 8, 5, m4, 1, true);
+        for id in [h1, h2, a1, a2, a3, m4, f4, m5] {
+            chain.mark_block_generation_completed(id);
+        }
+        assert_eq!(
+            chain.get_main_chain(),
+            [GENESIS_BLOCK_ID, a1, a2, a3, m4, m5]
+        );
+
+        assert_eq!(chain.honest_orphans_by_cause(&attackers, 0), (2, 1));
+        // 高さ 2 以上に限れば h1 は数えない。
+        assert_eq!(chain.honest_orphans_by_cause(&attackers, 2), (1, 1));
+        // 攻撃者がいなければすべて自然な分岐。
+        assert_eq!(chain.honest_orphans_by_cause(&HashSet::new(), 0), (0, 3));
+    }
+
     #[test]
     fn honest_stale_rate_counts_only_honest_announced_completed_blocks() {
         let protocol = test_protocol();
-        let mut chain = Blockchain::new(protocol.as_ref(), 3);
+        let mut chain = Blockchain::new(protocol.clone(), 3);
         let honest: HashSet<NodeId> = [1usize, 2].into_iter().map(NodeId::new).collect();
 
         // main: genesis -> h1(honest) -> h2(attacker) -> h3(honest)
@@ -426,10 +1269,182 @@ mod chain_metrics_tests {
         assert!(m.honest_mined_blocks < m_all.mined_blocks);
     }
 
+    #[test]
+    fn main_chain_blocks_climb_from_genesis() {
+        let mut chain = Blockchain::new(test_protocol(), 3);
+        let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 0, true);
+        let b2 = push_block(&mut chain, 2, 2, b1, 1, true);
+        let _fork = push_block(&mut chain, 3, 2, b1, 2, true);
+        let _b3 = push_block(&mut chain, 4, 3, b2, 0, true);
+        for id in 1..=4 {
+            chain.mark_block_generation_completed(BlockId::new(id));
+        }
+
+        let heights: Vec<i64> = chain.main_chain_blocks().map(|b| b.height()).collect();
+        assert_eq!(heights, vec![0, 1, 2, 3]);
+        assert!(heights.windows(2).all(|w| w[0] < w[1]));
+        let ids: Vec<BlockId> = chain.main_chain_blocks().map(|b| b.id()).collect();
+        assert_eq!(ids, chain.get_main_chain());
+        assert_eq!(
+            chain.main_chain_blocks_for_export().count(),
+            chain.get_main_chain_for_export().len()
+        );
+    }
+
+    #[test]
+    fn mining_time_grows_with_difficulty_at_fixed_hashrate() {
+        use crate::protocol::{BitcoinDifficulty, Difficulty};
+        use rand::{SeedableRng, rngs::StdRng};
+
+        // 難易度 1 で平均 600 秒になるハッシュレートのまま、400 ブロックごとに難易度を 2 倍にしていく。
+        let hashrate = (BitcoinDifficulty::HASHES_PER_DIFFICULTY / 600_000.0) as i64;
+        let levels = [1.0, 2.0, 4.0, 8.0, 16.0];
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut chain = Blockchain::new(test_protocol(), hashrate);
+        let mut prev = GENESIS_BLOCK_ID;
+        for (i, level) in levels.iter().flat_map(|&d| [d; 400]).enumerate() {
+            let difficulty = Difficulty::Bitcoin(BitcoinDifficulty::new(level));
+            let mining_time_us = difficulty.calculate_mining_time(&mut rng, hashrate);
+            let id = BlockId::new(i + 1);
+            let cumulative = chain.get_block(prev).unwrap().cumulative_chain_work()
+                + difficulty.chain_work_increment();
+            chain.add_block(Block::new(
+                i as i64 + 1,
+                Some(prev),
+                NodeId::new(0),
+                0,
+                0,
+                id,
+                difficulty,
+                cumulative,
+                mining_time_us as f64 / 1000.0,
+                true,
+            ));
+            chain.mark_block_generation_completed(id);
+            prev = id;
+        }
+
+        let pairs = chain.difficulty_vs_mining_time();
+        assert_eq!(pairs.len(), levels.len() * 400, "genesis is skipped");
+        for (level, chunk) in levels.iter().zip(pairs.chunks(400)) {
+            assert!(chunk.iter().all(|&(d, _)| d == *level));
+            let mean_ms = chunk.iter().map(|&(_, t)| t as f64).sum::<f64>() / 400.0;
+            let ratio = mean_ms / (level * 600_000.0);
+            assert!(
+                (0.8..1.2).contains(&ratio),
+                "difficulty {level}: mean {mean_ms} ms"
+            );
+        }
+    }
+
+    #[test]
+    fn json_tree_marks_main_chain_blocks() {
+        let mut chain = Blockchain::new(test_protocol(), 3);
+        let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 0, true);
+        let b2 = push_block(&mut chain, 2, 2, b1, 1, true);
+        let _fork = push_block(&mut chain, 3, 2, b1, 2, true);
+        let main = vec![GENESIS_BLOCK_ID, b1, b2];
+
+        let tree: Vec<TreeNode> = serde_json::from_str(&chain.to_json_tree(&main)).unwrap();
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.iter().filter(|n| n.on_main_chain).count(), main.len());
+        assert_eq!(tree[0].prev_id, None);
+        assert_eq!(tree[0].minter, None);
+        assert_eq!((tree[3].prev_id, tree[3].minter), (Some(1), Some(2)));
+        assert!(!tree[3].on_main_chain);
+    }
+
+    #[test]
+    fn main_chain_rewards_skip_genesis_and_credit_minters() {
+        let protocol = test_protocol();
+        let mut chain = Blockchain::new(protocol.clone(), 3);
+        assert_eq!(chain.get_block(GENESIS_BLOCK_ID).unwrap().minter(), None);
+        assert!(chain.main_chain_rewards(0, 0).is_empty(), "ジェネシスのみなら報酬なし");
+
+        let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 2, true);
+        let b2 = push_block(&mut chain, 2, 2, b1, 0, true);
+        let b3 = push_block(&mut chain, 3, 3, b2, 2, true);
+        for id in [b1, b2, b3] {
+            chain.mark_block_generation_completed(id);
+        }
+
+        let rewards = chain.main_chain_rewards(0, 0);
+        assert_eq!(rewards.len(), 2);
+        assert_eq!(rewards[&NodeId::new(2)], 2);
+        assert_eq!(rewards[&NodeId::new(0)], 1);
+        assert_eq!(rewards.values().sum::<u64>(), 3, "genesis + 3 blocks -> 3 rewards");
+    }
+
+    #[test]
+    fn main_chain_rewards_require_confirmations() {
+        let protocol = test_protocol();
+        let mut chain = Blockchain::new(protocol.clone(), 3);
+        let mut prev = GENESIS_BLOCK_ID;
+        for h in 1..=5 {
+            prev = push_block(&mut chain, h as usize, h, prev, 1, true);
+            chain.mark_block_generation_completed(prev);
+        }
+
+        let counted = |c: i64| chain.main_chain_rewards(c, 0).values().sum::<u64>();
+        assert_eq!(counted(0), 5, "C=0 は全メインチェーンブロック");
+        assert_eq!(counted(1), 4, "tip は確認 0");
+        assert_eq!(counted(3), 2);
+        assert!(counted(0) > counted(1) && counted(1) > counted(3));
+        assert_eq!(counted(5), 0, "ジェネシスは数えない");
+        assert_eq!(counted(100), 0, "C がチェーン長を超えたら報酬なし");
+
+        let after_burn_in = chain.main_chain_rewards(1, 3).values().sum::<u64>();
+        assert_eq!(after_burn_in, 2, "高さ 3, 4 のみ");
+    }
+
+    #[test]
+    fn main_chain_without_genesis_drops_only_genesis() {
+        let protocol = test_protocol();
+        let mut chain = Blockchain::new(protocol.clone(), 3);
+        assert!(chain.get_main_chain_without_genesis().is_empty());
+
+        let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 1, true);
+        let b2 = push_block(&mut chain, 2, 2, b1, 0, false);
+        for id in [b1, b2] {
+            chain.mark_block_generation_completed(id);
+        }
+
+        let with = chain.get_main_chain();
+        let without = chain.get_main_chain_without_genesis();
+        assert_eq!(with[0], GENESIS_BLOCK_ID);
+        assert_eq!(&with[1..], without.as_slice());
+        assert_eq!(without, vec![b1]);
+
+        let with = chain.get_main_chain_for_export();
+        let without = chain.get_main_chain_for_export_without_genesis();
+        assert_eq!(with.len(), without.len() + 1);
+        assert_eq!(&with[1..], without.as_slice());
+        assert_eq!(without, vec![b1, b2]);
+    }
+
+    #[test]
+    fn blocks_at_height_lists_every_fork() {
+        let protocol = test_protocol();
+        let mut chain = Blockchain::new(protocol.clone(), 3);
+
+        // genesis -> b1 -> {b2, b3} -> b4(on b2)
+        let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 1, true);
+        let b2 = push_block(&mut chain, 2, 2, b1, 1, true);
+        let b3 = push_block(&mut chain, 3, 2, b1, 0, true);
+        let b4 = push_block(&mut chain, 4, 3, b2, 1, true);
+
+        assert_eq!(chain.blocks_at_height(0), vec![GENESIS_BLOCK_ID]);
+        assert_eq!(chain.blocks_at_height(1), vec![b1]);
+        assert_eq!(chain.blocks_at_height(2), vec![b2, b3], "contested height");
+        assert_eq!(chain.blocks_at_height(3), vec![b4]);
+        assert!(chain.blocks_at_height(4).is_empty());
+        assert!(chain.blocks_at_height(-1).is_empty());
+    }
+
     #[test]
     fn private_attack_success_is_attacker_tip_in_eval_range() {
         let protocol = test_protocol();
-        let mut chain = Blockchain::new(protocol.as_ref(), 3);
+        let mut chain = Blockchain::new(protocol.clone(), 3);
         let honest: HashSet<NodeId> = HashSet::from([NodeId::new(1)]);
 
         let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 1, true);
@@ -440,7 +1455,7 @@ mod chain_metrics_tests {
         let m = chain.chain_metrics(Some(&honest), Some(2), Some(2));
         assert!(m.private_attack_reorg_success);
 
-        let mut chain2 = Blockchain::new(protocol.as_ref(), 3);
+        let mut chain2 = Blockchain::new(protocol.clone(), 3);
         let b1 = push_block(&mut chain2, 10, 1, GENESIS_BLOCK_ID, 1, true);
         let b2 = push_block(&mut chain2, 11, 2, b1, 1, true);
         for id in [b1, b2] {
@@ -453,7 +1468,7 @@ mod chain_metrics_tests {
     #[test]
     fn export_main_chain_includes_unannounced_heavier_branch() {
         let protocol = test_protocol();
-        let mut chain = Blockchain::new(protocol.as_ref(), 3);
+        let mut chain = Blockchain::new(protocol.clone(), 3);
 
         // 公衆鎖: genesis -> h1 (告知済み)
         let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 1, true);
@@ -476,7 +1491,7 @@ mod chain_metrics_tests {
     #[test]
     fn honest_stale_rate_respects_height_bounds_and_announced_filter() {
         let protocol = test_protocol();
-        let mut chain = Blockchain::new(protocol.as_ref(), 3);
+        let mut chain = Blockchain::new(protocol.clone(), 3);
         let honest: HashSet<NodeId> = HashSet::from([NodeId::new(1)]);
 
         let b1 = push_block(&mut chain, 1, 1, GENESIS_BLOCK_ID, 1, true);
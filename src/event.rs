@@ -25,7 +25,10 @@ impl Event {
     }
 
     pub fn is_propagation(&self) -> bool {
-        matches!(self.ty, EventType::Propagation { .. })
+        matches!(
+            self.ty,
+            EventType::Propagation { .. } | EventType::Broadcast { .. }
+        )
     }
 }
 
@@ -41,4 +44,18 @@ pub enum EventType {
         to: NodeId,
         block_id: BlockId,
     },
+    /// [`PropagationModel::Broadcast`](crate::PropagationModel::Broadcast) で、`to` の全ノードに同時にブロックが届く。
+    Broadcast {
+        from: NodeId,
+        to: Vec<NodeId>,
+        block_id: BlockId,
+    },
+    /// header-first relay で、先にヘッダを受け取って tip を切り替えたノードにブロック本体が届く。
+    BlockBody {
+        from: NodeId,
+        to: NodeId,
+        block_id: BlockId,
+    },
+    /// 途中参加ノードがネットワークに加わる。それまでは採掘も受信もしない。
+    NodeJoin { node: NodeId },
 }
@@ -1,21 +1,97 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 
 use priority_queue::PriorityQueue;
 
+use crate::blockchain::BlockId;
 use crate::event::{Event, EventType};
 use crate::node::NodeId;
 
+/// シミュレーションイベントのキュー。
+///
+/// 各 minter の保留中の `BlockGeneration` は高々 1 つで、`push` した採掘イベントはその minter の
+/// 保留中イベントとして記録される。同時刻のイベントは `push` した順（FIFO）に取り出す。
+pub trait EventQueue: Send + Sync {
+    fn is_empty(&self) -> bool;
+
+    /// 保留中のイベント数（取り消したものは数えない）。
+    fn len(&self) -> usize;
+
+    /// イベントを入れる。
+    fn push(&mut self, event: Event);
+
+    /// 時刻の最も早いイベントを取り出す。
+    fn pop(&mut self) -> Option<Event>;
+
+    /// `minter` の保留中の採掘イベントを取り消して返す。
+    fn cancel_mining(&mut self, minter: NodeId) -> Option<Event>;
+
+    /// `minter` の保留中の採掘イベント（あれば 1 つだけ）。
+    fn pending_mining(&self, minter: NodeId) -> Option<&Event>;
+
+    /// キュー内のイベントが参照するブロック（採掘の親・生成予定ブロック・伝播中のブロック）。
+    fn referenced_blocks(&self) -> Box<dyn Iterator<Item = BlockId> + '_>;
+
+    /// Enqueue a `BlockGeneration`, replacing any existing pending mining event for the same minter.
+    fn push_mining(&mut self, event: Event) {
+        if let EventType::BlockGeneration { minter, .. } = *event.event_type() {
+            self.cancel_mining(minter);
+        }
+        self.push(event);
+    }
+}
+
+/// `--event-queue` で選べるキューの実装。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum EventQueueKind {
+    /// [`PriorityEventQueue`]
+    #[default]
+    Priority,
+    /// [`HeapEventQueue`]
+    Heap,
+}
+
+impl EventQueueKind {
+    pub fn to_event_queue(self) -> Box<dyn EventQueue> {
+        match self {
+            EventQueueKind::Priority => Box::new(PriorityEventQueue::new()),
+            EventQueueKind::Heap => Box::new(HeapEventQueue::new()),
+        }
+    }
+}
+
+/// 同時刻イベントの決定的順序: 小さい `seq` を先に処理（FIFO）。値が大きいほど先に取り出す。
+/// `seq` は 64 bit のまま下位に詰めるので、何件積んでも同時刻の順序は積んだ順から崩れない。
+fn priority_key(time_us: i64, seq: u64) -> i128 {
+    let enc = (time_us as i128).saturating_mul(1 << 64) | seq as i128;
+    i128::MAX - enc
+}
+
+fn event_blocks(event: &Event) -> [Option<BlockId>; 2] {
+    match *event.event_type() {
+        EventType::BlockGeneration {
+            prev_block_id,
+            block_id,
+            ..
+        } => [Some(prev_block_id), Some(block_id)],
+        EventType::Propagation { block_id, .. }
+        | EventType::Broadcast { block_id, .. }
+        | EventType::BlockBody { block_id, .. } => [Some(block_id), None],
+        EventType::NodeJoin { .. } => [None, None],
+    }
+}
+
 /// Priority queue of simulation events plus a per-minter index of pending `BlockGeneration`s.
 ///
-/// At most one pending mining event exists per minter; a new `RestartMining` removes the old
-/// one via `PriorityQueue::remove` instead of scanning the whole queue.
-pub struct EventQueue {
+/// A new `RestartMining` removes the old pending event via `PriorityQueue::remove`
+/// instead of scanning the whole queue.
+pub struct PriorityEventQueue {
     inner: PriorityQueue<Event, i128>,
     pending_mining_by_minter: HashMap<NodeId, Event>,
     next_seq: u64,
 }
 
-impl EventQueue {
+impl PriorityEventQueue {
     pub fn new() -> Self {
         Self {
             inner: PriorityQueue::new(),
@@ -24,61 +100,219 @@ impl EventQueue {
         }
     }
 
-    /// 同時刻イベントの決定的順序: 小さい `seq` を先に処理（FIFO）。
-    fn priority_key(time_us: i64, seq: u64) -> i128 {
-        let enc = (time_us as i128).saturating_mul(1 << 24)
-            | ((seq & 0xFF_FFFF) as i128);
-        i128::MAX - enc
-    }
-
     fn bump_seq(&mut self) -> u64 {
         let s = self.next_seq;
         self.next_seq = self.next_seq.wrapping_add(1);
         s
     }
+}
+
+impl Default for PriorityEventQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    pub fn is_empty(&self) -> bool {
+impl EventQueue for PriorityEventQueue {
+    fn is_empty(&self) -> bool {
         self.inner.is_empty()
     }
 
-    /// Non-mining events (e.g. propagation) that are not tied 1:1 to a minter slot.
-    pub fn push(&mut self, event: Event) {
-        let time = event.time();
-        let seq = self.bump_seq();
-        let pk = Self::priority_key(time, seq);
-        self.inner.push(event, pk);
+    fn len(&self) -> usize {
+        self.inner.len()
     }
 
-    /// Enqueue a `BlockGeneration`, replacing any existing pending mining event for the same minter.
-    pub fn push_mining(&mut self, event: Event) {
-        let minter = match event.event_type() {
-            EventType::BlockGeneration { minter, .. } => *minter,
-            EventType::Propagation { .. } => {
-                self.push(event);
-                return;
-            }
-        };
-        if let Some(old) = self.pending_mining_by_minter.remove(&minter) {
-            let _ = self.inner.remove(&old);
-        }
-        let time = event.time();
+    fn push(&mut self, event: Event) {
         let seq = self.bump_seq();
-        let pk = Self::priority_key(time, seq);
-        self.pending_mining_by_minter.insert(minter, event.clone());
+        let pk = priority_key(event.time(), seq);
+        if let EventType::BlockGeneration { minter, .. } = *event.event_type() {
+            self.pending_mining_by_minter.insert(minter, event.clone());
+        }
         self.inner.push(event, pk);
     }
 
-    pub fn pop(&mut self) -> Option<Event> {
+    fn pop(&mut self) -> Option<Event> {
         let (event, _) = self.inner.pop()?;
         if let EventType::BlockGeneration { minter, .. } = event.event_type() {
             self.pending_mining_by_minter.remove(minter);
         }
         Some(event)
     }
+
+    fn cancel_mining(&mut self, minter: NodeId) -> Option<Event> {
+        let old = self.pending_mining_by_minter.remove(&minter)?;
+        self.inner.remove(&old).map(|(event, _)| event)
+    }
+
+    fn pending_mining(&self, minter: NodeId) -> Option<&Event> {
+        self.pending_mining_by_minter.get(&minter)
+    }
+
+    fn referenced_blocks(&self) -> Box<dyn Iterator<Item = BlockId> + '_> {
+        Box::new(
+            self.inner
+                .iter()
+                .flat_map(|(event, _)| event_blocks(event))
+                .flatten(),
+        )
+    }
+}
+
+/// [`HeapEventQueue`] の要素。`key` の大きい順に取り出す。
+struct HeapEntry {
+    key: i128,
+    seq: u64,
+    event: Event,
 }
 
-impl Default for EventQueue {
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// `BinaryHeap` による実装。取り消した採掘イベントはヒープに残したまま `seq` で印を付け、
+/// 取り出したときに読み捨てる（lazy deletion）。
+pub struct HeapEventQueue {
+    heap: BinaryHeap<HeapEntry>,
+    /// 取り消し済みでヒープに残っているイベントの `seq`。
+    cancelled: HashSet<u64>,
+    /// minter ごとの保留中の採掘イベントとその `seq`。
+    pending_mining_by_minter: HashMap<NodeId, (u64, Event)>,
+    next_seq: u64,
+}
+
+impl HeapEventQueue {
+    pub fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            cancelled: HashSet::new(),
+            pending_mining_by_minter: HashMap::new(),
+            next_seq: 0,
+        }
+    }
+}
+
+impl Default for HeapEventQueue {
     fn default() -> Self {
         Self::new()
     }
 }
+
+impl EventQueue for HeapEventQueue {
+    fn is_empty(&self) -> bool {
+        self.heap.len() == self.cancelled.len()
+    }
+
+    fn len(&self) -> usize {
+        self.heap.len() - self.cancelled.len()
+    }
+
+    fn push(&mut self, event: Event) {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        if let EventType::BlockGeneration { minter, .. } = *event.event_type() {
+            self.pending_mining_by_minter
+                .insert(minter, (seq, event.clone()));
+        }
+        self.heap.push(HeapEntry {
+            key: priority_key(event.time(), seq),
+            seq,
+            event,
+        });
+    }
+
+    fn pop(&mut self) -> Option<Event> {
+        while let Some(entry) = self.heap.pop() {
+            if self.cancelled.remove(&entry.seq) {
+                continue;
+            }
+            if let EventType::BlockGeneration { minter, .. } = entry.event.event_type() {
+                self.pending_mining_by_minter.remove(minter);
+            }
+            return Some(entry.event);
+        }
+        None
+    }
+
+    fn cancel_mining(&mut self, minter: NodeId) -> Option<Event> {
+        let (seq, event) = self.pending_mining_by_minter.remove(&minter)?;
+        self.cancelled.insert(seq);
+        Some(event)
+    }
+
+    fn pending_mining(&self, minter: NodeId) -> Option<&Event> {
+        self.pending_mining_by_minter
+            .get(&minter)
+            .map(|(_, event)| event)
+    }
+
+    fn referenced_blocks(&self) -> Box<dyn Iterator<Item = BlockId> + '_> {
+        Box::new(
+            self.heap
+                .iter()
+                .filter(|entry| !self.cancelled.contains(&entry.seq))
+                .flat_map(|entry| event_blocks(&entry.event))
+                .flatten(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_time_events_stay_fifo_past_24_bit_sequence_numbers() {
+        let t = 1_000_000;
+        assert!(priority_key(t, 0) > priority_key(t, 1));
+        // 以前は seq の下位 24 bit だけを使っていたので、2^24 件目で先頭と同じ優先度に戻っていた。
+        assert!(priority_key(t, 0) > priority_key(t, 1 << 24));
+        assert!(priority_key(t, (1 << 24) - 1) > priority_key(t, 1 << 24));
+        // 時刻の差は seq より常に優先する。
+        assert!(priority_key(t, u64::MAX) > priority_key(t + 1, 0));
+        assert!(priority_key(i64::MAX - 1, u64::MAX) > priority_key(i64::MAX, 0));
+    }
+
+    #[test]
+    fn len_does_not_count_cancelled_mining() {
+        let mining = |minter: usize, block_id: usize| {
+            Event::new(
+                block_id as i64,
+                EventType::BlockGeneration {
+                    minter: NodeId::new(minter),
+                    prev_block_id: BlockId::new(0),
+                    block_id: BlockId::new(block_id),
+                },
+            )
+        };
+        for kind in [EventQueueKind::Priority, EventQueueKind::Heap] {
+            let mut queue = kind.to_event_queue();
+            queue.push_mining(mining(0, 1));
+            queue.push_mining(mining(1, 2));
+            assert_eq!(queue.len(), 2);
+            // 同じ minter の採掘イベントは置き換わる。
+            queue.push_mining(mining(0, 3));
+            assert_eq!(queue.len(), 2, "{kind:?}");
+            queue.pop();
+            assert_eq!(queue.len(), 1, "{kind:?}");
+            queue.cancel_mining(NodeId::new(0));
+            assert_eq!(queue.len(), 0, "{kind:?}");
+            assert!(queue.is_empty());
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use crate::block::Block;
+
+/// 手数料の単純なモデル。
+///
+/// 取引手数料は一定の速度 `rate_per_ms` で mempool に入り、ブロックは採掘された時刻までに
+/// 入った未回収の手数料をすべて回収する（容量の上限はない）。そのため tip の上で次に回収できる
+/// 手数料は tip が採掘されてからの経過時間に比例する（申告された timestamp には依らない）。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeModel {
+    /// 1 ms あたりに mempool に入る手数料（ブロック報酬 1 を単位とする）
+    pub rate_per_ms: f64,
+}
+
+impl FeeModel {
+    pub fn new(rate_per_ms: f64) -> Self {
+        assert!(
+            rate_per_ms.is_finite() && rate_per_ms >= 0.0,
+            "fee rate must be non-negative, got {rate_per_ms}"
+        );
+        Self { rate_per_ms }
+    }
+
+    /// `tip` の上で時刻 `now_ms` に採掘したときに回収できる手数料。
+    pub fn unclaimed_fees(&self, tip: &Block, now_ms: i64) -> f64 {
+        self.rate_per_ms * (now_ms - tip.created_at()).max(0) as f64
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{
+    blockchain::{BlockId, Blockchain},
+    mining_strategy::TieBreakingRule,
+    node::NodeId,
+};
+
+/// プロトコルに依らず全ノードで共通に使うフォーク選択ルール。
+///
+/// `a` は現在の head、`b` は新たに受け取った tip として呼ばれる。`node` は選択するノードで、
+/// [`Blockchain::get_main_chain`] のときは `None`。ノードの採用と主鎖の両方がこの選択に従う。
+/// 同等のときの扱いも実装側が決める（ここで提供する実装は [`break_tie`] で `blockchain` の tie-breaking rule に従う）。
+pub trait ForkChoice: Send + Sync {
+    fn select(
+        &self,
+        blockchain: &Blockchain,
+        node: Option<NodeId>,
+        a: BlockId,
+        b: BlockId,
+    ) -> BlockId;
+}
+
+/// 高さが高い tip を選ぶ（難易度は無視する）。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LongestChain;
+
+impl ForkChoice for LongestChain {
+    fn select(
+        &self,
+        blockchain: &Blockchain,
+        node: Option<NodeId>,
+        a: BlockId,
+        b: BlockId,
+    ) -> BlockId {
+        let height = |id| blockchain.get_block(id).unwrap().height();
+        match height(b).cmp(&height(a)) {
+            std::cmp::Ordering::Greater => b,
+            std::cmp::Ordering::Less => a,
+            std::cmp::Ordering::Equal => break_tie(blockchain, node, a, b),
+        }
+    }
+}
+
+/// 累積 chainwork が大きい tip を選ぶ。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeaviestChain;
+
+impl ForkChoice for HeaviestChain {
+    fn select(
+        &self,
+        blockchain: &Blockchain,
+        node: Option<NodeId>,
+        a: BlockId,
+        b: BlockId,
+    ) -> BlockId {
+        let work = |id| blockchain.get_block(id).unwrap().cumulative_chain_work();
+        match work(b).cmp(&work(a)) {
+            std::cmp::Ordering::Greater => b,
+            std::cmp::Ordering::Less => a,
+            std::cmp::Ordering::Equal => break_tie(blockchain, node, a, b),
+        }
+    }
+}
+
+/// 同等な `a`（現在の head）と `b`（受け取った tip）から、`blockchain` の tie-breaking rule で選ぶ。
+/// どちらの規則でも結果は引数の順序に依らない。
+pub(crate) fn break_tie(
+    blockchain: &Blockchain,
+    node: Option<NodeId>,
+    a: BlockId,
+    b: BlockId,
+) -> BlockId {
+    match blockchain.tie_breaking_rule() {
+        // `node` が先に受け取った方（主鎖ではネットワークで先に現れた方）。まだ受け取っていない tip は
+        // 最後に見たものとして扱い、時刻まで等しければ id の小さい方を選ぶ。
+        TieBreakingRule::FirstSeen => {
+            let seen = |id| {
+                let time = match node {
+                    Some(node) => blockchain.first_seen(node, id),
+                    None => blockchain.first_seen_by_any(id),
+                };
+                (time.unwrap_or(i64::MAX), id)
+            };
+            if seen(b) < seen(a) { b } else { a }
+        }
+        // rand まで等しければ id の小さい方を選び、引数の順序に依らない全順序にする。
+        TieBreakingRule::Random => {
+            let rand = |id| blockchain.get_block(id).unwrap().rand();
+            if (rand(b), b) < (rand(a), a) { b } else { a }
+        }
+    }
+}
+
+/// `--fork-choice` で選べるフォーク選択ルール。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ForkChoiceRule {
+    /// プロトコル既定（[`Protocol::prefer`](crate::Protocol::prefer)）と `--tie-breaking-rule` に従う。
+    #[default]
+    Protocol,
+    /// [`LongestChain`]
+    Longest,
+    /// [`HeaviestChain`]
+    Heaviest,
+}
+
+impl ForkChoiceRule {
+    /// プロトコル既定のときは `None`。
+    pub fn to_fork_choice(self) -> Option<Box<dyn ForkChoice>> {
+        match self {
+            ForkChoiceRule::Protocol => None,
+            ForkChoiceRule::Longest => Some(Box::new(LongestChain)),
+            ForkChoiceRule::Heaviest => Some(Box::new(HeaviestChain)),
+        }
+    }
+}
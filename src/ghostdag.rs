@@ -0,0 +1,256 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    block::GENESIS_BLOCK_ID,
+    blockchain::{BlockId, Blockchain},
+};
+
+/// GHOSTDAG（Kaspa）で blockDAG を順序付けた結果。
+///
+/// 各ブロックは親のうち blue score 最大（同じなら id の小さい方）を selected parent とし、
+/// selected parent の過去に含まれない過去のブロック（mergeset）を (blue score, id) 順に調べて、
+/// 青いブロックの集合が k-cluster（どの青ブロックも青い anticone が k 個以下）を保つものだけを青とする。
+/// 順序は `order(B) = order(selected parent), mergeset(B), B` で、全 tip を親とする仮想ブロックから取る。
+#[derive(Debug, Clone, PartialEq)]
+pub struct GhostDagOrdering {
+    /// ジェネシスから並べた全ブロック（採掘完了済みのみ）
+    pub order: Vec<BlockId>,
+    /// 青（k-cluster に入った）ブロック。ジェネシスを含む
+    pub blue: HashSet<BlockId>,
+}
+
+impl GhostDagOrdering {
+    /// 採掘完了済みのブロックから DAG を組み立てて順序付ける。刈り込まれた親は無視する。
+    pub fn compute(blockchain: &Blockchain, k: usize) -> Self {
+        let mut dag = GhostDag {
+            blockchain,
+            k,
+            data: HashMap::new(),
+        };
+        let mut tips: Vec<BlockId> = Vec::new();
+        for block in blockchain.blocks() {
+            let id = block.id();
+            if !blockchain.is_generation_completed(id) {
+                continue;
+            }
+            let parents: Vec<BlockId> = block
+                .parents()
+                .filter(|p| dag.data.contains_key(p))
+                .collect();
+            let data = if id == GENESIS_BLOCK_ID {
+                GhostDagData::default()
+            } else if parents.is_empty() {
+                continue;
+            } else {
+                dag.ghostdag(&parents)
+            };
+            tips.retain(|tip| !parents.contains(tip));
+            tips.push(id);
+            dag.data.insert(id, data);
+        }
+
+        let virtual_block = dag.ghostdag(&tips);
+        let mut chain = Vec::new();
+        let mut cur = &virtual_block;
+        let mut blue = HashSet::new();
+        loop {
+            blue.extend(cur.mergeset_blues.iter().copied());
+            let Some(parent) = cur.selected_parent else {
+                break;
+            };
+            chain.push(parent);
+            cur = &dag.data[&parent];
+        }
+        let mut order = Vec::with_capacity(dag.data.len());
+        for &id in chain.iter().rev() {
+            order.extend(dag.data[&id].mergeset.iter().copied());
+            order.push(id);
+        }
+        order.extend(virtual_block.mergeset.iter().copied());
+        Self { order, blue }
+    }
+
+    pub fn is_blue(&self, id: BlockId) -> bool {
+        self.blue.contains(&id)
+    }
+}
+
+/// 1 ブロック分の GHOSTDAG の計算結果。
+#[derive(Debug, Clone, Default)]
+struct GhostDagData {
+    /// ジェネシスは `None`
+    selected_parent: Option<BlockId>,
+    /// 過去に含まれる青ブロックの数
+    blue_score: u64,
+    /// selected parent を先頭に、mergeset のうち青としたブロック
+    mergeset_blues: Vec<BlockId>,
+    /// selected parent を除く mergeset（(blue score, id) 順）
+    mergeset: Vec<BlockId>,
+    /// このブロックから見た青ブロックごとの青い anticone の大きさ（更新のあった分だけ）
+    blues_anticone_sizes: HashMap<BlockId, usize>,
+}
+
+struct GhostDag<'a> {
+    blockchain: &'a Blockchain,
+    k: usize,
+    data: HashMap<BlockId, GhostDagData>,
+}
+
+impl GhostDag<'_> {
+    fn ghostdag(&self, parents: &[BlockId]) -> GhostDagData {
+        let selected_parent = *parents
+            .iter()
+            .max_by_key(|&&p| (self.data[&p].blue_score, Reverse(p)))
+            .expect("a non-genesis block has a parent");
+        let mut new = GhostDagData {
+            selected_parent: Some(selected_parent),
+            blue_score: 0,
+            mergeset_blues: vec![selected_parent],
+            mergeset: self.mergeset(selected_parent, parents),
+            blues_anticone_sizes: HashMap::from([(selected_parent, 0)]),
+        };
+        for candidate in new.mergeset.clone() {
+            if let Some((size, blues_anticone_sizes)) = self.check_blue_candidate(&new, candidate) {
+                new.mergeset_blues.push(candidate);
+                new.blues_anticone_sizes.insert(candidate, size);
+                for (blue, size) in blues_anticone_sizes {
+                    new.blues_anticone_sizes.insert(blue, size + 1);
+                }
+            }
+        }
+        new.blue_score = self.data[&selected_parent].blue_score + new.mergeset_blues.len() as u64;
+        new
+    }
+
+    /// `parents` の過去のうち、`selected_parent` とその過去に含まれないブロック。
+    fn mergeset(&self, selected_parent: BlockId, parents: &[BlockId]) -> Vec<BlockId> {
+        let mut mergeset = Vec::new();
+        let mut seen: HashSet<BlockId> = HashSet::from([selected_parent]);
+        let mut queue: VecDeque<BlockId> = parents
+            .iter()
+            .copied()
+            .filter(|&p| p != selected_parent)
+            .collect();
+        while let Some(id) = queue.pop_front() {
+            if !seen.insert(id) || self.blockchain.is_dag_ancestor(id, selected_parent) {
+                continue;
+            }
+            mergeset.push(id);
+            if let Some(block) = self.blockchain.get_block(id) {
+                queue.extend(block.parents().filter(|p| self.data.contains_key(p)));
+            }
+        }
+        mergeset.sort_by_key(|id| (self.data[id].blue_score, *id));
+        mergeset
+    }
+
+    /// `candidate` を青にしても k-cluster が保たれるなら、その青い anticone の大きさと
+    /// anticone に入る青ブロックそれぞれの（追加前の）青い anticone の大きさを返す。
+    fn check_blue_candidate(
+        &self,
+        new: &GhostDagData,
+        candidate: BlockId,
+    ) -> Option<(usize, HashMap<BlockId, usize>)> {
+        if new.mergeset_blues.len() > self.k {
+            return None;
+        }
+        let mut blues_anticone_sizes = HashMap::new();
+        let mut size = 0;
+        let mut chain_block = new;
+        let mut chain_block_id = None;
+        loop {
+            // selected chain のブロックが candidate の過去にあれば、それより前の青ブロックも過去にある。
+            if chain_block_id.is_some_and(|id| self.blockchain.is_dag_ancestor(id, candidate)) {
+                break;
+            }
+            for &blue in &chain_block.mergeset_blues {
+                if self.blockchain.is_dag_ancestor(blue, candidate) {
+                    continue;
+                }
+                let blue_size = self.blue_anticone_size(blue, new);
+                blues_anticone_sizes.insert(blue, blue_size);
+                size += 1;
+                if size > self.k || blue_size == self.k {
+                    return None;
+                }
+            }
+            let Some(parent) = chain_block.selected_parent else {
+                break;
+            };
+            chain_block_id = Some(parent);
+            chain_block = &self.data[&parent];
+        }
+        Some((size, blues_anticone_sizes))
+    }
+
+    /// `context` から selected chain を遡り、`blue` の青い anticone の大きさの最新値を探す。
+    fn blue_anticone_size(&self, blue: BlockId, context: &GhostDagData) -> usize {
+        let mut cur = context;
+        loop {
+            if let Some(&size) = cur.blues_anticone_sizes.get(&blue) {
+                return size;
+            }
+            let parent = cur
+                .selected_parent
+                .expect("a blue block is in some mergeset of the selected chain");
+            cur = &self.data[&parent];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::Block, node::NodeId, protocol::GenesisDifficultyMode, protocol::ProtocolType,
+    };
+
+    /// G ← 1, 2, 3;  4 ← {1, 2};  5 ← 3。
+    fn small_dag() -> Blockchain {
+        let protocol = ProtocolType::GhostDag.to_protocol(GenesisDifficultyMode::Fixed);
+        let mut chain = Blockchain::new(protocol.into(), 1);
+        let genesis = chain.get_block(GENESIS_BLOCK_ID).unwrap().clone();
+        let blocks: [(&[usize], i64); 5] =
+            [(&[0], 1), (&[0], 1), (&[0], 1), (&[1, 2], 2), (&[3], 2)];
+        for (i, (parents, height)) in blocks.into_iter().enumerate() {
+            let id = chain.next_block_id();
+            let mut block = Block::new(
+                height,
+                Some(BlockId::new(parents[0])),
+                NodeId::new(i),
+                1000,
+                0,
+                id,
+                genesis.difficulty(),
+                genesis.cumulative_chain_work(),
+                1000.0,
+                true,
+            );
+            block.set_merge_parents(parents[1..].iter().map(|&p| BlockId::new(p)).collect());
+            chain.add_block(block);
+            chain.mark_block_generation_completed(id);
+        }
+        chain
+    }
+
+    #[test]
+    fn small_dag_matches_hand_computed_ghostdag() {
+        let chain = small_dag();
+        let ids = |ids: &[usize]| ids.iter().map(|&i| BlockId::new(i)).collect::<Vec<_>>();
+
+        // 4 の selected parent は 1（2 と blue score が同じで id が小さい）、mergeset は {2}。
+        // 仮想ブロックの selected parent は blue score 3 の 4 で、mergeset は 3, 5 の順。
+        let order = ids(&[0, 1, 2, 4, 3, 5]);
+
+        // k = 1: 3 の青い anticone は {4, 1, 2} で k を超えるので 3 と 5 は赤。
+        let k1 = GhostDagOrdering::compute(&chain, 1);
+        assert_eq!(k1.order, order);
+        assert_eq!(k1.blue, ids(&[0, 1, 2, 4]).into_iter().collect());
+
+        // k = 3: 3 の青い anticone は 3 個、5 の青い anticone は {4, 1, 2} でどちらも青。
+        let k3 = GhostDagOrdering::compute(&chain, 3);
+        assert_eq!(k3.order, order);
+        assert_eq!(k3.blue.len(), 6);
+    }
+}
@@ -1,12 +1,17 @@
+pub mod arrival;
 pub mod block;
 pub mod blockchain;
 pub mod event;
 pub mod event_queue;
+pub mod fee;
+pub mod fork_choice;
+pub mod ghostdag;
 pub mod mining_strategy;
 pub mod node;
 pub mod profile;
 pub mod propagation_delay;
 pub mod protocol;
+pub mod sampling;
 pub mod simulator;
 pub mod types;
 
@@ -14,16 +19,32 @@ pub mod types;
 /// honest / 攻撃者が並行して鎖を伸ばし、リードがこの値に達したら公開する（50% ハッシュレートとは無関係）。
 pub const PRIVATE_ATTACK_MIN_REORG_BLOCKS: i64 = 50;
 
+pub use arrival::{ArrivalExhausted, DeterministicArrival};
 pub use block::Block;
-pub use blockchain::Blockchain;
+pub use blockchain::{Blockchain, UncleRewards};
 pub use event::{Event, EventType};
+pub use event_queue::{EventQueue, EventQueueKind, HeapEventQueue, PriorityEventQueue};
+pub use fee::FeeModel;
+pub use fork_choice::{ForkChoice, ForkChoiceRule, HeaviestChain, LongestChain};
+pub use ghostdag::GhostDagOrdering;
 pub use mining_strategy::{
-    HonestMiningStrategy, MiningStrategy, MiningStrategyEnum, PrivateAttackMiningStrategy,
-    SelfishMiningStrategy,
+    FeeMaximizingStrategy, HonestMiningStrategy, MiningStrategy, MiningStrategyEnum,
+    PrivateAttackMiningStrategy, SelfishMiningStrategy, SpvMiningStrategy, TieBreakingRule,
 };
 pub use node::Node;
-pub use profile::{NetworkProfile, NodeProfile};
-pub use propagation_delay::PropagationDelayMode;
-pub use protocol::{GenesisDifficultyMode, Protocol, ProtocolType};
-pub use simulator::BlockchainSimulator;
-pub use types::{ChainMetrics, Record};
+pub use profile::{NetworkProfile, NodeProfile, Scenario};
+pub use propagation_delay::{PropagationDelayMode, PropagationModel, PropagationTarget};
+pub use protocol::{
+    DifficultyUpdate, GenesisDifficultyMode, Protocol, ProtocolType, UncleRewardRule,
+};
+pub use sampling::derive_run_seed;
+pub use simulator::{
+    BlockchainSimulator, EventHook, SimulationError, equal_hashrate_share_variance,
+    nakamoto_coefficient, theoretical_orphan_rate,
+};
+pub use types::{
+    AttackSuccessCondition, AttackSuccessReport, BlockTraceEvent, CatchUpStats, ChainMetrics,
+    Convergence, DoubleSpendReport, ForkEvent, GhostDagReport, ObserverStats, OutputFormat, Record,
+    RetargetClamp, RetargetEvent, RewardWeighting, SeedColumn, SimulationReport, StrategyShare,
+    TieBreakStats, TimeUnit, TraceEvent, TreeNode,
+};
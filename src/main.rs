@@ -1,28 +1,64 @@
 use blockchain_sim::{
-    BlockchainSimulator, GenesisDifficultyMode, NetworkProfile, PropagationDelayMode,
-    ProtocolType, node::NodeId,
+    ArrivalExhausted, AttackSuccessCondition, BlockchainSimulator, DeterministicArrival,
+    EventQueueKind, ForkChoiceRule, GenesisDifficultyMode, NetworkProfile, OutputFormat,
+    PropagationDelayMode, PropagationModel, PropagationTarget, ProtocolType, RewardWeighting,
+    Scenario, SeedColumn, TieBreakingRule, TimeUnit, blockchain::BlockId, derive_run_seed,
+    node::NodeId,
 };
 use clap::Parser;
 use rand::Rng;
+use serde::Serialize;
 use std::{
-    collections::{HashMap, HashSet},
-    path::PathBuf,
+    collections::HashSet,
+    path::{Path, PathBuf},
 };
 
+/// `--out-dir` に書き出すファイル名。
+const BLOCKS_FILE: &str = "blocks.csv";
+const FAIRNESS_FILE: &str = "fairness.csv";
+const TREE_FILE: &str = "tree.json";
+const MANIFEST_FILE: &str = "manifest.json";
+
 #[derive(Parser, Debug, Clone)]
 struct Cli {
-    /// The number of nodes.
-    #[clap(short, long, default_value = "10")]
-    num_nodes: usize,
+    /// The number of nodes. 省略時は 10（--hashrates / --profile 指定時はそのノード数。食い違えばエラー）。
+    #[clap(short, long)]
+    num_nodes: Option<usize>,
+
+    /// 採掘しない観測ノード（ハッシュレート 0 の honest）の数。ノード番号は採掘ノードの後ろに付く。
+    #[clap(long, default_value = "0")]
+    observers: usize,
+
+    /// ノードのハッシュレートをカンマ区切りで明示する（例: 100,200,300）。全ノード honest。
+    #[clap(long, value_delimiter = ',', conflicts_with = "profile")]
+    hashrates: Option<Vec<i64>>,
+
+    /// 生成したハッシュレートの大きい順にノード番号を振り直す（node 0 が最大）。ハッシュレートの組は変わらない。
+    #[clap(long, conflicts_with_all = ["profile", "hashrates", "scenario"])]
+    sort_by_hashrate: bool,
+
+    /// 名前付きのプリセットでノード構成を作る（攻撃者は node 0）。--num-nodes で総ノード数を指定する。
+    #[clap(long, value_enum, conflicts_with_all = ["profile", "hashrates"])]
+    scenario: Option<Scenario>,
 
     /// The seed for the random number generator.
     #[clap(short, long)]
     seed: Option<u64>,
 
+    /// 一括実行の何番目（0 始まり）か。指定すると --seed をそのまま使わず、`derive_run_seed(seed, index)` で導いた seed を使う。
+    /// CSV の seed 列と manifest には導いた seed が入る。
+    #[clap(long)]
+    run_index: Option<u64>,
+
     /// シミュレーションを続ける目標のメインチェーン高さ（完成済み・告知済みブロックのみ）。
     #[clap(long, default_value = "10")]
     end_round: i64,
 
+    /// 指定すると --end-round の代わりに、告知済みメインチェーンがこの高さに届いた時点で終了する
+    /// （分岐の多さに依らずメインチェーン長を揃える）。
+    #[clap(long)]
+    end_main_chain_height: Option<i64>,
+
     /// 伝播遅延 Δ（ms）。全モードでこの値を基準にする。
     #[clap(long, default_value = "600")]
     delay: i64,
@@ -31,25 +67,195 @@ struct Cli {
     #[clap(long, value_enum, default_value_t = PropagationDelayMode::Uniform)]
     propagation_delay_mode: PropagationDelayMode,
 
+    /// ブロックの公開先。all-nodes=全ノード、miners=ハッシュレートが正のノードだけ（観測ノードには送らない）。
+    #[clap(long, value_enum, default_value_t = PropagationTarget::AllNodes)]
+    propagation_target: PropagationTarget,
+
+    /// ブロックの届け方。per-peer=送り先ごとに伝播イベントを積む、broadcast=Δ ちょうど後に全員へ同時に届く。
+    #[clap(long, value_enum, default_value_t = PropagationModel::PerPeer)]
+    propagation_model: PropagationModel,
+
     #[clap(long, value_enum, default_value_t = ProtocolType::Bitcoin)]
     protocol: ProtocolType,
 
+    /// 同じ chainwork の tip が競合したときの選び方。first-seen=先に受け取った tip を維持、random=ブロックの乱数で決める。
+    #[clap(long, value_enum, default_value_t = TieBreakingRule::FirstSeen)]
+    tie_breaking_rule: TieBreakingRule,
+
+    /// 全ノード共通のフォーク選択。protocol=プロトコル既定、longest=高さ優先、heaviest=累積 chainwork 優先。
+    #[clap(long, value_enum, default_value_t = ForkChoiceRule::Protocol)]
+    fork_choice: ForkChoiceRule,
+
+    /// selfish mining の γ（0〜1）。honest/攻撃者ブロックの同着で honest ノードが攻撃者側を採る確率。
+    #[clap(long, value_parser = parse_fraction)]
+    gamma: Option<f64>,
+
+    /// 各ノードが受け入れる高さの差の上限。現在の tip よりこれを超えて高いブロックは採用しない。
+    #[clap(long, value_parser = clap::value_parser!(i64).range(0..))]
+    max_height_gap: Option<i64>,
+
+    /// 同着の判断に使う乱数の seed（省略時は --seed から導出）。採掘時間の乱数列は変わらない。
+    #[clap(long)]
+    tie_break_seed: Option<u64>,
+
+    /// 1 ms あたりに mempool に入る手数料（ブロック報酬 1 を単位）。fee_maximizing 戦略が同着の判断に使う。
+    #[clap(long)]
+    fee_rate: Option<f64>,
+
+    /// 1 ms あたりに mempool に入るトランザクション数。指定すると空ブロックの割合を報告する。
+    #[clap(long)]
+    tx_rate: Option<f64>,
+
+    /// 採掘時間のガンマ（Erlang）分布の形状。1 = 指数分布。大きいほど平均を変えずにばらつきが小さくなる。
+    #[clap(long, default_value_t = 1.0, value_parser = parse_positive)]
+    mining_time_shape: f64,
+
     /// How to determine genesis difficulty: inferred from total hashrate or fixed preset.
     #[clap(long, value_enum, default_value_t = GenesisDifficultyMode::Inferred)]
     genesis_difficulty_mode: GenesisDifficultyMode,
 
+    /// ブロック CSV・fairness CSV・ブロックツリー JSON と manifest.json（引数・seed・ノード構成）をまとめて書き出すディレクトリ。
+    /// 空でない既存ディレクトリは --overwrite なしではエラー。
+    #[clap(long, conflicts_with_all = ["output", "fairness_output", "output2", "tree_json"])]
+    out_dir: Option<PathBuf>,
+
+    /// --out-dir が空でなくても同名のファイルを上書きする。
+    #[clap(long, requires = "out_dir")]
+    overwrite: bool,
+
     /// The path to the CSV file for outputting block timestamp and difficulty.
     #[clap(long, short)]
     output: Option<PathBuf>,
 
+    /// CSV / サマリに出す時間（timestamp, mining_time 等）の単位。シミュレーション内部には影響しない。
+    #[clap(long, value_enum, default_value_t = TimeUnit::Ms)]
+    time_unit: TimeUnit,
+
+    /// CSV / サマリに出す浮動小数の小数点以下の桁数。省略時は丸めない。
+    #[clap(long)]
+    precision: Option<usize>,
+
     /// The path to the CSV file for outputting mining fairness.
+    #[clap(long)]
+    fairness_output: Option<PathBuf>,
+
+    /// Deprecated: use --fairness-output.
+    #[clap(hide = true, conflicts_with = "fairness_output")]
     output2: Option<PathBuf>,
 
+    /// 各ノードが採用 tip を切り替えるたびの (time, node_id, adopted_height) を書き出す CSV。
+    #[clap(long)]
+    tip_history: Option<PathBuf>,
+
+    /// 難易度調整ごとの (height, avg_block_time, clamp, old_difficulty, new_difficulty) を書き出す CSV。
+    #[clap(long)]
+    retargets: Option<PathBuf>,
+
+    /// メインチェーンの各ブロックの (difficulty, mining_time_ms) を書き出す CSV（難易度調整の検証用）。
+    #[clap(long)]
+    difficulty_times: Option<PathBuf>,
+
+    /// イベントキューの長さの時系列 (time, processed_events, queue_len) を書き出す CSV。
+    #[clap(long)]
+    queue_csv: Option<PathBuf>,
+
+    /// --queue-csv の標本を取る間隔（処理イベント数）。
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u64).range(1..))]
+    queue_sample_every: u64,
+
+    /// 終了時にキューに残った、到達済みの高さまでの採掘・伝播イベントを処理し切ってから止める（打ち切りの偏りを減らす）。
+    #[clap(long)]
+    drain_at_end: bool,
+
+    /// trace レベルのシミュレーションイベント（採掘・伝播）を JSON 1 行ずつで出す（RUST_LOG=trace と併用）。
+    #[clap(long)]
+    log_json: bool,
+
+    /// 指定した ID のブロックだけを追跡し、採掘・受信・各ノードの採用と放棄、終了時の状態を info レベルで出す。
+    #[clap(long)]
+    trace_block: Option<usize>,
+
+    /// イベントキューの実装（性能比較用。結果は変わらない）。
+    #[clap(long, value_enum, default_value_t = EventQueueKind::Priority)]
+    event_queue: EventQueueKind,
+
+    /// 分岐を含むブロックツリー全体を JSON 配列で書き出すパス（d3.js などの外部ツール向け）。
+    #[clap(long)]
+    tree_json: Option<PathBuf>,
+
+    /// 受信ノードがブロックを検証してから採用するまでの遅延（ms）。--delay に毎ホップ加算される。
+    #[clap(long, default_value = "0")]
+    verification_delay: i64,
+
+    /// header-first relay: ヘッダが --delay のこの割合（0〜1）で届き、受信ノードは本体を待たずに tip を切り替える（中継は本体の検証後）。
+    #[clap(long, value_parser = parse_fraction)]
+    header_first_relay: Option<f64>,
+
+    /// 採掘時間を指数分布から引かず、この CSV の `interval_ms` 列のブロック間隔を順に再生する。
+    #[clap(long)]
+    arrival_csv: Option<PathBuf>,
+
+    /// --arrival-csv の間隔を使い切ったときの扱い。loop=先頭から繰り返す、stop=ブロック生成をやめる。
+    #[clap(long, value_enum, default_value_t = ArrivalExhausted::Loop)]
+    arrival_exhausted: ArrivalExhausted,
+
+    /// 伝播遅延にメッセージごとに掛ける一様ジッタの幅（±%、0〜100）。0 なら常に --delay ちょうど。
+    #[clap(long, default_value = "0", value_parser = parse_percent)]
+    delay_jitter_pct: f64,
+
+    /// 送ったブロックがメッセージごとに届かない確率（0〜1）。再送はしない。
+    #[clap(long, default_value = "0", value_parser = parse_fraction)]
+    packet_loss: f64,
+
+    /// `<node,...>:<factor>` のノードを共有難易度の factor 倍で採掘するグループにする（例: 2,3:4）。複数回指定できる。
+    #[clap(long, value_parser = parse_difficulty_group)]
+    difficulty_group: Vec<(Vec<usize>, f64)>,
+
+    /// 難易度調整を無効化し、全ブロックで genesis の難易度を使う（ネットワーク効果だけを見るため）。
+    #[clap(long)]
+    freeze_difficulty: bool,
+
+    /// tip からこの深さより下の分岐ブロックをメモリから落とす（指標用の件数は保持）。reorg の深さより大きくすること。
+    #[clap(long, value_parser = clap::value_parser!(i64).range(1..))]
+    prune_depth: Option<i64>,
+
+    /// この高さ未満のブロックをサマリ・fairness・メトリクスから除外する（難易度が安定するまでの burn-in）。
+    #[clap(long, default_value = "0")]
+    burn_in_rounds: i64,
+
+    /// 報酬として数えるのに必要な確認数（上に積まれたブロック数）。0 ならメインチェーン全体。
+    #[clap(long, default_value = "0", value_parser = clap::value_parser!(i64).range(0..))]
+    confirmations: i64,
+
+    /// fairness の報酬の数え方。blocks=メインチェーンのブロック数、work=ブロックの難易度の合計。
+    #[clap(long, value_enum, default_value_t = RewardWeighting::Blocks)]
+    reward_weighting: RewardWeighting,
+
+    /// この高さで最初に honest ノードが採掘するブロックに対象トランザクションを含め、攻撃者に覆されるかを報告する。
+    #[clap(long)]
+    double_spend_height: Option<i64>,
+
+    /// honest ノードが攻撃者のチェーンへ reorg してこの確認数以上のブロックを捨てたら、攻撃成功として打ち切る。
+    #[clap(long, value_parser = clap::value_parser!(i64).range(1..))]
+    stop_on_attack_success: Option<i64>,
+
+    /// 実際に使ったノード構成（自動生成されたハッシュレートを含む）をプロファイルとして書き出すパス。
+    #[clap(long)]
+    export_profile: Option<PathBuf>,
+
+    /// 起動時に解決済みの設定（生成した seed、ノード数、プロトコル、遅延、生成間隔、同着ルールなど）をログに出す。
+    #[clap(long)]
+    verbose: bool,
+
     /// The path to the network profile file.
     /// See examples/honest.json for example.
     #[clap(long)]
     profile: Option<PathBuf>,
 
+    /// プロファイルの JSON Schema を標準出力に書き出して終了する（シミュレーションは行わない）。
+    #[clap(long)]
+    print_schema: bool,
+
     /// Single-row CSV: mined_blocks, …, stale_rate, honest_mined_blocks, …, honest_stale_rate, attacker_…, attacker_stale_rate
     #[clap(long)]
     metrics: Option<PathBuf>,
@@ -63,29 +269,142 @@ struct Cli {
     metrics_max_height: Option<i64>,
 }
 
+/// `--out-dir` の manifest.json。
+#[derive(Serialize)]
+struct Manifest {
+    /// 実際に使った seed（コマンドラインで省略したときは生成した値）
+    seed: u64,
+    /// プログラム名を除くコマンドライン引数
+    args: Vec<String>,
+    /// 実際に使ったノード構成（`--export-profile` と同じ）
+    profile: NetworkProfile,
+    /// このディレクトリに書き出したファイル
+    files: Vec<&'static str>,
+}
+
+/// `--difficulty-times` の 1 行。
+#[derive(Serialize)]
+struct DifficultyTime {
+    difficulty: f64,
+    mining_time_ms: i64,
+}
+
+/// `--difficulty-group` の `<node,...>:<factor>` を読む。
+fn parse_difficulty_group(s: &str) -> Result<(Vec<usize>, f64), String> {
+    let (nodes, factor) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected <node,...>:<factor>, got '{s}'"))?;
+    let nodes = nodes
+        .split(',')
+        .map(|n| {
+            n.trim()
+                .parse::<usize>()
+                .map_err(|e| format!("bad node id '{n}': {e}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let factor = factor
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("bad factor '{factor}': {e}"))?;
+    if !(factor.is_finite() && factor > 0.0) {
+        return Err(format!("factor must be positive, got {factor}"));
+    }
+    Ok((nodes, factor))
+}
+
+/// 0〜1 の割合を読む。
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let v = s
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("bad number '{s}': {e}"))?;
+    if !(0.0..=1.0).contains(&v) {
+        return Err(format!("must be within [0, 1], got {v}"));
+    }
+    Ok(v)
+}
+
+/// 0〜100 のパーセントを読む。
+fn parse_percent(s: &str) -> Result<f64, String> {
+    let v = s
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("bad number '{s}': {e}"))?;
+    if !(0.0..=100.0).contains(&v) {
+        return Err(format!("must be within [0, 100], got {v}"));
+    }
+    Ok(v)
+}
+
+/// 正の有限な実数を読む。
+fn parse_positive(s: &str) -> Result<f64, String> {
+    let v = s
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("bad number '{s}': {e}"))?;
+    if !(v.is_finite() && v > 0.0) {
+        return Err(format!("must be positive, got {v}"));
+    }
+    Ok(v)
+}
+
 fn main() {
     env_logger::init();
 
-    if let Err(e) = run() {
+    if let Err(e) = run(std::env::args().collect()) {
         eprintln!("Error: {}", e);
         std::process::exit(1);
     }
 }
 
-fn run() -> Result<(), Box<dyn std::error::Error>> {
-    let mut args = Cli::parse();
+/// `--out-dir` を作る。空でない既存ディレクトリは `overwrite` のときだけ使う。
+fn prepare_out_dir(dir: &Path, overwrite: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if dir.exists() && !overwrite && std::fs::read_dir(dir)?.next().is_some() {
+        return Err(format!(
+            "Output directory '{}' is not empty (pass --overwrite to replace its files)",
+            dir.display()
+        )
+        .into());
+    }
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+    Ok(())
+}
+
+fn run(argv: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = Cli::parse_from(&argv);
+    if args.print_schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&NetworkProfile::json_schema())?
+        );
+        return Ok(());
+    }
     if args.seed.is_none() {
         args.seed = Some(rand::thread_rng().r#gen::<u64>());
     }
+    if let Some(run_index) = args.run_index {
+        args.seed = args.seed.map(|seed| derive_run_seed(seed, run_index));
+    }
+    if let Some(dir) = args.out_dir.clone() {
+        prepare_out_dir(&dir, args.overwrite)?;
+        args.output = Some(dir.join(BLOCKS_FILE));
+        args.fairness_output = Some(dir.join(FAIRNESS_FILE));
+        args.tree_json = Some(dir.join(TREE_FILE));
+    }
 
     let mut output = args
         .output
         .as_ref()
         .map(|path| csv::Writer::from_path(path).expect("Failed to create CSV writer"));
 
-    let mut output2 = args
-        .output2
+    if args.output2.is_some() {
+        log::warn!("The positional fairness CSV path is deprecated; use --fairness-output instead");
+    }
+    let mut fairness_output = args
+        .fairness_output
         .as_ref()
+        .or(args.output2.as_ref())
         .map(|path| csv::Writer::from_path(path).expect("Failed to create CSV writer"));
 
     let mut simulator = if let Some(profile_path) = args.profile {
@@ -100,6 +419,9 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
             })?;
         log::info!("Loaded profile file '{}'", profile_path.display());
         log::info!("Number of nodes loaded: {}", profile.num_nodes());
+        profile
+            .check_num_nodes(args.num_nodes)
+            .map_err(|e| format!("Conflicting --profile and --num-nodes: {}", e))?;
         BlockchainSimulator::new_with_profile(
             profile,
             args.seed.unwrap(),
@@ -109,9 +431,43 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
             args.protocol.to_protocol(args.genesis_difficulty_mode),
         )
         .map_err(|e| format!("Failed to create simulator from profile: {}", e))?
+    } else if let Some(scenario) = args.scenario {
+        let profile = NetworkProfile::from_scenario(scenario, args.num_nodes.unwrap_or(10))
+            .map_err(|e| format!("Invalid --scenario: {}", e))?;
+        BlockchainSimulator::new_with_profile(
+            profile,
+            args.seed.unwrap(),
+            args.end_round,
+            args.delay,
+            args.propagation_delay_mode,
+            args.protocol.to_protocol(args.genesis_difficulty_mode),
+        )?
+    } else if let Some(hashrates) = &args.hashrates {
+        let profile = NetworkProfile::from_hashrates(hashrates)
+            .map_err(|e| format!("Invalid --hashrates: {}", e))?;
+        profile
+            .check_num_nodes(args.num_nodes)
+            .map_err(|e| format!("Invalid --hashrates: {}", e))?;
+        BlockchainSimulator::new_with_profile(
+            profile,
+            args.seed.unwrap(),
+            args.end_round,
+            args.delay,
+            args.propagation_delay_mode,
+            args.protocol.to_protocol(args.genesis_difficulty_mode),
+        )?
+    } else if args.sort_by_hashrate {
+        BlockchainSimulator::new_sorted_by_hashrate(
+            args.num_nodes.unwrap_or(10),
+            args.seed.unwrap(),
+            args.end_round,
+            args.delay,
+            args.propagation_delay_mode,
+            args.protocol.to_protocol(args.genesis_difficulty_mode),
+        )
     } else {
         BlockchainSimulator::new(
-            args.num_nodes,
+            args.num_nodes.unwrap_or(10),
             args.seed.unwrap(),
             args.end_round,
             args.delay,
@@ -120,25 +476,84 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
         )
     };
 
+    simulator.set_end_main_chain_height(args.end_main_chain_height);
+    simulator.set_propagation_target(args.propagation_target);
+    simulator.set_propagation_model(args.propagation_model);
+    simulator.set_json_trace(args.log_json);
+    simulator.set_trace_block(args.trace_block.map(BlockId::new));
+    simulator.set_drain_at_end(args.drain_at_end);
+    for (nodes, factor) in &args.difficulty_group {
+        let num_nodes = simulator.nodes.nodes().len();
+        if let Some(n) = nodes.iter().find(|&&n| n >= num_nodes) {
+            return Err(
+                format!("--difficulty-group: node {n} is out of range (0..{num_nodes})").into(),
+            );
+        }
+        let nodes: Vec<NodeId> = nodes.iter().map(|&n| NodeId::new(n)).collect();
+        simulator.set_difficulty_group(&nodes, *factor);
+    }
+    simulator.add_observers(args.observers);
+    simulator.set_tie_breaking_rule(args.tie_breaking_rule);
+    if let Some(tie_break_seed) = args.tie_break_seed {
+        simulator.set_tie_break_seed(tie_break_seed);
+    }
+    simulator.set_fork_choice(args.fork_choice.to_fork_choice());
+    simulator.set_gamma(args.gamma);
+    simulator.set_max_height_gap(args.max_height_gap);
+    simulator.set_fee_rate(args.fee_rate);
+    simulator.set_tx_arrival_rate(args.tx_rate);
+    simulator.set_mining_time_shape(args.mining_time_shape);
+    simulator.set_event_queue(args.event_queue);
+    simulator.set_burn_in_rounds(args.burn_in_rounds);
+    simulator.set_confirmations(args.confirmations);
+    simulator.set_reward_weighting(args.reward_weighting);
+    simulator.set_double_spend_height(args.double_spend_height);
+    simulator.set_attack_success_condition(
+        args.stop_on_attack_success
+            .map(|confirmations| AttackSuccessCondition { confirmations }),
+    );
+    simulator.set_prune_depth(args.prune_depth);
+    simulator.set_freeze_difficulty(args.freeze_difficulty);
+    simulator.set_delay_jitter_pct(args.delay_jitter_pct);
+    simulator.set_packet_loss(args.packet_loss);
+    simulator.set_verification_delay(args.verification_delay);
+    simulator.set_header_first_relay(args.header_first_relay);
+    if let Some(path) = &args.arrival_csv {
+        let arrival = DeterministicArrival::from_csv(path, args.arrival_exhausted)
+            .map_err(|e| format!("Failed to load arrival CSV '{}': {}", path.display(), e))?;
+        simulator.set_deterministic_arrival(Some(arrival));
+    }
+    simulator.set_record_tip_changes(args.tip_history.is_some());
+    simulator.set_queue_sample_interval(args.queue_csv.as_ref().map(|_| args.queue_sample_every));
+    simulator.set_output_format(OutputFormat {
+        time_unit: args.time_unit,
+        precision: args.precision,
+    });
+    if args.verbose {
+        simulator.log_resolved_config();
+    }
     simulator.print_hashrates();
-    simulator.simulation();
+    if let Some(path) = args.export_profile.as_ref() {
+        simulator
+            .export_profile()
+            .to_file(path)
+            .map_err(|e| format!("Failed to export profile to '{}': {}", path.display(), e))?;
+        log::info!("Exported profile to '{}'", path.display());
+    }
+    let report = simulator.run()?;
+    // 全 CSV の先頭列に実際に使った seed を入れる。
+    let seed = SeedColumn {
+        seed: simulator.seed(),
+    };
     //simulator.print_blockchain();
-    simulator.print_summary();
-    simulator.print_mining_fairness();
+    simulator.print_summary(&report);
+    simulator.print_mining_fairness(&report);
 
     // Output mainchain blocks to CSV
     // round,difficulty,time
     if let Some(csv) = &mut output {
-        for block in simulator.env.blockchain.get_main_chain_for_export() {
-            let block = simulator.env.blockchain.get_block(block).unwrap();
-            let record = blockchain_sim::types::Record {
-                round: block.height() as u32,
-                timestamp: block.time(),
-                difficulty: block.difficulty().as_f64(),
-                mining_time: block.mining_time,
-                minter: block.minter(),
-            };
-            csv.serialize(&record).unwrap();
+        for record in simulator.main_chain_records() {
+            csv.serialize((seed, &record)).unwrap();
         }
     }
 
@@ -150,68 +565,312 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
             .filter(|node| node.mining_strategy().is_honest())
             .map(|node| node.id)
             .collect();
+        // burn-in 区間は常に除外する。
+        let min_height = args
+            .metrics_min_height
+            .map_or(args.burn_in_rounds, |h| h.max(args.burn_in_rounds));
         let m = simulator.env.blockchain.chain_metrics(
             Some(&honest_minters),
-            args.metrics_min_height,
+            Some(min_height),
             args.metrics_max_height,
         );
         let mut csv = csv::Writer::from_path(path).expect("Failed to create metrics CSV writer");
-        csv.serialize(&m).expect("Failed to serialize chain metrics");
+        csv.serialize((seed, &m))
+            .expect("Failed to serialize chain metrics");
         csv.flush().ok();
     }
 
-    if let Some(csv) = &mut output2 {
-        let total_hashrate = simulator
-            .nodes
-            .nodes()
-            .iter()
-            .map(|node| node.hashrate())
-            .sum::<i64>();
-
-        let main_chain: Vec<_> = simulator.env.blockchain.get_main_chain_for_export();
-
-        let mut node_rewards = HashMap::<NodeId, usize>::new();
-        main_chain.iter().for_each(|block_id| {
-            let Some(block) = simulator.env.blockchain.get_block(*block_id) else {
-                unreachable!();
-            };
-            let minter = block.minter();
-            if minter != NodeId::dummy() {
-                let node_id = minter;
-                *node_rewards.entry(node_id).or_insert(0) += 1;
-            }
-        });
-
-        let total_reward: usize = node_rewards.values().sum();
-
-        for node in simulator.nodes.nodes() {
-            let reward = *node_rewards.get(&node.id).unwrap_or(&0);
-            let reward_share = if total_reward > 0 {
-                reward as f64 / total_reward as f64
-            } else {
-                0.0
-            };
-            let hashrate_share = if total_hashrate > 0 {
-                node.hashrate as f64 / total_hashrate as f64
-            } else {
-                0.0
-            };
-            let fairness = if hashrate_share > 0.0 {
-                reward_share / hashrate_share
-            } else {
-                0.0
-            };
-
-            let record = blockchain_sim::types::NodeInfo {
-                node_id: node.id.into_usize(),
-                strategy: node.mining_strategy.name().to_string(),
-                reward_share,
-                hashrate_share,
-                fairness,
-            };
-            csv.serialize(&record).unwrap();
+    if let Some(path) = args.tip_history.as_ref() {
+        let mut csv =
+            csv::Writer::from_path(path).expect("Failed to create tip history CSV writer");
+        for change in simulator.tip_change_records() {
+            csv.serialize((seed, &change)).unwrap();
+        }
+        csv.flush().ok();
+    }
+
+    if let Some(path) = args.queue_csv.as_ref() {
+        let mut csv = csv::Writer::from_path(path).expect("Failed to create queue CSV writer");
+        for sample in simulator.queue_sample_records() {
+            csv.serialize((seed, &sample)).unwrap();
+        }
+        csv.flush().ok();
+    }
+
+    if let Some(path) = args.retargets.as_ref() {
+        let mut csv = csv::Writer::from_path(path).expect("Failed to create retargets CSV writer");
+        for retarget in simulator.retarget_records() {
+            csv.serialize((seed, &retarget)).unwrap();
+        }
+        csv.flush().ok();
+    }
+
+    if let Some(path) = args.difficulty_times.as_ref() {
+        let mut csv = csv::Writer::from_path(path).expect("Failed to create difficulty CSV writer");
+        for (difficulty, mining_time_ms) in simulator.env.blockchain.difficulty_vs_mining_time() {
+            csv.serialize((
+                seed,
+                DifficultyTime {
+                    difficulty,
+                    mining_time_ms,
+                },
+            ))
+            .unwrap();
+        }
+        csv.flush().ok();
+    }
+
+    if let Some(path) = args.tree_json.as_ref() {
+        let main_chain = simulator.env.blockchain.get_main_chain_for_export();
+        std::fs::write(path, simulator.env.blockchain.to_json_tree(&main_chain))
+            .map_err(|e| format!("Failed to write block tree to '{}': {}", path.display(), e))?;
+    }
+
+    if let Some(csv) = &mut fairness_output {
+        for record in &report.fairness {
+            csv.serialize((seed, &record)).unwrap();
         }
     }
 
+    if let Some(dir) = args.out_dir.as_ref() {
+        let manifest = Manifest {
+            seed: simulator.seed(),
+            args: argv.into_iter().skip(1).collect(),
+            profile: simulator.export_profile(),
+            files: vec![BLOCKS_FILE, FAIRNESS_FILE, TREE_FILE, MANIFEST_FILE],
+        };
+        let path = dir.join(MANIFEST_FILE);
+        std::fs::write(&path, serde_json::to_string_pretty(&manifest)?)
+            .map_err(|e| format!("Failed to write manifest to '{}': {}", path.display(), e))?;
+        log::info!("Wrote outputs to '{}'", dir.display());
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn out_dir_contains_all_outputs_and_manifest() {
+        let dir =
+            std::env::temp_dir().join(format!("blockchain-sim-out-dir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let argv = |extra: &[&str]| -> Vec<String> {
+            [
+                "blockchain-sim",
+                "--seed",
+                "1",
+                "--num-nodes",
+                "3",
+                "--end-round",
+                "5",
+            ]
+            .iter()
+            .chain(extra)
+            .map(|s| s.to_string())
+            .collect()
+        };
+        let out_dir = dir.to_str().unwrap();
+        run(argv(&["--out-dir", out_dir])).unwrap();
+
+        let mut files: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [BLOCKS_FILE, FAIRNESS_FILE, MANIFEST_FILE, TREE_FILE]
+        );
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(manifest["seed"], 1);
+        assert_eq!(manifest["profile"]["nodes"].as_array().unwrap().len(), 3);
+
+        // --run-index を付けると、manifest には導いた seed が入る。
+        run(argv(&[
+            "--out-dir",
+            out_dir,
+            "--overwrite",
+            "--run-index",
+            "3",
+        ]))
+        .unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(manifest["seed"], derive_run_seed(1, 3));
+
+        // 2 回目は空でないのでエラー、--overwrite なら通る。
+        assert!(run(argv(&["--out-dir", out_dir])).is_err());
+        run(argv(&["--out-dir", out_dir, "--overwrite"])).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fairness_csv_reports_zero_share_for_a_node_that_never_mines() {
+        let dir =
+            std::env::temp_dir().join(format!("blockchain-sim-silent-node-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // node 2 はハッシュレート 0 で、メインチェーンのブロックを 1 つも持たない。
+        let profile = dir.join("profile.json");
+        std::fs::write(
+            &profile,
+            r#"{"nodes": [
+                {"hashrate": 10000, "strategy": {"type": "honest"}},
+                {"hashrate": 10000, "strategy": {"type": "honest"}},
+                {"hashrate": 0, "strategy": {"type": "honest"}}
+            ]}"#,
+        )
+        .unwrap();
+        let fairness = dir.join(FAIRNESS_FILE);
+        let argv = [
+            "blockchain-sim",
+            "--seed",
+            "1",
+            "--end-round",
+            "5",
+            "--profile",
+            profile.to_str().unwrap(),
+            "--fairness-output",
+            fairness.to_str().unwrap(),
+        ];
+        run(argv.iter().map(|s| s.to_string()).collect()).unwrap();
+
+        let rows: Vec<HashMap<String, String>> = csv::Reader::from_path(&fairness)
+            .unwrap()
+            .deserialize()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(rows.len(), 3);
+        let silent = rows.iter().find(|row| row["node_id"] == "2").unwrap();
+        assert_eq!(silent["reward_share"].parse::<f64>().unwrap(), 0.0);
+        assert_eq!(silent["fairness"].parse::<f64>().unwrap(), 0.0);
+        let total: f64 = rows
+            .iter()
+            .map(|row| row["reward_share"].parse::<f64>().unwrap())
+            .sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn queue_csv_is_a_time_series_of_queue_lengths() {
+        let dir =
+            std::env::temp_dir().join(format!("blockchain-sim-queue-csv-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let queue_csv = dir.join("queue.csv");
+        let argv = [
+            "blockchain-sim",
+            "--seed",
+            "1",
+            "--num-nodes",
+            "5",
+            "--end-round",
+            "50",
+            "--queue-csv",
+            queue_csv.to_str().unwrap(),
+            "--queue-sample-every",
+            "3",
+        ];
+        run(argv.iter().map(|s| s.to_string()).collect()).unwrap();
+
+        let rows: Vec<HashMap<String, String>> = csv::Reader::from_path(&queue_csv)
+            .unwrap()
+            .deserialize()
+            .map(Result::unwrap)
+            .collect();
+        assert!(rows.len() > 50);
+        let times: Vec<f64> = rows
+            .iter()
+            .map(|row| row["time"].parse().unwrap())
+            .collect();
+        assert!(times.windows(2).all(|w| w[0] <= w[1]), "{times:?}");
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row["processed_events"], (3 * (i + 1)).to_string());
+            // 採掘中のノードごとに採掘イベントが 1 つは残っている。
+            assert!(row["queue_len"].parse::<usize>().unwrap() >= 1);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn out_of_range_arguments_are_cli_errors() {
+        let parse = |arg: &str| Cli::try_parse_from(["blockchain-sim", arg]);
+        for arg in [
+            "--confirmations=-3",
+            "--gamma=2",
+            "--gamma=-0.1",
+            "--gamma=NaN",
+            "--packet-loss=3",
+            "--delay-jitter-pct=150",
+            "--header-first-relay=2",
+            "--mining-time-shape=0",
+            "--mining-time-shape=inf",
+            "--prune-depth=0",
+            "--prune-depth=-1",
+        ] {
+            assert!(parse(arg).is_err(), "{arg}");
+        }
+        assert!(parse("--confirmations=0").is_ok());
+        assert!(parse("--gamma=1").is_ok());
+        assert!(parse("--packet-loss=0.5").is_ok());
+        assert!(parse("--delay-jitter-pct=100").is_ok());
+        assert!(parse("--header-first-relay=0.3").is_ok());
+        assert!(parse("--mining-time-shape=2.5").is_ok());
+        assert!(parse("--prune-depth=1").is_ok());
+    }
+
+    #[test]
+    fn blocks_csv_writes_whole_milliseconds_as_integers() {
+        let dir =
+            std::env::temp_dir().join(format!("blockchain-sim-blocks-csv-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let blocks = dir.join(BLOCKS_FILE);
+        let rows = |extra: &[&str]| -> Vec<HashMap<String, String>> {
+            let argv = [
+                "blockchain-sim",
+                "--seed",
+                "1",
+                "--num-nodes",
+                "3",
+                "--end-round",
+                "5",
+                "--output",
+                blocks.to_str().unwrap(),
+            ];
+            run(argv.iter().chain(extra).map(|s| s.to_string()).collect()).unwrap();
+            csv::Reader::from_path(&blocks)
+                .unwrap()
+                .deserialize()
+                .map(Result::unwrap)
+                .collect()
+        };
+
+        // 既定の ms では、ms 単位の時刻は `600000.0` ではなく整数のまま書く。
+        for row in rows(&[]) {
+            for column in ["timestamp", "created_at"] {
+                assert!(
+                    row[column].parse::<i64>().is_ok(),
+                    "{column}: {}",
+                    row[column]
+                );
+            }
+        }
+        // 秒に換算すると端数が出る。
+        assert!(
+            rows(&["--time-unit", "s"])
+                .iter()
+                .any(|row| row["created_at"].contains('.'))
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+use std::{any::Any, cmp::Ordering};
+
+use crate::{block::GENESIS_BLOCK_ID, blockchain::BlockId, node::NodeId, simulator::Env};
+use serde::{Deserialize, Serialize};
+
+use super::{Action, MiningStrategy, MiningStrategyEnum, longest_chain};
+
+/// 手数料を最大化するマイニング戦略
+///
+/// フォーク選択には従うが、chainwork が等しい tip が競合したときは到着順ではなく、
+/// その上で採掘したときに回収できる手数料（[`Env::unclaimed_fees`]）が多い方を採る。
+/// 手数料モデルが設定されていなければ honest と同じく先に採用した tip を維持する。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeMaximizingStrategy {
+    current_block_id: BlockId,
+}
+
+impl Default for FeeMaximizingStrategy {
+    fn default() -> Self {
+        Self {
+            current_block_id: GENESIS_BLOCK_ID,
+        }
+    }
+}
+
+impl FeeMaximizingStrategy {
+    /// 現在の tip `current` と受信した tip `incoming` のどちらの上で採掘するか。
+    fn choose_tip(
+        &self,
+        env: &Env,
+        node_id: NodeId,
+        current: BlockId,
+        incoming: BlockId,
+        now_ms: i64,
+    ) -> BlockId {
+        let block1 = env.blockchain.get_block(current).unwrap();
+        let block2 = env.blockchain.get_block(incoming).unwrap();
+        if env.blockchain.fork_choice().is_some()
+            || env.protocol.prefer(block1, block2) != Ordering::Equal
+        {
+            return longest_chain(env, node_id, current, incoming);
+        }
+        if current == incoming {
+            return current;
+        }
+        let switched = env.unclaimed_fees(incoming, now_ms) > env.unclaimed_fees(current, now_ms);
+        env.record_tie(switched);
+        if switched { incoming } else { current }
+    }
+}
+
+impl MiningStrategy for FeeMaximizingStrategy {
+    fn name(&self) -> &'static str {
+        "FeeMaximizing"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn current_head(&self) -> BlockId {
+        self.current_block_id
+    }
+
+    fn to_profile(&self) -> MiningStrategyEnum {
+        MiningStrategyEnum::FeeMaximizing
+    }
+
+    fn on_mining_block(
+        &mut self,
+        block_id: BlockId,
+        _current_time_us: i64,
+        env: &Env,
+        node_id: NodeId,
+    ) -> Vec<Action> {
+        self.current_block_id = block_id;
+
+        let mut actions = Vec::new();
+        for node in env.propagation_targets(node_id) {
+            actions.push(Action::Propagate { block_id, to: node });
+        }
+        actions.push(Action::RestartMining {
+            prev_block_id: block_id,
+        });
+        actions
+    }
+
+    fn on_receiving_block(
+        &mut self,
+        block_id: BlockId,
+        current_time_us: i64,
+        env: &Env,
+        node_id: NodeId,
+    ) -> Vec<Action> {
+        let old_chain = self.current_block_id;
+        self.current_block_id = self.choose_tip(
+            env,
+            node_id,
+            self.current_block_id,
+            block_id,
+            current_time_us / 1000,
+        );
+
+        if old_chain == self.current_block_id {
+            vec![]
+        } else {
+            vec![Action::RestartMining {
+                prev_block_id: self.current_block_id,
+            }]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::Block,
+        fee::FeeModel,
+        propagation_delay::PropagationDelayMode,
+        protocol::{BitcoinDifficulty, Difficulty, GenesisDifficultyMode, ProtocolType},
+    };
+
+    /// genesis の子をタイムスタンプ `time_ms` で追加する。
+    fn add_child(env: &mut Env, time_ms: i64) -> BlockId {
+        let difficulty = Difficulty::Bitcoin(BitcoinDifficulty::new(1.0));
+        let genesis = env.blockchain.get_block(GENESIS_BLOCK_ID).unwrap().clone();
+        let id = env.blockchain.next_block_id();
+        env.blockchain.add_block(Block::new(
+            1,
+            Some(GENESIS_BLOCK_ID),
+            NodeId::new(0),
+            time_ms,
+            0,
+            id,
+            difficulty,
+            genesis.cumulative_chain_work() + difficulty.chain_work_increment(),
+            1.0,
+            true,
+        ));
+        id
+    }
+
+    #[test]
+    fn equal_work_tie_goes_to_the_tip_with_more_unclaimed_fees() {
+        let protocol = ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Fixed);
+        let mut env = Env::new(&[], 0, PropagationDelayMode::Uniform, protocol);
+        env.fee_model = Some(FeeModel::new(0.001));
+        // 同じ高さ・同じ chainwork。早い方のタイムスタンプの上では 9 秒分、遅い方の上では 1 秒分の手数料が残る。
+        let early = add_child(&mut env, 1_000);
+        let late = add_child(&mut env, 9_000);
+        let now_us = 10_000 * 1000;
+        assert!(env.unclaimed_fees(early, 10_000) > env.unclaimed_fees(late, 10_000));
+
+        for (first, second) in [(early, late), (late, early)] {
+            let mut strategy = FeeMaximizingStrategy::default();
+            strategy.on_receiving_block(first, now_us, &env, NodeId::new(1));
+            strategy.on_receiving_block(second, now_us, &env, NodeId::new(1));
+            assert_eq!(strategy.current_head(), early);
+        }
+
+        // 手数料モデルがなければ first-seen。
+        env.fee_model = None;
+        let mut strategy = FeeMaximizingStrategy::default();
+        strategy.on_receiving_block(late, now_us, &env, NodeId::new(1));
+        strategy.on_receiving_block(early, now_us, &env, NodeId::new(1));
+        assert_eq!(strategy.current_head(), late);
+    }
+}
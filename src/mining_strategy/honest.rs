@@ -1,7 +1,9 @@
+use std::any::Any;
+
 use crate::{block::GENESIS_BLOCK_ID, blockchain::BlockId, node::NodeId, simulator::Env};
 use serde::{Deserialize, Serialize};
 
-use super::{Action, MiningStrategy, longest_chain};
+use super::{Action, MiningStrategy, MiningStrategyEnum, best_tip, gamma_tie_break};
 
 /// 通常のマイニング戦略（何も調整しない）
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,30 +24,43 @@ impl MiningStrategy for HonestMiningStrategy {
         "Honest"
     }
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     fn is_honest(&self) -> bool {
         true
     }
 
+    fn current_head(&self) -> BlockId {
+        self.current_block_id
+    }
+
+    fn to_profile(&self) -> MiningStrategyEnum {
+        MiningStrategyEnum::Honest
+    }
+
     fn on_mining_block(
         &mut self,
         block_id: BlockId,
         _current_time_us: i64,
         env: &Env,
-        _node_id: NodeId,
+        node_id: NodeId,
     ) -> Vec<Action> {
         self.current_block_id = block_id;
 
+        // アクションは並び順に処理される: 先に全ノードへの伝播、最後に自分のブロック上での採掘再開。
+        // 伝播は採掘イベントに触れず、RestartMining は保留中の採掘イベントを置き換える
+        // （`EventQueue::push_mining`）ので、順序に依らず次の採掘は必ず `block_id` の上になる。
+        // 順序が効くのは乱数の消費順（伝播ジッタ → 採掘時間）だけで、再現性のためこの順に固定している。
         let mut actions = Vec::new();
 
-        // Immediately schedule propagation tasks to all other nodes.
-        for node in env.nodes() {
-            actions.push(Action::Propagate {
-                block_id,
-                to: *node,
-            });
+        // Immediately schedule propagation tasks to the other nodes (`env.propagation_target`).
+        for node in env.propagation_targets(node_id) {
+            actions.push(Action::Propagate { block_id, to: node });
         }
 
-        // Schedule a new mining task.
+        // Schedule a new mining task on top of the block just found.
         actions.push(Action::RestartMining {
             prev_block_id: block_id,
         });
@@ -57,10 +72,13 @@ impl MiningStrategy for HonestMiningStrategy {
         block_id: BlockId,
         _current_time_us: i64,
         env: &Env,
-        _node_id: NodeId,
+        node_id: NodeId,
     ) -> Vec<Action> {
         let old_chain = self.current_block_id;
-        self.current_block_id = longest_chain(env, self.current_block_id, block_id);
+        // current_block_id は既知の tip 全体の最良なので、受信 tip と比べれば集合全体から選ぶのと同じ（`best_tip`）。
+        // γ が設定されていれば honest/攻撃者の同着はノードごとの抽選で決まる。
+        self.current_block_id = gamma_tie_break(env, node_id, self.current_block_id, block_id)
+            .unwrap_or_else(|| best_tip(env, node_id, self.current_block_id, &[block_id]));
 
         if old_chain == self.current_block_id {
             // If the chain is not changed, continue mining.
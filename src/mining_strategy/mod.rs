@@ -1,45 +1,148 @@
-use primitive_types::U256;
+use std::any::Any;
+
 use serde::{Deserialize, Serialize};
 
-use crate::{blockchain::BlockId, node::NodeId, simulator::Env};
+use crate::{
+    blockchain::BlockId,
+    node::NodeId,
+    sampling::{SPLITMIX64_GAMMA, splitmix64},
+    simulator::Env,
+};
 
+mod fee_maximizing;
 mod honest;
+mod private_attack;
 mod selfish;
 mod selfish_timewarp;
+mod spv;
 mod timewarp;
-mod private_attack;
 
+pub use fee_maximizing::FeeMaximizingStrategy;
 pub use honest::HonestMiningStrategy;
 pub use private_attack::PrivateAttackMiningStrategy;
-pub use selfish::SelfishMiningStrategy;
+pub use selfish::{DEFAULT_REVEAL_LEAD, SelfishMiningStrategy};
 pub use selfish_timewarp::SelfishTimewarpStrategy;
+pub use spv::SpvMiningStrategy;
 pub use timewarp::{DEFAULT_MTP_WINDOW_SIZE, TimewarpStrategy};
 
 fn default_mtp_window_size() -> usize {
     DEFAULT_MTP_WINDOW_SIZE
 }
 
-fn cumulative_chain_work(env: &Env, tip_id: BlockId) -> U256 {
-    env.blockchain
-        .get_block(tip_id)
-        .map(|block| block.cumulative_chain_work())
-        .unwrap_or(U256::zero())
+fn default_reveal_lead() -> usize {
+    DEFAULT_REVEAL_LEAD
 }
 
-pub(crate) fn longest_chain(env: &Env, block1_id: BlockId, block2_id: BlockId) -> BlockId {
-    let weight1 = cumulative_chain_work(env, block1_id);
-    let weight2 = cumulative_chain_work(env, block2_id);
-    match weight1.cmp(&weight2) {
-        std::cmp::Ordering::Greater => block1_id,
-        std::cmp::Ordering::Less => block2_id,
-        std::cmp::Ordering::Equal => {
-            // Tie-break: keep current head.
-            // `longest_chain(current_head, incoming_head)` call sites preserve first-seen behavior.
-            block1_id
-        }
+/// chainwork が等しい tip 同士の選び方（`--tie-breaking-rule`）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TieBreakingRule {
+    /// ノードごとに記録した受信時刻を比べ、先に受け取った tip を採る。実ノードの挙動に近い。
+    #[default]
+    FirstSeen,
+    /// ブロックごとの乱数 `rand` が小さい方を選ぶ（等しければ id の小さい方）。到着順に依存しない。
+    Random,
+}
+
+/// ノード `node_id` の現在の head `block1_id` と、新たに受け取った tip `block2_id` から選ぶ（[`best_tip`] の 1 tip 版）。
+/// 主鎖と同じフォーク選択（[`Blockchain::choose_tip`](crate::blockchain::Blockchain::choose_tip)）に従い、
+/// プロトコル既定の選択で同等だったときは [`Env::tie_breaks`] に数える。
+/// 受信した tip が `env.max_height_gap` を超えて高ければ、比べずに現在の head を維持する。
+/// 刈り込まれたブロックは深い分岐上にあるので、生きているブロックには常に負ける。
+pub(crate) fn longest_chain(
+    env: &Env,
+    node_id: NodeId,
+    block1_id: BlockId,
+    block2_id: BlockId,
+) -> BlockId {
+    best_tip(env, node_id, block1_id, &[block2_id])
+}
+
+/// 高さの上限を考えずに `current` と `incoming` を比べる（刈り込まれた側は負ける）。
+fn prefer_tip(env: &Env, node_id: NodeId, current: BlockId, incoming: BlockId) -> BlockId {
+    if env.blockchain.get_block(incoming).is_none() {
+        return current;
+    }
+    if env.blockchain.get_block(current).is_none() {
+        return incoming;
+    }
+    let (chosen, tie) = env.blockchain.choose_tip(Some(node_id), current, incoming);
+    if tie {
+        env.record_tie(chosen == incoming);
+    }
+    chosen
+}
+
+/// ノード `node_id` から見た γ による同着の解決。γ が設定されていて、
+/// chainwork が等しい honest ブロックと攻撃者ブロックが競合したときだけ、到着順に依らず
+/// 確率 γ で攻撃者側を返す。それ以外（γ 未設定・`fork_choice` 指定・同着でない）は `None`。
+///
+/// γ の抽選は (ノード, 攻撃者ブロック) ごとに決定的な一様乱数で行う。同じブロックを何度受け取っても
+/// 判断は変わらず、honest ノード全体ではおよそ γ の割合が攻撃者側に付く。
+pub(crate) fn gamma_tie_break(
+    env: &Env,
+    node_id: NodeId,
+    current: BlockId,
+    incoming: BlockId,
+) -> Option<BlockId> {
+    let gamma = env.blockchain.gamma()?;
+    if env.blockchain.fork_choice().is_some() {
+        return None;
+    }
+    let block1 = env.blockchain.get_block(current)?;
+    let block2 = env.blockchain.get_block(incoming)?;
+    if env.protocol.prefer(block1, block2) != std::cmp::Ordering::Equal {
+        return None;
     }
+    let is_attacker = |minter: Option<NodeId>| minter.is_some_and(|m| env.is_attacker(m));
+    let (attacker, honest) = match (is_attacker(block1.minter()), is_attacker(block2.minter())) {
+        (true, false) => (current, incoming),
+        (false, true) => (incoming, current),
+        _ => return None,
+    };
+    let attacker_rand = if attacker == current {
+        block1.rand()
+    } else {
+        block2.rand()
+    };
+    let chosen = if unit_hash(attacker_rand as u64, node_id.into_usize() as u64) < gamma {
+        attacker
+    } else {
+        honest
+    };
+    env.record_tie(chosen == incoming);
+    Some(chosen)
 }
 
+/// `(a, b)` から [0, 1) の一様乱数を決定的に作る（splitmix64）。
+fn unit_hash(a: u64, b: u64) -> f64 {
+    let z = splitmix64((a ^ b.wrapping_mul(SPLITMIX64_GAMMA)).wrapping_add(SPLITMIX64_GAMMA));
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// ノード `node_id` の現在の head `head` と既知の tip 群 `tips` から [`longest_chain`] の規則で最良の tip を選ぶ。
+///
+/// `env.max_height_gap` を超える tip は `head` の高さを基準に先に取り除く（畳み込み途中の暫定 tip とは比べない）。
+/// 残りのフォーク選択は全順序（同等のときは first-seen なら受信時刻、random なら `rand`）なので、
+/// 先頭から順に畳み込めば集合全体からの選択と一致し、`tips` の並びに依らず同じ tip になる。
+pub(crate) fn best_tip(env: &Env, node_id: NodeId, head: BlockId, tips: &[BlockId]) -> BlockId {
+    let max_height = env
+        .max_height_gap
+        .zip(env.blockchain.get_block(head))
+        .map(|(gap, head)| head.height().saturating_add(gap));
+    tips.iter()
+        .copied()
+        .filter(|&tip| {
+            max_height.is_none_or(|max| {
+                env.blockchain
+                    .get_block(tip)
+                    .is_none_or(|tip| tip.height() <= max)
+            })
+        })
+        .fold(head, |best, tip| prefer_tip(env, node_id, best, tip))
+}
+
+/// 戦略がシミュレータに返す要求。`enqueue_actions` は返された順に処理する。
+#[derive(Debug, Clone, Copy)]
 pub enum Action {
     /// Propagate a block to a node.
     Propagate { block_id: BlockId, to: NodeId },
@@ -55,11 +158,31 @@ pub trait MiningStrategy: Send + Sync {
     /// 戦略の名前を取得する
     fn name(&self) -> &'static str;
 
+    /// 具体的な戦略型にダウンキャストして、終了後の内部状態を読むため。
+    fn as_any(&self) -> &dyn Any;
+
     /// honest ノード（通常マイニング）かどうか
     fn is_honest(&self) -> bool {
         false
     }
 
+    /// このノードが現在採用している tip（次のブロックの親）
+    fn current_head(&self) -> BlockId;
+
+    /// 戦略が保持していて後で参照するブロック（`current_head` を含む）。
+    /// 分岐の刈り込みはこれらとその祖先を残す。
+    fn referenced_blocks(&self) -> Vec<BlockId> {
+        vec![self.current_head()]
+    }
+
+    /// 非公開チェーンを公開して公開チェーンを上書きした回数（selfish 系のみ）。
+    fn overrides(&self) -> u64 {
+        0
+    }
+
+    /// プロファイルに書き出すための設定（`MiningStrategyEnum::to_strategy` の逆）
+    fn to_profile(&self) -> MiningStrategyEnum;
+
     /// ブロック生成時に呼ばれるコールバック
     /// Return: A list of actions to schedule.
     fn on_mining_block(
@@ -99,8 +222,16 @@ pub trait MiningStrategy: Send + Sync {
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MiningStrategyEnum {
     Honest,
-    Selfish,
+    Selfish {
+        /// 非公開分岐を一括公開するリード（2 以上）。省略時は 2（古典的な selfish mining）。
+        #[serde(default = "default_reveal_lead")]
+        reveal_lead: usize,
+        /// 非公開分岐を公開する相手（省略時は `env.propagation_target` の全ノード）。
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reveal_targets: Option<Vec<NodeId>>,
+    },
     PrivateAttack,
+    Spv,
     SelfishTimewarp {
         /// MTP（中央値）算出に使う直近ブロック数。省略時は 11（Bitcoin 既定）。
         #[serde(default = "default_mtp_window_size")]
@@ -111,20 +242,417 @@ pub enum MiningStrategyEnum {
         #[serde(default = "default_mtp_window_size")]
         mtp_window_size: usize,
     },
+    FeeMaximizing,
 }
 
 impl MiningStrategyEnum {
     pub fn to_strategy(&self) -> Box<dyn MiningStrategy> {
         match self {
             MiningStrategyEnum::Honest => Box::new(HonestMiningStrategy::default()),
-            MiningStrategyEnum::Selfish => Box::new(SelfishMiningStrategy::default()),
+            MiningStrategyEnum::Selfish {
+                reveal_lead,
+                reveal_targets,
+            } => Box::new(
+                SelfishMiningStrategy::with_reveal_lead(*reveal_lead)
+                    .with_reveal_targets(reveal_targets.clone()),
+            ),
             MiningStrategyEnum::PrivateAttack => Box::new(PrivateAttackMiningStrategy::default()),
+            MiningStrategyEnum::Spv => Box::new(SpvMiningStrategy::default()),
             MiningStrategyEnum::SelfishTimewarp { mtp_window_size } => {
                 Box::new(SelfishTimewarpStrategy::with_window_size(*mtp_window_size))
             }
             MiningStrategyEnum::Timewarp { mtp_window_size } => {
                 Box::new(TimewarpStrategy::with_window_size(*mtp_window_size))
             }
+            MiningStrategyEnum::FeeMaximizing => Box::new(FeeMaximizingStrategy::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::{Block, GENESIS_BLOCK_ID},
+        propagation_delay::PropagationDelayMode,
+        protocol::{
+            BitcoinDifficulty, Difficulty, EthereumDifficulty, GenesisDifficultyMode, ProtocolType,
+        },
+    };
+
+    /// `parent` の子を難易度 `difficulty` で追加する。
+    fn add_child(env: &mut Env, parent: BlockId, difficulty: Difficulty) -> BlockId {
+        let parent = env.blockchain.get_block(parent).unwrap().clone();
+        let id = env.blockchain.next_block_id();
+        env.blockchain.add_block(Block::new(
+            parent.height() + 1,
+            Some(parent.id()),
+            NodeId::new(0),
+            parent.timestamp() + 1000,
+            0,
+            id,
+            difficulty,
+            parent.cumulative_chain_work() + difficulty.chain_work_increment(),
+            1.0,
+            true,
+        ));
+        id
+    }
+
+    #[test]
+    fn fork_choice_follows_total_work_not_height() {
+        let cases = [
+            (
+                ProtocolType::Ethereum,
+                Difficulty::Ethereum(EthereumDifficulty::from_u64(1_000)),
+                Difficulty::Ethereum(EthereumDifficulty::from_u64(5_000)),
+            ),
+            (
+                ProtocolType::Bitcoin,
+                Difficulty::Bitcoin(BitcoinDifficulty::new(1.0)),
+                Difficulty::Bitcoin(BitcoinDifficulty::new(5.0)),
+            ),
+        ];
+        for (protocol, easy, hard) in cases {
+            let protocol = protocol.to_protocol(GenesisDifficultyMode::Fixed);
+            let mut env = Env::new(&[], 0, PropagationDelayMode::Uniform, protocol);
+
+            // 長いが軽い分岐: genesis -> easy -> easy
+            let long1 = add_child(&mut env, GENESIS_BLOCK_ID, easy);
+            let long2 = add_child(&mut env, long1, easy);
+            // 短いが重い分岐: genesis -> hard
+            let heavy = add_child(&mut env, GENESIS_BLOCK_ID, hard);
+            assert_eq!(longest_chain(&env, NodeId::new(0), long2, heavy), heavy);
+            assert_eq!(longest_chain(&env, NodeId::new(0), heavy, long2), heavy);
+
+            // 同じ難易度なら長い方が重い。
+            let short = add_child(&mut env, GENESIS_BLOCK_ID, easy);
+            assert_eq!(longest_chain(&env, NodeId::new(0), short, long2), long2);
+        }
+    }
+
+    #[test]
+    fn ethereum_prefer_compares_total_difficulty() {
+        let protocol = ProtocolType::Ethereum.to_protocol(GenesisDifficultyMode::Fixed);
+        let mut env = Env::new(&[], 0, PropagationDelayMode::Uniform, protocol);
+        let difficulty = |d| Difficulty::Ethereum(EthereumDifficulty::from_u64(d));
+
+        // total difficulty 2_500 + 2_500 = 5_000 の 2 ブロックと、5_000 の 1 ブロック。
+        let long1 = add_child(&mut env, GENESIS_BLOCK_ID, difficulty(2_500));
+        let long2 = add_child(&mut env, long1, difficulty(2_500));
+        let short = add_child(&mut env, GENESIS_BLOCK_ID, difficulty(5_000));
+        // total difficulty が 1 だけ大きい短い分岐。
+        let heavier = add_child(&mut env, GENESIS_BLOCK_ID, difficulty(5_001));
+        let block = |id| env.blockchain.get_block(id).unwrap();
+        // 高さが違っても total difficulty が等しければ同等で、tie-breaking rule に任せる。
+        assert_eq!(
+            env.protocol.prefer(block(long2), block(short)),
+            std::cmp::Ordering::Equal
+        );
+
+        // こちらは高さが低くても勝つ。
+        assert_eq!(
+            env.protocol.prefer(block(heavier), block(long2)),
+            std::cmp::Ordering::Greater
+        );
+        assert_eq!(longest_chain(&env, NodeId::new(0), long2, heavier), heavier);
+    }
+
+    #[test]
+    fn random_rule_breaks_equal_rand_ties_by_block_id() {
+        let protocol = ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Fixed);
+        let mut env = Env::new(&[], 0, PropagationDelayMode::Uniform, protocol);
+        env.blockchain
+            .set_tie_breaking_rule(TieBreakingRule::Random);
+        let difficulty = Difficulty::Bitcoin(BitcoinDifficulty::new(1.0));
+        // 同じ高さ・同じ chainwork・同じ rand の 2 ブロック。
+        let a = add_child(&mut env, GENESIS_BLOCK_ID, difficulty);
+        let b = add_child(&mut env, GENESIS_BLOCK_ID, difficulty);
+        let rand = |id| env.blockchain.get_block(id).unwrap().rand();
+        assert_eq!(rand(a), rand(b));
+
+        assert_eq!(longest_chain(&env, NodeId::new(0), a, b), a);
+        assert_eq!(longest_chain(&env, NodeId::new(0), b, a), a);
+        assert_eq!(best_tip(&env, NodeId::new(0), b, &[a]), a);
+    }
+
+    #[test]
+    fn swapping_fork_choice_changes_the_winner() {
+        use crate::fork_choice::{HeaviestChain, LongestChain};
+
+        let protocol = ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Fixed);
+        let mut env = Env::new(&[], 0, PropagationDelayMode::Uniform, protocol);
+        let easy = Difficulty::Bitcoin(BitcoinDifficulty::new(1.0));
+        let hard = Difficulty::Bitcoin(BitcoinDifficulty::new(5.0));
+        let long1 = add_child(&mut env, GENESIS_BLOCK_ID, easy);
+        let long2 = add_child(&mut env, long1, easy);
+        let heavy = add_child(&mut env, GENESIS_BLOCK_ID, hard);
+
+        env.blockchain.set_fork_choice(Some(Box::new(LongestChain)));
+        assert_eq!(longest_chain(&env, NodeId::new(0), heavy, long2), long2);
+        assert_eq!(longest_chain(&env, NodeId::new(0), long2, heavy), long2);
+
+        env.blockchain
+            .set_fork_choice(Some(Box::new(HeaviestChain)));
+        assert_eq!(longest_chain(&env, NodeId::new(0), heavy, long2), heavy);
+        assert_eq!(longest_chain(&env, NodeId::new(0), long2, heavy), heavy);
+
+        // 同じ高さなら LongestChain は先に受け取った方を採る。
+        env.blockchain.set_fork_choice(Some(Box::new(LongestChain)));
+        let other = add_child(&mut env, GENESIS_BLOCK_ID, easy);
+        env.blockchain.record_first_seen(NodeId::new(0), other, 0);
+        env.blockchain.record_first_seen(NodeId::new(0), heavy, 1);
+        assert_eq!(longest_chain(&env, NodeId::new(0), heavy, other), other);
+
+        // 主鎖も同じフォーク選択に従う。
+        for id in [long1, long2, heavy, other] {
+            env.blockchain.mark_block_generation_completed(id);
+        }
+        assert_eq!(env.blockchain.get_main_chain().last(), Some(&long2));
+        env.blockchain
+            .set_fork_choice(Some(Box::new(HeaviestChain)));
+        assert_eq!(env.blockchain.get_main_chain().last(), Some(&heavy));
+    }
+
+    #[test]
+    fn custom_fork_choice_breaks_ties_by_the_tie_breaking_rule() {
+        use crate::fork_choice::{HeaviestChain, LongestChain};
+
+        let (mut env, high_rand, low_rand) = env_with_competing_tips(TieBreakingRule::Random);
+        env.blockchain.mark_block_generation_completed(high_rand);
+        env.blockchain.mark_block_generation_completed(low_rand);
+        let choices: [Box<dyn crate::ForkChoice>; 2] =
+            [Box::new(LongestChain), Box::new(HeaviestChain)];
+        for fork_choice in choices {
+            env.blockchain.set_fork_choice(Some(fork_choice));
+            assert_eq!(
+                longest_chain(&env, NodeId::new(0), high_rand, low_rand),
+                low_rand
+            );
+            assert_eq!(
+                longest_chain(&env, NodeId::new(0), low_rand, high_rand),
+                low_rand
+            );
+            assert_eq!(env.blockchain.get_main_chain().last(), Some(&low_rand));
+        }
+    }
+
+    /// ジェネシスの子として同じ chainwork の 2 ブロック（rand: 100, 1）を持つ Env。
+    fn env_with_competing_tips(rule: TieBreakingRule) -> (Env, BlockId, BlockId) {
+        let protocol = ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Fixed);
+        let mut env = Env::new(&[], 0, PropagationDelayMode::Uniform, protocol);
+        env.blockchain.set_tie_breaking_rule(rule);
+        let genesis = env.blockchain.get_block(GENESIS_BLOCK_ID).unwrap().clone();
+        let mut ids = Vec::new();
+        for (minter, rand) in [(0, 100), (1, 1)] {
+            let id = env.blockchain.next_block_id();
+            env.blockchain.add_block(Block::new(
+                1,
+                Some(GENESIS_BLOCK_ID),
+                NodeId::new(minter),
+                1000,
+                rand,
+                id,
+                genesis.difficulty(),
+                genesis.cumulative_chain_work() + genesis.difficulty().chain_work_increment(),
+                1.0,
+                true,
+            ));
+            ids.push(id);
+        }
+        (env, ids[0], ids[1])
+    }
+
+    #[test]
+    fn first_seen_keeps_whichever_tip_arrived_first() {
+        let (mut env, high_rand, low_rand) = env_with_competing_tips(TieBreakingRule::FirstSeen);
+        // ノード 0 には high_rand が、ノード 1 には low_rand が先に届く。
+        let (early, late) = (NodeId::new(0), NodeId::new(1));
+        env.blockchain.record_first_seen(early, high_rand, 1_000);
+        env.blockchain.record_first_seen(early, low_rand, 2_000);
+        env.blockchain.record_first_seen(late, low_rand, 1_500);
+        env.blockchain.record_first_seen(late, high_rand, 2_500);
+        // 後から届き直しても最初の受信時刻は変わらない。
+        env.blockchain.record_first_seen(early, low_rand, 0);
+        assert_eq!(env.blockchain.first_seen(early, low_rand), Some(2_000));
+
+        // 引数の順や rand に依らず、そのノードが先に受け取った方を採る。
+        for (a, b) in [(high_rand, low_rand), (low_rand, high_rand)] {
+            assert_eq!(longest_chain(&env, early, a, b), high_rand);
+            assert_eq!(longest_chain(&env, late, a, b), low_rand);
+        }
+        // 主鎖はどれかのノードが最初に受け取った方。
+        env.blockchain.mark_block_generation_completed(high_rand);
+        env.blockchain.mark_block_generation_completed(low_rand);
+        assert_eq!(env.blockchain.get_main_chain().last(), Some(&high_rand));
+    }
+
+    #[test]
+    fn three_competing_tips_resolve_to_the_same_tip_in_any_order() {
+        let (mut env, a, b) = env_with_competing_tips(TieBreakingRule::Random);
+        // 3 つ目の同じ高さ・同じ chainwork の tip（rand: 50）。
+        let genesis = env.blockchain.get_block(GENESIS_BLOCK_ID).unwrap().clone();
+        let c = env.blockchain.next_block_id();
+        env.blockchain.add_block(Block::new(
+            1,
+            Some(GENESIS_BLOCK_ID),
+            NodeId::new(2),
+            1000,
+            50,
+            c,
+            genesis.difficulty(),
+            genesis.cumulative_chain_work() + genesis.difficulty().chain_work_increment(),
+            1.0,
+            true,
+        ));
+        let orders = [
+            [a, b, c],
+            [a, c, b],
+            [b, a, c],
+            [b, c, a],
+            [c, a, b],
+            [c, b, a],
+        ];
+        for order in orders {
+            assert_eq!(
+                best_tip(&env, NodeId::new(0), order[0], &order[1..]),
+                b,
+                "order {order:?}"
+            );
+            // ノードが 1 つずつ受け取っても同じ tip に落ち着く。
+            let mut node = HonestMiningStrategy::default();
+            for tip in order {
+                node.on_receiving_block(tip, 0, &env, NodeId::new(3));
+            }
+            assert_eq!(node.current_head(), b, "order {order:?}");
+        }
+
+        // first-seen では最初に受け取った tip が、並べた順に依らず残る。
+        env.blockchain
+            .set_tie_breaking_rule(TieBreakingRule::FirstSeen);
+        for (i, order) in orders.into_iter().enumerate() {
+            let node = NodeId::new(i);
+            for (time, tip) in (0..).zip(order) {
+                env.blockchain.record_first_seen(node, tip, time);
+            }
+            for tips in orders {
+                assert_eq!(best_tip(&env, node, tips[0], &tips[1..]), order[0]);
+            }
+        }
+        assert_eq!(best_tip(&env, NodeId::new(0), a, &[]), a);
+    }
+
+    #[test]
+    fn main_chain_resolves_ties_like_the_nodes() {
+        for rule in [TieBreakingRule::FirstSeen, TieBreakingRule::Random] {
+            let (mut env, high_rand, low_rand) = env_with_competing_tips(rule);
+            env.blockchain.mark_block_generation_completed(high_rand);
+            env.blockchain.mark_block_generation_completed(low_rand);
+            // 生成順に受け取ったノードが採る tip と主鎖の先端が一致する。
+            let adopted = Some(best_tip(&env, NodeId::new(0), high_rand, &[low_rand]));
+            assert_eq!(
+                env.blockchain.get_main_chain().last().copied(),
+                adopted,
+                "{rule:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn main_chain_follows_the_side_most_honest_nodes_take_under_gamma() {
+        for rule in [TieBreakingRule::FirstSeen, TieBreakingRule::Random] {
+            // 先に生成された方（rand: 100）を攻撃者のブロックにする。
+            let (mut env, attacker_tip, honest_tip) = env_with_competing_tips(rule);
+            env.blockchain.set_attackers([NodeId::new(0)].into());
+            env.blockchain.mark_block_generation_completed(attacker_tip);
+            env.blockchain.mark_block_generation_completed(honest_tip);
+            for (gamma, expected) in [(0.9, attacker_tip), (0.1, honest_tip)] {
+                env.blockchain.set_gamma(Some(gamma));
+                assert_eq!(
+                    env.blockchain.get_main_chain().last(),
+                    Some(&expected),
+                    "{rule:?} γ={gamma}"
+                );
+                // honest ノードの過半数も同じ側に付く。
+                let to_attacker = (2..202)
+                    .filter(|&n| {
+                        gamma_tie_break(&env, NodeId::new(n), honest_tip, attacker_tip)
+                            == Some(attacker_tip)
+                    })
+                    .count();
+                assert_eq!(to_attacker > 100, expected == attacker_tip);
+            }
+        }
+    }
+
+    #[test]
+    fn height_gap_refuses_a_much_longer_chain_presented_at_once() {
+        let protocol = ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Fixed);
+        let mut env = Env::new(&[], 0, PropagationDelayMode::Uniform, protocol);
+        env.max_height_gap = Some(2);
+        let difficulty = Difficulty::Bitcoin(BitcoinDifficulty::new(1.0));
+        let honest_tip = add_child(&mut env, GENESIS_BLOCK_ID, difficulty);
+        // genesis から伸ばした 6 ブロックの攻撃者鎖。tip だけを一度に受け取る。
+        let mut attacker_tip = GENESIS_BLOCK_ID;
+        for _ in 0..6 {
+            attacker_tip = add_child(&mut env, attacker_tip, difficulty);
+        }
+
+        let mut node = HonestMiningStrategy::default();
+        node.on_receiving_block(honest_tip, 0, &env, NodeId::new(1));
+        let actions = node.on_receiving_block(attacker_tip, 0, &env, NodeId::new(1));
+        assert!(actions.is_empty());
+        assert_eq!(node.current_head(), honest_tip);
+        assert_eq!(
+            longest_chain(&env, NodeId::new(0), honest_tip, attacker_tip),
+            honest_tip
+        );
+
+        // 差が上限以内なら通常どおり長い方を採る。
+        env.max_height_gap = Some(5);
+        assert_eq!(
+            longest_chain(&env, NodeId::new(0), honest_tip, attacker_tip),
+            attacker_tip
+        );
+        env.max_height_gap = None;
+        let mut node = HonestMiningStrategy::default();
+        node.on_receiving_block(honest_tip, 0, &env, NodeId::new(1));
+        node.on_receiving_block(attacker_tip, 0, &env, NodeId::new(1));
+        assert_eq!(node.current_head(), attacker_tip);
+    }
+
+    #[test]
+    fn height_gap_is_measured_from_the_current_head_in_any_tip_order() {
+        let protocol = ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Fixed);
+        let mut env = Env::new(&[], 0, PropagationDelayMode::Uniform, protocol);
+        env.max_height_gap = Some(3);
+        let difficulty = Difficulty::Bitcoin(BitcoinDifficulty::new(1.0));
+        let head = add_child(&mut env, GENESIS_BLOCK_ID, difficulty);
+        // head から 3 高い tip（上限内）と 6 高い tip（上限外）。
+        let mut tips = Vec::new();
+        let mut tip = head;
+        for height in 2..=7 {
+            tip = add_child(&mut env, tip, difficulty);
+            if height == 4 || height == 7 {
+                tips.push(tip);
+            }
         }
+        let (near, far) = (tips[0], tips[1]);
+
+        assert_eq!(best_tip(&env, NodeId::new(0), head, &[near, far]), near);
+        assert_eq!(best_tip(&env, NodeId::new(0), head, &[far, near]), near);
+    }
+
+    #[test]
+    fn random_rule_ignores_arrival_order() {
+        let (env, high_rand, low_rand) = env_with_competing_tips(TieBreakingRule::Random);
+        assert_eq!(
+            longest_chain(&env, NodeId::new(0), high_rand, low_rand),
+            low_rand
+        );
+        assert_eq!(
+            longest_chain(&env, NodeId::new(0), low_rand, high_rand),
+            low_rand
+        );
     }
 }
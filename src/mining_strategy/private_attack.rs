@@ -1,9 +1,9 @@
-use std::collections::HashSet;
+use std::{any::Any, collections::HashSet};
 
 use crate::{block::GENESIS_BLOCK_ID, blockchain::BlockId, node::NodeId, simulator::Env};
 use serde::{Deserialize, Serialize};
 
-use super::{Action, MiningStrategy, longest_chain};
+use super::{Action, MiningStrategy, MiningStrategyEnum, longest_chain};
 
 use crate::PRIVATE_ATTACK_MIN_REORG_BLOCKS;
 
@@ -49,21 +49,20 @@ impl PrivateAttackMiningStrategy {
         blocks
     }
 
-    fn publish_block(&mut self, block: BlockId, env: &Env) -> Vec<Action> {
+    fn publish_block(&mut self, block: BlockId, env: &Env, from: NodeId) -> Vec<Action> {
         if self.published_blocks.contains(&block) {
             return vec![];
         }
         self.published_blocks.insert(block);
-        env.nodes()
-            .iter()
+        env.propagation_targets(from)
             .map(|node| Action::Propagate {
                 block_id: block,
-                to: *node,
+                to: node,
             })
             .collect()
     }
 
-    fn publish_private_chain_if_ahead(&mut self, env: &Env) -> Vec<Action> {
+    fn publish_private_chain_if_ahead(&mut self, env: &Env, from: NodeId) -> Vec<Action> {
         let private_h = self.chain_height(env, self.private_chain);
         let public_h = self.chain_height(env, self.public_chain);
         if private_h < public_h + PRIVATE_ATTACK_MIN_REORG_BLOCKS {
@@ -71,7 +70,7 @@ impl PrivateAttackMiningStrategy {
         }
         let mut actions = Vec::new();
         for block_id in self.get_private_branch(env) {
-            actions.extend(self.publish_block(block_id, env));
+            actions.extend(self.publish_block(block_id, env, from));
         }
         self.private_branch_len = 0;
         actions
@@ -83,17 +82,35 @@ impl MiningStrategy for PrivateAttackMiningStrategy {
         "private_attack"
     }
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn current_head(&self) -> BlockId {
+        // 非公開分岐も含め、実際に採掘している tip。
+        self.private_chain
+    }
+
+    fn referenced_blocks(&self) -> Vec<BlockId> {
+        // 非公開分岐は private_chain の祖先として残る。
+        vec![self.private_chain, self.public_chain]
+    }
+
+    fn to_profile(&self) -> MiningStrategyEnum {
+        MiningStrategyEnum::PrivateAttack
+    }
+
     fn on_mining_block(
         &mut self,
         block_id: BlockId,
         _current_time_us: i64,
         env: &Env,
-        _node_id: NodeId,
+        node_id: NodeId,
     ) -> Vec<Action> {
         self.private_chain = block_id;
         self.private_branch_len += 1;
 
-        let mut actions = self.publish_private_chain_if_ahead(env);
+        let mut actions = self.publish_private_chain_if_ahead(env, node_id);
         actions.push(Action::RestartMining {
             prev_block_id: self.private_chain,
         });
@@ -105,9 +122,9 @@ impl MiningStrategy for PrivateAttackMiningStrategy {
         block_id: BlockId,
         _current_time_us: i64,
         env: &Env,
-        _node_id: NodeId,
+        node_id: NodeId,
     ) -> Vec<Action> {
-        self.public_chain = longest_chain(env, self.public_chain, block_id);
+        self.public_chain = longest_chain(env, node_id, self.public_chain, block_id);
 
         let private_h = self.chain_height(env, self.private_chain);
         let public_h = self.chain_height(env, self.public_chain);
@@ -120,7 +137,7 @@ impl MiningStrategy for PrivateAttackMiningStrategy {
             }];
         }
 
-        let mut actions = self.publish_private_chain_if_ahead(env);
+        let mut actions = self.publish_private_chain_if_ahead(env, node_id);
         actions.push(Action::RestartMining {
             prev_block_id: self.private_chain,
         });
@@ -1,9 +1,12 @@
-use std::collections::HashSet;
+use std::{any::Any, collections::HashSet};
 
 use crate::{block::GENESIS_BLOCK_ID, blockchain::BlockId, node::NodeId, simulator::Env};
 use serde::{Deserialize, Serialize};
 
-use super::{Action, MiningStrategy, longest_chain};
+use super::{Action, MiningStrategy, MiningStrategyEnum, longest_chain};
+
+/// 非公開分岐を一括公開するリードのデフォルト値（Eyal–Sirer の古典的な規則）。
+pub const DEFAULT_REVEAL_LEAD: usize = 2;
 
 // Selfish mining strategy
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,20 +19,60 @@ pub struct SelfishMiningStrategy {
     private_branch_len: usize,
     // published blocks
     published_blocks: HashSet<BlockId>,
+    /// honest ブロックを受け取った時点のリードがこの値ちょうどなら非公開分岐をすべて公開して上書きする。
+    /// これより大きければ 1 ブロックずつ公開し、1 より大きくこれ未満なら公開せずに隠し続ける。
+    reveal_lead: usize,
+    /// 公開チェーンより長い非公開分岐を公開して honest のブロックを押しのけた回数。
+    overrides: u64,
+    /// 非公開分岐を公開する相手。`None` なら `env.propagation_targets` の全ノード。
+    /// 一部のノードにだけ先に見せて、同着でそちらに付くノードを選ぶ攻撃を表す。
+    reveal_targets: Option<Vec<NodeId>>,
 }
 
 impl Default for SelfishMiningStrategy {
     fn default() -> Self {
+        Self::with_reveal_lead(DEFAULT_REVEAL_LEAD)
+    }
+}
+
+impl SelfishMiningStrategy {
+    pub fn with_reveal_lead(reveal_lead: usize) -> Self {
+        assert!(
+            reveal_lead >= 2,
+            "reveal_lead は 2 以上である必要があります"
+        );
         Self {
             public_chain: GENESIS_BLOCK_ID,
             private_chain: GENESIS_BLOCK_ID,
             private_branch_len: 0,
             published_blocks: HashSet::new(),
+            reveal_lead,
+            overrides: 0,
+            reveal_targets: None,
         }
     }
-}
 
-impl SelfishMiningStrategy {
+    /// 非公開分岐を `reveal_targets` のノードにだけ公開する（`None` で全ノード）。
+    pub fn with_reveal_targets(mut self, reveal_targets: Option<Vec<NodeId>>) -> Self {
+        self.reveal_targets = reveal_targets;
+        self
+    }
+
+    pub fn reveal_lead(&self) -> usize {
+        self.reveal_lead
+    }
+
+    /// 追跡中の非公開分岐の長さ（公開済みのブロックも含む）。
+    pub fn private_branch_len(&self) -> usize {
+        self.private_branch_len
+    }
+
+    /// 未公開のまま手元に残っているブロックがあるか。分岐は古い方から公開するので、
+    /// 非公開分岐の先端が未公開かどうかで分かる。
+    pub fn has_unpublished_blocks(&self) -> bool {
+        self.private_branch_len > 0 && !self.published_blocks.contains(&self.private_chain)
+    }
+
     fn get_private_branch(&self, env: &Env) -> Vec<BlockId> {
         let mut blocks = Vec::new();
 
@@ -45,10 +88,6 @@ impl SelfishMiningStrategy {
         blocks
     }
 
-    fn get_last_private_block(&self) -> BlockId {
-        self.private_chain
-    }
-
     /// 追跡中の `private_branch_len` 本だけを走査して未公開ブロックを探す。
     ///
     /// 高さ差 `delta_prev > 2` のとき古典的な selfish では未公開が必ず存在する前提だが、
@@ -70,17 +109,21 @@ impl SelfishMiningStrategy {
         unpublished.last().copied()
     }
 
-    fn publish_block(&mut self, block: BlockId, env: &Env) -> Vec<Action> {
+    fn publish_block(&mut self, block: BlockId, env: &Env, from: NodeId) -> Vec<Action> {
         let published = self.published_blocks.contains(&block);
         if published {
             vec![]
         } else {
             let mut actions = vec![];
             self.published_blocks.insert(block);
-            for node in env.nodes() {
+            let targets: Vec<NodeId> = match &self.reveal_targets {
+                Some(targets) => targets.iter().copied().filter(|&to| to != from).collect(),
+                None => env.propagation_targets(from).collect(),
+            };
+            for node in targets {
                 actions.push(Action::Propagate {
                     block_id: block,
-                    to: *node,
+                    to: node,
                 });
             }
             actions
@@ -93,12 +136,37 @@ impl MiningStrategy for SelfishMiningStrategy {
         "Selfish"
     }
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn current_head(&self) -> BlockId {
+        // 非公開分岐も含め、実際に採掘している tip。
+        self.private_chain
+    }
+
+    fn referenced_blocks(&self) -> Vec<BlockId> {
+        // 非公開分岐は private_chain の祖先として残る。
+        vec![self.private_chain, self.public_chain]
+    }
+
+    fn overrides(&self) -> u64 {
+        self.overrides
+    }
+
+    fn to_profile(&self) -> MiningStrategyEnum {
+        MiningStrategyEnum::Selfish {
+            reveal_lead: self.reveal_lead,
+            reveal_targets: self.reveal_targets.clone(),
+        }
+    }
+
     fn on_mining_block(
         &mut self,
         block_id: BlockId,
         _current_time_us: i64,
         env: &Env,
-        _node_id: NodeId,
+        node_id: NodeId,
     ) -> Vec<Action> {
         let mut actions = Vec::new();
 
@@ -123,9 +191,10 @@ impl MiningStrategy for SelfishMiningStrategy {
             // Publish all the blocks in the private chain.
             // This node can win due to the lead of 1 block.
             for private_block_id in self.get_private_branch(env) {
-                actions.extend(self.publish_block(private_block_id, env));
+                actions.extend(self.publish_block(private_block_id, env, node_id));
             }
             self.private_branch_len = 0;
+            self.overrides += 1;
         }
 
         // Schedule a new mining task.
@@ -140,7 +209,7 @@ impl MiningStrategy for SelfishMiningStrategy {
         block_id: BlockId,
         _current_time_us: i64,
         env: &Env,
-        _node_id: NodeId,
+        node_id: NodeId,
     ) -> Vec<Action> {
         let mut actions = Vec::new();
 
@@ -157,7 +226,7 @@ impl MiningStrategy for SelfishMiningStrategy {
         let delta_prev = private_chain_height - public_chain_height;
 
         // update the public chain if the incoming block is longer than the known public chain.
-        self.public_chain = longest_chain(env, self.public_chain, block_id);
+        self.public_chain = longest_chain(env, node_id, self.public_chain, block_id);
 
         if delta_prev <= 0 {
             // they win.
@@ -167,20 +236,24 @@ impl MiningStrategy for SelfishMiningStrategy {
                 prev_block_id: self.public_chain,
             });
         } else if delta_prev == 1 {
-            // publish the last block of the private chain.
+            // publish the rest of the private chain (only the last block with the default reveal lead).
             // Now the same length. Try our luck.
-            let published_block_id = self.get_last_private_block();
-            actions.extend(self.publish_block(published_block_id, env));
-        } else if delta_prev == 2 {
+            for private_block_id in self.get_private_branch(env) {
+                actions.extend(self.publish_block(private_block_id, env, node_id));
+            }
+        } else if delta_prev == self.reveal_lead as i64 {
             // Publish all the blocks in the private chain.
-            // This node can win due to the lead of 1 block.
+            // This node can win due to the lead of `reveal_lead - 1` blocks.
             for private_block_id in self.get_private_branch(env) {
-                actions.extend(self.publish_block(private_block_id, env));
+                actions.extend(self.publish_block(private_block_id, env, node_id));
             }
             self.private_branch_len = 0;
+            self.overrides += 1;
+        } else if delta_prev < self.reveal_lead as i64 {
+            // Not enough lead to reveal yet. Keep withholding.
         } else if let Some(published_block_id) = self.get_first_unpublished_private_block(env) {
             // Publish the first unpublished block in the private chain.
-            actions.extend(self.publish_block(published_block_id, env));
+            actions.extend(self.publish_block(published_block_id, env, node_id));
         }
         actions
     }
@@ -1,7 +1,9 @@
+use std::any::Any;
+
 use crate::{blockchain::BlockId, node::NodeId, simulator::Env};
 
 use super::{
-    Action, MiningStrategy,
+    Action, MiningStrategy, MiningStrategyEnum,
     selfish::SelfishMiningStrategy,
     timewarp::{DEFAULT_MTP_WINDOW_SIZE, timewarp_adjusted_timestamp},
 };
@@ -30,6 +32,11 @@ impl SelfishTimewarpStrategy {
     pub fn mtp_window_size(&self) -> usize {
         self.mtp_window_size
     }
+
+    /// 分岐・公開の状態を持つ内側の selfish 戦略。
+    pub fn selfish(&self) -> &SelfishMiningStrategy {
+        &self.inner
+    }
 }
 
 impl MiningStrategy for SelfishTimewarpStrategy {
@@ -37,6 +44,28 @@ impl MiningStrategy for SelfishTimewarpStrategy {
         "selfish_timewarp"
     }
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn current_head(&self) -> BlockId {
+        self.inner.current_head()
+    }
+
+    fn referenced_blocks(&self) -> Vec<BlockId> {
+        self.inner.referenced_blocks()
+    }
+
+    fn overrides(&self) -> u64 {
+        self.inner.overrides()
+    }
+
+    fn to_profile(&self) -> MiningStrategyEnum {
+        MiningStrategyEnum::SelfishTimewarp {
+            mtp_window_size: self.mtp_window_size,
+        }
+    }
+
     fn on_mining_block(
         &mut self,
         block_id: BlockId,
@@ -0,0 +1,87 @@
+use std::any::Any;
+
+use crate::{block::GENESIS_BLOCK_ID, blockchain::BlockId, node::NodeId, simulator::Env};
+use serde::{Deserialize, Serialize};
+
+use super::{Action, MiningStrategy, MiningStrategyEnum};
+
+/// SPV（検証なし）マイニング戦略
+///
+/// 受信したブロックがいまの tip 以上の高さなら、chainwork を比べずに（= 検証を待たずに）
+/// 即座にその上で採掘を再開する。同じ高さの競合ブロックにも乗り換えるため、
+/// 後に孤立する分岐の上で採掘して stale を増やしやすい。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpvMiningStrategy {
+    current_block_id: BlockId,
+}
+
+impl Default for SpvMiningStrategy {
+    fn default() -> Self {
+        Self {
+            current_block_id: GENESIS_BLOCK_ID,
+        }
+    }
+}
+
+impl MiningStrategy for SpvMiningStrategy {
+    fn name(&self) -> &'static str {
+        "SPV"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn current_head(&self) -> BlockId {
+        self.current_block_id
+    }
+
+    fn to_profile(&self) -> MiningStrategyEnum {
+        MiningStrategyEnum::Spv
+    }
+
+    fn on_mining_block(
+        &mut self,
+        block_id: BlockId,
+        _current_time_us: i64,
+        env: &Env,
+        node_id: NodeId,
+    ) -> Vec<Action> {
+        self.current_block_id = block_id;
+
+        let mut actions = Vec::new();
+
+        // Immediately schedule propagation tasks to all other nodes.
+        for node in env.propagation_targets(node_id) {
+            actions.push(Action::Propagate { block_id, to: node });
+        }
+
+        // Schedule a new mining task.
+        actions.push(Action::RestartMining {
+            prev_block_id: block_id,
+        });
+        actions
+    }
+
+    fn on_receiving_block(
+        &mut self,
+        block_id: BlockId,
+        _current_time_us: i64,
+        env: &Env,
+        _node_id: NodeId,
+    ) -> Vec<Action> {
+        let current_height = env.blockchain.block_height(self.current_block_id);
+        let received_height = env.blockchain.block_height(block_id);
+
+        if block_id == self.current_block_id || received_height < current_height {
+            // Lower than the current tip: keep mining.
+            return vec![];
+        }
+
+        // Header-only check: jump onto the received tip without comparing chainwork.
+        self.current_block_id = block_id;
+        vec![Action::RestartMining {
+            prev_block_id: block_id,
+        }]
+    }
+}
@@ -1,7 +1,9 @@
+use std::any::Any;
+
 use crate::{block::GENESIS_BLOCK_ID, blockchain::BlockId, node::NodeId, simulator::Env};
 use serde::{Deserialize, Serialize};
 
-use super::{Action, MiningStrategy, longest_chain};
+use super::{Action, MiningStrategy, MiningStrategyEnum, longest_chain};
 
 /// MTP（Median Time Past）算出に使う直近ブロック数のデフォルト値（Bitcoin 既定の 11）。
 pub const DEFAULT_MTP_WINDOW_SIZE: usize = 11;
@@ -50,12 +52,12 @@ pub(crate) fn timewarp_adjusted_timestamp(
 
     assert!(mtp_window_size >= 1, "mtp_window_size は 1 以上である必要があります");
 
-    let parent_timestamp = env.blockchain.get_block(parent_block_id).unwrap().time();
+    let parent_timestamp = env.blockchain.get_block(parent_block_id).unwrap().timestamp();
     let mut timestamps: Vec<i64> = env
         .blockchain
         .get_last_n_blocks(parent_block_id, mtp_window_size - 1)
         .iter()
-        .map(|b| b.time())
+        .map(|b| b.timestamp())
         .collect();
     timestamps.push(parent_timestamp);
 
@@ -76,22 +78,33 @@ impl MiningStrategy for TimewarpStrategy {
         "TimeWarp"
     }
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn current_head(&self) -> BlockId {
+        self.current_block_id
+    }
+
+    fn to_profile(&self) -> MiningStrategyEnum {
+        MiningStrategyEnum::Timewarp {
+            mtp_window_size: self.mtp_window_size,
+        }
+    }
+
     fn on_mining_block(
         &mut self,
         block_id: BlockId,
         _current_time_us: i64,
         env: &Env,
-        _node_id: NodeId,
+        node_id: NodeId,
     ) -> Vec<Action> {
         self.current_block_id = block_id;
         let mut actions = Vec::new();
 
         // Immediately schedule propagation tasks to all other nodes.
-        for node in env.nodes() {
-            actions.push(Action::Propagate {
-                block_id,
-                to: *node,
-            });
+        for node in env.propagation_targets(node_id) {
+            actions.push(Action::Propagate { block_id, to: node });
         }
 
         // Schedule a new mining task.
@@ -106,10 +119,10 @@ impl MiningStrategy for TimewarpStrategy {
         block_id: BlockId,
         _current_time_us: i64,
         env: &Env,
-        _node_id: NodeId,
+        node_id: NodeId,
     ) -> Vec<Action> {
         let old_chain = self.current_block_id;
-        self.current_block_id = longest_chain(env, self.current_block_id, block_id);
+        self.current_block_id = longest_chain(env, node_id, self.current_block_id, block_id);
 
         if old_chain == self.current_block_id {
             // If the chain is not changed, continue mining.
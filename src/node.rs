@@ -1,8 +1,8 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::mining_strategy::MiningStrategy;
 
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct NodeId(usize);
 
 impl NodeId {
@@ -13,10 +13,6 @@ impl NodeId {
     pub fn into_usize(self) -> usize {
         self.0
     }
-
-    pub fn dummy() -> Self {
-        Self(usize::MAX)
-    }
 }
 
 impl std::fmt::Display for NodeId {
@@ -32,6 +28,8 @@ pub struct Node {
     /// The hashrate of the node.
     pub hashrate: i64,
     pub mining_strategy: Box<dyn MiningStrategy>,
+    /// 途中参加する時刻（ms）。`None` または 0 以下なら最初から参加している。
+    pub join_time: Option<i64>,
 }
 
 impl Node {
@@ -52,6 +50,7 @@ impl Node {
             id,
             hashrate,
             mining_strategy,
+            join_time: None,
         }
     }
 
@@ -63,6 +62,11 @@ impl Node {
         self.hashrate
     }
 
+    /// シミュレーション開始時点で参加しているか
+    pub fn is_initially_active(&self) -> bool {
+        self.join_time.is_none_or(|t| t <= 0)
+    }
+
     pub fn mining_strategy(&self) -> &dyn MiningStrategy {
         self.mining_strategy.as_ref()
     }
@@ -96,4 +100,8 @@ impl NodeList {
     pub fn get_node_mut(&mut self, id: NodeId) -> &mut Node {
         &mut self.nodes[id.into_usize()]
     }
+
+    pub fn push(&mut self, node: Node) {
+        self.nodes.push(node);
+    }
 }
@@ -1,8 +1,47 @@
-use crate::mining_strategy::{MiningStrategy, MiningStrategyEnum};
+use crate::mining_strategy::{
+    DEFAULT_MTP_WINDOW_SIZE, DEFAULT_REVEAL_LEAD, MiningStrategy, MiningStrategyEnum,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::fs;
 use std::path::Path;
 
+/// 手書きのプロファイルなしで古典的な実験を再現するためのプリセット（`--scenario`）。
+/// いずれも honest ノードは同じハッシュレートを持つ。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Scenario {
+    /// 全ノード honest。
+    Honest,
+    /// selfish ノード 1 台が総ハッシュレートの 25%、残りは honest。
+    #[value(name = "selfish-25")]
+    Selfish25,
+    /// selfish ノード 1 台が総ハッシュレートの約 1/3、残りは honest。
+    #[value(name = "selfish-33")]
+    Selfish33,
+    /// private attack ノード 1 台が総ハッシュレートの 51%、残りは honest。
+    #[value(name = "private-attack-51")]
+    PrivateAttack51,
+}
+
+impl Scenario {
+    /// 攻撃者 1 台の戦略とハッシュレート比率（honest のみなら `None`）。
+    fn attacker(self) -> Option<(MiningStrategyEnum, f64)> {
+        let selfish = MiningStrategyEnum::Selfish {
+            reveal_lead: DEFAULT_REVEAL_LEAD,
+            reveal_targets: None,
+        };
+        match self {
+            Scenario::Honest => None,
+            Scenario::Selfish25 => Some((selfish, 0.25)),
+            Scenario::Selfish33 => Some((selfish, 1.0 / 3.0)),
+            Scenario::PrivateAttack51 => Some((MiningStrategyEnum::PrivateAttack, 0.51)),
+        }
+    }
+}
+
+/// プリセットでの honest ノード 1 台あたりのハッシュレート。
+const SCENARIO_HONEST_HASHRATE: i64 = 10_000;
+
 /// A struct representing node configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeProfile {
@@ -10,6 +49,9 @@ pub struct NodeProfile {
     pub hashrate: i64,
     /// Mining strategy
     pub strategy: MiningStrategyEnum,
+    /// 途中参加する時刻（ms）。省略時は最初から参加する。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub join_time: Option<i64>,
 }
 
 /// Network profile (configuration for all nodes)
@@ -38,8 +80,14 @@ pub struct NodeProfile {
 /// # Strategy Types and Parameters
 ///
 /// - `honest`: No parameters.
-/// - `selfish`: No parameters.
+/// - `selfish`: `reveal_lead` (optional, default 2) — the lead at which the private branch is published.
+///   `reveal_targets` (optional) — node indices the private branch is revealed to (default: all nodes).
 /// - `private_attack`: No parameters.
+/// - `spv`: No parameters.
+/// - `fee_maximizing`: No parameters. Breaks equal-work ties toward the tip with more unclaimed fees (`--fee-rate`).
+///
+/// A node may also set `"join_time"` (ms) to come online partway through the run.
+/// The same format is available as a JSON Schema from [`NetworkProfile::json_schema`] (`--print-schema`).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkProfile {
     /// A list of node profiles.
@@ -61,19 +109,200 @@ impl NetworkProfile {
         Ok(())
     }
 
+    /// Build an all-honest profile whose node `i` has hashrate `hashrates[i]` (`--hashrates`).
+    pub fn from_hashrates(hashrates: &[i64]) -> Result<Self, Box<dyn std::error::Error>> {
+        if hashrates.is_empty() {
+            return Err("hashrate list must not be empty".into());
+        }
+        if let Some(h) = hashrates.iter().find(|&&h| h <= 0) {
+            return Err(format!("hashrates must be positive, got {h}").into());
+        }
+        Ok(Self {
+            nodes: hashrates
+                .iter()
+                .map(|&hashrate| NodeProfile {
+                    hashrate,
+                    strategy: MiningStrategyEnum::Honest,
+                    join_time: None,
+                })
+                .collect(),
+        })
+    }
+
+    /// Build the profile of `scenario` with `num_nodes` nodes in total (the attacker, if any, is node 0).
+    pub fn from_scenario(
+        scenario: Scenario,
+        num_nodes: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let honest_node = |_| NodeProfile {
+            hashrate: SCENARIO_HONEST_HASHRATE,
+            strategy: MiningStrategyEnum::Honest,
+            join_time: None,
+        };
+        let Some((strategy, share)) = scenario.attacker() else {
+            if num_nodes == 0 {
+                return Err("scenario needs at least 1 node".into());
+            }
+            return Ok(Self {
+                nodes: (0..num_nodes).map(honest_node).collect(),
+            });
+        };
+        if num_nodes < 2 {
+            return Err("scenario needs at least 2 nodes (one attacker and honest nodes)".into());
+        }
+        // 攻撃者 a と honest 合計 H で a / (a + H) = share。
+        let honest_total = (num_nodes - 1) as i64 * SCENARIO_HONEST_HASHRATE;
+        let attacker_hashrate = (honest_total as f64 * share / (1.0 - share)).round() as i64;
+        let mut nodes = vec![NodeProfile {
+            hashrate: attacker_hashrate,
+            strategy,
+            join_time: None,
+        }];
+        nodes.extend((1..num_nodes).map(honest_node));
+        Ok(Self { nodes })
+    }
+
     /// Create mining strategy for a node from profile
     pub fn create_strategy(
         &self,
         node_index: usize,
     ) -> Result<Box<dyn MiningStrategy>, Box<dyn std::error::Error>> {
         let node_profile = &self.nodes[node_index];
+        if let MiningStrategyEnum::Selfish {
+            reveal_lead,
+            reveal_targets,
+        } = &node_profile.strategy
+        {
+            if *reveal_lead < 2 {
+                return Err(format!(
+                    "node {node_index}: reveal_lead must be at least 2, got {reveal_lead}"
+                )
+                .into());
+            }
+            if let Some(target) = reveal_targets
+                .iter()
+                .flatten()
+                .find(|t| t.into_usize() >= self.num_nodes())
+            {
+                return Err(format!(
+                    "node {node_index}: reveal target {target} is out of range (0..{})",
+                    self.num_nodes()
+                )
+                .into());
+            }
+        }
         Ok(node_profile.strategy.to_strategy())
     }
 
+    /// プロファイルが受け付ける形式の JSON Schema（draft 2020-12）。`--print-schema` が出力する。
+    ///
+    /// フィールド名・戦略の種類・値の範囲を書き出したもので、`reveal_targets` がノード数未満かどうかのように
+    /// プロファイル全体を見ないと分からない制約は含まない（[`NetworkProfile::create_strategy`] が検査する）。
+    pub fn json_schema() -> serde_json::Value {
+        let strategy = |name: &str, properties: serde_json::Value, description: &str| {
+            let mut properties = properties;
+            properties["type"] = json!({ "const": name });
+            json!({
+                "description": description,
+                "type": "object",
+                "properties": properties,
+                "required": ["type"],
+            })
+        };
+        let mtp_window_size = json!({
+            "description": "MTP（中央値）算出に使う直近ブロック数",
+            "type": "integer",
+            "minimum": 1,
+            "default": DEFAULT_MTP_WINDOW_SIZE,
+        });
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "NetworkProfile",
+            "type": "object",
+            "properties": {
+                "nodes": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "hashrate": {
+                                "description": "ハッシュレート（0 なら採掘しない観測ノード）",
+                                "type": "integer",
+                                "minimum": 0,
+                                "maximum": i64::MAX,
+                            },
+                            "strategy": {
+                                "oneOf": [
+                                    strategy("honest", json!({}), "honest mining"),
+                                    strategy(
+                                        "selfish",
+                                        json!({
+                                            "reveal_lead": {
+                                                "description": "非公開分岐を一括公開するリード",
+                                                "type": "integer",
+                                                "minimum": 2,
+                                                "default": DEFAULT_REVEAL_LEAD,
+                                            },
+                                            "reveal_targets": {
+                                                "description": "非公開分岐を公開する相手のノード番号（省略時は全ノード）",
+                                                "type": "array",
+                                                "items": { "type": "integer", "minimum": 0 },
+                                            },
+                                        }),
+                                        "selfish mining",
+                                    ),
+                                    strategy("private_attack", json!({}), "private-chain attack"),
+                                    strategy("spv", json!({}), "SPV mining"),
+                                    strategy(
+                                        "selfish_timewarp",
+                                        json!({ "mtp_window_size": mtp_window_size }),
+                                        "selfish mining with time warp",
+                                    ),
+                                    strategy(
+                                        "timewarp",
+                                        json!({ "mtp_window_size": mtp_window_size }),
+                                        "time warp",
+                                    ),
+                                    strategy(
+                                        "fee_maximizing",
+                                        json!({}),
+                                        "breaks equal-work ties toward the tip with more unclaimed fees",
+                                    ),
+                                ],
+                            },
+                            "join_time": {
+                                "description": "途中参加する時刻（ms）。省略時は最初から参加する",
+                                "type": "integer",
+                            },
+                        },
+                        "required": ["hashrate", "strategy"],
+                    },
+                },
+            },
+            "required": ["nodes"],
+        })
+    }
+
     /// Get the number of nodes
     pub fn num_nodes(&self) -> usize {
         self.nodes.len()
     }
+
+    /// `--num-nodes` が指定されていて、このプロファイルのノード数と食い違えばエラーにする
+    /// （黙ってプロファイル側を優先しない）。
+    pub fn check_num_nodes(
+        &self,
+        num_nodes: Option<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match num_nodes {
+            Some(n) if n != self.num_nodes() => Err(format!(
+                "--num-nodes ({n}) does not match the {} nodes in the profile",
+                self.num_nodes()
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -89,10 +318,15 @@ mod tests {
                 NodeProfile {
                     hashrate: 1000,
                     strategy: MiningStrategyEnum::Honest,
+                    join_time: None,
                 },
                 NodeProfile {
                     hashrate: 2000,
-                    strategy: MiningStrategyEnum::Selfish,
+                    strategy: MiningStrategyEnum::Selfish {
+                        reveal_lead: 3,
+                        reveal_targets: None,
+                    },
+                    join_time: Some(60_000),
                 },
             ],
         };
@@ -104,6 +338,172 @@ mod tests {
         assert_eq!(deserialized.nodes.len(), 2);
         assert_eq!(deserialized.nodes[0].hashrate, 1000);
         assert_eq!(deserialized.nodes[1].hashrate, 2000);
-        assert_eq!(deserialized.nodes[1].strategy, MiningStrategyEnum::Selfish);
+        assert_eq!(
+            deserialized.nodes[1].strategy,
+            MiningStrategyEnum::Selfish {
+                reveal_lead: 3,
+                reveal_targets: None
+            }
+        );
+        assert_eq!(deserialized.nodes[0].join_time, None);
+        assert_eq!(deserialized.nodes[1].join_time, Some(60_000));
+
+        // reveal_lead を省略した既存のプロファイルは古典的な規則になる。
+        let legacy: MiningStrategyEnum = serde_json::from_str(r#"{"type":"selfish"}"#).unwrap();
+        assert_eq!(
+            legacy,
+            MiningStrategyEnum::Selfish {
+                reveal_lead: DEFAULT_REVEAL_LEAD,
+                reveal_targets: None,
+            }
+        );
+    }
+
+    #[test]
+    fn example_profiles_validate_against_the_schema() {
+        let schema = NetworkProfile::json_schema();
+        assert!(jsonschema::meta::is_valid(&schema));
+        let validator = jsonschema::validator_for(&schema).unwrap();
+
+        let examples = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/examples")).unwrap();
+        for path in examples.map(|entry| entry.unwrap().path()) {
+            let profile: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            let errors: Vec<String> = validator
+                .iter_errors(&profile)
+                .map(|e| e.to_string())
+                .collect();
+            assert!(errors.is_empty(), "{}: {errors:?}", path.display());
+        }
+
+        // 書き出したプロファイルはすべての戦略について schema を満たす。
+        let strategies = [
+            MiningStrategyEnum::Honest,
+            MiningStrategyEnum::Selfish {
+                reveal_lead: 3,
+                reveal_targets: Some(vec![crate::node::NodeId::new(0)]),
+            },
+            MiningStrategyEnum::PrivateAttack,
+            MiningStrategyEnum::Spv,
+            MiningStrategyEnum::SelfishTimewarp {
+                mtp_window_size: 11,
+            },
+            MiningStrategyEnum::Timewarp { mtp_window_size: 5 },
+            MiningStrategyEnum::FeeMaximizing,
+        ];
+        let profile = NetworkProfile {
+            nodes: strategies
+                .into_iter()
+                .map(|strategy| NodeProfile {
+                    hashrate: 100,
+                    strategy,
+                    join_time: Some(1_000),
+                })
+                .collect(),
+        };
+        assert!(validator.is_valid(&serde_json::to_value(&profile).unwrap()));
+
+        for invalid in [
+            r#"{"nodes": [{"hashrate": -1, "strategy": {"type": "honest"}}]}"#,
+            r#"{"nodes": [{"hashrate": 1, "strategy": {"type": "lazy"}}]}"#,
+            r#"{"nodes": [{"hashrate": 1, "strategy": {"type": "selfish", "reveal_lead": 1}}]}"#,
+            r#"{"nodes": [{"strategy": {"type": "honest"}}]}"#,
+            r#"{}"#,
+        ] {
+            let value: serde_json::Value = serde_json::from_str(invalid).unwrap();
+            assert!(!validator.is_valid(&value), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn conflicting_num_nodes_is_reported() {
+        let profile = NetworkProfile::from_hashrates(&[1, 2, 3]).unwrap();
+        assert!(profile.check_num_nodes(None).is_ok());
+        assert!(profile.check_num_nodes(Some(3)).is_ok());
+        let err = profile.check_num_nodes(Some(10)).unwrap_err();
+        assert!(err.to_string().contains("--num-nodes (10)"), "{err}");
+    }
+
+    #[test]
+    fn selfish_33_has_one_selfish_node_with_a_third_of_hashrate() {
+        for num_nodes in [2, 3, 10, 64] {
+            let profile = NetworkProfile::from_scenario(Scenario::Selfish33, num_nodes).unwrap();
+            assert_eq!(profile.num_nodes(), num_nodes);
+            let selfish: Vec<_> = profile
+                .nodes
+                .iter()
+                .filter(|n| matches!(n.strategy, MiningStrategyEnum::Selfish { .. }))
+                .collect();
+            assert_eq!(selfish.len(), 1);
+            assert!(
+                profile.nodes[1..]
+                    .iter()
+                    .all(|n| n.strategy == MiningStrategyEnum::Honest)
+            );
+            let total: i64 = profile.nodes.iter().map(|n| n.hashrate).sum();
+            let share = selfish[0].hashrate as f64 / total as f64;
+            assert!(
+                (share - 1.0 / 3.0).abs() < 1e-3,
+                "{num_nodes} nodes: {share}"
+            );
+        }
+        assert!(NetworkProfile::from_scenario(Scenario::Selfish33, 1).is_err());
+        assert_eq!(
+            NetworkProfile::from_scenario(Scenario::Honest, 1)
+                .unwrap()
+                .num_nodes(),
+            1
+        );
+    }
+
+    #[test]
+    fn selfish_reveal_lead_below_two_is_reported() {
+        let profile: NetworkProfile = serde_json::from_str(
+            r#"{"nodes": [
+                {"hashrate": 1, "strategy": {"type": "selfish", "reveal_lead": 1}},
+                {"hashrate": 2, "strategy": {"type": "honest"}}
+            ]}"#,
+        )
+        .unwrap();
+        let err = profile.create_strategy(0).err().unwrap();
+        assert!(err.to_string().contains("reveal_lead"), "{err}");
+        assert!(profile.create_strategy(1).is_ok());
+    }
+
+    #[test]
+    fn hashrate_list_builds_honest_nodes_in_order() {
+        use crate::simulator::BlockchainSimulator;
+        use crate::{GenesisDifficultyMode, PropagationDelayMode, ProtocolType};
+
+        let hashrates = [100, 300, 200];
+        let profile = NetworkProfile::from_hashrates(&hashrates).unwrap();
+        let sim = BlockchainSimulator::new_with_profile(
+            profile,
+            0,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Inferred),
+        )
+        .unwrap();
+        let exported = sim.export_profile();
+        assert_eq!(
+            exported
+                .nodes
+                .iter()
+                .map(|n| n.hashrate)
+                .collect::<Vec<_>>(),
+            hashrates
+        );
+        assert!(
+            exported
+                .nodes
+                .iter()
+                .all(|n| n.strategy == MiningStrategyEnum::Honest)
+        );
+
+        assert!(NetworkProfile::from_hashrates(&[]).is_err());
+        assert!(NetworkProfile::from_hashrates(&[100, 0]).is_err());
+        assert!(NetworkProfile::from_hashrates(&[-5]).is_err());
     }
 }
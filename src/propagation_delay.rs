@@ -10,6 +10,31 @@ pub enum PropagationDelayMode {
     AttackerUnfavorable,
 }
 
+/// 戦略がブロックを公開するときの送り先（`--propagation-target`）。
+///
+/// ノード間のピア構成はモデル化していない（受信したブロックを中継する経路がない）ため、
+/// 送り先はノード集合で選ぶ。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PropagationTarget {
+    /// 全ノード（ハッシュレート 0 の観測ノードを含む）。
+    #[default]
+    AllNodes,
+    /// ハッシュレートが正のノードだけ。観測ノードへの伝播イベントを積まない。
+    Miners,
+}
+
+/// ブロックの届け方（`--propagation-model`）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PropagationModel {
+    /// 送り先ごとに伝播イベントを積む。遅延モード・ジッタ・パケット損失などを受信者ごとに適用する。
+    #[default]
+    PerPeer,
+    /// 公開したブロックは Δ ちょうど後に送り先全員へ同時に届く（1 ブロックあたり 1 イベント）。
+    /// 解析モデルの「固定遅延の即時 gossip」に相当し、遅延モード・ジッタ・ノードごとの遅延・
+    /// パケット損失・header-first relay・検証遅延は適用しない。
+    Broadcast,
+}
+
 /// 送信元・受信先の honest 属性とモードから伝播遅延（マイクロ秒）を返す。
 pub fn propagation_delay_us(
     mode: PropagationDelayMode,
@@ -39,6 +64,20 @@ pub fn propagation_delay_us(
     }
 }
 
+/// 遅延に ±`jitter_pct`% の一様ジッタを掛ける（メッセージごとに `rng` から引く）。
+/// `jitter_pct` が 0 または遅延が 0 のときは乱数を消費せずそのまま返す。
+pub fn jittered_delay_us<R: rand::Rng + ?Sized>(
+    delay_us: i64,
+    jitter_pct: f64,
+    rng: &mut R,
+) -> i64 {
+    if jitter_pct <= 0.0 || delay_us == 0 {
+        return delay_us;
+    }
+    let factor = 1.0 + rng.gen_range(-jitter_pct..=jitter_pct) / 100.0;
+    (delay_us as f64 * factor).round() as i64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,9 +1,16 @@
-use crate::{block::Block, simulator::Env};
+use crate::{
+    block::Block,
+    sampling::sample_exponential,
+    simulator::Env,
+    types::{RetargetClamp, RetargetEvent},
+};
 use primitive_types::U256;
 use rand::rngs::StdRng;
-use rand_distr::{Distribution, Exp};
+use std::cmp::Ordering;
 
-use super::{Difficulty, GenesisDifficultyMode, Protocol};
+use super::{
+    Difficulty, DifficultyUpdate, GenesisDifficultyMode, Protocol, difficulty::mining_time_us,
+};
 
 /// Bitcoin Protocol
 /// expected generation time = expected required hash / hashrate
@@ -28,6 +35,8 @@ pub struct BitcoinDifficulty {
 impl BitcoinDifficulty {
     const MIN: f64 = 1.0;
     const NBITS_DIFFICULTY_1: u32 = 0x1d00ffff;
+    /// 難易度 1 あたりの期待ハッシュ数（`2^32`）。採掘時間・chainwork・genesis 難易度の逆算はすべてこれを使う。
+    pub const HASHES_PER_DIFFICULTY: f64 = 4_294_967_296.0;
 
     pub fn new(value: f64) -> Self {
         assert!(value.is_finite(), "difficulty became non-finite ({value}).");
@@ -58,7 +67,7 @@ impl BitcoinDifficulty {
 
     /// 採掘モデルと整合する整数 chainwork 増分（期待ハッシュ数 `D·2^32` を `U256` に載せる）。
     pub fn chain_work_increment(self) -> U256 {
-        let eh = (self.value * 2f64.powi(32)).max(1.0);
+        let eh = (self.value * Self::HASHES_PER_DIFFICULTY).max(1.0);
         if eh >= u128::MAX as f64 {
             U256::from(u128::MAX)
         } else {
@@ -66,14 +75,16 @@ impl BitcoinDifficulty {
         }
     }
 
+    /// ハッシュレート `hashrate` での期待生成時間（ms）= `D·2^32 / hashrate`。
+    pub fn expected_generation_time_ms(self, hashrate: i64) -> f64 {
+        let expected_hashes = self.value * Self::HASHES_PER_DIFFICULTY;
+        expected_hashes / hashrate as f64
+    }
+
     /// 次の採掘までの待ち時間（**マイクロ秒**）。
     pub fn calculate_mining_time(self, rng: &mut StdRng, hashrate: i64) -> i64 {
-        let exp_dist: Exp<f64> = Exp::new(1.0).unwrap();
-        let expected_hashes = self.value * 2f64.powi(32);
-        let expected_generation_time_ms = expected_hashes / hashrate as f64;
-        let dt_ms = exp_dist.sample(rng) * expected_generation_time_ms;
-        let dt_us = (dt_ms * 1000.0).round() as i64;
-        dt_us.max(1)
+        let dt_ms = sample_exponential(rng, self.expected_generation_time_ms(hashrate));
+        mining_time_us(dt_ms)
     }
 }
 
@@ -82,21 +93,31 @@ impl Protocol for BitcoinProtocol {
         "Bitcoin"
     }
 
+    fn target_block_time_ms(&self) -> i64 {
+        10 * 60 * 1000
+    }
+
     fn default_difficulty(&self, total_hashrate: i64) -> Difficulty {
         match self.genesis_difficulty_mode {
             GenesisDifficultyMode::Inferred => {
                 // Expected time = difficulty * 2^32 / hashrate.
                 // Solve for difficulty so that the network target is 10 minutes per block.
-                const TARGET_BLOCK_TIME_MS: f64 = 10.0 * 60.0 * 1000.0;
                 let safe_hashrate = total_hashrate.max(1) as f64;
-                let difficulty = TARGET_BLOCK_TIME_MS * safe_hashrate / 2f64.powi(32);
+                let difficulty = self.target_block_time_ms() as f64 * safe_hashrate
+                    / BitcoinDifficulty::HASHES_PER_DIFFICULTY;
                 Difficulty::Bitcoin(BitcoinDifficulty::new(difficulty))
             }
             GenesisDifficultyMode::Fixed => Difficulty::Bitcoin(BitcoinDifficulty::new(1.0)),
         }
     }
 
-    fn calculate_difficulty(&self, parent_block: &Block, env: &Env) -> Difficulty {
+    /// 累積仕事量（`nChainWork`）が最大のチェーン。高さではなく仕事量で比べるので、
+    /// retarget をまたぐ分岐では短くても重いチェーンが選ばれる。
+    fn prefer(&self, a: &Block, b: &Block) -> Ordering {
+        a.cumulative_chain_work().cmp(&b.cumulative_chain_work())
+    }
+
+    fn calculate_difficulty(&self, parent_block: &Block, env: &Env) -> DifficultyUpdate {
         const BTC_DAA_EPOCH: i64 = 2016;
         /// BTCの目標生成時間 (ms)
         const TWO_WEEKS_MS: i64 = 14 * 24 * 60 * 60 * 1000;
@@ -107,43 +128,58 @@ impl Protocol for BitcoinProtocol {
 
         let new_height = parent_height + 1;
 
-        let next_difficulty = if new_height % BTC_DAA_EPOCH == 0 && new_height >= BTC_DAA_EPOCH {
-            let first_block_in_epoch = {
-                let mut block_id = parent_block_id;
-                let mut block = env.blockchain.get_block(block_id).unwrap();
-                for _ in 0..(BTC_DAA_EPOCH - 1) {
-                    block_id = block.prev_block_id().unwrap();
-                    block = env.blockchain.get_block(block_id).unwrap();
-                }
-                block
-            };
-            // Bitcoin-style retarget:
-            //   actual_timespan = last_timestamp - first_timestamp
-            //   actual_timespan is clamped to [expected/4, expected*4]
-            //   new_difficulty = old_difficulty * expected / actual_timespan
-            //
-            // Note: we intentionally do NOT take abs(). If timestamps go backwards
-            // (actual_timespan <= 0), clamping will pin it to the minimum timespan.
-            let mut actual_timespan_ms = parent_block.time() - first_block_in_epoch.time();
-
-            // TODO: remove this debug log
-            let apparent_epoch_time_in_week: f64 =
-                actual_timespan_ms as f64 / (7 * 24 * 60 * 60 * 1000) as f64;
-            log::debug!("見かけでかかった時間: {:.2}週", apparent_epoch_time_in_week);
-
-            // Bitcoinのretargetは常に timespan を [expected/4, expected*4] にclampする
-            let min_timespan_ms = TWO_WEEKS_MS / 4;
-            let max_timespan_ms = TWO_WEEKS_MS * 4;
-            if actual_timespan_ms < min_timespan_ms {
-                actual_timespan_ms = min_timespan_ms;
-            } else if actual_timespan_ms > max_timespan_ms {
-                actual_timespan_ms = max_timespan_ms;
-            }
+        if new_height % BTC_DAA_EPOCH != 0 || new_height < BTC_DAA_EPOCH {
+            return DifficultyUpdate::unchanged(parent_block.difficulty());
+        }
 
-            parent_difficulty * (TWO_WEEKS_MS as f64) / (actual_timespan_ms as f64)
-        } else {
-            parent_difficulty
+        let first_block_in_epoch = {
+            let mut block_id = parent_block_id;
+            let mut block = env.blockchain.get_block(block_id).unwrap();
+            for _ in 0..(BTC_DAA_EPOCH - 1) {
+                block_id = block.prev_block_id().unwrap();
+                block = env.blockchain.get_block(block_id).unwrap();
+            }
+            block
         };
-        Difficulty::Bitcoin(BitcoinDifficulty::new(next_difficulty))
+        // Bitcoin-style retarget:
+        //   actual_timespan = last_timestamp - first_timestamp
+        //   actual_timespan is clamped to [expected/4, expected*4]
+        //   new_difficulty = old_difficulty * expected / actual_timespan
+        //
+        // Note: we intentionally do NOT take abs(). If timestamps go backwards
+        // (actual_timespan <= 0), clamping will pin it to the minimum timespan.
+        let measured_timespan_ms = parent_block.timestamp() - first_block_in_epoch.timestamp();
+        let mut actual_timespan_ms = measured_timespan_ms;
+
+        // Bitcoinのretargetは常に timespan を [expected/4, expected*4] にclampする
+        let min_timespan_ms = TWO_WEEKS_MS / 4;
+        let max_timespan_ms = TWO_WEEKS_MS * 4;
+        let mut clamp = RetargetClamp::None;
+        if actual_timespan_ms < min_timespan_ms {
+            actual_timespan_ms = min_timespan_ms;
+            clamp = RetargetClamp::MinTimespan;
+        } else if actual_timespan_ms > max_timespan_ms {
+            actual_timespan_ms = max_timespan_ms;
+            clamp = RetargetClamp::MaxTimespan;
+        }
+
+        let next_difficulty =
+            parent_difficulty * (TWO_WEEKS_MS as f64) / (actual_timespan_ms as f64);
+        DifficultyUpdate {
+            difficulty: Difficulty::Bitcoin(BitcoinDifficulty::new(next_difficulty)),
+            retarget: Some(RetargetEvent {
+                height: new_height,
+                // エポックの先頭から親までの間隔は BTC_DAA_EPOCH - 1 個。
+                avg_block_time: measured_timespan_ms as f64 / (BTC_DAA_EPOCH - 1) as f64,
+                clamp,
+                old_difficulty: parent_difficulty,
+                new_difficulty: next_difficulty,
+            }),
+        }
+    }
+
+    /// 難易度が親から再計算した値と一致するブロックだけを受け入れる。
+    fn validate_block(&self, block: &Block, parent: &Block, env: &Env) -> bool {
+        block.difficulty() == self.calculate_difficulty(parent, env).difficulty
     }
 }
@@ -2,7 +2,30 @@ use primitive_types::U256;
 use rand::rngs::StdRng;
 
 use super::{BitcoinDifficulty, EthereumDifficulty};
+use crate::sampling::sample_gamma;
 
+/// 1 回の採掘待ち時間の上限（μs）。現在時刻に足してもイベント時刻が `i64` に収まるよう余裕を残す。
+pub const MAX_MINING_TIME_US: i64 = i64::MAX / 4;
+
+/// 待ち時間（ms, `f64`）を μs の `i64` に変換する。最低 1μs、[`MAX_MINING_TIME_US`] で頭打ち。
+/// 難易度に比べてハッシュレートが極端に小さいと `i64` に収まらないので、その場合は警告して丸める。
+pub(super) fn mining_time_us(dt_ms: f64) -> i64 {
+    let dt_us = (dt_ms * 1000.0).round();
+    if dt_us.is_nan() || dt_us >= MAX_MINING_TIME_US as f64 {
+        log::warn!(
+            "mining time {dt_ms:e} ms does not fit in the event clock; clamped to {MAX_MINING_TIME_US} us"
+        );
+        return MAX_MINING_TIME_US;
+    }
+    (dt_us as i64).max(1)
+}
+
+/// ブロックの難易度。表現はプロトコルごとに固定し、難易度調整はその表現のまま計算する。
+///
+/// - Bitcoin: `f64`（実装の nBits と同じく相対値で、丸めは調整の比率計算だけ）
+/// - Ethereum: `U256` の整数。調整は Yellow Paper どおり整数演算で行うので、長い実行でもずれない。
+///
+/// `f64` への変換（[`Self::as_f64`]）は CSV やログなど出力用に限る。
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Difficulty {
     Bitcoin(BitcoinDifficulty),
@@ -18,6 +41,32 @@ impl Difficulty {
         }
     }
 
+    /// [`Self::calculate_mining_time`] を、平均はそのままに形状 `shape` のガンマ分布から引く。
+    /// `shape` = 1 なら同じ指数分布（乱数列も同じ）で、大きいほどばらつきが小さい。
+    pub fn calculate_mining_time_with_shape(
+        self,
+        rng: &mut StdRng,
+        hashrate: i64,
+        shape: f64,
+    ) -> i64 {
+        if shape == 1.0 {
+            return self.calculate_mining_time(rng, hashrate);
+        }
+        mining_time_us(sample_gamma(
+            rng,
+            shape,
+            self.expected_generation_time_ms(hashrate),
+        ))
+    }
+
+    /// ハッシュレート `hashrate` で 1 ブロック見つけるまでの期待時間（ms）。
+    pub fn expected_generation_time_ms(self, hashrate: i64) -> f64 {
+        match self {
+            Difficulty::Bitcoin(d) => d.expected_generation_time_ms(hashrate),
+            Difficulty::Ethereum(d) => d.expected_generation_time_ms(hashrate),
+        }
+    }
+
     /// Conversion intended for output boundaries (CSV/logs, etc.).
     /// Keep protocol calculation logic typed as `Difficulty`.
     pub fn as_f64(self) -> f64 {
@@ -27,6 +76,24 @@ impl Difficulty {
         }
     }
 
+    /// 難易度を `factor` 倍する（別の目標で採掘するノードグループ用）。各プロトコルの上下限で頭打ち。
+    pub fn scaled(self, factor: f64) -> Difficulty {
+        match self {
+            Difficulty::Bitcoin(d) => {
+                Difficulty::Bitcoin(BitcoinDifficulty::new(d.as_f64() * factor))
+            }
+            Difficulty::Ethereum(d) => {
+                let scaled = d.as_f64() * factor;
+                let value = if scaled >= u128::MAX as f64 {
+                    U256::from(u128::MAX)
+                } else {
+                    U256::from(scaled as u128)
+                };
+                Difficulty::Ethereum(EthereumDifficulty::new(value))
+            }
+        }
+    }
+
     /// フォーク選択用の整数 chainwork 増分（累積は `U256` で保持）。
     pub fn chain_work_increment(self) -> U256 {
         match self {
@@ -1,12 +1,19 @@
-use crate::{block::Block, simulator::Env};
+use crate::{
+    block::Block,
+    sampling::sample_exponential,
+    simulator::Env,
+    types::{RetargetClamp, RetargetEvent},
+};
 use primitive_types::U256;
 use rand::rngs::StdRng;
-use rand_distr::{Distribution, Exp};
+use std::cmp::Ordering;
 
-use super::{Difficulty, GenesisDifficultyMode, Protocol};
+use super::{
+    Difficulty, DifficultyUpdate, GenesisDifficultyMode, Protocol, UncleRewardRule,
+    difficulty::mining_time_us,
+};
 
 /// Ethereumプロトコルの実装
-///  TODO: implement total difficulty (mainchain choosing)
 pub(super) struct EthereumProtocol {
     genesis_difficulty_mode: GenesisDifficultyMode,
 }
@@ -26,6 +33,8 @@ pub struct EthereumDifficulty {
 
 impl EthereumDifficulty {
     const MIN: U256 = U256([1, 0, 0, 0]);
+    /// 難易度 1 あたりの期待ハッシュ数（難易度そのものが期待ハッシュ数）。
+    pub const HASHES_PER_DIFFICULTY: f64 = 1.0;
 
     pub fn new(value: U256) -> Self {
         let value = value.clamp(Self::MIN, Self::max());
@@ -65,13 +74,15 @@ impl EthereumDifficulty {
         self.value
     }
 
+    /// ハッシュレート `hashrate` での期待生成時間（ms）= `D / hashrate`。
+    pub fn expected_generation_time_ms(self, hashrate: i64) -> f64 {
+        self.as_f64() * Self::HASHES_PER_DIFFICULTY / hashrate as f64
+    }
+
     /// 次の採掘までの待ち時間（**マイクロ秒**）。
     pub fn calculate_mining_time(self, rng: &mut StdRng, hashrate: i64) -> i64 {
-        let exp_dist: Exp<f64> = Exp::new(1.0).unwrap();
-        let expected_generation_time_ms = self.as_f64() / hashrate as f64;
-        let dt_ms = exp_dist.sample(rng) * expected_generation_time_ms;
-        let dt_us = (dt_ms * 1000.0).round() as i64;
-        dt_us.max(1)
+        let dt_ms = sample_exponential(rng, self.expected_generation_time_ms(hashrate));
+        mining_time_us(dt_ms)
     }
 }
 
@@ -80,15 +91,18 @@ impl Protocol for EthereumProtocol {
         "Ethereum"
     }
 
+    fn target_block_time_ms(&self) -> i64 {
+        12_000
+    }
+
     fn default_difficulty(&self, total_hashrate: i64) -> Difficulty {
         match self.genesis_difficulty_mode {
             GenesisDifficultyMode::Inferred => {
-                // Expected time = difficulty / hashrate in this simulator's Eth model.
+                // Expected time = difficulty * HASHES_PER_DIFFICULTY (= 1) / hashrate.
                 // Solve for difficulty so that the network target is 12 seconds per block.
-                const TARGET_BLOCK_TIME_MS: i64 = 12_000;
                 let safe_hashrate = total_hashrate.max(1);
-                let difficulty =
-                    U256::from(safe_hashrate as u64) * U256::from(TARGET_BLOCK_TIME_MS as u64);
+                let difficulty = U256::from(safe_hashrate as u64)
+                    * U256::from(self.target_block_time_ms() as u64);
                 Difficulty::Ethereum(EthereumDifficulty::new(difficulty))
             }
             GenesisDifficultyMode::Fixed => {
@@ -97,17 +111,29 @@ impl Protocol for EthereumProtocol {
         }
     }
 
-    fn calculate_difficulty(&self, parent_block: &Block, env: &Env) -> Difficulty {
+    /// total difficulty（ジェネシスからの難易度の総和）が最大のチェーン。
+    fn prefer(&self, a: &Block, b: &Block) -> Ordering {
+        // Ethereum の chainwork 増分は難易度そのものなので、累積 chainwork = total difficulty。
+        a.cumulative_chain_work().cmp(&b.cumulative_chain_work())
+    }
+
+    fn uncle_reward_rule(&self) -> Option<UncleRewardRule> {
+        Some(UncleRewardRule::ETHEREUM)
+    }
+
+    fn calculate_difficulty(&self, parent_block: &Block, env: &Env) -> DifficultyUpdate {
         if parent_block.height() <= 1 {
-            return self.default_difficulty(env.total_hashrate);
+            return DifficultyUpdate::unchanged(self.default_difficulty(env.total_hashrate));
         }
         let grand_parent_block = env
             .blockchain
             .get_block(parent_block.prev_block_id().unwrap())
             .unwrap();
 
-        let time_diff = (parent_block.time() - grand_parent_block.time()) / 1_000; // ms to s
-        let adjustment_factor = (1 - (time_diff / 10)).max(-99);
+        let time_diff_ms = parent_block.timestamp() - grand_parent_block.timestamp();
+        let time_diff = time_diff_ms / 1_000; // ms to s
+        let raw_adjustment_factor = 1 - (time_diff / 10);
+        let adjustment_factor = raw_adjustment_factor.max(-99);
         let parent_difficulty = match parent_block.difficulty() {
             Difficulty::Ethereum(d) => d.as_u256(),
             Difficulty::Bitcoin(_) => unreachable!("difficulty/protocol mismatch"),
@@ -146,7 +172,22 @@ impl Protocol for EthereumProtocol {
             );
         }
         */
-        Difficulty::Ethereum(EthereumDifficulty::new(next_difficulty))
+        let difficulty = Difficulty::Ethereum(EthereumDifficulty::new(next_difficulty));
+        DifficultyUpdate {
+            difficulty,
+            // Ethereum はブロックごとに調整する（間隔は親と祖父の 1 区間）。
+            retarget: Some(RetargetEvent {
+                height: parent_block.height() + 1,
+                avg_block_time: time_diff_ms as f64,
+                clamp: if raw_adjustment_factor < -99 {
+                    RetargetClamp::MaxTimespan
+                } else {
+                    RetargetClamp::None
+                },
+                old_difficulty: parent_block.difficulty().as_f64(),
+                new_difficulty: difficulty.as_f64(),
+            }),
+        }
     }
 }
 
@@ -0,0 +1,58 @@
+use crate::{block::Block, simulator::Env};
+use std::cmp::Ordering;
+
+use super::{BitcoinDifficulty, Difficulty, DifficultyUpdate, GenesisDifficultyMode, Protocol};
+
+/// Kaspa 風の blockDAG。ノードは自分の tip 以外に知っている tip もマージ親として参照し、
+/// ブロックの順序と青・赤は GHOSTDAG（[`GhostDagOrdering`](crate::ghostdag::GhostDagOrdering)）で決める。
+///
+/// 初期版なので難易度調整は行わず、ノードが採掘の土台にする selected parent は
+/// chainwork 最大の（単一親の）チェーンで選ぶ。
+pub(super) struct GhostDagProtocol {
+    genesis_difficulty_mode: GenesisDifficultyMode,
+}
+
+impl GhostDagProtocol {
+    /// Kaspa の k（同時に生成されうる honest ブロック数の上限）。
+    const K: usize = 18;
+
+    pub fn new(genesis_difficulty_mode: GenesisDifficultyMode) -> Self {
+        Self {
+            genesis_difficulty_mode,
+        }
+    }
+}
+
+impl Protocol for GhostDagProtocol {
+    fn name(&self) -> &'static str {
+        "GHOSTDAG"
+    }
+
+    fn target_block_time_ms(&self) -> i64 {
+        1000
+    }
+
+    fn default_difficulty(&self, total_hashrate: i64) -> Difficulty {
+        match self.genesis_difficulty_mode {
+            GenesisDifficultyMode::Inferred => {
+                let safe_hashrate = total_hashrate.max(1) as f64;
+                let difficulty = self.target_block_time_ms() as f64 * safe_hashrate
+                    / BitcoinDifficulty::HASHES_PER_DIFFICULTY;
+                Difficulty::Bitcoin(BitcoinDifficulty::new(difficulty))
+            }
+            GenesisDifficultyMode::Fixed => Difficulty::Bitcoin(BitcoinDifficulty::new(1.0)),
+        }
+    }
+
+    fn prefer(&self, a: &Block, b: &Block) -> Ordering {
+        a.cumulative_chain_work().cmp(&b.cumulative_chain_work())
+    }
+
+    fn calculate_difficulty(&self, parent_block: &Block, _env: &Env) -> DifficultyUpdate {
+        DifficultyUpdate::unchanged(parent_block.difficulty())
+    }
+
+    fn ghostdag_k(&self) -> Option<usize> {
+        Some(Self::K)
+    }
+}
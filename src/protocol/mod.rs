@@ -1,15 +1,18 @@
-use crate::{block::Block, simulator::Env};
+use crate::{block::Block, simulator::Env, types::RetargetEvent};
 use clap::ValueEnum;
+use std::cmp::Ordering;
 
 mod bitcoin;
 mod difficulty;
 mod ethereum;
+mod ghostdag;
 
 pub use bitcoin::BitcoinDifficulty;
 use bitcoin::BitcoinProtocol;
-pub use difficulty::Difficulty;
+pub use difficulty::{Difficulty, MAX_MINING_TIME_US};
 pub use ethereum::EthereumDifficulty;
 use ethereum::EthereumProtocol;
+use ghostdag::GhostDagProtocol;
 
 #[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum GenesisDifficultyMode {
@@ -23,8 +26,81 @@ pub enum GenesisDifficultyMode {
 
 pub trait Protocol: Send + Sync {
     fn name(&self) -> &'static str;
+    /// 難易度調整が目標とするブロック生成間隔（ms）。
+    fn target_block_time_ms(&self) -> i64;
+    /// 難易度 `difficulty` のとき、ネットワーク全体（`total_hashrate`）での期待ブロック間隔（ms）。
+    fn expected_block_interval(&self, total_hashrate: i64, difficulty: Difficulty) -> i64 {
+        difficulty
+            .expected_generation_time_ms(total_hashrate.max(1))
+            .round() as i64
+    }
     fn default_difficulty(&self, total_hashrate: i64) -> Difficulty;
-    fn calculate_difficulty(&self, parent_block: &Block, env: &Env) -> Difficulty;
+    /// フォーク選択: tip `a` が `b` より好ましければ `Greater`。`Equal` のときは tie-breaking rule に任せる。
+    fn prefer(&self, a: &Block, b: &Block) -> Ordering;
+    /// `parent_block` の子に使う難易度。調整を行ったときはその記録も返す。
+    ///
+    /// 時刻は `parent_block` とその祖先のタイムスタンプだけから取り、シミュレーションの現在時刻や
+    /// 他の分岐の tip は使わない。同じ高さでも分岐ごとに難易度が変わりうる。
+    fn calculate_difficulty(&self, parent_block: &Block, env: &Env) -> DifficultyUpdate;
+    /// `parent` の子として `block` を受け入れてよいか。ノードが採用する前に呼ばれ、
+    /// `false` のブロックは捨てられる。既定では何も検査しない。
+    fn validate_block(&self, _block: &Block, _parent: &Block, _env: &Env) -> bool {
+        true
+    }
+    /// uncle（GHOST）報酬の規則。`None` ならメインチェーンのブロックだけが報酬を得る。
+    fn uncle_reward_rule(&self) -> Option<UncleRewardRule> {
+        None
+    }
+    /// blockDAG（GHOSTDAG）のプロトコルなら k-cluster の k。`Some` のときノードは知っている他の tip も
+    /// マージ親として参照する。既定は単一親のチェーン（`None`）。
+    fn ghostdag_k(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// GHOST の uncle/nephew 報酬の規則。報酬はブロック報酬 1 を単位とする。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UncleRewardRule {
+    /// uncle として取り込める最大の深さ（nephew の高さ − uncle の高さ）。
+    pub max_depth: i64,
+    /// 1 ブロックが取り込める uncle の最大数。
+    pub max_uncles_per_block: usize,
+}
+
+impl UncleRewardRule {
+    /// Ethereum（PoW 期）の規則: 深さ 6 まで、1 ブロックに 2 個まで。
+    pub const ETHEREUM: Self = Self {
+        max_depth: 6,
+        max_uncles_per_block: 2,
+    };
+
+    /// 深さ `depth` で取り込まれた uncle の報酬 `(8 - depth) / 8`。
+    pub fn uncle_reward(self, depth: i64) -> f64 {
+        (8 - depth) as f64 / 8.0
+    }
+
+    /// uncle を 1 つ取り込んだ nephew の報酬 `1 / 32`。
+    pub fn nephew_reward(self) -> f64 {
+        1.0 / 32.0
+    }
+}
+
+/// [`Protocol::calculate_difficulty`] の結果。
+#[derive(Debug, Clone)]
+pub struct DifficultyUpdate {
+    pub difficulty: Difficulty,
+    /// 難易度調整を行ったときの記録（調整しないブロックでは `None`）。
+    pub retarget: Option<RetargetEvent>,
+}
+
+impl DifficultyUpdate {
+    /// 調整を行わない（`difficulty` をそのまま使う）。
+    pub fn unchanged(difficulty: Difficulty) -> Self {
+        Self {
+            difficulty,
+            retarget: None,
+        }
+    }
 }
 
 /// プロトコル列挙型（CLI用）
@@ -33,6 +109,8 @@ pub enum ProtocolType {
     #[default]
     Bitcoin,
     Ethereum,
+    /// Kaspa 風の blockDAG（GHOSTDAG で順序付け）
+    GhostDag,
 }
 
 impl ProtocolType {
@@ -40,6 +118,133 @@ impl ProtocolType {
         match self {
             ProtocolType::Bitcoin => Box::new(BitcoinProtocol::new(genesis_difficulty_mode)),
             ProtocolType::Ethereum => Box::new(EthereumProtocol::new(genesis_difficulty_mode)),
+            ProtocolType::GhostDag => Box::new(GhostDagProtocol::new(genesis_difficulty_mode)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{Node, NodeId};
+    use crate::propagation_delay::PropagationDelayMode;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    // GHOSTDAG の 1 秒間隔でも genesis 難易度が下限の 1 に張り付かない大きさ。
+    const HASHRATE: i64 = 100_000_000;
+
+    /// `protocol` で、直前のブロックから常に `interval_ms` 後に採掘されるブロックを `blocks` 個積む。
+    /// 各ブロックの難易度は親から `calculate_difficulty` で決め、記録された調整を順に返す。
+    fn mine_at_fixed_interval(
+        protocol: Box<dyn Protocol>,
+        interval_ms: i64,
+        blocks: usize,
+    ) -> Vec<RetargetEvent> {
+        let mut env = Env::new(
+            &[Node::new(NodeId::new(0), HASHRATE)],
+            0,
+            PropagationDelayMode::Uniform,
+            protocol,
+        );
+        let mut tip = env
+            .blockchain
+            .get_block(crate::block::GENESIS_BLOCK_ID)
+            .unwrap()
+            .clone();
+        let mut retargets = Vec::new();
+        for _ in 0..blocks {
+            let update = env.protocol.calculate_difficulty(&tip, &env);
+            retargets.extend(update.retarget);
+            let block = Block::new(
+                tip.height() + 1,
+                Some(tip.id()),
+                NodeId::new(0),
+                tip.timestamp() + interval_ms,
+                0,
+                env.blockchain.next_block_id(),
+                update.difficulty,
+                tip.cumulative_chain_work()
+                    .saturating_add(update.difficulty.chain_work_increment()),
+                interval_ms as f64,
+                true,
+            );
+            env.blockchain.add_block(block.clone());
+            tip = block;
+        }
+        retargets
+    }
+
+    /// `new_protocol` が作るプロトコルが満たすべき性質をまとめて確かめる。
+    ///
+    /// - 推定した genesis 難易度では、総ハッシュレートでの期待生成間隔が目標どおりになる。
+    /// - 生成時間はハッシュレートに反比例する（期待値も、乱数で引いた平均も）。
+    /// - 目標より遅いブロックが続けば難易度は下がり、速ければ上がる。`adjusts_difficulty` が false なら変えない。
+    fn assert_protocol_conformance(
+        new_protocol: impl Fn() -> Box<dyn Protocol>,
+        adjusts_difficulty: bool,
+    ) {
+        let protocol = new_protocol();
+        let name = protocol.name();
+        let target_ms = protocol.target_block_time_ms();
+        let difficulty = protocol.default_difficulty(HASHRATE);
+        assert_eq!(
+            protocol.expected_block_interval(HASHRATE, difficulty),
+            target_ms,
+            "{name}"
+        );
+
+        let single = difficulty.expected_generation_time_ms(HASHRATE);
+        let double = difficulty.expected_generation_time_ms(2 * HASHRATE);
+        assert!(
+            (single / double - 2.0).abs() < 1e-9,
+            "{name}: {single} vs {double}"
+        );
+        let mean_mining_time_us = |hashrate: i64| {
+            let mut rng = StdRng::seed_from_u64(0);
+            let n = 20_000;
+            (0..n)
+                .map(|_| difficulty.calculate_mining_time(&mut rng, hashrate) as f64)
+                .sum::<f64>()
+                / n as f64
+        };
+        let ratio = mean_mining_time_us(HASHRATE) / mean_mining_time_us(2 * HASHRATE);
+        assert!((ratio - 2.0).abs() < 1e-6, "{name}: sampled ratio {ratio}");
+        assert!(
+            (mean_mining_time_us(HASHRATE) / 1000.0 - target_ms as f64).abs()
+                < target_ms as f64 * 0.03,
+            "{name}"
+        );
+
+        // Bitcoin の 1 エポック（2016 ブロック）を超える長さ。
+        let blocks = 2_100;
+        for (interval_ms, slower) in [(2 * target_ms, true), (target_ms / 2, false)] {
+            let retargets = mine_at_fixed_interval(new_protocol(), interval_ms, blocks);
+            for retarget in &retargets {
+                let moved = if slower {
+                    retarget.new_difficulty < retarget.old_difficulty
+                } else {
+                    retarget.new_difficulty > retarget.old_difficulty
+                };
+                assert!(moved, "{name}: interval {interval_ms} ms: {retarget:?}");
+            }
+            assert_eq!(
+                !retargets.is_empty(),
+                adjusts_difficulty,
+                "{name}: {} retargets in {blocks} blocks",
+                retargets.len()
+            );
+        }
+    }
+
+    #[test]
+    fn every_builtin_protocol_conforms() {
+        for protocol_type in ProtocolType::value_variants() {
+            // GHOSTDAG の初期版は難易度調整を行わない。
+            let adjusts_difficulty = protocol_type != &ProtocolType::GhostDag;
+            assert_protocol_conformance(
+                || protocol_type.to_protocol(GenesisDifficultyMode::Inferred),
+                adjusts_difficulty,
+            );
         }
     }
 }
@@ -0,0 +1,168 @@
+use rand::Rng;
+use rand_distr::{Distribution, Exp1, Gamma, Poisson};
+
+/// 平均 `mean` の指数分布から 1 つ引く。ブロック生成間隔など、到着間隔の乱数はすべてここを通す。
+/// 標準指数分布（平均 1）のサンプルを `mean` 倍するので、`mean` の値に依らず乱数の消費は 1 回。
+pub fn sample_exponential<R: Rng + ?Sized>(rng: &mut R, mean: f64) -> f64 {
+    let unit: f64 = rng.sample(Exp1);
+    unit * mean
+}
+
+/// 形状 `shape`・平均 `mean` のガンマ分布（`shape` が整数なら Erlang 分布）から 1 つ引く。分散は `mean² / shape`。
+/// `shape` = 1 は指数分布で、[`sample_exponential`] と同じ乱数列になる。`mean` が 0 以下なら 0。
+pub fn sample_gamma<R: Rng + ?Sized>(rng: &mut R, shape: f64, mean: f64) -> f64 {
+    if shape == 1.0 {
+        return sample_exponential(rng, mean);
+    }
+    if mean <= 0.0 {
+        return 0.0;
+    }
+    Gamma::new(shape, mean / shape)
+        .expect("shape and mean are positive and finite")
+        .sample(rng)
+}
+
+/// 平均 `mean` のポアソン分布から 1 つ引く。`mean` が 0 以下なら乱数を消費せずに 0。
+pub fn sample_poisson<R: Rng + ?Sized>(rng: &mut R, mean: f64) -> u64 {
+    if mean <= 0.0 {
+        return 0;
+    }
+    Poisson::new(mean)
+        .expect("mean is positive and finite")
+        .sample(rng) as u64
+}
+
+/// SplitMix64 の増分（2^64 / 黄金比）。
+pub(crate) const SPLITMIX64_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// SplitMix64 の出力関数。入力の 1 ビットの違いが出力のほぼ半分のビットに広がる。
+pub(crate) fn splitmix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// 一括実行の `run_index` 番目（0 始まり）に使う seed を `base_seed` から導く。
+///
+/// seed を 1 ずつずらすと乱数列が似通うことがあるので、`base_seed` を混ぜた状態から始まる
+/// SplitMix64 の列の `run_index` 番目の値を使う。同じ `(base_seed, run_index)` からは常に同じ seed になる。
+pub fn derive_run_seed(base_seed: u64, run_index: u64) -> u64 {
+    let state = splitmix64(base_seed);
+    splitmix64(state.wrapping_add(run_index.wrapping_add(1).wrapping_mul(SPLITMIX64_GAMMA)))
+}
+
+/// 自由度 `df` のカイ二乗分布で `x` 以上になる確率。Wilson–Hilferty の正規近似で求める
+/// （df が小さいと裾で数 % ずれるが、有意かどうかの判定には十分）。
+pub(crate) fn chi_square_survival(x: f64, df: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let v = 2.0 / (9.0 * df);
+    let z = ((x / df).cbrt() - (1.0 - v)) / v.sqrt();
+    0.5 * erfc(z / std::f64::consts::SQRT_2)
+}
+
+/// 相補誤差関数（Numerical Recipes の Chebyshev 近似、相対誤差 1.2e-7 以下）。
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x.abs());
+    let poly = -x * x - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let ans = t * poly.exp();
+    if x >= 0.0 { ans } else { 2.0 - ans }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn sample_mean_converges_to_requested_mean() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for mean in [0.5, 12_000.0, 600_000.0] {
+            let n = 200_000;
+            let sum: f64 = (0..n).map(|_| sample_exponential(&mut rng, mean)).sum();
+            let observed = sum / n as f64;
+            assert!(
+                (observed - mean).abs() < mean * 0.01,
+                "mean {mean}: observed {observed}"
+            );
+        }
+        assert_eq!(sample_exponential(&mut rng, 0.0), 0.0);
+    }
+
+    #[test]
+    fn gamma_keeps_the_mean_and_divides_the_variance_by_shape() {
+        let mean = 600_000.0;
+        for shape in [1.0, 4.0, 16.0] {
+            let mut rng = StdRng::seed_from_u64(1);
+            let n = 100_000;
+            let samples: Vec<f64> = (0..n)
+                .map(|_| sample_gamma(&mut rng, shape, mean))
+                .collect();
+            let observed_mean = samples.iter().sum::<f64>() / n as f64;
+            let variance = samples
+                .iter()
+                .map(|x| (x - observed_mean).powi(2))
+                .sum::<f64>()
+                / n as f64;
+            assert!(
+                (observed_mean - mean).abs() < mean * 0.01,
+                "shape {shape}: mean {observed_mean}"
+            );
+            let expected_variance = mean * mean / shape;
+            assert!(
+                (variance - expected_variance).abs() < expected_variance * 0.05,
+                "shape {shape}: variance {variance}"
+            );
+        }
+        // shape = 1 は指数分布と同じ乱数列。
+        let mut a = StdRng::seed_from_u64(2);
+        let mut b = StdRng::seed_from_u64(2);
+        assert_eq!(
+            sample_gamma(&mut a, 1.0, 10.0),
+            sample_exponential(&mut b, 10.0)
+        );
+    }
+
+    #[test]
+    fn derived_run_seeds_are_reproducible_and_spread_over_all_bits() {
+        let seeds: Vec<u64> = (0..4096).map(|i| derive_run_seed(7, i)).collect();
+        assert_eq!(derive_run_seed(7, 123), seeds[123]);
+        assert_ne!(derive_run_seed(8, 123), seeds[123]);
+        let distinct: std::collections::HashSet<_> = seeds.iter().collect();
+        assert_eq!(distinct.len(), seeds.len());
+
+        // 隣り合う run の seed は平均して半分（32）のビットが違う。
+        let flipped: u32 = seeds.windows(2).map(|w| (w[0] ^ w[1]).count_ones()).sum();
+        let mean_flipped = flipped as f64 / (seeds.len() - 1) as f64;
+        assert!(
+            (mean_flipped - 32.0).abs() < 1.0,
+            "mean flipped bits {mean_flipped}"
+        );
+        // 上位 4 ビットで 16 個の箱に分けると、どの箱にもおよそ 256 個ずつ入る。
+        let mut buckets = [0usize; 16];
+        for seed in &seeds {
+            buckets[(seed >> 60) as usize] += 1;
+        }
+        assert!(
+            buckets.iter().all(|&n| (192..=320).contains(&n)),
+            "buckets {buckets:?}"
+        );
+    }
+
+    #[test]
+    fn chi_square_survival_matches_table_values() {
+        // 5% 点: df=1 で 3.841、df=10 で 18.307。
+        assert!((chi_square_survival(3.841, 1.0) - 0.05).abs() < 0.005);
+        assert!((chi_square_survival(18.307, 10.0) - 0.05).abs() < 0.002);
+        assert_eq!(chi_square_survival(0.0, 3.0), 1.0);
+        assert!(chi_square_survival(100.0, 3.0) < 1e-6);
+    }
+}
@@ -1,20 +1,102 @@
-use std::collections::HashMap;
-
+use crate::arrival::DeterministicArrival;
 use crate::block::{Block, GENESIS_BLOCK_ID};
-use crate::blockchain::{BlockId, Blockchain};
+use crate::blockchain::{BlockId, Blockchain, UncleRewards};
 use crate::event::{Event, EventType};
-use crate::event_queue::EventQueue;
-use crate::mining_strategy::Action;
+use crate::event_queue::{EventQueue, EventQueueKind};
+use crate::fee::FeeModel;
+use crate::fork_choice::ForkChoice;
+use crate::ghostdag::GhostDagOrdering;
+use crate::mining_strategy::{Action, TieBreakingRule};
 use crate::node::{Node, NodeId, NodeList};
-use crate::profile::NetworkProfile;
-use crate::propagation_delay::{propagation_delay_us, PropagationDelayMode};
-use crate::protocol::Protocol;
+use crate::profile::{NetworkProfile, NodeProfile};
+use crate::propagation_delay::{
+    PropagationDelayMode, PropagationModel, PropagationTarget, jittered_delay_us,
+    propagation_delay_us,
+};
+use crate::protocol::{DifficultyUpdate, Protocol};
+use crate::sampling::{
+    SPLITMIX64_GAMMA, chi_square_survival, sample_exponential, sample_poisson, splitmix64,
+};
+use crate::types::{
+    AttackSuccessCondition, AttackSuccessReport, BlockTraceEvent, CatchUpStats, Convergence,
+    DoubleSpendReport, GhostDagReport, NodeInfo, ObserverStats, OutputFormat, QueueSample, Record,
+    RetargetEvent, RewardWeighting, SimulationReport, StrategyShare, TieBreakStats, TipChange,
+    TraceEvent,
+};
 use rand::prelude::*;
-use rand_distr::Exp;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// 主鎖が `end_round` に届かないまま分岐上の最大生成高さだけが伸び続ける場合の打ち切り余裕。
 const MAX_BRANCH_HEIGHT_ABOVE_END_ROUND: i64 = 4096;
 
+/// 既定のイベント上限に使う「1 ラウンド・1 ノードあたり」の許容イベント数。
+/// 通常の honest 網では 1 ラウンドあたり概ね `num_nodes + 1` イベントなので十分な余裕がある。
+const EVENT_BUDGET_PER_ROUND_PER_NODE: u64 = 64;
+
+/// 壁時計上限を確認する間隔（処理イベント数）。
+const WALL_CLOCK_CHECK_INTERVAL: u64 = 4096;
+
+/// blockDAG で 1 ブロックが参照するマージ親の上限（Kaspa の親数の上限 10 から selected parent を除く）。
+const MAX_MERGE_PARENTS: usize = 9;
+
+/// シミュレーションが終了条件に達する前に打ち切られた理由。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimulationError {
+    /// 処理イベント数が上限を超えた。
+    EventLimitExceeded {
+        processed_events: u64,
+        max_events: u64,
+        current_round: i64,
+        end_round: i64,
+    },
+    /// 壁時計での実行時間が上限を超えた。
+    WallClockLimitExceeded {
+        elapsed: Duration,
+        max_wall_clock: Duration,
+        current_round: i64,
+        end_round: i64,
+    },
+}
+
+impl std::fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulationError::EventLimitExceeded {
+                processed_events,
+                max_events,
+                current_round,
+                end_round,
+            } => write!(
+                f,
+                "aborted after {processed_events} events (limit {max_events}) at round {current_round}/{end_round}",
+            )?,
+            SimulationError::WallClockLimitExceeded {
+                elapsed,
+                max_wall_clock,
+                current_round,
+                end_round,
+            } => write!(
+                f,
+                "aborted after {:.1}s (limit {:.1}s) at round {current_round}/{end_round}",
+                elapsed.as_secs_f64(),
+                max_wall_clock.as_secs_f64(),
+            )?,
+        }
+        write!(
+            f,
+            ". The simulation is unlikely to terminate: the propagation delay is probably far \
+             larger than the block generation time (Δ/T >> 1), so every block fans out to all \
+             nodes while forks keep the rounds from advancing. Try a smaller --delay, fewer \
+             nodes, or a lower total hashrate."
+        )
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
 pub struct Env {
     // Configuration
     /// The number of nodes.
@@ -23,9 +105,22 @@ pub struct Env {
     pub delay_us: i64,
     /// H/A 間で Δ の適用を変えるモード（`--propagation-delay-mode`）。
     pub propagation_delay_mode: PropagationDelayMode,
-    /// The total hashrate of all nodes.
+    /// 戦略がブロックを公開する送り先（[`Env::propagation_targets`]）。
+    pub propagation_target: PropagationTarget,
+    /// ハッシュレートが正のノード（途中参加ノードを含む）。
+    miners: Vec<NodeId>,
+    /// The total hashrate of the nodes currently online (late joiners are added on `NodeJoin`).
     pub total_hashrate: i64,
+    /// A protocol used (difficulty adjustment and fork choice). フォーク選択のため `blockchain` と共有する。
+    pub protocol: Arc<dyn Protocol>,
+    /// 取引手数料のモデル。`None` なら手数料はない（[`Env::unclaimed_fees`] は常に 0）。
+    pub fee_model: Option<FeeModel>,
+    /// 受信した tip の高さが現在の tip をこれより多く上回っていたら採用しない（`None` で無制限）。
+    /// 同期の上限や部分的な eclipse を模した DoS 対策。
+    pub max_height_gap: Option<i64>,
     // Current environments
+    /// フォーク選択で同着になった回数（戦略は `&Env` しか持たないので `Cell` で数える）。
+    tie_breaks: Cell<TieBreakStats>,
     /// A instance of the blockchain.
     pub blockchain: Blockchain,
 }
@@ -35,27 +130,113 @@ impl Env {
         nodes: &[Node],
         delay_ms: i64,
         propagation_delay_mode: PropagationDelayMode,
-        protocol: &dyn Protocol,
+        protocol: Box<dyn Protocol>,
     ) -> Self {
-        let total_hashrate = nodes.iter().map(|n| n.hashrate()).sum();
+        // 途中参加ノードは参加するまでハッシュレートに数えない（genesis 難易度の推定にも使わない）。
+        let total_hashrate = nodes
+            .iter()
+            .filter(|n| n.is_initially_active())
+            .map(|n| n.hashrate())
+            .sum();
+        let protocol: Arc<dyn Protocol> = protocol.into();
+        let mut blockchain = Blockchain::new(protocol.clone(), total_hashrate);
+        blockchain.set_attackers(
+            nodes
+                .iter()
+                .filter(|n| !n.mining_strategy().is_honest())
+                .map(|n| n.id())
+                .collect(),
+        );
         Self {
             nodes: nodes.iter().map(|n| n.id()).collect(),
             delay_us: delay_ms.saturating_mul(1000),
             propagation_delay_mode,
+            propagation_target: PropagationTarget::default(),
+            miners: nodes
+                .iter()
+                .filter(|n| n.hashrate() > 0)
+                .map(|n| n.id())
+                .collect(),
             total_hashrate,
-            blockchain: Blockchain::new(&*protocol, total_hashrate),
+            protocol,
+            fee_model: None,
+            max_height_gap: None,
+            tie_breaks: Cell::new(TieBreakStats::default()),
+            blockchain,
         }
     }
 
     pub fn nodes(&self) -> &[NodeId] {
         &self.nodes
     }
+
+    /// ノード `from` がブロックを公開するときに `Propagate` を積む先（`propagation_target` に従う）。
+    /// 自分自身は含まない。
+    pub fn propagation_targets(&self, from: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let targets = match self.propagation_target {
+            PropagationTarget::AllNodes => &self.nodes,
+            PropagationTarget::Miners => &self.miners,
+        };
+        targets.iter().copied().filter(move |&to| to != from)
+    }
+
+    /// `tip` の上で時刻 `now_ms` に採掘したときに回収できる手数料（手数料モデルがなければ 0）。
+    pub fn unclaimed_fees(&self, tip: BlockId, now_ms: i64) -> f64 {
+        let Some(fee_model) = self.fee_model else {
+            return 0.0;
+        };
+        let tip = self.blockchain.get_block(tip).unwrap();
+        fee_model.unclaimed_fees(tip, now_ms)
+    }
+
+    /// `node` が honest でない戦略で動いているか。
+    pub fn is_attacker(&self, node: NodeId) -> bool {
+        self.blockchain.is_attacker(node)
+    }
+
+    /// 現在の tip と受信した tip が同着だったことを記録する。`switched` は受信した側を選んだか。
+    pub(crate) fn record_tie(&self, switched: bool) {
+        let mut stats = self.tie_breaks.get();
+        stats.record(switched);
+        self.tie_breaks.set(stats);
+    }
+
+    /// これまでのフォーク選択の同着の集計。
+    pub fn tie_breaks(&self) -> TieBreakStats {
+        self.tie_breaks.get()
+    }
+}
+
+/// `end_round`（と分岐の打ち切り余裕）とノード数から見積もった処理イベント数の上限。
+fn default_max_events(end_round: i64, num_nodes: usize) -> u64 {
+    let rounds = end_round.saturating_add(MAX_BRANCH_HEIGHT_ABOVE_END_ROUND).max(1) as u64;
+    rounds
+        .saturating_mul(num_nodes as u64 + 1)
+        .saturating_mul(EVENT_BUDGET_PER_ROUND_PER_NODE)
+}
+
+/// `set_tie_break_seed` を呼ばないときの同着用 seed。`seed` と同じ乱数列にならないようにずらす。
+///
+/// ブロックの `rand` を採掘時間とは別の乱数列から引くようになったので、同じ `seed` でも
+/// それ以前の版とは採掘時間の列、ひいてはシミュレーション結果が一致しない。
+fn default_tie_break_seed(seed: u64) -> u64 {
+    seed ^ SPLITMIX64_GAMMA
+}
+
+/// トランザクション数の乱数列の seed。`seed` からも同着用の seed からも離れた値にする。
+fn tx_arrival_seed(seed: u64) -> u64 {
+    splitmix64(seed)
+}
+
+/// 伝播先のシャッフルに使う乱数列の seed。ほかのどの乱数列の seed とも離れた値にする。
+fn propagation_shuffle_seed(seed: u64) -> u64 {
+    splitmix64(default_tie_break_seed(seed))
 }
 
 pub struct BlockchainSimulator {
     /// Configuration of the simulation.
     pub env: Env,
-    event_queue: EventQueue,
+    event_queue: Box<dyn EventQueue>,
     /// Maximum height of the blocks created.
     current_round: i64,
     /// The current time of the simulation in **microseconds**.
@@ -67,12 +248,127 @@ pub struct BlockchainSimulator {
     total_hashrate: i64,
     /// The maximum round to simulate.
     end_round: i64,
-    /// A protocol used.
-    protocol: Box<dyn Protocol>,
+    /// The seed `rng` was created from.
+    seed: u64,
     /// A random number generator.
     rng: StdRng,
+    /// `tie_break_rng` の seed。
+    tie_break_seed: u64,
+    /// 同着の判断に使うブロックの `rand` だけを引く乱数生成器。採掘時間などの `rng` とは独立。
+    tie_break_rng: StdRng,
+    /// ブロックの `tx_count` だけを引く乱数生成器。`set_tx_arrival_rate` の有無で採掘時間の乱数列が変わらないよう `rng` と分ける。
+    tx_rng: StdRng,
+    /// 伝播先の順序のシャッフルだけに使う乱数生成器。送り先の数で採掘時間の乱数列がずれないよう `rng` と分ける。
+    shuffle_rng: StdRng,
+    /// 処理済みイベント数。
+    processed_events: u64,
+    /// 処理イベント数の上限。超えたら設定ミスとみなして打ち切る。
+    max_events: u64,
+    /// 壁時計での実行時間の上限（既定は無制限）。
+    max_wall_clock: Option<Duration>,
+    /// `Some(k)` のとき、`current_round` が k の倍数に進むたびにメインチェーンを記録する。
+    chain_snapshot_interval: Option<i64>,
+    /// 記録したメインチェーンのスナップショット（アニメーション用）。
+    chain_snapshots: Vec<Vec<BlockId>>,
+    /// `Some(k)` のとき、イベントを k 件処理するたびにイベントキューの長さを記録する。
+    queue_sample_interval: Option<u64>,
+    /// 記録したイベントキューの長さ（時刻順）。
+    queue_samples: Vec<QueueSample>,
+    /// この高さ未満のブロックを指標（stale rate, fairness, 平均ブロック時間）から除外する。
+    burn_in_rounds: i64,
+    /// 報酬として数えるのに必要な確認数（`report` の fairness に使う）。
+    confirmations: i64,
+    /// fairness の報酬をブロック数で数えるか、難易度で重み付けするか。
+    reward_weighting: RewardWeighting,
+    /// 二重支払いの対象トランザクションを含める高さ。
+    double_spend_height: Option<i64>,
+    /// 対象トランザクションを含むブロック。
+    marked_tx_block: Option<BlockId>,
+    /// 対象ブロックとその子孫（対象トランザクションを含むチェーンの tip になりうるブロック）。
+    marked_tx_chain: HashSet<BlockId>,
+    /// honest ノードが対象ブロックを含まないチェーンへ reorg した回数。
+    marked_tx_reversals: u64,
+    /// 覆されたときの対象ブロックの確認数の最大値。
+    max_reversed_confirmations: i64,
+    /// 攻撃成功とみなして打ち切る条件。
+    attack_success_condition: Option<AttackSuccessCondition>,
+    /// honest ノードが攻撃者ブロックを含むチェーンへ reorg したときに捨てたブロック数の最大値。
+    max_attacker_reorg_depth: i64,
+    /// 攻撃成功の条件を満たした時刻（µs）。
+    attack_succeeded_at: Option<i64>,
+    /// 観測ノードごとの (reorg 回数, 捨てたブロック数の最大値)。
+    observer_reorgs: HashMap<NodeId, (u64, i64)>,
+    /// blockDAG のプロトコルで、各ノードが知っている tip（互いに祖先でないブロック）。
+    dag_tips: HashMap<NodeId, Vec<BlockId>>,
+    /// 各ノードの tip の切り替えを記録するかどうか。
+    record_tip_changes: bool,
+    /// 記録した tip の切り替え（時刻順）。
+    tip_changes: Vec<TipChange>,
+    /// 出力時の時間単位・桁数。
+    output_format: OutputFormat,
+    /// `Some(d)` のとき、tip から d より深い分岐ブロックを定期的にメモリから落とす。
+    prune_depth: Option<i64>,
+    /// true のとき難易度調整を行わず、全ブロックで genesis の難易度を使う。
+    freeze_difficulty: bool,
+    /// [`Protocol::validate_block`] の結果のキャッシュ。ブロックごとに一度だけ検証する。
+    /// 刈り込んだブロックの分は一緒に捨てる。
+    block_validity: HashMap<BlockId, bool>,
+    /// 送り先ごとに伝播イベントを積むか、全員に同時に届く 1 イベントにまとめるか。
+    propagation_model: PropagationModel,
+    /// 伝播遅延に掛ける一様ジッタの幅（±%）。0 なら常に Δ ちょうど。
+    delay_jitter_pct: f64,
+    /// 送ったブロックが届かない確率（メッセージごと）。
+    packet_loss: f64,
+    /// 受信ノードがブロックを検証してから採用するまでの処理遅延（µs）。ホップごとに Δ に加算される。
+    verification_delay_us: i64,
+    /// `Some(f)` のとき header-first relay。ヘッダは Δ の f 倍で届いて tip を切り替えさせ、
+    /// 本体は Δ + 検証遅延で届く。
+    header_delay_fraction: Option<f64>,
+    /// header-first relay で、ヘッダだけ受け取って本体をまだ受け取っていないブロック（ノードごと）。
+    headers_only: HashMap<NodeId, HashSet<BlockId>>,
+    /// ブロック自身か祖先の本体が届いていないので、本体が届くまで保留している中継（ノードごと）。
+    held_relays: HashMap<NodeId, Vec<Action>>,
+    /// mempool に入るトランザクションの速度（1 ms あたり）。`None` ならブロックの `tx_count` は数えない。
+    tx_arrival_rate: Option<f64>,
+    /// 採掘時間のガンマ分布の形状。1 なら指数分布（既定）。
+    mining_time_shape: f64,
+    /// `Some` のとき採掘時間を指数分布から引かず、実測のブロック間隔を順に再生する。
+    arrival: Option<DeterministicArrival>,
+    /// 決定的な到着過程で次のブロックを掘るノードとその時刻（µs）。間隔を使い切って止まったら `None`。
+    next_arrival: Option<(NodeId, i64)>,
+    /// まだ参加していない（`NodeJoin` 前の）ノード。採掘も受信もしない。
+    pending_joins: HashSet<NodeId>,
+    /// 各ブロックの採掘イベントを処理した時刻（µs）。
+    generated_at: HashMap<BlockId, i64>,
+    /// オンラインの全ノードの tip の共通祖先。これより浅いブロックは全ノードが採用している。
+    agreed_block: BlockId,
+    /// 全ノードが採用した子を初めて得た時刻（µs）。確認時間の計測に使う。
+    confirmed_at: HashMap<BlockId, i64>,
+    /// 生成予定ブロックの難易度調整の記録。採掘が完了したら `retargets` に移す。
+    pending_retargets: HashMap<BlockId, RetargetEvent>,
+    /// 採掘されたブロックで行われた難易度調整（採掘順）。
+    retargets: Vec<RetargetEvent>,
+    /// `Some(h)` のとき `end_round` の代わりに、告知済みメインチェーンの高さが h に届いた時点で終了する。
+    end_main_chain_height: Option<i64>,
+    /// 最後に計算した告知済みメインチェーンの高さ。ブロックが新たに告知・採掘されるまで使い回す。
+    main_chain_height: Option<i64>,
+    /// true のとき trace レベルのイベントを JSON 1 行ずつで出す（[`TraceEvent`]）。
+    json_trace: bool,
+    /// `Some` のとき、このブロックの採掘・受信・採用・放棄と最終状態だけを info レベルで出す。
+    traced_block: Option<BlockId>,
+    /// `traced_block` に起きたこと（時刻順）。
+    block_trace: Vec<BlockTraceEvent>,
+    /// true のとき終了時点の高さまでの残りイベントを処理してから止める（[`Self::simulation`]）。
+    drain_at_end: bool,
+    /// ノードごとの難易度の倍率（[`Self::set_difficulty_group`]）。載っていないノードは 1。
+    difficulty_factors: HashMap<NodeId, f64>,
+    /// 各イベントを処理する直前に呼ぶコールバック（[`Self::add_event_hook`]）。
+    event_hooks: Vec<EventHook>,
 }
 
+/// [`BlockchainSimulator::add_event_hook`] で登録するコールバック。処理前のシミュレータを読み取り専用で受け取る。
+pub type EventHook = Box<dyn FnMut(&Event, &BlockchainSimulator) + Send>;
+
 impl BlockchainSimulator {
     pub fn new(
         num_nodes: usize,
@@ -81,16 +377,62 @@ impl BlockchainSimulator {
         delay: i64,
         propagation_delay_mode: PropagationDelayMode,
         protocol: Box<dyn Protocol>,
+    ) -> Self {
+        Self::with_sampled_hashrates(
+            num_nodes,
+            seed,
+            end_round,
+            delay,
+            propagation_delay_mode,
+            protocol,
+            false,
+        )
+    }
+
+    /// [`Self::new`] と同じ乱数でハッシュレートを引いた後、大きい順にノード番号を振り直す（node 0 が最大）。
+    /// ハッシュレートの多重集合と以降の乱数列は [`Self::new`] と変わらない（`--sort-by-hashrate`）。
+    pub fn new_sorted_by_hashrate(
+        num_nodes: usize,
+        seed: u64,
+        end_round: i64,
+        delay: i64,
+        propagation_delay_mode: PropagationDelayMode,
+        protocol: Box<dyn Protocol>,
+    ) -> Self {
+        Self::with_sampled_hashrates(
+            num_nodes,
+            seed,
+            end_round,
+            delay,
+            propagation_delay_mode,
+            protocol,
+            true,
+        )
+    }
+
+    fn with_sampled_hashrates(
+        num_nodes: usize,
+        seed: u64,
+        end_round: i64,
+        delay: i64,
+        propagation_delay_mode: PropagationDelayMode,
+        protocol: Box<dyn Protocol>,
+        sort_by_hashrate: bool,
     ) -> Self {
         let mut rng = StdRng::seed_from_u64(seed);
-        let exp_dist = Exp::new(1.0).unwrap();
-        let mut nodes = Vec::with_capacity(num_nodes);
 
         // Sample hashrates from an exponential distribution and create nodes.
-        for i in 0..num_nodes {
-            let hashrate = (exp_dist.sample(&mut rng) * 10000.0) as i64 + 1; // Ensure at least 1.
-            nodes.push(Node::new(NodeId::new(i), hashrate));
+        let mut hashrates: Vec<i64> = (0..num_nodes)
+            .map(|_| sample_exponential(&mut rng, 10000.0) as i64 + 1) // Ensure at least 1.
+            .collect();
+        if sort_by_hashrate {
+            hashrates.sort_unstable_by(|a, b| b.cmp(a));
         }
+        let nodes: Vec<Node> = hashrates
+            .into_iter()
+            .enumerate()
+            .map(|(i, hashrate)| Node::new(NodeId::new(i), hashrate))
+            .collect();
         log::info!(
             "Hashrates: {:?}",
             nodes.iter().map(|n| n.hashrate()).collect::<Vec<_>>()
@@ -99,15 +441,70 @@ impl BlockchainSimulator {
         let total_hashrate = nodes.iter().map(|n| n.hashrate()).sum();
 
         Self {
-            env: Env::new(&nodes, delay, propagation_delay_mode, &*protocol),
+            env: Env::new(&nodes, delay, propagation_delay_mode, protocol),
             current_round: 0,
             current_time: 0,
             nodes: NodeList::new(nodes),
             total_hashrate,
             end_round,
+            seed,
             rng,
-            protocol,
-            event_queue: EventQueue::new(),
+            tie_break_seed: default_tie_break_seed(seed),
+            tie_break_rng: StdRng::seed_from_u64(default_tie_break_seed(seed)),
+            tx_rng: StdRng::seed_from_u64(tx_arrival_seed(seed)),
+            shuffle_rng: StdRng::seed_from_u64(propagation_shuffle_seed(seed)),
+            event_queue: EventQueueKind::default().to_event_queue(),
+            processed_events: 0,
+            max_events: default_max_events(end_round, num_nodes),
+            max_wall_clock: None,
+            chain_snapshot_interval: None,
+            chain_snapshots: Vec::new(),
+            queue_sample_interval: None,
+            queue_samples: Vec::new(),
+            burn_in_rounds: 0,
+            confirmations: 0,
+            reward_weighting: RewardWeighting::default(),
+            double_spend_height: None,
+            marked_tx_block: None,
+            marked_tx_chain: HashSet::new(),
+            marked_tx_reversals: 0,
+            max_reversed_confirmations: 0,
+            attack_success_condition: None,
+            max_attacker_reorg_depth: 0,
+            attack_succeeded_at: None,
+            observer_reorgs: HashMap::new(),
+            dag_tips: HashMap::new(),
+            record_tip_changes: false,
+            tip_changes: Vec::new(),
+            output_format: OutputFormat::default(),
+            prune_depth: None,
+            freeze_difficulty: false,
+            block_validity: HashMap::new(),
+            propagation_model: PropagationModel::default(),
+            delay_jitter_pct: 0.0,
+            packet_loss: 0.0,
+            verification_delay_us: 0,
+            header_delay_fraction: None,
+            headers_only: HashMap::new(),
+            held_relays: HashMap::new(),
+            tx_arrival_rate: None,
+            mining_time_shape: 1.0,
+            arrival: None,
+            next_arrival: None,
+            pending_joins: HashSet::new(),
+            generated_at: HashMap::new(),
+            agreed_block: GENESIS_BLOCK_ID,
+            confirmed_at: HashMap::new(),
+            pending_retargets: HashMap::new(),
+            retargets: Vec::new(),
+            end_main_chain_height: None,
+            main_chain_height: None,
+            json_trace: false,
+            traced_block: None,
+            block_trace: Vec::new(),
+            drain_at_end: false,
+            difficulty_factors: HashMap::new(),
+            event_hooks: Vec::new(),
         }
     }
 
@@ -125,395 +522,5207 @@ impl BlockchainSimulator {
         // Create nodes from the profile.
         for i in 0..profile.num_nodes() {
             let node_profile = &profile.nodes[i];
+            if node_profile.hashrate < 0 {
+                return Err(format!(
+                    "node {i} has a negative hashrate ({})",
+                    node_profile.hashrate
+                )
+                .into());
+            }
             let strategy = profile.create_strategy(i)?;
-            nodes.push(Node::new_with_strategy(
-                NodeId::new(i),
-                node_profile.hashrate,
-                strategy,
-            ));
+            let mut node = Node::new_with_strategy(NodeId::new(i), node_profile.hashrate, strategy);
+            node.join_time = node_profile.join_time;
+            nodes.push(node);
         }
 
         let total_hashrate = nodes.iter().map(|n| n.hashrate()).sum();
         let rng = StdRng::seed_from_u64(seed);
+        let max_events = default_max_events(end_round, nodes.len());
 
         Ok(Self {
-            env: Env::new(&nodes, delay, propagation_delay_mode, &*protocol),
+            env: Env::new(&nodes, delay, propagation_delay_mode, protocol),
             current_round: 0,
             current_time: 0,
             nodes: NodeList::new(nodes),
             total_hashrate,
             end_round,
+            seed,
             rng,
-            protocol,
-            event_queue: EventQueue::new(),
+            tie_break_seed: default_tie_break_seed(seed),
+            tie_break_rng: StdRng::seed_from_u64(default_tie_break_seed(seed)),
+            tx_rng: StdRng::seed_from_u64(tx_arrival_seed(seed)),
+            shuffle_rng: StdRng::seed_from_u64(propagation_shuffle_seed(seed)),
+            event_queue: EventQueueKind::default().to_event_queue(),
+            processed_events: 0,
+            max_events,
+            max_wall_clock: None,
+            chain_snapshot_interval: None,
+            chain_snapshots: Vec::new(),
+            queue_sample_interval: None,
+            queue_samples: Vec::new(),
+            burn_in_rounds: 0,
+            confirmations: 0,
+            reward_weighting: RewardWeighting::default(),
+            double_spend_height: None,
+            marked_tx_block: None,
+            marked_tx_chain: HashSet::new(),
+            marked_tx_reversals: 0,
+            max_reversed_confirmations: 0,
+            attack_success_condition: None,
+            max_attacker_reorg_depth: 0,
+            attack_succeeded_at: None,
+            observer_reorgs: HashMap::new(),
+            dag_tips: HashMap::new(),
+            record_tip_changes: false,
+            tip_changes: Vec::new(),
+            output_format: OutputFormat::default(),
+            prune_depth: None,
+            freeze_difficulty: false,
+            block_validity: HashMap::new(),
+            propagation_model: PropagationModel::default(),
+            delay_jitter_pct: 0.0,
+            packet_loss: 0.0,
+            verification_delay_us: 0,
+            header_delay_fraction: None,
+            headers_only: HashMap::new(),
+            held_relays: HashMap::new(),
+            tx_arrival_rate: None,
+            mining_time_shape: 1.0,
+            arrival: None,
+            next_arrival: None,
+            pending_joins: HashSet::new(),
+            generated_at: HashMap::new(),
+            agreed_block: GENESIS_BLOCK_ID,
+            confirmed_at: HashMap::new(),
+            pending_retargets: HashMap::new(),
+            retargets: Vec::new(),
+            end_main_chain_height: None,
+            main_chain_height: None,
+            json_trace: false,
+            traced_block: None,
+            block_trace: Vec::new(),
+            drain_at_end: false,
+            difficulty_factors: HashMap::new(),
+            event_hooks: Vec::new(),
         })
     }
 
-    fn propagation_time(&self, from: NodeId, to: NodeId) -> i64 {
-        let from_honest = self.nodes.get_node(from).mining_strategy().is_honest();
-        propagation_delay_us(
-            self.env.propagation_delay_mode,
-            self.env.delay_us,
-            from_honest,
-            from == to,
-        )
+    /// 処理イベント数の上限を設定する（既定は `end_round` とノード数から算出）。
+    pub fn set_max_events(&mut self, max_events: u64) {
+        self.max_events = max_events;
     }
 
-    pub fn enqueue_actions(&mut self, node_id: NodeId, actions: &[Action]) {
-        // Time when actions are issued; events are scheduled at their completion time.
-        let base_time = self.current_time;
-        for action in actions {
-            // Build the event type for this action.
-            let mut event_type = match action {
-                Action::Propagate { block_id, to } => {
-                    // Avoid self-propagation.
-                    if node_id == *to {
-                        continue;
-                    }
-                    EventType::Propagation {
-                        from: node_id,
-                        to: *to,
-                        block_id: *block_id,
-                    }
-                }
-                Action::RestartMining { prev_block_id } => EventType::BlockGeneration {
-                    minter: node_id,
-                    prev_block_id: *prev_block_id,
-                    // Dummy. We set it to proper value later in this function.
-                    block_id: GENESIS_BLOCK_ID,
-                },
-            };
+    /// イベントキューの実装を選ぶ（性能比較用。結果は実装に依らない）。シミュレーション開始前に呼ぶこと。
+    pub fn set_event_queue(&mut self, kind: EventQueueKind) {
+        assert!(
+            self.event_queue.is_empty(),
+            "event queue can only be replaced before the simulation starts"
+        );
+        self.event_queue = kind.to_event_queue();
+    }
 
-            // Enqueue the event (and supersede prior mining events when needed).
-            match event_type {
-                EventType::BlockGeneration {
-                    minter,
-                    prev_block_id,
-                    block_id: _,
-                } => {
-                    let mining_base_block = self.env.blockchain.get_block(prev_block_id).unwrap();
+    /// 告知済みメインチェーンの高さが `height` に届くまで走らせる（`None` で `end_round` に従う）。
+    /// 分岐が多い設定でも最終的なメインチェーン長を揃えられる。処理イベント数の上限はこの高さに合わせて
+    /// 引き上げるので、`set_max_events` はこの後で呼ぶこと。
+    pub fn set_end_main_chain_height(&mut self, height: Option<i64>) {
+        if let Some(height) = height {
+            self.max_events = self
+                .max_events
+                .max(default_max_events(height, self.nodes.nodes().len()));
+        }
+        self.end_main_chain_height = height;
+    }
 
-                    // Difficulty adjustment
-                    let new_difficulty = self
-                        .protocol
-                        .calculate_difficulty(mining_base_block, &self.env);
-                    let minter_hashrate = self.nodes.get_node(minter).hashrate();
-                    let generation_time_us =
-                        new_difficulty.calculate_mining_time(&mut self.rng, minter_hashrate);
-                    let next_mining_time = base_time + generation_time_us;
-
-                    // Create the block.
-                    let node = self.nodes.get_node(minter);
-                    let new_block_height = mining_base_block.height() + 1;
-                    let wall_clock_ms = next_mining_time / 1000;
-                    let timestamp = node.mining_strategy().handle_timestamp(
-                        wall_clock_ms,
-                        prev_block_id,
-                        new_block_height,
-                        &self.env,
-                    );
-                    let cumulative_chain_work = mining_base_block
-                        .cumulative_chain_work()
-                        .saturating_add(new_difficulty.chain_work_increment());
-                    let mining_time_ms = generation_time_us as f64 / 1000.0;
-                    let new_block = Block::new(
-                        new_block_height,
-                        Some(prev_block_id),
-                        minter,
-                        timestamp,
-                        (self.rng.r#gen::<f64>() * (i64::MAX - 10) as f64) as i64,
-                        self.env.blockchain.next_block_id(),
-                        new_difficulty,
-                        cumulative_chain_work,
-                        mining_time_ms,
-                        false,
-                    );
-
-                    if new_difficulty != mining_base_block.difficulty() {
-                        let rate =
-                            new_difficulty.as_f64() / mining_base_block.difficulty().as_f64();
-                        log::debug!(
-                            "DAA: {:e} -> {:e} (rate: {:.2}) @ round {}, block ID: {}",
-                            mining_base_block.difficulty().as_f64(),
-                            new_difficulty.as_f64(),
-                            rate,
-                            mining_base_block.height(),
-                            new_block.id(),
-                        );
-                    }
+    /// 壁時計での実行時間の上限を設定する。`None` で無制限。
+    pub fn set_max_wall_clock(&mut self, max_wall_clock: Option<Duration>) {
+        self.max_wall_clock = max_wall_clock;
+    }
 
-                    let EventType::BlockGeneration {
-                        minter: _,
-                        prev_block_id: _,
-                        block_id,
-                    } = &mut event_type
-                    else {
-                        unreachable!("event_type should be BlockGeneration");
-                    };
-                    *block_id = new_block.id();
-                    let mining_event = Event::new(next_mining_time, event_type);
-                    self.event_queue.push_mining(mining_event);
-                    self.env.blockchain.add_block(new_block);
-                }
-                EventType::Propagation {
-                    from,
-                    to,
-                    block_id,
-                } => {
-                    self.env.blockchain.mark_block_announced(block_id);
-                    let prop_delay = self.propagation_time(from, to);
-                    let event_time = base_time + prop_delay;
-                    self.event_queue.push(Event::new(event_time, event_type));
-                }
-            }
+    /// 終了条件を満たした後、その時点の最大高さまでの採掘・伝播イベントを処理し切ってから止めるかどうか。
+    /// 打ち切りで採掘途中のブロックが捨てられる偏りを減らす（[`Self::simulation`]）。
+    pub fn set_drain_at_end(&mut self, drain_at_end: bool) {
+        self.drain_at_end = drain_at_end;
+    }
+
+    /// `nodes` を、共有の難易度の `factor` 倍の目標で採掘するグループにする（マージマイニングされる
+    /// サイドチェーンなど）。グループのノードは採掘時間と chainwork をその難易度で計算する。
+    /// 難易度調整とブロックに記録する `difficulty` は全ノード共有のまま。
+    pub fn set_difficulty_group(&mut self, nodes: &[NodeId], factor: f64) {
+        assert!(
+            factor.is_finite() && factor > 0.0,
+            "difficulty factor must be positive, got {factor}"
+        );
+        for &node in nodes {
+            assert!(
+                node.into_usize() < self.nodes.nodes().len(),
+                "unknown node {node} in difficulty group"
+            );
+            self.difficulty_factors.insert(node, factor);
         }
     }
 
-    /// Event loop.
-    pub fn simulation(&mut self) {
-        self.enqueue_first_mining_task();
+    /// trace レベルのシミュレーションイベントを JSON 1 行ずつで出すかどうか。
+    pub fn set_json_trace(&mut self, json_trace: bool) {
+        self.json_trace = json_trace;
+    }
 
-        // 終了条件は完成済みメインチェーン高さ（`get_main_chain` 上の tip height）。
-        // 分岐だけが伸び続ける場合は `current_round` の上限で打ち切る。
-        while !self.event_queue.is_empty()
-            && self.current_round
-                < self
-                    .end_round
-                    .saturating_add(MAX_BRANCH_HEIGHT_ABOVE_END_ROUND)
-        {
-            let current_event = self
-                .event_queue
-                .pop()
-                .expect("Task queue should not be empty");
-            self.current_time = current_event.time();
+    /// 1 ブロックだけを追跡し、採掘・受信・各ノードの採用と放棄、終了時の状態を info レベルで出す。
+    pub fn set_trace_block(&mut self, block_id: Option<BlockId>) {
+        self.traced_block = block_id;
+    }
 
-            match current_event.event_type() {
-                EventType::BlockGeneration {
-                    minter,
-                    prev_block_id: _,
-                    block_id,
-                } => self.handle_block_generation(*minter, *block_id),
+    /// [`Self::set_trace_block`] で追跡したブロックに起きたこと（時刻順、最後が終了時の状態）。
+    pub fn block_trace(&self) -> &[BlockTraceEvent] {
+        &self.block_trace
+    }
 
-                EventType::Propagation { from, to, block_id } => {
-                    self.handle_propagation(*from, *to, *block_id)
-                }
-            }
-        }
+    /// mempool に入る手数料の速度（1 ms あたり、ブロック報酬 1 を単位）を設定する。`None` で手数料なし。
+    pub fn set_fee_rate(&mut self, rate_per_ms: Option<f64>) {
+        self.env.fee_model = rate_per_ms.map(FeeModel::new);
     }
 
-    fn enqueue_first_mining_task(&mut self) {
-        let mut actions: Vec<(NodeId, Action)> = vec![];
-        for node_id in self.env.nodes() {
-            actions.push((
-                *node_id,
-                Action::RestartMining {
-                    prev_block_id: GENESIS_BLOCK_ID,
-                },
-            ));
-        }
-        for (node_id, action) in actions {
-            self.enqueue_actions(node_id, &[action]);
-        }
+    /// mempool に入るトランザクションの速度（1 ms あたり）を設定する。各ブロックは親が採掘されて以降に
+    /// 届いたトランザクションをすべて取り込み、その数はポアソン分布に従う。`None` で数えない（既定）。
+    pub fn set_tx_arrival_rate(&mut self, rate_per_ms: Option<f64>) {
+        assert!(
+            rate_per_ms.is_none_or(|r| r.is_finite() && r >= 0.0),
+            "transaction arrival rate must be non-negative"
+        );
+        self.tx_arrival_rate = rate_per_ms;
     }
 
-    fn handle_block_generation(&mut self, minter: NodeId, block_id: BlockId) {
-        self.env
-            .blockchain
-            .mark_block_generation_completed(block_id);
-        let new_block = self.env.blockchain.get_block(block_id).unwrap();
+    /// 各ノードの採掘時間を、平均を変えずに形状 `shape` のガンマ（Erlang）分布から引く。
+    /// 1 なら指数分布（既定）で、大きいほど間隔のばらつきが小さい（分散は平均² / `shape`）。
+    /// 分布は採掘を始めるたびに引き直すので、1 より大きいと新しい tip への乗り換えで進捗を失う。
+    /// ノードが複数いるとネットワーク全体の間隔は各ノードの採掘時間の最小値になり、その平均は変わる。
+    pub fn set_mining_time_shape(&mut self, shape: f64) {
+        assert!(
+            shape.is_finite() && shape > 0.0,
+            "mining time shape must be positive"
+        );
+        self.mining_time_shape = shape;
+    }
 
-        // Run strategy callback and schedule follow-up tasks.
-        let actions = self
-            .nodes
-            .get_node_mut(minter)
-            .mining_strategy_mut()
-            .on_mining_block(block_id, self.current_time, &self.env, minter);
+    /// 戦略がブロックを公開する送り先を設定する。
+    pub fn set_propagation_target(&mut self, target: PropagationTarget) {
+        self.env.propagation_target = target;
+    }
 
-        if self.current_round < new_block.height() {
-            self.current_round = new_block.height();
-        }
+    /// ブロックの届け方を設定する（[`PropagationModel`]）。
+    pub fn set_propagation_model(&mut self, model: PropagationModel) {
+        self.propagation_model = model;
+    }
 
-        log::trace!(
-            "📦 time (ms): {}, minter: {}, difficulty: {:.4}, height: {}",
-            self.current_time / 1000,
-            new_block.minter(),
-            new_block.difficulty().as_f64(),
-            new_block.height()
-        );
+    /// 同じ chainwork の tip が競合したときの選び方を設定する（既定は first-seen）。
+    pub fn set_tie_breaking_rule(&mut self, rule: TieBreakingRule) {
+        self.env.blockchain.set_tie_breaking_rule(rule);
+    }
 
-        self.enqueue_actions(minter, &actions);
+    /// 同着の判断に使う乱数（ブロックの `rand`）の seed を設定する（既定は `seed` から導出）。
+    /// 採掘時間は `seed` の乱数列から引くので、同じ履歴の下では到着時刻を変えずに同着の結果だけを変えられる。
+    pub fn set_tie_break_seed(&mut self, seed: u64) {
+        self.tie_break_seed = seed;
+        self.tie_break_rng = StdRng::seed_from_u64(seed);
     }
 
-    fn handle_propagation(&mut self, from: NodeId, to: NodeId, block_id: BlockId) {
-        // Run strategy callback and schedule follow-up tasks.
-        let actions = self
-            .nodes
-            .get_node_mut(to)
-            .mining_strategy_mut()
-            .on_receiving_block(block_id, self.current_time, &self.env, to);
-        self.enqueue_actions(to, &actions);
+    /// honest ノードと `longest_chain` が使うフォーク選択を差し替える（`None` でプロトコル既定）。
+    pub fn set_fork_choice(&mut self, fork_choice: Option<Box<dyn ForkChoice>>) {
+        self.env.blockchain.set_fork_choice(fork_choice);
+    }
 
-        log::trace!(
-            "🚚 time (ms): {}, {}->{}, height: {}",
-            self.current_time / 1000,
-            from,
-            to,
-            self.env.blockchain.get_block(block_id).unwrap().height()
+    /// selfish mining の γ を設定する（`None` で無効）。honest ノードは honest/攻撃者ブロックの同着を
+    /// 確率 γ で攻撃者側に倒す。
+    pub fn set_gamma(&mut self, gamma: Option<f64>) {
+        assert!(
+            gamma.is_none_or(|g| (0.0..=1.0).contains(&g)),
+            "gamma must be between 0 and 1"
         );
+        self.env.blockchain.set_gamma(gamma);
     }
 
-    pub fn print_hashrates(&self) {
-        log::info!(
-            "hashrates: {:?}",
-            self.nodes
-                .nodes()
-                .iter()
-                .map(|n| n.hashrate())
-                .collect::<Vec<_>>()
+    /// 各ノードが受け入れる高さの差の上限を設定する（`None` で無制限）。現在の tip より `gap` を超えて
+    /// 高いブロックを受け取っても、フォーク選択の前に捨てて現在の tip を維持する。
+    pub fn set_max_height_gap(&mut self, gap: Option<i64>) {
+        assert!(
+            gap.is_none_or(|g| g >= 0),
+            "max height gap must be non-negative"
         );
+        self.env.max_height_gap = gap;
     }
 
-    pub fn print_blockchain(&self) {
-        log::info!("Blockchain:");
-        for block in self.env.blockchain.blocks() {
-            log::info!(
-                "Block ID: {}, Difficulty: {:.4}, Height: {}, Minter: {}, Time: {}, Prev Block ID: {:?}, Rand: {}",
-                block.id(),
-                block.difficulty().as_f64(),
-                block.height(),
-                block.minter(),
-                block.time(),
-                block.prev_block_id(),
-                block.rand()
-            );
-        }
+    /// ラウンドが進むたびにメインチェーンを記録する。`interval` ラウンドごとに間引く（`None` で無効）。
+    pub fn set_chain_snapshot_interval(&mut self, interval: Option<i64>) {
+        assert!(
+            interval.is_none_or(|k| k >= 1),
+            "chain snapshot interval must be at least 1"
+        );
+        self.chain_snapshot_interval = interval;
     }
 
-    pub fn print_summary(&self) {
-        log::info!("Simulation Summary:");
-        log::info!("- Current time (ms): {}", self.current_time / 1000);
-        log::info!("- End round target (main chain): {}", self.end_round);
-        log::info!("- Max generated height (any branch): {}", self.current_round);
-        log::info!("- Total blocks: {}", self.env.blockchain.len());
-        let main_h = self.env.blockchain.main_chain_height();
-        let main_export_h = self.env.blockchain.main_chain_height_for_export();
-        let max_h = self.env.blockchain.max_height();
-        log::info!("- Main chain height (announced): {}", main_h);
-        log::info!(
-            "- Main chain height (export, incl. unannounced): {}",
-            main_export_h
+    /// イベントを `interval` 件処理するたびにイベントキューの長さを記録する（`None` で無効）。
+    pub fn set_queue_sample_interval(&mut self, interval: Option<u64>) {
+        assert!(
+            interval.is_none_or(|k| k >= 1),
+            "queue sample interval must be at least 1"
         );
-        log::info!("- Max block height (any branch): {}", max_h);
-        // difficulty
-        log::info!(
-            "Difficulty: {:.4}",
-            self.env
-                .blockchain
-                .last_block()
-                .map_or(0.0, |b| b.difficulty().as_f64())
+        self.queue_sample_interval = interval;
+    }
+
+    /// tip から `prune_depth` より深い分岐ブロックを定期的に tombstone に置き換える（`None` で無効）。
+    /// `prune_depth` は起こり得る reorg の深さより大きくしておくこと。
+    pub fn set_prune_depth(&mut self, prune_depth: Option<i64>) {
+        assert!(
+            prune_depth.is_none_or(|d| d >= 1),
+            "prune depth must be at least 1"
         );
-        log::info!(
-            "- Avg. time/block (ms): {}",
-            (self.current_time as f64 / 1000.0) / main_h.max(1) as f64
+        self.prune_depth = prune_depth;
+    }
+
+    /// 難易度を genesis の値に固定する（プロトコルの難易度調整を無効化し、生成時間モデルだけを使う）。
+    pub fn set_freeze_difficulty(&mut self, freeze: bool) {
+        self.freeze_difficulty = freeze;
+    }
+
+    /// 伝播遅延にメッセージごとの ±`pct`% 一様ジッタを加える（0 で無効、0〜100）。
+    pub fn set_delay_jitter_pct(&mut self, pct: f64) {
+        assert!(
+            (0.0..=100.0).contains(&pct),
+            "delay jitter must be within 0-100%"
         );
+        self.delay_jitter_pct = pct;
     }
 
-    /// Traverse the main chain, compute rewards, and print mining fairness
-    /// (fairness = reward share / hashrate share).
-    pub fn print_mining_fairness(&self) {
-        let main_chain = self.env.blockchain.get_main_chain_for_export();
+    /// 各 `Propagate` を確率 `probability`（0〜1）で黙って落とす。再送はしないので、落ちたブロックは
+    /// 他のノードからの中継で届かない限り届かない。0（既定）なら乱数を消費せず従来と同じ結果になる。
+    pub fn set_packet_loss(&mut self, probability: f64) {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "packet loss must be within 0-1"
+        );
+        self.packet_loss = probability;
+    }
 
-        // Count rewards per node (exclude genesis minter).
-        let mut rewards: HashMap<NodeId, f64> = HashMap::new();
+    /// 受信ノードがブロックを採用する前の検証遅延（ms）を設定する。ネットワーク遅延とは別に毎ホップ加算される。
+    pub fn set_verification_delay(&mut self, verification_delay_ms: i64) {
+        assert!(
+            verification_delay_ms >= 0,
+            "verification delay must be non-negative"
+        );
+        self.verification_delay_us = verification_delay_ms.saturating_mul(1000);
+    }
 
-        for &block_id in &main_chain {
-            if let Some(block) = self.env.blockchain.get_block(block_id) {
-                let minter = block.minter();
-                if minter != NodeId::dummy() {
-                    *rewards.entry(minter).or_insert(0.0) += 1.0;
-                }
-            }
+    /// header-first relay を有効にする。ヘッダは伝播遅延の `fraction` 倍（0〜1）で届き、受信ノードは
+    /// 本体を待たずにその上で採掘を始める。本体は通常どおり伝播遅延 + 検証遅延で届くので、
+    /// 帯域（1 ブロックを送り終えるまでの時間）は変わらない。`None` で無効（既定）。
+    ///
+    /// 本体を検証し終えるまでは、そのブロックも、その上に自分で掘ったブロックも他ノードへ中継しない。
+    pub fn set_header_first_relay(&mut self, fraction: Option<f64>) {
+        if let Some(fraction) = fraction {
+            assert!(
+                (0.0..=1.0).contains(&fraction),
+                "header delay fraction must be within [0, 1], got {fraction}"
+            );
         }
+        self.header_delay_fraction = fraction;
+    }
 
-        // Total reward across nodes.
-        let total_reward: f64 = rewards.values().sum::<f64>();
+    /// 採掘時間の代わりに `arrival` のブロック間隔を順に使う（`None` で指数分布に戻す）。
+    /// 各間隔のブロックを掘るノードはハッシュレートに比例して選び、それ以外のノードは採掘しない。
+    pub fn set_deterministic_arrival(&mut self, arrival: Option<DeterministicArrival>) {
+        self.arrival = arrival;
+    }
 
-        // fairness = reward_share / hashrate_share
-        let mut fairness_data: Vec<(NodeId, f64, f64, f64, f64, f64)> = self
-            .nodes
-            .nodes()
+    /// 高さ `burn_in_rounds` 未満のブロックを指標から除外する（シミュレーションはそのまま行う）。
+    pub fn set_burn_in_rounds(&mut self, burn_in_rounds: i64) {
+        self.burn_in_rounds = burn_in_rounds;
+    }
+
+    /// `report` の fairness で報酬として数えるのに必要な確認数を設定する（既定は 0）。
+    pub fn set_confirmations(&mut self, confirmations: i64) {
+        assert!(confirmations >= 0, "confirmations must be non-negative");
+        self.confirmations = confirmations;
+    }
+
+    /// `report` の fairness で報酬をブロック数で数えるか、ブロックの難易度で重み付けするかを設定する。
+    pub fn set_reward_weighting(&mut self, weighting: RewardWeighting) {
+        self.reward_weighting = weighting;
+    }
+
+    /// 高さ `height` で最初に honest ノードが採掘するブロックに二重支払いの対象トランザクションを含め、
+    /// そのブロックが攻撃者のチェーンに覆されるかを追跡する（[`SimulationReport::double_spend`]）。
+    pub fn set_double_spend_height(&mut self, height: Option<i64>) {
+        self.double_spend_height = height;
+    }
+
+    /// honest ノードが攻撃者のチェーンへ reorg して `confirmations` 個以上のブロックを捨てたら、
+    /// 攻撃成功としてシミュレーションを打ち切る（[`SimulationReport::attack`]）。`None` で無効。
+    pub fn set_attack_success_condition(&mut self, condition: Option<AttackSuccessCondition>) {
+        assert!(
+            condition.is_none_or(|c| c.confirmations >= 1),
+            "attack success needs at least 1 confirmation"
+        );
+        self.attack_success_condition = condition;
+    }
+
+    /// 各イベントを処理する直前に `hook` を呼ぶ（登録順）。hook にはそのイベントと、イベントを処理する前の
+    /// シミュレータが渡るので、crate を変えずに独自の統計を集められる。結果には影響しない。
+    pub fn add_event_hook(
+        &mut self,
+        hook: impl FnMut(&Event, &BlockchainSimulator) + Send + 'static,
+    ) {
+        self.event_hooks.push(Box::new(hook));
+    }
+
+    /// 採掘せずにブロックを受け取るだけの観測ノード（ハッシュレート 0 の honest ノード）を `count` 台追加する。
+    /// 観測ノードの reorg と採用高さは採掘ノードとは別に報告する（[`SimulationReport::observers`]）。
+    /// シミュレーション開始前に呼ぶ。
+    pub fn add_observers(&mut self, count: usize) {
+        assert_eq!(
+            self.processed_events, 0,
+            "observers must be added before the simulation starts"
+        );
+        let default_budget = default_max_events(self.end_round, self.nodes.nodes().len());
+        for _ in 0..count {
+            let id = NodeId::new(self.nodes.nodes().len());
+            self.nodes.push(Node::new(id, 0));
+            self.env.nodes.push(id);
+        }
+        if self.max_events == default_budget {
+            self.max_events = default_max_events(self.end_round, self.nodes.nodes().len());
+        }
+    }
+
+    /// 観測ノード（ハッシュレート 0 の honest ノード）か。
+    fn is_observer(&self, node_id: NodeId) -> bool {
+        let node = self.nodes.get_node(node_id);
+        node.hashrate() == 0 && node.mining_strategy().is_honest()
+    }
+
+    /// 各ノードが採用 tip を切り替えるたびに (時刻, ノード, 高さ) を記録する。
+    pub fn set_record_tip_changes(&mut self, record: bool) {
+        self.record_tip_changes = record;
+    }
+
+    /// CSV / サマリに出す時間の単位と桁数を設定する（シミュレーション内部には影響しない）。
+    pub fn set_output_format(&mut self, output_format: OutputFormat) {
+        self.output_format = output_format;
+    }
+
+    /// 記録した tip の切り替え（時刻順）。時刻 0 に全ノードの genesis 採用を含む。
+    pub fn tip_changes(&self) -> &[TipChange] {
+        &self.tip_changes
+    }
+
+    /// 記録したメインチェーンのスナップショット（古い順）。
+    pub fn chain_snapshots(&self) -> &[Vec<BlockId>] {
+        &self.chain_snapshots
+    }
+
+    /// 記録したイベントキューの長さ（時刻順）。
+    pub fn queue_samples(&self) -> &[QueueSample] {
+        &self.queue_samples
+    }
+
+    /// これまでに処理したイベント数。
+    pub fn processed_events(&self) -> u64 {
+        self.processed_events
+    }
+
+    /// これまでに生成されたブロックの最大高さ（いずれかの分岐上）。
+    pub fn current_round(&self) -> i64 {
+        self.current_round
+    }
+
+    fn current_head(&self, node_id: NodeId) -> BlockId {
+        self.nodes
+            .get_node(node_id)
+            .mining_strategy()
+            .current_head()
+    }
+
+    /// 戦略コールバックの前後で tip が変わっていれば記録する。
+    fn record_tip_change(&mut self, node_id: NodeId, previous_head: BlockId) {
+        let head = self.current_head(node_id);
+        if head == previous_head {
+            return;
+        }
+        self.update_agreed_block();
+        self.trace_block_tip_change(node_id, previous_head, head);
+        if !self.env.is_attacker(node_id)
+            && self.marked_tx_chain.contains(&previous_head)
+            && !self.marked_tx_chain.contains(&head)
+        {
+            let marked_height = self.double_spend_height.unwrap_or(0);
+            let previous_height = self.env.blockchain.block_height(previous_head);
+            self.marked_tx_reversals += 1;
+            self.max_reversed_confirmations = self
+                .max_reversed_confirmations
+                .max(previous_height - marked_height + 1);
+        }
+        if !self.env.is_attacker(node_id) {
+            self.track_attacker_reorg(previous_head, head);
+        }
+        if self.is_observer(node_id) {
+            let fork_point = self.env.blockchain.common_ancestor(previous_head, head);
+            if fork_point != previous_head {
+                let height = |id| self.env.blockchain.block_height(id);
+                let depth = height(previous_head) - height(fork_point);
+                let (reorgs, max_depth) = self.observer_reorgs.entry(node_id).or_default();
+                *reorgs += 1;
+                *max_depth = (*max_depth).max(depth);
+            }
+        }
+        if !self.record_tip_changes {
+            return;
+        }
+        self.tip_changes.push(TipChange {
+            time: self.current_time as f64 / 1000.0,
+            node_id: node_id.into_usize(),
+            adopted_height: self.env.blockchain.block_height(head),
+        });
+    }
+
+    /// honest ノードの `previous_head` → `head` の切り替えが攻撃者ブロックを含むチェーンへの reorg なら、
+    /// 捨てたブロック数（最も深いブロックの確認数）を記録し、攻撃成功の条件と比べる。
+    fn track_attacker_reorg(&mut self, previous_head: BlockId, head: BlockId) {
+        let Some(condition) = self.attack_success_condition else {
+            return;
+        };
+        let blockchain = &self.env.blockchain;
+        let fork_point = blockchain.common_ancestor(previous_head, head);
+        let mut new = head;
+        let mut attacker_branch = false;
+        // 刈り込まれたブロックも tombstone から minter と親を引ける。
+        while new != fork_point {
+            let Some(block) = blockchain.block_summary(new) else {
+                return;
+            };
+            attacker_branch |= block.minter.is_some_and(|m| self.env.is_attacker(m));
+            let Some(prev) = block.prev_block_id else {
+                return;
+            };
+            new = prev;
+        }
+        if !attacker_branch {
+            return;
+        }
+        let depth = blockchain.block_height(previous_head) - blockchain.block_height(fork_point);
+        self.max_attacker_reorg_depth = self.max_attacker_reorg_depth.max(depth);
+        if depth >= condition.confirmations && self.attack_succeeded_at.is_none() {
+            self.attack_succeeded_at = Some(self.current_time);
+        }
+    }
+
+    /// 採掘されたブロックが対象トランザクションを含むか（含むチェーンに積まれたか）を記録する。
+    fn track_marked_tx(&mut self, minter: NodeId, block_id: BlockId) {
+        let Some(marked_height) = self.double_spend_height else {
+            return;
+        };
+        let block = self.env.blockchain.get_block(block_id).unwrap();
+        let parent = block.prev_block_id().unwrap();
+        if self.marked_tx_chain.contains(&parent) {
+            self.marked_tx_chain.insert(block_id);
+        } else if self.marked_tx_block.is_none()
+            && block.height() == marked_height
+            && !self.env.is_attacker(minter)
+        {
+            self.env
+                .blockchain
+                .get_block_mut(block_id)
+                .unwrap()
+                .set_marked_tx(true);
+            self.marked_tx_block = Some(block_id);
+            self.marked_tx_chain.insert(block_id);
+        }
+    }
+
+    /// オンラインの全ノードの tip の共通祖先を求め直し、その真の祖先で未確認のものを現在時刻で確認済みにする。
+    fn update_agreed_block(&mut self) {
+        let blockchain = &self.env.blockchain;
+        let parent = |id: BlockId| blockchain.get_block(id).and_then(|b| b.prev_block_id());
+        let Some(agreed) = self
+            .nodes
+            .nodes()
+            .iter()
+            .filter(|node| !self.pending_joins.contains(&node.id()))
+            .map(|node| node.mining_strategy().current_head())
+            .reduce(|a, b| blockchain.common_ancestor(a, b))
+        else {
+            return;
+        };
+        if agreed == self.agreed_block {
+            return;
+        }
+        self.agreed_block = agreed;
+        // 祖先はそれより先に確認されているので、確認済みのブロックに当たったら止める。
+        let mut current = parent(agreed);
+        while let Some(id) = current {
+            if self.confirmed_at.contains_key(&id) {
+                break;
+            }
+            self.confirmed_at.insert(id, self.current_time);
+            current = parent(id);
+        }
+    }
+
+    /// メインチェーンのブロック（高さ `min_height` 以上）が採掘されてから、全ノードが採用した子を得る
+    /// （1 確認で全ノードが合意する）までの平均時間（ms）。確認されたブロックがなければ `None`。
+    pub fn avg_confirmation_time_ms(&self, min_height: i64) -> Option<f64> {
+        let latencies: Vec<i64> = self
+            .env
+            .blockchain
+            .main_chain_blocks()
+            .filter(|b| b.height() >= min_height.max(1))
+            .filter_map(|b| {
+                let generated = self.generated_at.get(&b.id())?;
+                let confirmed = self.confirmed_at.get(&b.id())?;
+                Some(confirmed - generated)
+            })
+            .collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        Some(latencies.iter().sum::<i64>() as f64 / latencies.len() as f64 / 1000.0)
+    }
+
+    /// メインチェーンのブロック（ジェネシスを除く）のうちトランザクションを 1 つも含まないものの割合。
+    /// `set_tx_arrival_rate` を設定していないか、採掘されたブロックがなければ `None`。
+    pub fn empty_block_rate(&self) -> Option<f64> {
+        self.tx_arrival_rate?;
+        let blocks: Vec<&Block> = self.env.blockchain.main_chain_blocks().skip(1).collect();
+        if blocks.is_empty() {
+            return None;
+        }
+        let empty = blocks.iter().filter(|b| b.tx_count() == 0).count();
+        Some(empty as f64 / blocks.len() as f64)
+    }
+
+    /// 各ノードの戦略とキュー内のイベントが参照するブロックを残して分岐を刈り込む。
+    fn prune_stale_forks(&mut self) {
+        let Some(prune_depth) = self.prune_depth else {
+            return;
+        };
+        let mut keep: HashSet<BlockId> = self.event_queue.referenced_blocks().collect();
+        keep.extend(self.headers_only.values().flatten());
+        keep.extend(
+            self.held_relays
+                .values()
+                .flatten()
+                .filter_map(|action| match *action {
+                    Action::Propagate { block_id, .. } => Some(block_id),
+                    Action::RestartMining { .. } => None,
+                }),
+        );
+        keep.extend(
+            self.nodes
+                .nodes()
+                .iter()
+                .flat_map(|node| node.mining_strategy().referenced_blocks()),
+        );
+        let pruned = self.env.blockchain.prune_stale_forks(prune_depth, &keep);
+        for id in &pruned {
+            self.block_validity.remove(id);
+        }
+        log::debug!(
+            "pruned {} stale blocks @ round {}",
+            pruned.len(),
+            self.current_round
+        );
+    }
+
+    fn calculate_new_difficulty(&self, parent_block: &Block) -> DifficultyUpdate {
+        if self.freeze_difficulty {
+            return DifficultyUpdate::unchanged(
+                self.env
+                    .blockchain
+                    .get_block(GENESIS_BLOCK_ID)
+                    .expect("genesis is never pruned")
+                    .difficulty(),
+            );
+        }
+        self.env
+            .protocol
+            .calculate_difficulty(parent_block, &self.env)
+    }
+
+    /// `block_id` がプロトコルの検証規則を満たすか。不正なブロックは最初の検証時にログに残す。
+    ///
+    /// 難易度を固定しているときは DAA を使わないので、プロトコルの検証は行わない。
+    fn is_block_valid(&mut self, block_id: BlockId) -> bool {
+        if self.freeze_difficulty {
+            return true;
+        }
+        if let Some(&valid) = self.block_validity.get(&block_id) {
+            return valid;
+        }
+        let block = self.env.blockchain.get_block(block_id).unwrap();
+        let valid = match block.prev_block_id() {
+            Some(parent_id) => {
+                let parent = self.env.blockchain.get_block(parent_id).unwrap();
+                self.env.protocol.validate_block(block, parent, &self.env)
+            }
+            None => true,
+        };
+        if !valid {
+            log::warn!(
+                "dropped invalid block {} at height {} (minter: {:?})",
+                block_id,
+                block.height(),
+                block.minter()
+            );
+        }
+        self.block_validity.insert(block_id, valid);
+        valid
+    }
+
+    fn propagation_time(&mut self, from: NodeId, to: NodeId) -> i64 {
+        let from_honest = self.nodes.get_node(from).mining_strategy().is_honest();
+        let delay_us = propagation_delay_us(
+            self.env.propagation_delay_mode,
+            self.env.delay_us,
+            from_honest,
+            from == to,
+        );
+        jittered_delay_us(delay_us, self.delay_jitter_pct, &mut self.rng)
+    }
+
+    /// `minter` が `prev_block_id` の上に採掘する次のブロックを作る（チェーンにはまだ追加しない）。
+    /// 難易度調整・採掘時間のサンプリング・`rand`・ID の割り当てをまとめて行い、
+    /// タイムスタンプは現在時刻 + 採掘時間を戦略が調整したものになる。
+    /// 難易度調整の記録は残さないので、`retarget_records` に載るのはシミュレーション中に採掘されたブロックだけ。
+    pub fn mint_block(&mut self, minter: NodeId, prev_block_id: BlockId) -> Block {
+        self.mint_block_with_generation_time(minter, prev_block_id)
+            .0
+    }
+
+    /// [`Self::mint_block`] に加えて、採掘イベントの時刻に使う生成時間（µs）と、
+    /// このブロックで行われる難易度調整も返す。
+    fn mint_block_with_generation_time(
+        &mut self,
+        minter: NodeId,
+        prev_block_id: BlockId,
+    ) -> (Block, i64, Option<RetargetEvent>) {
+        let minter_hashrate = self.nodes.get_node(minter).hashrate();
+        let mining_base_block = self.env.blockchain.get_block(prev_block_id).unwrap();
+
+        // Difficulty adjustment
+        let DifficultyUpdate {
+            difficulty: new_difficulty,
+            retarget,
+        } = self.calculate_new_difficulty(mining_base_block);
+        // 難易度グループのノードは倍率を掛けた難易度に対して採掘する。
+        let work_difficulty = match self.difficulty_factors.get(&minter) {
+            Some(&factor) => new_difficulty.scaled(factor),
+            None => new_difficulty,
+        };
+        let generation_time_us = match (&self.arrival, self.next_arrival) {
+            (Some(_), Some((_, at))) => at.saturating_sub(self.current_time).max(0),
+            _ => work_difficulty.calculate_mining_time_with_shape(
+                &mut self.rng,
+                minter_hashrate,
+                self.mining_time_shape,
+            ),
+        };
+
+        // Create the block.
+        let node = self.nodes.get_node(minter);
+        let new_block_height = mining_base_block.height() + 1;
+        let wall_clock_ms = self.current_time.saturating_add(generation_time_us) / 1000;
+        let timestamp = node.mining_strategy().handle_timestamp(
+            wall_clock_ms,
+            prev_block_id,
+            new_block_height,
+            &self.env,
+        );
+        let cumulative_chain_work = mining_base_block
+            .cumulative_chain_work()
+            .saturating_add(work_difficulty.chain_work_increment());
+        let mining_time_ms = generation_time_us as f64 / 1000.0;
+        let mut new_block = Block::new(
+            new_block_height,
+            Some(prev_block_id),
+            minter,
+            timestamp,
+            (self.tie_break_rng.r#gen::<f64>() * (i64::MAX - 10) as f64) as i64,
+            self.env.blockchain.next_block_id(),
+            new_difficulty,
+            cumulative_chain_work,
+            mining_time_ms,
+            false,
+        );
+        new_block.set_created_at(wall_clock_ms);
+        if self.env.protocol.ghostdag_k().is_some() {
+            new_block.set_merge_parents(self.merge_parents(minter, prev_block_id));
+        }
+        if let Some(rate_per_ms) = self.tx_arrival_rate {
+            let parent_time = self
+                .env
+                .blockchain
+                .get_block(prev_block_id)
+                .unwrap()
+                .created_at();
+            let elapsed_ms = (wall_clock_ms - parent_time).max(0) as f64;
+            new_block.set_tx_count(sample_poisson(&mut self.tx_rng, rate_per_ms * elapsed_ms));
+        }
+        (new_block, generation_time_us, retarget)
+    }
+
+    /// `minter` が知っている tip のうち、`prev_block_id` とその祖先を除いた新しいものから
+    /// [`MAX_MERGE_PARENTS`] 個。
+    fn merge_parents(&self, minter: NodeId, prev_block_id: BlockId) -> Vec<BlockId> {
+        let chain = &self.env.blockchain;
+        let mut merge_parents: Vec<BlockId> = self
+            .dag_tips
+            .get(&minter)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|&tip| tip != prev_block_id && !chain.is_dag_ancestor(tip, prev_block_id))
+            .collect();
+        merge_parents.sort_unstable_by(|a, b| b.cmp(a));
+        merge_parents.truncate(MAX_MERGE_PARENTS);
+        merge_parents
+    }
+
+    /// blockDAG のプロトコルで、`node_id` が `block_id` を知ったときに tip を更新する。
+    fn note_dag_block(&mut self, node_id: NodeId, block_id: BlockId) {
+        if self.env.protocol.ghostdag_k().is_none() {
+            return;
+        }
+        let chain = &self.env.blockchain;
+        let tips = self.dag_tips.entry(node_id).or_default();
+        // 子孫が先に届いていれば tip は変わらない。
+        if tips
+            .iter()
+            .any(|&tip| tip == block_id || chain.is_dag_ancestor(block_id, tip))
+        {
+            return;
+        }
+        tips.retain(|&tip| !chain.is_dag_ancestor(tip, block_id));
+        tips.push(block_id);
+    }
+
+    /// 同じブロックを続けて送る `Propagate` の受信者の順序を `shuffle_rng` で混ぜる。
+    /// 同時刻のイベントは積んだ順に処理されるので、昇順のままだと番号の小さいノードが常に先に受け取り、
+    /// 同着の中継競争で有利になる。ブロック間の順序（親→子）と他のアクションの位置は変えない。
+    fn shuffle_propagation_targets<'a>(&mut self, actions: &'a [Action]) -> Vec<&'a Action> {
+        let mut ordered: Vec<&Action> = actions.iter().collect();
+        let mut start = 0;
+        while start < ordered.len() {
+            let mut end = start + 1;
+            if let Action::Propagate { block_id, .. } = *ordered[start] {
+                while end < ordered.len()
+                    && matches!(*ordered[end], Action::Propagate { block_id: b, .. } if b == block_id)
+                {
+                    end += 1;
+                }
+                ordered[start..end].shuffle(&mut self.shuffle_rng);
+            }
+            start = end;
+        }
+        ordered
+    }
+
+    /// [`PropagationModel::Broadcast`] で、同じブロックの `Propagate` をまとめた送り先に Δ 後に届ける。
+    fn enqueue_broadcast(
+        &mut self,
+        from: NodeId,
+        base_time: i64,
+        to: Vec<NodeId>,
+        block_id: BlockId,
+    ) {
+        self.env.blockchain.mark_block_announced(block_id);
+        self.main_chain_height = None;
+        self.event_queue.push(Event::new(
+            base_time + self.env.delay_us,
+            EventType::Broadcast { from, to, block_id },
+        ));
+    }
+
+    pub fn enqueue_actions(&mut self, node_id: NodeId, actions: &[Action]) {
+        // Time when actions are issued; events are scheduled at their completion time.
+        let base_time = self.current_time;
+        // 本体待ちのブロック（とその子孫）の中継は、本体が届くまで保留する。
+        let (actions, held): (Vec<Action>, Vec<Action>) =
+            actions.iter().partition(|action| match **action {
+                Action::Propagate { block_id, .. } => !self.lacks_body(node_id, block_id),
+                Action::RestartMining { .. } => true,
+            });
+        if !held.is_empty() {
+            self.held_relays.entry(node_id).or_default().extend(held);
+        }
+        let actions = actions.as_slice();
+        // broadcast では続けて並ぶ同じブロックの `Propagate` を 1 つのイベントにまとめる。
+        let mut broadcast: Option<(BlockId, Vec<NodeId>)> = None;
+        for action in self.shuffle_propagation_targets(actions) {
+            if self.propagation_model == PropagationModel::Broadcast {
+                if let Action::Propagate { block_id, to } = *action {
+                    debug_assert_ne!(node_id, to, "a node must not propagate to itself");
+                    if self.pending_joins.contains(&to) {
+                        continue;
+                    }
+                    match &mut broadcast {
+                        Some((pending, targets)) if *pending == block_id => targets.push(to),
+                        _ => {
+                            if let Some((pending, targets)) =
+                                broadcast.replace((block_id, vec![to]))
+                            {
+                                self.enqueue_broadcast(node_id, base_time, targets, pending);
+                            }
+                        }
+                    }
+                    continue;
+                }
+                if let Some((pending, targets)) = broadcast.take() {
+                    self.enqueue_broadcast(node_id, base_time, targets, pending);
+                }
+            }
+            // Build the event type for this action.
+            let mut event_type = match action {
+                Action::Propagate { block_id, to } => {
+                    debug_assert_ne!(node_id, *to, "a node must not propagate to itself");
+                    // 未参加ノードには届かない。
+                    if self.pending_joins.contains(to) {
+                        continue;
+                    }
+                    EventType::Propagation {
+                        from: node_id,
+                        to: *to,
+                        block_id: *block_id,
+                    }
+                }
+                Action::RestartMining { prev_block_id } => EventType::BlockGeneration {
+                    minter: node_id,
+                    prev_block_id: *prev_block_id,
+                    // Dummy. We set it to proper value later in this function.
+                    block_id: GENESIS_BLOCK_ID,
+                },
+            };
+
+            // Enqueue the event (and supersede prior mining events when needed).
+            match event_type {
+                EventType::BlockGeneration {
+                    minter,
+                    prev_block_id,
+                    block_id: _,
+                } => {
+                    let minter_hashrate = self.nodes.get_node(minter).hashrate();
+                    if minter_hashrate <= 0 {
+                        // ハッシュレート 0 のノードは受信・中継だけして採掘しない（生成時間が無限大になる）。
+                        continue;
+                    }
+                    if self.arrival.is_some()
+                        && self
+                            .next_arrival
+                            .is_none_or(|(next_minter, _)| next_minter != minter)
+                    {
+                        // 決定的な到着過程では次のブロックを掘るノードだけが採掘する。
+                        if let Some(&EventType::BlockGeneration {
+                            block_id: superseded,
+                            ..
+                        }) = self
+                            .event_queue
+                            .cancel_mining(minter)
+                            .as_ref()
+                            .map(|e| e.event_type())
+                        {
+                            self.pending_retargets.remove(&superseded);
+                        }
+                        continue;
+                    }
+                    let (new_block, generation_time_us, retarget) =
+                        self.mint_block_with_generation_time(minter, prev_block_id);
+                    // 待ち時間は MAX_MINING_TIME_US で頭打ちなので、ここで飽和しても時刻が負になることはない。
+                    let next_mining_time = base_time.saturating_add(generation_time_us);
+
+                    // 置き換えられる採掘イベントのブロックは採掘されないので、その記録は捨てる。
+                    if let Some(&EventType::BlockGeneration {
+                        block_id: superseded,
+                        ..
+                    }) = self
+                        .event_queue
+                        .pending_mining(minter)
+                        .map(|e| e.event_type())
+                    {
+                        self.pending_retargets.remove(&superseded);
+                    }
+                    let EventType::BlockGeneration {
+                        minter: _,
+                        prev_block_id: _,
+                        block_id,
+                    } = &mut event_type
+                    else {
+                        unreachable!("event_type should be BlockGeneration");
+                    };
+                    *block_id = new_block.id();
+                    if let Some(retarget) = retarget {
+                        self.pending_retargets.insert(new_block.id(), retarget);
+                    }
+                    let mining_event = Event::new(next_mining_time, event_type);
+                    self.event_queue.push_mining(mining_event);
+                    self.env.blockchain.add_block(new_block);
+                }
+                EventType::Propagation {
+                    from,
+                    to,
+                    block_id,
+                } => {
+                    self.env.blockchain.mark_block_announced(block_id);
+                    self.main_chain_height = None;
+                    // 送信はしたが届かない（ヘッダも本体も落ちる）。
+                    if self.packet_loss > 0.0 && self.rng.gen_bool(self.packet_loss) {
+                        continue;
+                    }
+                    let prop_delay = self.propagation_time(from, to);
+                    // 受信側は検証を終えてから on_receiving_block に進む。
+                    let body_time = base_time + prop_delay + self.verification_delay_us;
+                    match self.header_delay_fraction {
+                        Some(fraction) => {
+                            // ヘッダで tip を切り替え、本体は後から届く。
+                            let header_delay = (prop_delay as f64 * fraction).round() as i64;
+                            self.event_queue
+                                .push(Event::new(base_time + header_delay, event_type));
+                            self.event_queue.push(Event::new(
+                                body_time,
+                                EventType::BlockBody { from, to, block_id },
+                            ));
+                        }
+                        None => self.event_queue.push(Event::new(body_time, event_type)),
+                    }
+                }
+                EventType::Broadcast { .. }
+                | EventType::BlockBody { .. }
+                | EventType::NodeJoin { .. } => {
+                    unreachable!("actions only produce propagation and mining events")
+                }
+            }
+        }
+        if let Some((block_id, targets)) = broadcast {
+            self.enqueue_broadcast(node_id, base_time, targets, block_id);
+        }
+    }
+
+    /// Event loop.
+    ///
+    /// 処理イベント数または壁時計が上限を超えた場合は、終了しない設定とみなして
+    /// [`SimulationError`] を返す。
+    ///
+    /// 終了条件を満たした時点でキューに残っているイベント（他ノードの採掘途中のブロックや伝播中のブロック）は
+    /// 既定では捨てる。直前に採掘したノードのブロックだけが数えられ、同じ高さを争っていたノードの
+    /// ブロックは孤立にすら数えられないので、短い実行では fairness が直近の採掘者に少し偏る。
+    /// [`Self::set_drain_at_end`] を有効にすると、その高さまでのイベントを処理し切ってから止める。
+    pub fn simulation(&mut self) -> Result<(), SimulationError> {
+        self.run_events(|_| false)
+    }
+
+    /// [`Self::simulation`] を走らせ、結果を [`SimulationReport`] で返す。
+    ///
+    /// ログは補助的な出力にすぎず、ロガーを入れない組み込み先でもすべての指標をここから読める。
+    pub fn run(&mut self) -> Result<SimulationReport, SimulationError> {
+        self.simulation()?;
+        Ok(self.report())
+    }
+
+    /// 固定の `end_round` の代わりに、`metric` が安定するまで走らせる。
+    ///
+    /// 生成高さ（`current_round`）が進むたびに `metric` を評価し、直近 `window` 回の値の幅
+    /// （最大 − 最小）が `tolerance` 以下になったら止める。収束しなければ通常の終了条件
+    /// （`end_round` または `set_end_main_chain_height`）が上限になる。
+    pub fn run_until_converged(
+        &mut self,
+        mut metric: impl FnMut(&Self) -> f64,
+        tolerance: f64,
+        window: usize,
+    ) -> Result<Convergence, SimulationError> {
+        assert!(
+            window >= 2,
+            "convergence window must be at least 2, got {window}"
+        );
+        let mut values = VecDeque::with_capacity(window);
+        let mut evaluated_round = self.current_round;
+        let mut converged = false;
+        self.run_events(|sim| {
+            if sim.current_round == evaluated_round {
+                return false;
+            }
+            evaluated_round = sim.current_round;
+            if values.len() == window {
+                values.pop_front();
+            }
+            values.push_back(metric(sim));
+            if values.len() < window {
+                return false;
+            }
+            let (min, max) = values
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+                    (min.min(v), max.max(v))
+                });
+            converged = max - min <= tolerance;
+            converged
+        })?;
+        Ok(Convergence {
+            converged,
+            rounds: self.current_round,
+            value: values.back().copied(),
+        })
+    }
+
+    /// イベントループ本体。各イベントの処理後に `stop` が `true` を返したら、通常の終了条件より前に止める。
+    fn run_events(&mut self, mut stop: impl FnMut(&Self) -> bool) -> Result<(), SimulationError> {
+        self.enqueue_first_mining_task();
+        let started_at = Instant::now();
+
+        // 終了条件は完成済みメインチェーン高さ（`get_main_chain` 上の tip height）。
+        // 分岐だけが伸び続ける場合は `current_round` の上限で打ち切る。
+        let round_limit = self
+            .end_main_chain_height
+            .unwrap_or(self.end_round)
+            .saturating_add(MAX_BRANCH_HEIGHT_ABOVE_END_ROUND);
+        while !self.event_queue.is_empty()
+            && self.current_round < round_limit
+            && !self.reached_end_main_chain_height()
+            && self.attack_succeeded_at.is_none()
+        {
+            self.check_runaway(started_at)?;
+            let current_event = self
+                .event_queue
+                .pop()
+                .expect("Task queue should not be empty");
+            self.process_event(&current_event);
+            if self
+                .queue_sample_interval
+                .is_some_and(|k| self.processed_events.is_multiple_of(k))
+            {
+                self.queue_samples.push(QueueSample {
+                    time: self.current_time as f64 / 1000.0,
+                    processed_events: self.processed_events,
+                    queue_len: self.event_queue.len(),
+                });
+            }
+            if stop(self) {
+                break;
+            }
+        }
+        if self.drain_at_end {
+            self.drain_pending_events(started_at)?;
+        }
+        self.trace_block_final_status();
+        Ok(())
+    }
+
+    fn process_event(&mut self, event: &Event) {
+        if !self.event_hooks.is_empty() {
+            // hook に `&self` を渡すあいだは一旦取り出しておく。
+            let mut hooks = std::mem::take(&mut self.event_hooks);
+            for hook in &mut hooks {
+                hook(event, self);
+            }
+            self.event_hooks = hooks;
+        }
+        self.current_time = event.time();
+        self.processed_events += 1;
+
+        match event.event_type() {
+            EventType::BlockGeneration {
+                minter,
+                prev_block_id: _,
+                block_id,
+            } => self.handle_block_generation(*minter, *block_id),
+
+            EventType::Propagation { from, to, block_id } => {
+                self.handle_propagation(*from, *to, *block_id)
+            }
+            EventType::Broadcast { from, to, block_id } => {
+                for &to in to {
+                    self.handle_propagation(*from, to, *block_id);
+                }
+            }
+            EventType::BlockBody { from, to, block_id } => {
+                self.handle_block_body(*from, *to, *block_id)
+            }
+            EventType::NodeJoin { node } => self.handle_node_join(*node),
+        }
+    }
+
+    /// 終了時点の `current_round` を超える高さのブロックを作らない範囲で、残りのイベントを処理し切る。
+    /// 同じ高さを争っていた採掘は、勝者のブロックが届く前に終わるものだけが完了する。
+    /// 未参加ノードの `NodeJoin` は捨てる。
+    fn drain_pending_events(&mut self, started_at: Instant) -> Result<(), SimulationError> {
+        let last_round = self.current_round;
+        while let Some(event) = self.event_queue.pop() {
+            self.check_runaway(started_at)?;
+            let beyond_last_round = match *event.event_type() {
+                EventType::BlockGeneration { block_id, .. } => self
+                    .env
+                    .blockchain
+                    .get_block(block_id)
+                    .is_none_or(|b| b.height() > last_round),
+                EventType::Propagation { .. }
+                | EventType::Broadcast { .. }
+                | EventType::BlockBody { .. } => false,
+                EventType::NodeJoin { .. } => true,
+            };
+            if !beyond_last_round {
+                self.process_event(&event);
+            }
+        }
+        Ok(())
+    }
+
+    /// `end_main_chain_height` が設定されていて、告知済みメインチェーンがその高さに届いたか。
+    fn reached_end_main_chain_height(&mut self) -> bool {
+        let Some(target) = self.end_main_chain_height else {
+            return false;
+        };
+        let height = *self
+            .main_chain_height
+            .get_or_insert_with(|| self.env.blockchain.main_chain_height());
+        height >= target
+    }
+
+    fn check_runaway(&self, started_at: Instant) -> Result<(), SimulationError> {
+        if self.processed_events >= self.max_events {
+            return Err(SimulationError::EventLimitExceeded {
+                processed_events: self.processed_events,
+                max_events: self.max_events,
+                current_round: self.current_round,
+                end_round: self.end_round,
+            });
+        }
+        if let Some(max_wall_clock) = self.max_wall_clock
+            && self.processed_events.is_multiple_of(WALL_CLOCK_CHECK_INTERVAL)
+        {
+            let elapsed = started_at.elapsed();
+            if elapsed > max_wall_clock {
+                return Err(SimulationError::WallClockLimitExceeded {
+                    elapsed,
+                    max_wall_clock,
+                    current_round: self.current_round,
+                    end_round: self.end_round,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn enqueue_first_mining_task(&mut self) {
+        // 途中参加ノードは参加時刻に NodeJoin を積み、それまでは何もしない。
+        for node in self.nodes.nodes() {
+            if let Some(join_time) = node.join_time.filter(|_| !node.is_initially_active()) {
+                self.pending_joins.insert(node.id());
+                self.event_queue.push(Event::new(
+                    join_time.saturating_mul(1000),
+                    EventType::NodeJoin { node: node.id() },
+                ));
+            }
+        }
+        if self.record_tip_changes {
+            // 全ノードは時刻 0 に genesis を採用した状態から始まる。
+            for node_id in self.env.nodes() {
+                if self.pending_joins.contains(node_id) {
+                    continue;
+                }
+                self.tip_changes.push(TipChange {
+                    time: 0.0,
+                    node_id: node_id.into_usize(),
+                    adopted_height: 0,
+                });
+            }
+        }
+        self.schedule_next_arrival();
+        let mut actions: Vec<(NodeId, Action)> = vec![];
+        for node_id in self.env.nodes() {
+            if self.pending_joins.contains(node_id) {
+                continue;
+            }
+            actions.push((
+                *node_id,
+                Action::RestartMining {
+                    prev_block_id: GENESIS_BLOCK_ID,
+                },
+            ));
+        }
+        for (node_id, action) in actions {
+            self.enqueue_actions(node_id, &[action]);
+        }
+    }
+
+    /// 決定的な到着過程の次の間隔を取り出し、次のブロックを掘るノードをハッシュレートに比例して選ぶ。
+    fn schedule_next_arrival(&mut self) {
+        let Some(arrival) = self.arrival.as_mut() else {
+            return;
+        };
+        let Some(interval_us) = arrival.next_interval_us() else {
+            self.next_arrival = None;
+            return;
+        };
+        let miners: Vec<NodeId> = self
+            .env
+            .nodes()
+            .iter()
+            .copied()
+            .filter(|id| !self.pending_joins.contains(id))
+            .filter(|id| self.nodes.get_node(*id).hashrate() > 0)
+            .collect();
+        self.next_arrival = miners
+            .choose_weighted(&mut self.rng, |id| self.nodes.get_node(*id).hashrate())
+            .ok()
+            .map(|&minter| (minter, self.current_time.saturating_add(interval_us)));
+    }
+
+    fn handle_block_generation(&mut self, minter: NodeId, block_id: BlockId) {
+        if !self.is_block_valid(block_id) {
+            // 不正なブロックは完成扱いにも公開もせず、元の tip の上で採掘し直す。
+            let prev_block_id = self.current_head(minter);
+            self.enqueue_actions(minter, &[Action::RestartMining { prev_block_id }]);
+            return;
+        }
+        self.generated_at.insert(block_id, self.current_time);
+        if self.traced_block == Some(block_id) {
+            let height = self.env.blockchain.get_block(block_id).unwrap().height();
+            self.record_block_trace(BlockTraceEvent::Generated {
+                time_ms: self.current_time / 1000,
+                block_id,
+                minter: minter.into_usize(),
+                height,
+            });
+        }
+        self.track_marked_tx(minter, block_id);
+        self.env
+            .blockchain
+            .mark_block_generation_completed(block_id);
+        self.env
+            .blockchain
+            .record_first_seen(minter, block_id, self.current_time);
+        self.note_dag_block(minter, block_id);
+        self.main_chain_height = None;
+        if let Some(retarget) = self.pending_retargets.remove(&block_id) {
+            log::debug!(
+                "DAA: {:e} -> {:e} (rate: {:.2}) @ height {}, avg block time: {:.0} ms, clamp: {:?}, block ID: {}",
+                retarget.old_difficulty,
+                retarget.new_difficulty,
+                retarget.new_difficulty / retarget.old_difficulty,
+                retarget.height,
+                retarget.avg_block_time,
+                retarget.clamp,
+                block_id,
+            );
+            self.retargets.push(retarget);
+        }
+        let new_block = self.env.blockchain.get_block(block_id).unwrap();
+
+        // Run strategy callback and schedule follow-up tasks.
+        let previous_head = self.current_head(minter);
+        let actions = self
+            .nodes
+            .get_node_mut(minter)
+            .mining_strategy_mut()
+            .on_mining_block(block_id, self.current_time, &self.env, minter);
+
+        let round_advanced = self.current_round < new_block.height();
+        if round_advanced {
+            self.current_round = new_block.height();
+            if self
+                .chain_snapshot_interval
+                .is_some_and(|k| self.current_round % k == 0)
+            {
+                self.chain_snapshots.push(self.env.blockchain.get_main_chain());
+            }
+        }
+
+        if log::log_enabled!(log::Level::Trace) {
+            self.trace(TraceEvent::BlockGeneration {
+                time_ms: self.current_time / 1000,
+                block_id,
+                height: new_block.height(),
+                minter: minter.into_usize(),
+                difficulty: new_block.difficulty().as_f64(),
+            });
+        }
+
+        self.record_tip_change(minter, previous_head);
+        self.schedule_next_arrival();
+        self.enqueue_actions(minter, &actions);
+        if let Some((next_minter, _)) = self.next_arrival
+            && self.event_queue.pending_mining(next_minter).is_none()
+        {
+            // 次に掘るノードが別なら、いま採用している tip の上で採掘を始めさせる。
+            let prev_block_id = self.current_head(next_minter);
+            self.enqueue_actions(next_minter, &[Action::RestartMining { prev_block_id }]);
+        }
+
+        // 新しい採掘イベントがキューに載った後で刈り込む。
+        if round_advanced
+            && self
+                .prune_depth
+                .is_some_and(|d| self.current_round % d == 0)
+        {
+            self.prune_stale_forks();
+        }
+    }
+
+    /// 途中参加ノードをオンラインにし、現在の（告知済み）メインチェーン tip を渡して採掘を始めさせる。
+    fn handle_node_join(&mut self, node_id: NodeId) {
+        self.pending_joins.remove(&node_id);
+        self.env.total_hashrate += self.nodes.get_node(node_id).hashrate();
+
+        let tip = *self
+            .env
+            .blockchain
+            .get_main_chain()
+            .last()
+            .expect("main chain always has genesis");
+        self.env
+            .blockchain
+            .record_first_seen(node_id, tip, self.current_time);
+        let mut actions = self
+            .nodes
+            .get_node_mut(node_id)
+            .mining_strategy_mut()
+            .on_receiving_block(tip, self.current_time, &self.env, node_id);
+        if !actions
+            .iter()
+            .any(|a| matches!(a, Action::RestartMining { .. }))
+        {
+            // tip が genesis のままなら乗り換えが起きないので、ここで採掘を始める。
+            actions.push(Action::RestartMining {
+                prev_block_id: self.current_head(node_id),
+            });
+        }
+        if self.record_tip_changes {
+            // 参加時刻に渡された tip を採用した状態から始まる（genesis でも 1 行残す）。
+            let head = self.current_head(node_id);
+            self.tip_changes.push(TipChange {
+                time: self.current_time as f64 / 1000.0,
+                node_id: node_id.into_usize(),
+                adopted_height: self.env.blockchain.block_height(head),
+            });
+        }
+        self.update_agreed_block();
+        self.enqueue_actions(node_id, &actions);
+        log::debug!(
+            "🔌 time (ms): {}, node {} joined at height {}",
+            self.current_time / 1000,
+            node_id,
+            self.env.blockchain.block_height(tip)
+        );
+    }
+
+    fn handle_propagation(&mut self, from: NodeId, to: NodeId, block_id: BlockId) {
+        if !self.is_block_valid(block_id) {
+            return;
+        }
+        self.note_dag_block(to, block_id);
+        self.env
+            .blockchain
+            .record_first_seen(to, block_id, self.current_time);
+        if self.header_delay_fraction.is_some()
+            && self.propagation_model == PropagationModel::PerPeer
+        {
+            // header-first relay ではこれはヘッダで、本体は `handle_block_body` で届く。
+            self.headers_only.entry(to).or_default().insert(block_id);
+        }
+        if self.traced_block == Some(block_id) {
+            self.record_block_trace(BlockTraceEvent::Received {
+                time_ms: self.current_time / 1000,
+                block_id,
+                from: from.into_usize(),
+                to: to.into_usize(),
+            });
+        }
+        // Run strategy callback and schedule follow-up tasks.
+        let previous_head = self.current_head(to);
+        let actions = self
+            .nodes
+            .get_node_mut(to)
+            .mining_strategy_mut()
+            .on_receiving_block(block_id, self.current_time, &self.env, to);
+        self.record_tip_change(to, previous_head);
+        self.enqueue_actions(to, &actions);
+
+        if log::log_enabled!(log::Level::Trace) {
+            let block = self.env.blockchain.get_block(block_id).unwrap();
+            self.trace(TraceEvent::Propagation {
+                time_ms: self.current_time / 1000,
+                block_id,
+                height: block.height(),
+                minter: block.minter().map(NodeId::into_usize),
+                from: from.into_usize(),
+                to: to.into_usize(),
+            });
+        }
+    }
+
+    /// `node` が `block_id` かその祖先の本体をまだ受け取っていないか（header-first relay）。
+    fn lacks_body(&self, node: NodeId, block_id: BlockId) -> bool {
+        let Some(pending) = self.headers_only.get(&node).filter(|p| !p.is_empty()) else {
+            return false;
+        };
+        // 本体待ちのブロックより低い祖先は辿らなくてよい。
+        let lowest = pending
+            .iter()
+            .map(|&id| self.env.blockchain.block_height(id))
+            .min()
+            .unwrap_or(0);
+        let mut current = self.env.blockchain.get_block(block_id);
+        while let Some(block) = current.filter(|b| b.height() >= lowest) {
+            if pending.contains(&block.id()) {
+                return true;
+            }
+            current = block
+                .prev_block_id()
+                .and_then(|id| self.env.blockchain.get_block(id));
+        }
+        false
+    }
+
+    /// header-first relay でブロック本体が届いた（検証遅延込み）。tip の切り替えはヘッダの時点で
+    /// 済んでいるので、ここでは保留していた中継のうち本体が揃ったものを送り出す。
+    fn handle_block_body(&mut self, from: NodeId, to: NodeId, block_id: BlockId) {
+        if let Some(pending) = self.headers_only.get_mut(&to) {
+            pending.remove(&block_id);
+        }
+        if let Some(held) = self.held_relays.remove(&to) {
+            // まだ祖先の本体が足りない中継は enqueue_actions がもう一度保留する。
+            self.enqueue_actions(to, &held);
+        }
+        if log::log_enabled!(log::Level::Trace) {
+            let height = self
+                .env
+                .blockchain
+                .get_block(block_id)
+                .map_or(0, |b| b.height());
+            self.trace(TraceEvent::BlockBody {
+                time_ms: self.current_time / 1000,
+                block_id,
+                height,
+                from: from.into_usize(),
+                to: to.into_usize(),
+            });
+        }
+    }
+
+    /// 追跡ブロックに起きたことを記録し、`json_trace` に応じて JSON 1 行か自由書式で info レベルに出す。
+    fn record_block_trace(&mut self, event: BlockTraceEvent) {
+        if self.json_trace {
+            let line = serde_json::to_string(&event).expect("trace event is serializable");
+            log::info!("{line}");
+        } else {
+            log::info!("{event}");
+        }
+        self.block_trace.push(event);
+    }
+
+    /// 追跡ブロックが `previous_head` と `head` の一方のチェーンにだけあれば、採用・放棄として記録する。
+    fn trace_block_tip_change(&mut self, node_id: NodeId, previous_head: BlockId, head: BlockId) {
+        let Some(block_id) = self.traced_block else {
+            return;
+        };
+        let chain = &self.env.blockchain;
+        let on_chain = |tip| {
+            chain.get_block(block_id).is_some() && chain.common_ancestor(block_id, tip) == block_id
+        };
+        let time_ms = self.current_time / 1000;
+        let node = node_id.into_usize();
+        match (on_chain(previous_head), on_chain(head)) {
+            (false, true) => self.record_block_trace(BlockTraceEvent::Adopted {
+                time_ms,
+                block_id,
+                node,
+            }),
+            (true, false) => self.record_block_trace(BlockTraceEvent::Abandoned {
+                time_ms,
+                block_id,
+                node,
+                new_head: head,
+            }),
+            _ => {}
+        }
+    }
+
+    /// 追跡ブロックが告知済みメインチェーンに残ったか、残ったならその確認数を記録する。
+    fn trace_block_final_status(&mut self) {
+        let Some(block_id) = self.traced_block else {
+            return;
+        };
+        let main_chain = self.env.blockchain.get_main_chain();
+        let depth = main_chain
+            .iter()
+            .position(|&id| id == block_id)
+            .map(|index| (main_chain.len() - index) as i64);
+        self.record_block_trace(BlockTraceEvent::Final {
+            block_id,
+            main_chain: depth.is_some(),
+            depth,
+        });
+    }
+
+    /// trace レベルのイベントを `json_trace` に応じて JSON 1 行か自由書式で出す。
+    fn trace(&self, event: TraceEvent) {
+        if self.json_trace {
+            let line = serde_json::to_string(&event).expect("trace event is serializable");
+            log::trace!("{line}");
+        } else {
+            log::trace!("{event}");
+        }
+    }
+
+    /// 現在のノード構成（ハッシュレート・戦略）をプロファイルとして書き出す。
+    /// 同じプロファイルと seed で `new_with_profile` すれば同じネットワークを再現できる。
+    pub fn export_profile(&self) -> NetworkProfile {
+        NetworkProfile {
+            nodes: self
+                .nodes
+                .nodes()
+                .iter()
+                .map(|node| NodeProfile {
+                    hashrate: node.hashrate(),
+                    strategy: node.mining_strategy().to_profile(),
+                    join_time: node.join_time,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn print_hashrates(&self) {
+        log::info!(
+            "hashrates: {:?}",
+            self.nodes
+                .nodes()
+                .iter()
+                .map(|n| n.hashrate())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// 解決済みの設定（生成した seed やハッシュレートを含む）を `key: value` の 1 行ずつにまとめる。
+    pub fn resolved_config(&self) -> String {
+        let genesis_difficulty = self
+            .env
+            .blockchain
+            .get_block(GENESIS_BLOCK_ID)
+            .expect("genesis is never pruned")
+            .difficulty();
+        let hashrates: Vec<i64> = self.nodes.nodes().iter().map(|n| n.hashrate()).collect();
+        let strategies: Vec<&str> = self
+            .nodes
+            .nodes()
+            .iter()
+            .map(|n| n.mining_strategy().name())
+            .collect();
+        let fork_choice = if self.env.blockchain.fork_choice().is_some() {
+            "custom"
+        } else {
+            "protocol"
+        };
+        [
+            format!("seed: {}", self.seed),
+            format!("tie_break_seed: {}", self.tie_break_seed),
+            format!("num_nodes: {}", hashrates.len()),
+            format!("hashrates: {hashrates:?}"),
+            format!("strategies: {strategies:?}"),
+            format!("protocol: {}", self.env.protocol.name()),
+            format!(
+                "target_block_time_ms: {}",
+                self.env.protocol.target_block_time_ms()
+            ),
+            format!("genesis_difficulty: {:e}", genesis_difficulty.as_f64()),
+            format!(
+                "expected_block_interval_ms: {}",
+                self.env
+                    .protocol
+                    .expected_block_interval(self.total_hashrate, genesis_difficulty)
+            ),
+            format!("freeze_difficulty: {}", self.freeze_difficulty),
+            format!("mining_time_shape: {}", self.mining_time_shape),
+            format!("delay_ms: {}", self.env.delay_us as f64 / 1000.0),
+            format!(
+                "propagation_delay_mode: {:?}",
+                self.env.propagation_delay_mode
+            ),
+            format!("propagation_target: {:?}", self.env.propagation_target),
+            format!("propagation_model: {:?}", self.propagation_model),
+            format!("delay_jitter_pct: {}", self.delay_jitter_pct),
+            format!("packet_loss: {}", self.packet_loss),
+            format!(
+                "verification_delay_ms: {}",
+                self.verification_delay_us as f64 / 1000.0
+            ),
+            format!(
+                "tie_breaking_rule: {:?}",
+                self.env.blockchain.tie_breaking_rule()
+            ),
+            format!("fork_choice: {fork_choice}"),
+            format!("gamma: {:?}", self.env.blockchain.gamma()),
+            format!("max_height_gap: {:?}", self.env.max_height_gap),
+            format!("end_round: {}", self.end_round),
+            format!("end_main_chain_height: {:?}", self.end_main_chain_height),
+            format!("drain_at_end: {}", self.drain_at_end),
+            format!("burn_in_rounds: {}", self.burn_in_rounds),
+            format!("confirmations: {}", self.confirmations),
+            format!("reward_weighting: {:?}", self.reward_weighting),
+            format!("prune_depth: {:?}", self.prune_depth),
+            format!("max_events: {}", self.max_events),
+        ]
+        .join("\n")
+    }
+
+    /// [`Self::resolved_config`] を info レベルで出す（再現用のログ）。
+    pub fn log_resolved_config(&self) {
+        log::info!("Resolved configuration:");
+        for line in self.resolved_config().lines() {
+            log::info!("- {line}");
+        }
+    }
+
+    pub fn print_blockchain(&self) {
+        log::info!("Blockchain:");
+        for block in self.env.blockchain.blocks() {
+            log::info!(
+                "Block ID: {}, Difficulty: {:.4}, Height: {}, Minter: {:?}, Time: {}, Created at: {}, Prev Block ID: {:?}, Rand: {}",
+                block.id(),
+                block.difficulty().as_f64(),
+                block.height(),
+                block.minter(),
+                block.timestamp(),
+                block.created_at(),
+                block.prev_block_id(),
+                block.rand()
+            );
+        }
+    }
+
+    /// 乱数生成器の seed（`--seed` 省略時に自動生成されたものを含む）。
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// シミュレーション結果のサマリを返す。指標は burn-in 区間のブロックを除いて計算する。
+    pub fn report(&self) -> SimulationReport {
+        let current_time_ms = self.current_time / 1000;
+        let main_chain = self.env.blockchain.get_main_chain();
+        let main_chain_height = self.env.blockchain.main_chain_height();
+
+        // 計測はジェネシスの次、または burn-in 後最初の高さから。
+        // 直前のブロック（burn-in 区間の最後）の時刻を計測開始時刻とする。
+        let first_measured_height = self.burn_in_rounds.max(1);
+        let measured_main_chain_blocks = (main_chain_height - first_measured_height + 1).max(0);
+        let measure_start_ms = main_chain
+            .get((first_measured_height - 1) as usize)
+            .and_then(|&id| self.env.blockchain.get_block(id))
+            .map_or(current_time_ms, |b| b.created_at());
+        let measured_time_ms = self.current_time as f64 / 1000.0 - measure_start_ms as f64;
+
+        let ideal_blocks = measured_time_ms / self.env.protocol.target_block_time_ms() as f64;
+        let effective_block_rate = if ideal_blocks > 0.0 {
+            measured_main_chain_blocks as f64 / ideal_blocks
+        } else {
+            0.0
+        };
+        let stale_rate = self
+            .env
+            .blockchain
+            .chain_metrics(None, Some(first_measured_height), None)
+            .stale_rate;
+        let tip_difficulty = main_chain
+            .last()
+            .and_then(|&id| self.env.blockchain.get_block(id))
+            .expect("main chain always has genesis")
+            .difficulty();
+        let expected_block_interval_ms = self
+            .env
+            .protocol
+            .expected_block_interval(self.total_hashrate, tip_difficulty);
+        let delay_over_block_interval =
+            (self.env.delay_us as f64 / 1000.0) / expected_block_interval_ms.max(1) as f64;
+        let fairness = self.mining_fairness(self.confirmations);
+        let (fairness_chi_square, fairness_p_value) = self.fairness_chi_square();
+        let (honest_orphans_by_attacker, honest_orphans_by_fork) = self
+            .env
+            .blockchain
+            .honest_orphans_by_cause(self.env.blockchain.attackers(), first_measured_height);
+        SimulationReport {
+            seed: self.seed,
+            current_time_ms,
+            end_round: self.end_round,
+            max_generated_height: self.current_round,
+            total_blocks: self.env.blockchain.len(),
+            main_chain_height,
+            main_chain_height_for_export: self.env.blockchain.main_chain_height_for_export(),
+            max_block_height: self.env.blockchain.max_height(),
+            last_difficulty: self
+                .env
+                .blockchain
+                .last_block()
+                .map_or(0.0, |b| b.difficulty().as_f64()),
+            burn_in_rounds: self.burn_in_rounds,
+            measured_main_chain_blocks,
+            avg_block_time_ms: measured_time_ms / measured_main_chain_blocks.max(1) as f64,
+            block_time_autocorrelation: self.env.blockchain.inter_block_autocorrelation(1),
+            effective_block_rate,
+            stale_rate,
+            expected_block_interval_ms,
+            delay_over_block_interval,
+            theoretical_stale_rate: theoretical_orphan_rate(delay_over_block_interval),
+            longest_fork: self.env.blockchain.longest_fork(),
+            wasted_hashrate_fraction: self.wasted_hashrate_fraction(measure_start_ms as f64),
+            avg_confirmation_time_ms: self.avg_confirmation_time_ms(first_measured_height),
+            empty_block_rate: self.empty_block_rate(),
+            nakamoto_coefficient: nakamoto_coefficient(&fairness),
+            equal_hashrate_share_variance: equal_hashrate_share_variance(&fairness),
+            fairness_chi_square,
+            fairness_p_value,
+            fairness,
+            strategies: self.strategy_shares(self.confirmations),
+            observers: self
+                .nodes
+                .nodes()
+                .iter()
+                .filter(|node| self.is_observer(node.id()))
+                .map(|node| {
+                    let (reorgs, max_reorg_depth) = self
+                        .observer_reorgs
+                        .get(&node.id())
+                        .copied()
+                        .unwrap_or_default();
+                    let head = node.mining_strategy().current_head();
+                    ObserverStats {
+                        node_id: node.id().into_usize(),
+                        reorgs,
+                        max_reorg_depth,
+                        adopted_height: self.env.blockchain.block_height(head),
+                    }
+                })
+                .collect(),
+            catch_up: CatchUpStats::from_intervals(
+                &self
+                    .env
+                    .blockchain
+                    .catch_up_intervals(self.env.blockchain.attackers(), first_measured_height),
+            ),
+            honest_orphans_by_attacker,
+            honest_orphans_by_fork,
+            tie_breaks: self.env.tie_breaks(),
+            double_spend: self.double_spend_height.map(|height| DoubleSpendReport {
+                height,
+                block_id: self.marked_tx_block,
+                reversals: self.marked_tx_reversals,
+                max_reversed_confirmations: self.max_reversed_confirmations,
+                succeeded: self
+                    .marked_tx_block
+                    .is_some_and(|id| !main_chain.contains(&id)),
+            }),
+            attack: self
+                .attack_success_condition
+                .map(|condition| AttackSuccessReport {
+                    confirmations: condition.confirmations,
+                    max_reorg_depth: self.max_attacker_reorg_depth,
+                    succeeded_at_ms: self.attack_succeeded_at.map(|t| t as f64 / 1000.0),
+                }),
+            ghostdag: self.env.protocol.ghostdag_k().map(|k| {
+                let ordering = GhostDagOrdering::compute(&self.env.blockchain, k);
+                // ジェネシスは常に青なので数えない。
+                GhostDagReport {
+                    k,
+                    blue_blocks: ordering.blue.len() - 1,
+                    red_blocks: ordering.order.len() - ordering.blue.len(),
+                }
+            }),
+        }
+    }
+
+    /// 孤立ブロックごとに minter のハッシュレート × `mining_time` を足し、
+    /// `since_ms` から現在までの総ハッシュレート × 時間（途中参加ノードは参加後のみ）で割る。
+    fn wasted_hashrate_fraction(&self, since_ms: f64) -> f64 {
+        let now_ms = self.current_time as f64 / 1000.0;
+        let total: f64 = self
+            .nodes
+            .nodes()
+            .iter()
+            .map(|node| {
+                let active_from = since_ms.max(node.join_time.unwrap_or(0) as f64);
+                node.hashrate() as f64 * (now_ms - active_from).max(0.0)
+            })
+            .sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let wasted: f64 = self
+            .env
+            .blockchain
+            .orphaned_blocks(self.burn_in_rounds)
+            .iter()
+            .filter_map(|b| {
+                let minter = b.minter?;
+                Some(self.nodes.get_node(minter).hashrate() as f64 * b.mining_time)
+            })
+            .sum();
+        wasted / total
+    }
+
+    pub fn print_summary(&self, report: &SimulationReport) {
+        let format = self.output_format;
+        let unit = format.time_unit.suffix();
+        log::info!("Simulation Summary:");
+        log::info!("- Seed: {}", report.seed);
+        log::info!(
+            "- Current time ({unit}): {}",
+            format.format_time(report.current_time_ms as f64)
+        );
+        log::info!("- End round target (main chain): {}", report.end_round);
+        log::info!(
+            "- Max generated height (any branch): {}",
+            report.max_generated_height
+        );
+        log::info!("- Total blocks: {}", report.total_blocks);
+        log::info!(
+            "- Main chain height (announced): {}",
+            report.main_chain_height
+        );
+        log::info!(
+            "- Main chain height (export, incl. unannounced): {}",
+            report.main_chain_height_for_export
+        );
+        log::info!(
+            "- Max block height (any branch): {}",
+            report.max_block_height
+        );
+        // difficulty
+        log::info!("Difficulty: {:.4}", report.last_difficulty);
+        if report.burn_in_rounds > 0 {
+            log::info!(
+                "- Burn-in: blocks below height {} are excluded from the metrics below",
+                report.burn_in_rounds
+            );
+            if report.measured_main_chain_blocks == 0 {
+                log::warn!(
+                    "- No main-chain blocks remain after the burn-in period (burn-in rounds: {}, main chain height: {}); metrics are empty",
+                    report.burn_in_rounds,
+                    report.main_chain_height
+                );
+            }
+        }
+        log::info!(
+            "- Avg. time/block ({unit}): {}",
+            format.format_time(report.avg_block_time_ms)
+        );
+        log::info!(
+            "- Block time autocorrelation (lag 1): {:.4}",
+            report.block_time_autocorrelation
+        );
+        log::info!(
+            "- Effective block rate (main chain / ideal): {:.4}",
+            report.effective_block_rate
+        );
+        log::info!(
+            "- Stale rate: {:.4} (theoretical from Δ/T: {:.4})",
+            report.stale_rate,
+            report.theoretical_stale_rate
+        );
+        if let Some(confirmation_ms) = report.avg_confirmation_time_ms {
+            log::info!(
+                "- Avg. confirmation time, 1 block agreed by all nodes ({unit}): {}",
+                format.format_time(confirmation_ms)
+            );
+        }
+        if let Some(empty_block_rate) = report.empty_block_rate {
+            log::info!("- Empty block rate: {empty_block_rate:.4}");
+        }
+        log::info!(
+            "- Hashrate wasted on orphans: {:.4}",
+            report.wasted_hashrate_fraction
+        );
+        log::info!(
+            "- Δ/T: {:.4} (expected block interval ({unit}): {})",
+            report.delay_over_block_interval,
+            format.format_time(report.expected_block_interval_ms as f64)
+        );
+        match &report.longest_fork {
+            Some(fork) => log::info!(
+                "- Longest fork: {} blocks from height {} (nodes: {:?})",
+                fork.depth,
+                fork.start_height,
+                fork.nodes
+            ),
+            None => log::info!("- Longest fork: none"),
+        }
+        for observer in &report.observers {
+            log::info!(
+                "- Observer {}: adopted height {}, {} reorgs (deepest {} blocks)",
+                observer.node_id,
+                observer.adopted_height,
+                observer.reorgs,
+                observer.max_reorg_depth
+            );
+        }
+        if let Some(attack) = &report.attack {
+            match attack.succeeded_at_ms {
+                Some(time_ms) => log::info!(
+                    "- Attack succeeded at {} {unit}: reorg of {} blocks (>= {} confirmations)",
+                    format.format_time(time_ms),
+                    attack.max_reorg_depth,
+                    attack.confirmations
+                ),
+                None => log::info!(
+                    "- Attack did not succeed: deepest reorg {} blocks (< {} confirmations)",
+                    attack.max_reorg_depth,
+                    attack.confirmations
+                ),
+            }
+        }
+        if let Some(ghostdag) = &report.ghostdag {
+            log::info!(
+                "- GHOSTDAG (k = {}): {} blue / {} red blocks",
+                ghostdag.k,
+                ghostdag.blue_blocks,
+                ghostdag.red_blocks
+            );
+        }
+        if let Some(double_spend) = &report.double_spend {
+            match double_spend.block_id {
+                Some(block_id) => log::info!(
+                    "- Double spend at height {} (block {}): {}, reversed {} times (max {} confirmations)",
+                    double_spend.height,
+                    block_id,
+                    if double_spend.succeeded {
+                        "succeeded"
+                    } else {
+                        "failed"
+                    },
+                    double_spend.reversals,
+                    double_spend.max_reversed_confirmations
+                ),
+                None => log::info!(
+                    "- Double spend: no honest block at height {} yet",
+                    double_spend.height
+                ),
+            }
+        }
+        if report.honest_orphans_by_attacker > 0 {
+            log::info!(
+                "- Honest orphans: {} displaced by attacker chains, {} lost in natural forks",
+                report.honest_orphans_by_attacker,
+                report.honest_orphans_by_fork
+            );
+        }
+        if report.tie_breaks.ties > 0 {
+            log::info!(
+                "- Ties: {} ({} switched to the incoming tip, {} kept the current tip)",
+                report.tie_breaks.ties,
+                report.tie_breaks.resolved_for_incoming,
+                report.tie_breaks.unchanged
+            );
+        }
+        if report.catch_up.recoveries > 0 {
+            log::info!(
+                "- Catch-up: {} recoveries, mean {:.2} blocks (max {}), mean {} {unit} (max {})",
+                report.catch_up.recoveries,
+                report.catch_up.mean_blocks,
+                report.catch_up.max_blocks,
+                format.format_time(report.catch_up.mean_time_ms),
+                format.format_time(report.catch_up.max_time_ms as f64)
+            );
+        }
+    }
+
+    /// エクスポート用メインチェーン（未告知含む）の各ブロックを出力書式で CSV 行にする。
+    pub fn main_chain_records(&self) -> Vec<Record> {
+        let format = self.output_format;
+        self.env
+            .blockchain
+            .main_chain_blocks_for_export()
+            .map(|block| Record {
+                round: block.height() as u32,
+                timestamp: format.time(block.timestamp() as f64),
+                difficulty: format.round(block.difficulty().as_f64()),
+                mining_time: format.time(block.mining_time),
+                minter: block.minter(),
+                created_at: format.time(block.created_at() as f64),
+            })
+            .collect()
+    }
+
+    /// 採掘されたブロックで行われた難易度調整（採掘順）を出力書式で換算したもの。
+    pub fn retarget_records(&self) -> Vec<RetargetEvent> {
+        let format = self.output_format;
+        self.retargets
+            .iter()
+            .map(|retarget| RetargetEvent {
+                avg_block_time: format.time(retarget.avg_block_time),
+                old_difficulty: format.round(retarget.old_difficulty),
+                new_difficulty: format.round(retarget.new_difficulty),
+                ..retarget.clone()
+            })
+            .collect()
+    }
+
+    /// 記録した tip の切り替えを出力書式で換算したもの。
+    pub fn tip_change_records(&self) -> Vec<TipChange> {
+        self.tip_changes
+            .iter()
+            .map(|change| TipChange {
+                time: self.output_format.time(change.time),
+                ..change.clone()
+            })
+            .collect()
+    }
+
+    /// 記録したイベントキューの長さを出力書式で換算したもの。
+    pub fn queue_sample_records(&self) -> Vec<QueueSample> {
+        self.queue_samples
+            .iter()
+            .map(|sample| QueueSample {
+                time: self.output_format.time(sample.time),
+                ..sample.clone()
+            })
+            .collect()
+    }
+
+    /// Traverse the main chain, compute rewards, and return per-node mining fairness
+    /// (fairness = reward share / hashrate share) in node ID order.
+    ///
+    /// Only main-chain blocks buried under at least `confirmations` blocks are rewarded;
+    /// `confirmations = 0` rewards every main-chain block. Blocks in the burn-in period are
+    /// never rewarded.
+    pub fn mining_fairness(&self, confirmations: i64) -> Vec<NodeInfo> {
+        // Count rewards per node (genesis has no minter).
+        let mut rewards: HashMap<NodeId, f64> = match self.reward_weighting {
+            RewardWeighting::Blocks => self
+                .env
+                .blockchain
+                .main_chain_rewards(confirmations, self.burn_in_rounds)
+                .into_iter()
+                .map(|(node, blocks)| (node, blocks as f64))
+                .collect(),
+            RewardWeighting::Work => self
+                .env
+                .blockchain
+                .main_chain_work_rewards(confirmations, self.burn_in_rounds),
+        };
+        // GHOST のプロトコルでは uncle/nephew 報酬も収入に含める（ブロック報酬を単位とするので、ブロック数で数えるときだけ）。
+        let uncle_rewards = self
+            .env
+            .protocol
+            .uncle_reward_rule()
+            .map(|rule| {
+                self.env
+                    .blockchain
+                    .uncle_rewards(rule, confirmations, self.burn_in_rounds)
+            })
+            .unwrap_or_default();
+        if self.reward_weighting == RewardWeighting::Blocks {
+            for (node, extra) in &uncle_rewards {
+                *rewards.entry(*node).or_insert(0.0) += extra.uncle + extra.nephew;
+            }
+        }
+        let orphans = self
+            .env
+            .blockchain
+            .orphaned_blocks_by_minter(self.burn_in_rounds);
+        self.fairness_from_rewards(&rewards, &orphans, &uncle_rewards)
+    }
+
+    /// 戦略名ごとにハッシュレートとメインチェーンのブロック数（burn-in 後、確認数 `confirmations`）を合計し、
+    /// 全体に対する割合にする。戦略クラスが力に見合う以上に稼いでいるかを一目で比べる用。
+    pub fn strategy_shares(&self, confirmations: i64) -> Vec<StrategyShare> {
+        let blocks = self
+            .env
+            .blockchain
+            .main_chain_rewards(confirmations, self.burn_in_rounds);
+        let total_blocks = blocks.values().sum::<u64>();
+        // 戦略名 -> (ノード数, ハッシュレート, ブロック数)
+        let mut groups: BTreeMap<&str, (usize, i64, u64)> = BTreeMap::new();
+        for node in self.nodes.nodes() {
+            let group = groups.entry(node.mining_strategy().name()).or_default();
+            group.0 += 1;
+            group.1 += node.hashrate();
+            group.2 += blocks.get(&node.id()).copied().unwrap_or(0);
+        }
+        groups
+            .into_iter()
+            .map(|(strategy, (nodes, hashrate, blocks))| {
+                let hashrate_share = if self.total_hashrate > 0 {
+                    hashrate as f64 / self.total_hashrate as f64
+                } else {
+                    0.0
+                };
+                let block_share = if total_blocks > 0 {
+                    blocks as f64 / total_blocks as f64
+                } else {
+                    0.0
+                };
+                StrategyShare {
+                    strategy: strategy.to_string(),
+                    nodes,
+                    hashrate_share,
+                    block_share,
+                    fairness: if hashrate_share > 0.0 {
+                        block_share / hashrate_share
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// ハッシュレート比を期待比率、メインチェーンのブロック数（burn-in 後）を観測値とした
+    /// カイ二乗適合度検定。`(統計量, p 値)` を返す。p 値が小さいほど報酬がハッシュレートに比例していない。
+    ///
+    /// ハッシュレート 0 のノードは除外し、自由度は残りのノード数 − 1。
+    /// 自由度が 0 かブロックがないときは `(0.0, 1.0)`。
+    pub fn fairness_chi_square(&self) -> (f64, f64) {
+        let blocks = self
+            .env
+            .blockchain
+            .main_chain_rewards(0, self.burn_in_rounds);
+        let miners: Vec<&Node> = self
+            .nodes
+            .nodes()
+            .iter()
+            .filter(|node| node.hashrate() > 0)
+            .collect();
+        let total_blocks: u64 = miners
+            .iter()
+            .map(|node| blocks.get(&node.id()).copied().unwrap_or(0))
+            .sum();
+        let total_hashrate: i64 = miners.iter().map(|node| node.hashrate()).sum();
+        if miners.len() < 2 || total_blocks == 0 {
+            return (0.0, 1.0);
+        }
+        let statistic = miners
+            .iter()
+            .map(|node| {
+                let observed = blocks.get(&node.id()).copied().unwrap_or(0) as f64;
+                let expected = total_blocks as f64 * node.hashrate() as f64 / total_hashrate as f64;
+                (observed - expected).powi(2) / expected
+            })
+            .sum::<f64>();
+        let df = (miners.len() - 1) as f64;
+        (statistic, chi_square_survival(statistic, df))
+    }
+
+    fn fairness_from_rewards(
+        &self,
+        rewards: &HashMap<NodeId, f64>,
+        orphans: &HashMap<NodeId, u64>,
+        uncle_rewards: &HashMap<NodeId, UncleRewards>,
+    ) -> Vec<NodeInfo> {
+        // Total reward across nodes.
+        let total_reward = rewards.values().sum::<f64>();
+
+        let infos: Vec<NodeInfo> = self
+            .nodes
+            .nodes()
+            .iter()
+            .map(|node| {
+                let reward = *rewards.get(&node.id()).unwrap_or(&0.0);
+                let hashrate = node.hashrate() as f64;
+                let uncle = uncle_rewards.get(&node.id()).copied().unwrap_or_default();
+
+                // reward_share = this node's reward / sum of all rewards
+                let reward_share = if total_reward > 0.0 {
+                    reward / total_reward
+                } else {
+                    0.0
+                };
+
+                // hashrate_share = this node's hashrate / total hashrate
+                let hashrate_share = if self.total_hashrate > 0 {
+                    hashrate / self.total_hashrate as f64
+                } else {
+                    0.0
+                };
+
+                // fairness = reward_share / hashrate_share
+                let fairness = if hashrate_share > 0.0 {
+                    reward_share / hashrate_share
+                } else {
+                    0.0
+                };
+
+                NodeInfo {
+                    node_id: node.id().into_usize(),
+                    strategy: node.mining_strategy().name().to_string(),
+                    reward_share,
+                    hashrate_share,
+                    fairness,
+                    orphaned_blocks: *orphans.get(&node.id()).unwrap_or(&0),
+                    uncle_reward: uncle.uncle,
+                    nephew_reward: uncle.nephew,
+                    overrides: node.mining_strategy().overrides(),
+                }
+            })
+            .collect();
+        debug_assert_fairness_invariants(&infos);
+        infos
+    }
+
+    /// Print the mining fairness ranking (the `fairness` of [`Self::report`]).
+    pub fn print_mining_fairness(&self, report: &SimulationReport) {
+        let mut fairness_data = report.fairness.clone();
+
+        // Sort by fairness descending.
+        fairness_data.sort_by(|a, b| {
+            b.fairness
+                .partial_cmp(&a.fairness)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        // Show all nodes if there are at most 30; otherwise cap at 30 rows.
+        let display_count = fairness_data.len().min(30);
+
+        if display_count == fairness_data.len() {
+            log::info!("Mining Fairness Ranking (all nodes):");
+        } else {
+            log::info!("Mining Fairness Ranking (top {}):", display_count);
+        }
+        log::info!(
+            "Rank | Node ID | Reward (%) | Hashrate (%) | Fairness (Reward Share/Hashrate Share) | Orphaned | Strategy"
+        );
+        log::info!(
+            "-----|---------|------------|--------------|--------------------------|----------|----------"
+        );
+        for (rank, info) in fairness_data.iter().take(display_count).enumerate() {
+            log::info!(
+                "{:4} | {:7} | {:10.2} | {:12.2} | {:24.6} | {:8} | {}",
+                rank + 1,
+                info.node_id,
+                info.reward_share * 100.0,
+                info.hashrate_share * 100.0,
+                info.fairness,
+                info.orphaned_blocks,
+                info.strategy
+            );
+        }
+        log::info!(
+            "Chi-square vs hashrate share: {:.2} (p = {:.4})",
+            report.fairness_chi_square,
+            report.fairness_p_value
+        );
+        log::info!("Nakamoto coefficient: {}", report.nakamoto_coefficient);
+        if let Some(variance) = report.equal_hashrate_share_variance {
+            log::info!("Reward share variance among equal-hashrate nodes: {variance:.3e}");
+        }
+
+        log::info!("Per-strategy shares:");
+        log::info!("Strategy       | Nodes | Hashrate (%) | Blocks (%) | Fairness");
+        log::info!("---------------|-------|--------------|------------|---------");
+        for share in &report.strategies {
+            log::info!(
+                "{:14} | {:5} | {:12.2} | {:10.2} | {:8.4}",
+                share.strategy,
+                share.nodes,
+                share.hashrate_share * 100.0,
+                share.block_share * 100.0,
+                share.fairness
+            );
+        }
+    }
+}
+
+/// Δ/T から見積もる理論上の孤立（stale）率 `1 - e^(-Δ/T)`。
+/// ブロックが伝播し終わる前（Δ 以内）に他のノードが次のブロックを見つける確率。
+/// 分岐が起きても孤立するのは片方だけで、採掘者自身とは競合しないので、観測値はこれをやや下回る。
+pub fn theoretical_orphan_rate(delay_over_block_interval: f64) -> f64 {
+    1.0 - (-delay_over_block_interval.max(0.0)).exp()
+}
+
+/// Nakamoto 係数: reward share の大きい順に足して、合計が 50% を**超える**までに必要な最小ノード数。
+/// ちょうど 50% は過半数ではないので、もう 1 ノード必要になる。報酬がなければ 0。
+pub fn nakamoto_coefficient(fairness: &[NodeInfo]) -> usize {
+    let mut shares: Vec<f64> = fairness.iter().map(|info| info.reward_share).collect();
+    shares.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    let mut cumulative = 0.0;
+    for (i, share) in shares.iter().enumerate() {
+        cumulative += share;
+        // 浮動小数の誤差でちょうど 0.5 を超えたと判定しないよう許容幅を取る。
+        if cumulative > 0.5 + 1e-9 {
+            return i + 1;
+        }
+    }
+    0
+}
+
+/// ハッシュレート比の相対差がこれ以内のノードを「同じハッシュレート」とみなす。
+const EQUAL_HASHRATE_TOLERANCE: f64 = 0.01;
+
+/// ハッシュレートが（ほぼ）等しいノード同士の reward share の分散。有限の実行長による運の成分で、
+/// 実行が長くなるほど 0 に近づく。
+///
+/// ハッシュレート比の相対差が [`EQUAL_HASHRATE_TOLERANCE`] 以内のノードをまとめ、2 ノード以上の組ごとの
+/// 平均からの偏差の二乗をすべての組について平均する。ハッシュレート 0 のノードは除く。
+/// そのような組がなければ `None`。
+pub fn equal_hashrate_share_variance(fairness: &[NodeInfo]) -> Option<f64> {
+    let mut nodes: Vec<&NodeInfo> = fairness
+        .iter()
+        .filter(|info| info.hashrate_share > 0.0)
+        .collect();
+    nodes.sort_by(|a, b| a.hashrate_share.total_cmp(&b.hashrate_share));
+    let (mut squared_deviation, mut grouped) = (0.0, 0);
+    let mut start = 0;
+    while start < nodes.len() {
+        let base = nodes[start].hashrate_share;
+        let end = start
+            + nodes[start..]
+                .iter()
+                .take_while(|info| info.hashrate_share - base <= base * EQUAL_HASHRATE_TOLERANCE)
+                .count();
+        let group = &nodes[start..end];
+        if group.len() >= 2 {
+            let mean = group.iter().map(|info| info.reward_share).sum::<f64>() / group.len() as f64;
+            squared_deviation += group
+                .iter()
+                .map(|info| (info.reward_share - mean).powi(2))
+                .sum::<f64>();
+            grouped += group.len();
+        }
+        start = end;
+    }
+    (grouped > 0).then(|| squared_deviation / grouped as f64)
+}
+
+/// reward share の合計が 1（報酬がなければ 0）で、fairness がすべて有限かつ非負であることを確かめる。
+/// ノード以外の minter（genesis など）が報酬に混入すると合計が 1 を下回る。
+fn debug_assert_fairness_invariants(infos: &[NodeInfo]) {
+    let total_share: f64 = infos.iter().map(|info| info.reward_share).sum();
+    debug_assert!(
+        total_share == 0.0 || (total_share - 1.0).abs() < 1e-9,
+        "reward shares must sum to 1, got {total_share}"
+    );
+    debug_assert!(
+        infos
+            .iter()
+            .all(|info| info.fairness.is_finite() && info.fairness >= 0.0),
+        "fairness must be finite and non-negative"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        BitcoinDifficulty, Difficulty, EthereumDifficulty, GenesisDifficultyMode, ProtocolType,
+    };
+    use crate::types::TimeUnit;
+
+    fn bitcoin() -> Box<dyn Protocol> {
+        ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Inferred)
+    }
+
+    /// `(hashrate, strategy)` の並びから、全ノードが最初から参加しているプロファイルを作る。
+    fn profile(
+        nodes: impl IntoIterator<Item = (i64, crate::mining_strategy::MiningStrategyEnum)>,
+    ) -> NetworkProfile {
+        NetworkProfile {
+            nodes: nodes
+                .into_iter()
+                .map(|(hashrate, strategy)| NodeProfile {
+                    hashrate,
+                    strategy,
+                    join_time: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn runaway_guard_trips_when_delay_dwarfs_generation_time() {
+        // Δ = 6 時間, T = 10 分（Δ/T = 36）。全ノードが各自の分岐を掘り続ける。
+        let mut sim = BlockchainSimulator::new(
+            30,
+            7,
+            10,
+            6 * 60 * 60 * 1000,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        );
+        sim.set_max_events(20_000);
+
+        let err = sim.simulation().unwrap_err();
+        assert!(matches!(
+            err,
+            SimulationError::EventLimitExceeded {
+                processed_events: 20_000,
+                max_events: 20_000,
+                ..
+            }
+        ));
+        assert_eq!(sim.processed_events(), 20_000);
+        assert!(err.to_string().contains("--delay"));
+    }
+
+    #[test]
+    fn chain_snapshots_follow_round_advances() {
+        let mut every_round =
+            BlockchainSimulator::new(5, 3, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        every_round.set_chain_snapshot_interval(Some(1));
+        every_round.simulation().unwrap();
+        let rounds = every_round.current_round();
+        assert_eq!(every_round.chain_snapshots().len() as i64, rounds);
+
+        let mut sampled =
+            BlockchainSimulator::new(5, 3, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        sampled.set_chain_snapshot_interval(Some(100));
+        sampled.simulation().unwrap();
+        assert_eq!(sampled.current_round(), rounds, "sampling must not change the run");
+        assert_eq!(sampled.chain_snapshots().len() as i64, rounds / 100);
+        assert_eq!(sampled.chain_snapshots()[0], every_round.chain_snapshots()[99]);
+    }
+
+    #[test]
+    fn high_delay_lowers_effective_block_rate() {
+        let run = |delay_ms| {
+            let mut sim = BlockchainSimulator::new(
+                10,
+                11,
+                10,
+                delay_ms,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            );
+            sim.simulation().unwrap();
+            sim.report().effective_block_rate
+        };
+        let instant = run(0);
+        let slow = run(5 * 60 * 1000); // Δ/T = 0.5
+        assert!(
+            instant > 0.9 && instant <= 1.1,
+            "zero delay ≈ ideal: {instant}"
+        );
+        assert!(
+            slow < instant * 0.9,
+            "high delay {slow} vs zero delay {instant}"
+        );
+    }
+
+    #[test]
+    fn burn_in_excludes_difficulty_ramp_from_metrics() {
+        // Fixed genesis difficulty (1.0) is far too easy for this hashrate, so blocks come fast
+        // until the first retarget at height 2016 ramps the difficulty up.
+        let mut sim = BlockchainSimulator::new(
+            5,
+            5,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Fixed),
+        );
+        sim.simulation().unwrap();
+        let full = sim.report();
+        let full_fairness = sim.mining_fairness(0);
+
+        sim.set_burn_in_rounds(2016);
+        let burned_in = sim.report();
+        assert!(burned_in.measured_main_chain_blocks < full.measured_main_chain_blocks);
+        assert!(
+            burned_in.avg_block_time_ms > full.avg_block_time_ms * 1.5,
+            "post-retarget blocks are slower: {} vs {}",
+            burned_in.avg_block_time_ms,
+            full.avg_block_time_ms
+        );
+        let burned_in_fairness = sim.mining_fairness(0);
+        assert!(
+            full_fairness
+                .iter()
+                .zip(&burned_in_fairness)
+                .any(|(a, b)| a.reward_share != b.reward_share)
+        );
+
+        sim.set_burn_in_rounds(full.main_chain_height + 1);
+        let empty = sim.report();
+        assert_eq!(empty.measured_main_chain_blocks, 0);
+        assert_eq!(empty.effective_block_rate, 0.0);
+        assert_eq!(empty.stale_rate, 0.0);
+        assert!(sim.mining_fairness(0).iter().all(|n| n.reward_share == 0.0));
+    }
+
+    #[test]
+    fn bitcoin_expected_interval_matches_observed_mean() {
+        let mut sim =
+            BlockchainSimulator::new(10, 13, 10, 0, PropagationDelayMode::Uniform, bitcoin());
+        sim.simulation().unwrap();
+        let report = sim.report();
+        let expected = report.expected_block_interval_ms as f64;
+        assert!(
+            (report.avg_block_time_ms - expected).abs() / expected < 0.1,
+            "observed {} vs expected {}",
+            report.avg_block_time_ms,
+            expected
+        );
+        assert_eq!(report.delay_over_block_interval, 0.0);
+    }
+
+    #[test]
+    fn tip_changes_start_at_genesis_and_converge() {
+        let mut sim =
+            BlockchainSimulator::new(5, 17, 10, 0, PropagationDelayMode::Uniform, bitcoin());
+        sim.set_record_tip_changes(true);
+        sim.simulation().unwrap();
+        let changes = sim.tip_changes();
+
+        // 時刻 0 に全ノードが genesis を採用する。
+        for (i, change) in changes.iter().take(5).enumerate() {
+            assert_eq!(
+                (change.time, change.node_id, change.adopted_height),
+                (0.0, i, 0)
+            );
+        }
+        assert!(changes.windows(2).all(|w| w[0].time <= w[1].time));
+
+        // 各ノードの最終行は戦略の current_head と一致する。
+        let mut final_heights = [0; 5];
+        for change in changes {
+            final_heights[change.node_id] = change.adopted_height;
+        }
+        for node in sim.nodes.nodes() {
+            let head = node.mining_strategy().current_head();
+            let height = sim.env.blockchain.get_block(head).unwrap().height();
+            assert_eq!(final_heights[node.id().into_usize()], height);
+        }
+
+        // Δ = 0 なので、最後に採掘されたブロックの伝播を除けば全ノードが収束している。
+        let tip_height = *final_heights.iter().max().unwrap();
+        assert!(
+            final_heights
+                .iter()
+                .all(|&h| h == tip_height || h == tip_height - 1)
+        );
+        let converged = changes
+            .iter()
+            .filter(|c| c.adopted_height == tip_height - 1)
+            .map(|c| c.node_id)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(converged.len(), 5);
+    }
+
+    #[test]
+    fn protocols_share_hashrate_to_time_scale() {
+        let ethereum = ProtocolType::Ethereum.to_protocol(GenesisDifficultyMode::Inferred);
+        // 同じ期待ハッシュ数（Bitcoin の D=1 と Ethereum の D=2^32）なら期待間隔も同じ。
+        let hashrate = 1_000_000;
+        assert_eq!(
+            bitcoin().expected_block_interval(
+                hashrate,
+                Difficulty::Bitcoin(BitcoinDifficulty::new(1.0))
+            ),
+            ethereum.expected_block_interval(
+                hashrate,
+                Difficulty::Ethereum(EthereumDifficulty::from_u64(1 << 32))
+            ),
+        );
+
+        // 目標時間に対する実際の平均ブロック間隔の比は、どちらのプロトコルでも ≈ 1。
+        let mut ratios = Vec::new();
+        for protocol in [ProtocolType::Bitcoin, ProtocolType::Ethereum] {
+            let protocol = protocol.to_protocol(GenesisDifficultyMode::Inferred);
+            let target = protocol.target_block_time_ms() as f64;
+            let mut sim =
+                BlockchainSimulator::new(10, 21, 10, 0, PropagationDelayMode::Uniform, protocol);
+            sim.simulation().unwrap();
+            ratios.push(sim.report().avg_block_time_ms / target);
+        }
+        // Ethereum の DAA は平衡点が目標よりやや長いので許容幅を広めに取る。
+        assert!(ratios.iter().all(|r| (r - 1.0).abs() < 0.2), "{ratios:?}");
+    }
+
+    #[test]
+    fn spv_miners_go_stale_more_often_than_honest_ones() {
+        use crate::mining_strategy::MiningStrategyEnum;
+        use std::collections::HashSet;
+
+        // 同じハッシュレートの honest / SPV を 2 台ずつ。Ethereum は毎ブロック難易度が変わるので、
+        // 高さだけで乗り換える SPV は chainwork の軽い（後に孤立する）分岐に乗りやすい。
+        let strategies = [
+            MiningStrategyEnum::Honest,
+            MiningStrategyEnum::Spv,
+            MiningStrategyEnum::Honest,
+            MiningStrategyEnum::Spv,
+        ];
+        let profile = profile(strategies.into_iter().map(|strategy| (10_000, strategy)));
+        let ethereum = ProtocolType::Ethereum.to_protocol(GenesisDifficultyMode::Inferred);
+        let mut sim = BlockchainSimulator::new_with_profile(
+            profile,
+            5,
+            10,
+            3_000,
+            PropagationDelayMode::Uniform,
+            ethereum,
+        )
+        .unwrap();
+        sim.simulation().unwrap();
+
+        let honest: HashSet<NodeId> = sim
+            .nodes
+            .nodes()
+            .iter()
+            .filter(|node| node.mining_strategy().is_honest())
+            .map(|node| node.id())
+            .collect();
+        assert_eq!(honest.len(), 2);
+        let m = sim.env.blockchain.chain_metrics(Some(&honest), None, None);
+        assert!(
+            m.attacker_stale_rate > m.honest_stale_rate,
+            "SPV stale rate {} should exceed honest stale rate {}",
+            m.attacker_stale_rate,
+            m.honest_stale_rate
+        );
+    }
+
+    #[test]
+    fn time_unit_only_rescales_emitted_values() {
+        let run = |format: OutputFormat| {
+            let mut sim =
+                BlockchainSimulator::new(4, 23, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+            sim.set_output_format(format);
+            sim.simulation().unwrap();
+            sim.main_chain_records()
+        };
+        let ms = run(OutputFormat::default());
+        let s = run(OutputFormat {
+            time_unit: TimeUnit::S,
+            precision: None,
+        });
+        let min = run(OutputFormat {
+            time_unit: TimeUnit::Min,
+            precision: Some(3),
+        });
+
+        // 単位はシミュレーションに影響しないので、同じブロック列が出る。
+        assert_eq!(ms.len(), s.len());
+        assert_eq!(ms.len(), min.len());
+        for ((ms, s), min) in ms.iter().zip(&s).zip(&min) {
+            assert_eq!(ms.round, s.round);
+            assert!((ms.timestamp - 1000.0 * s.timestamp).abs() < 1e-6);
+            assert!((ms.mining_time - 1000.0 * s.mining_time).abs() < 1e-6);
+            assert!((ms.timestamp / 60_000.0 - min.timestamp).abs() <= 0.0005 + 1e-9);
+            assert_eq!(min.timestamp, (min.timestamp * 1000.0).round() / 1000.0);
+        }
+    }
+
+    #[test]
+    fn pruning_keeps_main_chain_and_metrics_with_bounded_memory() {
+        let run = |prune_depth: Option<i64>| {
+            // Δ/T が大きく分岐の多い設定。
+            let mut sim = BlockchainSimulator::new(
+                8,
+                29,
+                10,
+                60_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            );
+            sim.set_prune_depth(prune_depth);
+            sim.simulation().unwrap();
+            sim
+        };
+        let full = run(None);
+        let pruned = run(Some(20));
+
+        let main_chain = pruned.env.blockchain.get_main_chain();
+        assert_eq!(main_chain, full.env.blockchain.get_main_chain());
+        assert_eq!(
+            pruned.env.blockchain.get_main_chain_for_export(),
+            full.env.blockchain.get_main_chain_for_export()
+        );
+        let (m_pruned, m_full) = (
+            pruned.env.blockchain.chain_metrics(None, None, None),
+            full.env.blockchain.chain_metrics(None, None, None),
+        );
+        assert!(m_full.stale_blocks > 0);
+        assert_eq!(m_pruned.mined_blocks, m_full.mined_blocks);
+        assert_eq!(m_pruned.stale_blocks, m_full.stale_blocks);
+
+        // 残るのはメインチェーンと、tip 付近（prune_depth + 刈り込み間隔）の分岐程度。
+        let live = pruned.env.blockchain.live_blocks();
+        assert!(live < full.env.blockchain.live_blocks());
+        let near_tip = full
+            .env
+            .blockchain
+            .blocks()
+            .filter(|b| b.height() > full.current_round() - 2 * 20)
+            .count();
+        assert!(live <= main_chain.len() + near_tip, "{live} live blocks");
+
+        // 刈り込まれたブロックは tombstone を返す。
+        let stale = full
+            .env
+            .blockchain
+            .blocks()
+            .find(|b| b.height() < 100 && !main_chain.contains(&b.id()))
+            .unwrap();
+        assert!(pruned.env.blockchain.get_block(stale.id()).is_none());
+        let tombstone = pruned.env.blockchain.pruned_block(stale.id()).unwrap();
+        assert_eq!(tombstone.height, stale.height());
+        assert_eq!(tombstone.minter, stale.minter());
+        assert_eq!(
+            pruned.env.blockchain.block_height(stale.id()),
+            stale.height()
+        );
+        assert_eq!(live, pruned.env.blockchain.blocks().count());
+
+        // 検証結果のキャッシュも刈り込みに合わせて捨てる。
+        let validated_stale = full
+            .block_validity
+            .keys()
+            .find(|&&id| full.env.blockchain.block_height(id) < 100 && !main_chain.contains(&id))
+            .unwrap();
+        assert!(!pruned.block_validity.contains_key(validated_stale));
+        assert!(
+            pruned
+                .block_validity
+                .keys()
+                .all(|&id| pruned.env.blockchain.get_block(id).is_some())
+        );
+
+        // 刈り込まれたブロックは生きている tip に負けるだけで、比べても panic しない。
+        let head = *main_chain.last().unwrap();
+        let choose =
+            |a, b| crate::mining_strategy::longest_chain(&pruned.env, NodeId::new(0), a, b);
+        assert_eq!(choose(head, stale.id()), head);
+        assert_eq!(choose(stale.id(), head), head);
+        assert_eq!(
+            pruned.env.blockchain.common_ancestor(stale.id(), head),
+            full.env.blockchain.common_ancestor(stale.id(), head)
+        );
+    }
+
+    #[test]
+    fn pruning_keeps_blocks_held_by_selfish_strategy() {
+        use crate::mining_strategy::{DEFAULT_REVEAL_LEAD, MiningStrategyEnum};
+
+        // selfish は current_head（非公開分岐の先端）とは別に公開チェーンの tip を持ち続ける。
+        // 分岐の多い設定と浅い prune_depth でも、それを刈り込まずに最後まで走る。
+        let run = |prune_depth: Option<i64>| {
+            let selfish = MiningStrategyEnum::Selfish {
+                reveal_lead: DEFAULT_REVEAL_LEAD,
+                reveal_targets: None,
+            };
+            let nodes = std::iter::once((12_000, selfish))
+                .chain(std::iter::repeat_n((3_000, MiningStrategyEnum::Honest), 6));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                0,
+                60,
+                60_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_prune_depth(prune_depth);
+            sim.simulation().unwrap();
+            sim
+        };
+        let (pruned, full) = (run(Some(2)), run(None));
+        assert_eq!(
+            pruned.env.blockchain.get_main_chain_for_export(),
+            full.env.blockchain.get_main_chain_for_export()
+        );
+        assert!(pruned.env.blockchain.live_blocks() < full.env.blockchain.live_blocks());
+        for node in pruned.nodes.nodes() {
+            for id in node.mining_strategy().referenced_blocks() {
+                assert!(pruned.env.blockchain.get_block(id).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn frozen_difficulty_stays_at_genesis_under_bitcoin() {
+        let run = |freeze: bool| {
+            let mut sim =
+                BlockchainSimulator::new(5, 31, 10, 0, PropagationDelayMode::Uniform, bitcoin());
+            sim.set_freeze_difficulty(freeze);
+            sim.simulation().unwrap();
+            let chain = &sim.env.blockchain;
+            let genesis = chain.get_block(GENESIS_BLOCK_ID).unwrap().difficulty();
+            chain.blocks().all(|b| b.difficulty() == genesis)
+        };
+        // 2016 ブロックを超えるので、通常は retarget で難易度が変わる。
+        assert!(!run(false));
+        assert!(run(true));
+    }
+
+    #[test]
+    fn high_latency_node_orphans_more_blocks() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        // ノード単位の遅延はないので、遅延モードで「honest 以外」を遅いノードにする。
+        // 同じハッシュレートの honest 3 台と SPV 1 台で、遅い側が多く孤立することを両モードで確かめる。
+        let run = |mode: PropagationDelayMode| -> (u64, u64) {
+            let strategies = [
+                MiningStrategyEnum::Honest,
+                MiningStrategyEnum::Honest,
+                MiningStrategyEnum::Honest,
+                MiningStrategyEnum::Spv,
+            ];
+            let profile = profile(strategies.into_iter().map(|strategy| (10_000, strategy)));
+            let mut sim =
+                BlockchainSimulator::new_with_profile(profile, 11, 200, 120_000, mode, bitcoin())
+                    .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.simulation().unwrap();
+            let infos = sim.mining_fairness(0);
+            (infos[0].orphaned_blocks, infos[3].orphaned_blocks)
+        };
+
+        // SPV（node 3）の送信だけ Δ 遅れる。
+        let (honest, slow) = run(PropagationDelayMode::AttackerUnfavorable);
+        assert!(slow > honest, "slow {slow} vs well-connected {honest}");
+        // honest の送信だけ Δ 遅れる。
+        let (slow, fast) = run(PropagationDelayMode::AttackerFavorable);
+        assert!(slow > fast, "slow {slow} vs well-connected {fast}");
+    }
+
+    #[test]
+    fn late_joiner_mines_nothing_before_joining() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        // 3 台目は 1000 分（≒ 100 ブロック）経ってから参加する。
+        let join_time_ms = 1000 * 60 * 1000;
+        let profile = NetworkProfile {
+            nodes: [None, None, Some(join_time_ms)]
+                .into_iter()
+                .map(|join_time| NodeProfile {
+                    hashrate: 10_000,
+                    strategy: MiningStrategyEnum::Honest,
+                    join_time,
+                })
+                .collect(),
+        };
+        let mut sim = BlockchainSimulator::new_with_profile(
+            profile,
+            3,
+            300,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        // genesis 難易度は最初から参加している 2 台分のハッシュレートで推定する。
+        assert_eq!(sim.env.total_hashrate, 20_000);
+        sim.set_record_tip_changes(true);
+        sim.simulation().unwrap();
+        assert_eq!(sim.env.total_hashrate, 30_000);
+
+        let late = NodeId::new(2);
+        let late_blocks: Vec<_> = sim
+            .env
+            .blockchain
+            .blocks()
+            .filter(|b| b.minter() == Some(late))
+            .collect();
+        assert!(!late_blocks.is_empty());
+        // 参加前には 1 ブロックも採掘せず、最初のブロックは参加時の tip より上に積む。
+        assert!(late_blocks.iter().all(|b| b.timestamp() >= join_time_ms));
+        let first_change = sim.tip_changes().iter().find(|c| c.node_id == 2).unwrap();
+        assert_eq!(first_change.time, join_time_ms as f64);
+        assert!(first_change.adopted_height > 50);
+        assert!(
+            late_blocks
+                .iter()
+                .all(|b| b.height() > first_change.adopted_height)
+        );
+    }
+
+    #[test]
+    fn higher_delay_wastes_more_hashrate_on_orphans() {
+        let wasted = |delay_ms: i64| {
+            let mut sim = BlockchainSimulator::new(
+                5,
+                13,
+                300,
+                delay_ms,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            );
+            sim.simulation().unwrap();
+            sim.report().wasted_hashrate_fraction
+        };
+        let (none, low, high) = (wasted(0), wasted(6_000), wasted(120_000));
+        assert_eq!(none, 0.0);
+        assert!(low > 0.0 && low < high, "Δ=6s: {low}, Δ=120s: {high}");
+        assert!(high < 1.0);
+    }
+
+    #[test]
+    fn reported_seed_reproduces_the_summary() {
+        let run = |seed: u64| {
+            let mut sim = BlockchainSimulator::new(
+                5,
+                seed,
+                30,
+                600,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            );
+            sim.simulation().unwrap();
+            sim.report()
+        };
+        // main.rs と同様に seed を自動生成し、報告された値をそのまま渡し直す。
+        let first = run(rand::thread_rng().r#gen::<u64>());
+        let second = run(first.seed);
+        assert_eq!(second.seed, first.seed);
+        assert_eq!(
+            serde_json::to_string(&second).unwrap(),
+            serde_json::to_string(&first).unwrap()
+        );
+    }
+
+    #[test]
+    fn zero_hashrate_node_relays_but_never_mines() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        let honest = |hashrates: &[i64]| {
+            profile(
+                hashrates
+                    .iter()
+                    .map(|&hashrate| (hashrate, MiningStrategyEnum::Honest)),
+            )
+        };
+        let mut sim = BlockchainSimulator::new_with_profile(
+            honest(&[10_000, 0, 10_000]),
+            4,
+            50,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        sim.simulation().unwrap();
+
+        let idle = NodeId::new(1);
+        assert!(
+            sim.env
+                .blockchain
+                .blocks()
+                .all(|b| b.mining_time.is_finite())
+        );
+        assert!(
+            sim.env
+                .blockchain
+                .blocks()
+                .all(|b| b.minter() != Some(idle))
+        );
+        // 受信はするので他ノードと同じ tip に追随している。
+        let head = sim.nodes.get_node(idle).mining_strategy().current_head();
+        assert!(sim.env.blockchain.get_block(head).unwrap().height() >= 50);
+        let fairness = sim.mining_fairness(0);
+        assert_eq!(fairness[1].reward_share, 0.0);
+        assert!(fairness.iter().all(|info| info.fairness.is_finite()));
+
+        assert!(
+            BlockchainSimulator::new_with_profile(
+                honest(&[10_000, -1]),
+                4,
+                50,
+                600,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn observed_stale_rate_matches_theoretical_estimate() {
+        // Δ/T = 30 s / 600 s = 0.05。難易度を固定して T を一定に保つ。
+        let mut sim = BlockchainSimulator::new(
+            10,
+            21,
+            2_000,
+            30_000,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        );
+        sim.set_freeze_difficulty(true);
+        sim.simulation().unwrap();
+        let report = sim.report();
+        assert!((report.theoretical_stale_rate - theoretical_orphan_rate(0.05)).abs() < 0.005);
+        let ratio = report.stale_rate / report.theoretical_stale_rate;
+        assert!(
+            (0.75..1.25).contains(&ratio),
+            "observed {} vs theoretical {}",
+            report.stale_rate,
+            report.theoretical_stale_rate
+        );
+        assert_eq!(theoretical_orphan_rate(0.0), 0.0);
+    }
+
+    #[test]
+    fn honest_node_restarts_mining_on_its_own_block() {
+        let mut sim =
+            BlockchainSimulator::new(4, 9, 10, 60_000, PropagationDelayMode::Uniform, bitcoin());
+        // ジッタありでも（伝播が乱数を消費しても）順序に依らず自分のブロックの上で採掘を続ける。
+        sim.set_delay_jitter_pct(50.0);
+        sim.enqueue_first_mining_task();
+        let mut mined = 0;
+        while mined < 100 {
+            let event = sim.event_queue.pop().unwrap();
+            sim.current_time = event.time();
+            match *event.event_type() {
+                EventType::BlockGeneration {
+                    minter, block_id, ..
+                } => {
+                    sim.handle_block_generation(minter, block_id);
+                    let next = sim.event_queue.pending_mining(minter).unwrap();
+                    let EventType::BlockGeneration { prev_block_id, .. } = *next.event_type()
+                    else {
+                        unreachable!();
+                    };
+                    assert_eq!(prev_block_id, block_id);
+                    mined += 1;
+                }
+                EventType::Propagation { from, to, block_id } => {
+                    sim.handle_propagation(from, to, block_id)
+                }
+                EventType::BlockBody { from, to, block_id } => {
+                    sim.handle_block_body(from, to, block_id)
+                }
+                EventType::Broadcast { .. } => unreachable!("per-peer propagation is the default"),
+                EventType::NodeJoin { node } => sim.handle_node_join(node),
+            }
+        }
+    }
+
+    #[test]
+    fn selfish_revenue_grows_with_gamma() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        // α = 1/3 の selfish 1 台と honest 10 台。γ = 0 では ≒ α、γ が上がるほど取り分が増える。
+        let run = |gamma: f64| {
+            let selfish = MiningStrategyEnum::Selfish {
+                reveal_lead: crate::mining_strategy::DEFAULT_REVEAL_LEAD,
+                reveal_targets: None,
+            };
+            let nodes = std::iter::once((10_000, selfish))
+                .chain(std::iter::repeat_n((2_000, MiningStrategyEnum::Honest), 10));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                5,
+                1_000,
+                1_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.set_gamma(Some(gamma));
+            sim.simulation().unwrap();
+            sim.mining_fairness(0)[0].reward_share
+        };
+
+        let shares: Vec<f64> = [0.0, 0.5, 1.0].into_iter().map(run).collect();
+        assert!(
+            shares.windows(2).all(|w| w[0] < w[1]),
+            "selfish shares {shares:?}"
+        );
+    }
+
+    #[test]
+    fn higher_reveal_lead_makes_selfish_revenue_more_volatile() {
+        use crate::mining_strategy::{DEFAULT_REVEAL_LEAD, MiningStrategyEnum};
+
+        // α = 0.4 の selfish 1 台と honest 6 台。公開を遅らせると、攻撃者のブロックは
+        // 稀だが長い塊でメインチェーンに入るので、10 ブロックごとの攻撃者ブロック数の分散が大きくなる。
+        // （最終的な取り分の seed 間の分散は採掘の運が支配的で、リードではほとんど変わらない。）
+        const WINDOW: usize = 10;
+        let run = |reveal_lead: usize, seed: u64| {
+            let nodes = std::iter::once((
+                12_000,
+                MiningStrategyEnum::Selfish {
+                    reveal_lead,
+                    reveal_targets: None,
+                },
+            ))
+            .chain(std::iter::repeat_n((3_000, MiningStrategyEnum::Honest), 6));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                seed,
+                500,
+                1_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.simulation().unwrap();
+            let attacker_blocks: Vec<f64> = sim
+                .env
+                .blockchain
+                .main_chain_blocks_for_export()
+                .skip(1)
+                .map(|b| f64::from(u8::from(b.minter() == Some(NodeId::new(0)))))
+                .collect();
+            let counts: Vec<f64> = attacker_blocks
+                .chunks_exact(WINDOW)
+                .map(|window| window.iter().sum())
+                .collect();
+            let mean = counts.iter().sum::<f64>() / counts.len() as f64;
+            let var =
+                counts.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (counts.len() - 1) as f64;
+            (sim.mining_fairness(0)[0].reward_share, var)
+        };
+        // 同じ seed の組で比べ、seed 間で平均する。
+        let mean_of = |reveal_lead: usize| {
+            let runs: Vec<(f64, f64)> = (0..8).map(|seed| run(reveal_lead, seed)).collect();
+            let n = runs.len() as f64;
+            (
+                runs.iter().map(|r| r.0).sum::<f64>() / n,
+                runs.iter().map(|r| r.1).sum::<f64>() / n,
+            )
+        };
+
+        let (default_share, default_var) = mean_of(DEFAULT_REVEAL_LEAD);
+        let (high_share, high_var) = mean_of(4);
+        assert!(
+            high_var > default_var,
+            "variance per {WINDOW} blocks {high_var} vs {default_var}"
+        );
+        assert!(
+            (high_share - default_share).abs() > 0.01,
+            "share {high_share} vs {default_share}"
+        );
+    }
+
+    #[test]
+    fn extreme_mining_time_is_clamped_instead_of_overflowing() {
+        use crate::protocol::MAX_MINING_TIME_US;
+
+        // genesis 難易度は合計ハッシュレートから決まるので、ハッシュレート 1 のノードの期待生成時間は
+        // 600 s × 1e15 ≒ 6e20 μs となり i64 に収まらない。
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[1, 1_000_000_000_000_000]).unwrap(),
+            1,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        sim.enqueue_first_mining_task();
+        let slow = sim.event_queue.pending_mining(NodeId::new(0)).unwrap();
+        assert_eq!(slow.time(), MAX_MINING_TIME_US);
+
+        // 頭打ちのイベントが残っていても最後まで走り、時刻は負にならない。
+        sim.simulation().unwrap();
+        assert!(sim.current_time >= 0);
+        assert!(
+            sim.event_queue
+                .pending_mining(NodeId::new(0))
+                .unwrap()
+                .time()
+                > 0
+        );
+    }
+
+    #[test]
+    fn retargeting_leaves_structure_in_block_time_autocorrelation() {
+        // 固定の genesis 難易度 2^32 は総ハッシュレート 18_000 には重すぎ（間隔 ≈ 240 秒）、
+        // Ethereum の DAA は 1 ブロックごとに 1% ほど下げながら 12 秒へ追いつくので、間隔が続けて縮んでいく。
+        // 難易度固定なら間隔は独立な指数分布。1 本の自己相関は seed で揺れるので、seed 間で平均する。
+        let run = |freeze: bool, seed: u64| {
+            let mut sim = BlockchainSimulator::new_with_profile(
+                NetworkProfile::from_hashrates(&[9_000, 9_000]).unwrap(),
+                seed,
+                600,
+                0,
+                PropagationDelayMode::Uniform,
+                ProtocolType::Ethereum.to_protocol(GenesisDifficultyMode::Fixed),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(freeze);
+            sim.set_end_main_chain_height(Some(600));
+            sim.simulation().unwrap();
+            sim.env.blockchain.inter_block_autocorrelation(1)
+        };
+
+        let mean_lag1 = |freeze: bool| (0..4).map(|seed| run(freeze, seed)).sum::<f64>() / 4.0;
+        let fixed = mean_lag1(true);
+        let retargeting = mean_lag1(false);
+        assert!(fixed.abs() < 0.05, "fixed difficulty lag-1 {fixed}");
+        assert!(
+            retargeting > fixed + 0.1,
+            "retargeting lag-1 {retargeting} vs fixed {fixed}"
+        );
+    }
+
+    #[test]
+    fn work_weighting_reorders_fairness_when_difficulty_rises() {
+        // 固定の genesis 難易度 2^32 は均衡（総ハッシュレート × 12 秒 ≈ 2.4e10）より低いので、
+        // 実行中ずっと難易度が上がり続ける。
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[250_000; 8]).unwrap(),
+            8,
+            2_000,
+            0,
+            PropagationDelayMode::Uniform,
+            ProtocolType::Ethereum.to_protocol(GenesisDifficultyMode::Fixed),
+        )
+        .unwrap();
+        sim.set_end_main_chain_height(Some(2_000));
+        sim.simulation().unwrap();
+        let difficulties: Vec<f64> = sim
+            .env
+            .blockchain
+            .main_chain_blocks_for_export()
+            .map(|b| b.difficulty().as_f64())
+            .collect();
+        assert!(difficulties[difficulties.len() - 1] > 2.0 * difficulties[0]);
+
+        let ranking = |infos: &[NodeInfo]| {
+            let mut ids: Vec<usize> = (0..infos.len()).collect();
+            ids.sort_by(|&a, &b| infos[b].fairness.total_cmp(&infos[a].fairness));
+            ids
+        };
+        let by_blocks = sim.mining_fairness(0);
+        sim.set_reward_weighting(RewardWeighting::Work);
+        let by_work = sim.mining_fairness(0);
+        assert_ne!(ranking(&by_blocks), ranking(&by_work));
+
+        // Work では取り分が採掘したブロックの難易度の合計に比例する。
+        let mut work = vec![0.0; 8];
+        for block in sim.env.blockchain.main_chain_blocks_for_export().skip(1) {
+            work[block.minter().unwrap().into_usize()] += block.difficulty().as_f64();
+        }
+        let total: f64 = work.iter().sum();
+        for (info, w) in by_work.iter().zip(&work) {
+            assert!((info.reward_share - w / total).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn reported_fairness_matches_mining_fairness() {
+        let mut sim =
+            BlockchainSimulator::new(5, 13, 100, 600, PropagationDelayMode::Uniform, bitcoin());
+        sim.set_confirmations(6);
+        sim.simulation().unwrap();
+        // CSV（`--fairness-output`）と表は report の fairness を書き出す。
+        let reported = serde_json::to_string(&sim.report().fairness).unwrap();
+        let direct = serde_json::to_string(&sim.mining_fairness(6)).unwrap();
+        assert_eq!(reported, direct);
+        assert_ne!(
+            direct,
+            serde_json::to_string(&sim.mining_fairness(0)).unwrap()
+        );
+    }
+
+    #[test]
+    fn bitcoin_retarget_is_recorded_once_at_the_epoch_boundary() {
+        use crate::types::RetargetClamp;
+
+        // Δ = 0 なので分岐せず、各高さのブロックはちょうど 1 つ採掘される。
+        let mut sim =
+            BlockchainSimulator::new(3, 8, 2_020, 0, PropagationDelayMode::Uniform, bitcoin());
+        sim.simulation().unwrap();
+        let retargets = sim.retarget_records();
+        // 調整はエポック境界でだけ起き、高さ 2016 では 1 回だけ。
+        assert!(
+            retargets.iter().all(|r| r.height % 2016 == 0),
+            "{retargets:?}"
+        );
+        let at_boundary: Vec<_> = retargets.iter().filter(|r| r.height == 2016).collect();
+        assert_eq!(at_boundary.len(), 1, "{retargets:?}");
+        let retarget = at_boundary[0];
+        assert_eq!(retarget.clamp, RetargetClamp::None);
+
+        let main_chain = sim.env.blockchain.get_main_chain();
+        let time = |height: usize| {
+            sim.env
+                .blockchain
+                .get_block(main_chain[height])
+                .unwrap()
+                .timestamp()
+        };
+        let timespan_ms = (time(2015) - time(0)) as f64;
+        assert!((retarget.avg_block_time * 2015.0 - timespan_ms).abs() < 1e-6);
+        let two_weeks_ms = 14.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+        let ratio = retarget.new_difficulty / retarget.old_difficulty;
+        assert!(
+            (ratio - two_weeks_ms / timespan_ms).abs() < 1e-9,
+            "ratio {ratio}"
+        );
+        let block_2016 = sim.env.blockchain.get_block(main_chain[2016]).unwrap();
+        assert_eq!(block_2016.difficulty().as_f64(), retarget.new_difficulty);
+    }
+
+    #[test]
+    fn retarget_uses_the_timestamps_of_the_branch_being_mined_on() {
+        use crate::types::RetargetClamp;
+
+        const WEEK_US: i64 = 7 * 24 * 60 * 60 * 1000 * 1000;
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[10_000, 10_000]).unwrap(),
+            0,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        let (a, b) = (NodeId::new(0), NodeId::new(1));
+        // 高さ 2014 までは共通の鎖。
+        let mut tip = GENESIS_BLOCK_ID;
+        for _ in 0..2014 {
+            let block = sim.mint_block(a, tip);
+            sim.current_time = block.timestamp() * 1000;
+            tip = sim.env.blockchain.add_block(block);
+        }
+        // エポック最後の高さ 2015 で分岐し、b の枝は 1 週間遅れて採掘される。
+        let block = sim.mint_block(a, tip);
+        let early = sim.env.blockchain.add_block(block);
+        sim.current_time += WEEK_US;
+        let block = sim.mint_block(b, tip);
+        let late = sim.env.blockchain.add_block(block);
+        // 現在時刻はどちらの枝の tip とも無関係に進んでいる。
+        sim.current_time += 10 * WEEK_US;
+
+        let genesis = sim.env.blockchain.get_block(GENESIS_BLOCK_ID).unwrap();
+        let next_difficulty = |parent: BlockId| {
+            let parent = sim.env.blockchain.get_block(parent).unwrap();
+            let update = sim.calculate_new_difficulty(parent);
+            assert_eq!(update.retarget.unwrap().clamp, RetargetClamp::None);
+            let timespan_ms = (parent.timestamp() - genesis.timestamp()) as f64;
+            let two_weeks_ms = 2.0 * WEEK_US as f64 / 1000.0;
+            let expected = genesis.difficulty().as_f64() * two_weeks_ms / timespan_ms;
+            let actual = update.difficulty.as_f64();
+            assert!(
+                (actual - expected).abs() < expected * 1e-12,
+                "{actual} vs {expected}"
+            );
+            actual
+        };
+        let (on_early, on_late) = (next_difficulty(early), next_difficulty(late));
+        assert!(on_early > on_late, "{on_early} vs {on_late}");
+    }
+
+    #[test]
+    fn retarget_follows_the_declared_timestamp_not_the_creation_time() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        const TWO_HOURS_MS: i64 = 2 * 60 * 60 * 1000;
+        let profile = profile([
+            (10_000, MiningStrategyEnum::Honest),
+            (
+                10_000,
+                MiningStrategyEnum::Timewarp {
+                    mtp_window_size: 11,
+                },
+            ),
+        ]);
+        let mut sim = BlockchainSimulator::new_with_profile(
+            profile,
+            0,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        let (honest, warper) = (NodeId::new(0), NodeId::new(1));
+        let mut tip = GENESIS_BLOCK_ID;
+        for _ in 0..2014 {
+            let block = sim.mint_block(honest, tip);
+            assert_eq!(block.timestamp(), block.created_at());
+            sim.current_time = block.created_at() * 1000;
+            tip = sim.env.blockchain.add_block(block);
+        }
+        // エポック最後の高さ 2015 で、timewarp の採掘者は timestamp を 2 時間先に申告する。
+        let block = sim.mint_block(warper, tip);
+        assert_eq!(block.timestamp(), block.created_at() + TWO_HOURS_MS);
+        let warped = sim.env.blockchain.add_block(block);
+
+        let genesis = sim.env.blockchain.get_block(GENESIS_BLOCK_ID).unwrap();
+        let parent = sim.env.blockchain.get_block(warped).unwrap();
+        let retarget = |end_ms: i64| {
+            let two_weeks_ms = 14.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+            genesis.difficulty().as_f64() * two_weeks_ms / (end_ms - genesis.timestamp()) as f64
+        };
+        let actual = sim.calculate_new_difficulty(parent).difficulty.as_f64();
+        let declared = retarget(parent.timestamp());
+        let created = retarget(parent.created_at());
+        assert!(
+            (actual - declared).abs() < declared * 1e-12,
+            "{actual} vs {declared}"
+        );
+        assert!(actual < created, "{actual} vs {created}");
+
+        // 手数料の経過時間は申告した timestamp ではなく、実際に採掘された時刻から測る。
+        sim.set_fee_rate(Some(0.001));
+        let now_ms = sim.env.blockchain.get_block(warped).unwrap().created_at() + 60_000;
+        assert_eq!(sim.env.unclaimed_fees(warped, now_ms), 60.0);
+    }
+
+    #[test]
+    fn bitcoin_rejects_a_block_with_the_wrong_difficulty() {
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[10_000, 10_000]).unwrap(),
+            0,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        let (from, to) = (NodeId::new(0), NodeId::new(1));
+        let valid = sim.mint_block(from, GENESIS_BLOCK_ID);
+        let valid_id = sim.env.blockchain.add_block(valid.clone());
+        // 同じブロックを難易度だけ 2 倍にして注入する。
+        let forged = Block::new(
+            valid.height(),
+            valid.prev_block_id(),
+            valid.minter().unwrap(),
+            valid.timestamp(),
+            valid.rand(),
+            sim.env.blockchain.next_block_id(),
+            valid.difficulty().scaled(2.0),
+            valid.cumulative_chain_work(),
+            valid.mining_time,
+            false,
+        );
+        let forged_id = sim.env.blockchain.add_block(forged);
+
+        sim.handle_propagation(from, to, forged_id);
+        assert_eq!(sim.current_head(to), GENESIS_BLOCK_ID);
+        assert_eq!(sim.block_validity.get(&forged_id), Some(&false));
+
+        sim.handle_propagation(from, to, valid_id);
+        assert_eq!(sim.current_head(to), valid_id);
+    }
+
+    #[test]
+    fn higher_delay_increases_average_confirmation_time() {
+        let run = |delay_ms: i64| {
+            let mut sim = BlockchainSimulator::new_with_profile(
+                NetworkProfile::from_hashrates(&[10_000; 5]).unwrap(),
+                3,
+                200,
+                delay_ms,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.simulation().unwrap();
+            sim.report().avg_confirmation_time_ms.unwrap()
+        };
+
+        // Δ ≒ 0 なら子が採掘された瞬間に全ノードが採用するので、確認時間はほぼ T（10 分）。
+        let fast = run(1);
+        let slow = run(120_000);
+        assert!((0.8..1.2).contains(&(fast / 600_000.0)), "fast {fast}");
+        // 子が全ノードに届くまで少なくとも Δ 余計にかかる。
+        assert!(slow > fast + 120_000.0, "slow {slow} vs fast {fast}");
+    }
+
+    #[test]
+    fn selfish_reorg_reverses_the_marked_transaction() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        const HEIGHT: i64 = 20;
+        // α = 0.45 の selfish 1 台と honest 5 台。対象ブロックが覆されるシードを探す。
+        let run = |seed: u64| {
+            let nodes = std::iter::once((
+                9_000,
+                MiningStrategyEnum::Selfish {
+                    reveal_lead: 2,
+                    reveal_targets: None,
+                },
+            ))
+            .chain(std::iter::repeat_n((2_200, MiningStrategyEnum::Honest), 5));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                seed,
+                60,
+                1_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.set_double_spend_height(Some(HEIGHT));
+            sim.simulation().unwrap();
+            sim
+        };
+        let (sim, report) = (0..20)
+            .map(run)
+            .map(|sim| {
+                let report = sim.report().double_spend.unwrap();
+                (sim, report)
+            })
+            .find(|(_, report)| report.succeeded)
+            .expect("a selfish reorg should reverse the marked block for some seed");
+
+        let marked = report.block_id.unwrap();
+        let block = sim.env.blockchain.get_block(marked).unwrap();
+        assert!(block.contains_marked_tx());
+        assert_eq!(block.height(), HEIGHT);
+        assert!(!sim.env.is_attacker(block.minter().unwrap()));
+        assert!(!sim.env.blockchain.get_main_chain().contains(&marked));
+        // honest ノードが対象ブロックを含むチェーンを捨て、その高さは攻撃者のブロックが占める。
+        assert!(report.reversals > 0);
+        assert!(report.max_reversed_confirmations >= 1);
+        let replacement = sim.env.blockchain.get_main_chain()[HEIGHT as usize];
+        let minter = sim.env.blockchain.get_block(replacement).unwrap().minter();
+        assert!(sim.env.is_attacker(minter.unwrap()));
+
+        // 攻撃者がいなければ対象トランザクションは覆らない。
+        let mut honest = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[2_000; 5]).unwrap(),
+            0,
+            60,
+            1_000,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        honest.set_freeze_difficulty(true);
+        honest.set_double_spend_height(Some(HEIGHT));
+        honest.simulation().unwrap();
+        let report = honest.report().double_spend.unwrap();
+        assert!(report.block_id.is_some());
+        assert!(!report.succeeded);
+        assert_eq!(report.reversals, 0);
+    }
+
+    #[test]
+    fn honest_fairness_converges_before_the_round_cap() {
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[10_000; 5]).unwrap(),
+            7,
+            1_000,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        sim.set_freeze_difficulty(true);
+        sim.set_end_main_chain_height(Some(5_000));
+        let convergence = sim
+            .run_until_converged(|sim| sim.mining_fairness(0)[0].fairness, 0.05, 200)
+            .unwrap();
+
+        assert!(convergence.converged);
+        assert!(convergence.rounds < 5_000, "rounds {}", convergence.rounds);
+        assert_eq!(convergence.rounds, sim.current_round());
+        // honest だけなら fairness は 1 の周りに収束する。
+        let fairness = convergence.value.unwrap();
+        assert!((fairness - 1.0).abs() < 0.2, "fairness {fairness}");
+    }
+
+    #[test]
+    fn header_first_relay_lowers_the_fork_rate() {
+        let run = |header_first: Option<f64>| {
+            // Δ/T = 0.1。本体が届くまでの時間はどちらも同じ Δ。
+            let mut sim = BlockchainSimulator::new_with_profile(
+                NetworkProfile::from_hashrates(&[10_000; 10]).unwrap(),
+                4,
+                10,
+                60_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.set_end_main_chain_height(Some(1_000));
+            sim.set_header_first_relay(header_first);
+            sim.simulation().unwrap();
+            sim.report().stale_rate
+        };
+
+        let full_block = run(None);
+        let header_first = run(Some(0.1));
+        assert!(full_block > 0.05, "full-block stale rate {full_block}");
+        assert!(
+            header_first < full_block / 3.0,
+            "header-first {header_first} vs full-block {full_block}"
+        );
+    }
+
+    #[test]
+    fn selfish_group_earns_more_blocks_than_its_hashrate_share() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        // selfish 1 台（30%）と honest 7 台（計 70%）。γ = 1 なら α = 0.3 でも selfish mining が得をする。
+        let nodes = std::iter::once((
+            3_000,
+            MiningStrategyEnum::Selfish {
+                reveal_lead: 2,
+                reveal_targets: None,
+            },
+        ))
+        .chain(std::iter::repeat_n((1_000, MiningStrategyEnum::Honest), 7));
+        let mut sim = BlockchainSimulator::new_with_profile(
+            profile(nodes),
+            6,
+            2_000,
+            1_000,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        sim.set_freeze_difficulty(true);
+        sim.set_gamma(Some(1.0));
+        sim.simulation().unwrap();
+
+        let shares = sim.report().strategies;
+        assert_eq!(shares.len(), 2);
+        let (honest, selfish) = (&shares[0], &shares[1]);
+        assert_eq!((honest.strategy.as_str(), honest.nodes), ("Honest", 7));
+        assert_eq!((selfish.strategy.as_str(), selfish.nodes), ("Selfish", 1));
+        assert!((selfish.hashrate_share - 0.3).abs() < 1e-9);
+        assert!((honest.block_share + selfish.block_share - 1.0).abs() < 1e-9);
+        assert!(
+            selfish.block_share > 0.3,
+            "selfish block share {}",
+            selfish.block_share
+        );
+        assert!(selfish.fairness > 1.0 && honest.fairness < 1.0);
+    }
+
+    #[test]
+    fn ghostdag_blocks_merge_the_tips_their_miner_knows() {
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[1_000_000; 6]).unwrap(),
+            4,
+            300,
+            500,
+            PropagationDelayMode::Uniform,
+            ProtocolType::GhostDag.to_protocol(GenesisDifficultyMode::Inferred),
+        )
+        .unwrap();
+        sim.set_end_main_chain_height(Some(300));
+        sim.simulation().unwrap();
+        let report = sim.report();
+        let ghostdag = report.ghostdag.expect("GHOSTDAG report");
+
+        let chain = &sim.env.blockchain;
+        let main_chain: HashSet<BlockId> = chain.get_main_chain_for_export().into_iter().collect();
+        let merged: HashSet<BlockId> = chain
+            .blocks()
+            .flat_map(|b| b.merge_parents().iter().copied())
+            .collect();
+        let completed: Vec<BlockId> = chain
+            .blocks()
+            .map(|b| b.id())
+            .filter(|&id| id != GENESIS_BLOCK_ID && chain.is_generation_completed(id))
+            .collect();
+        let off_chain: Vec<BlockId> = completed
             .iter()
-            .map(|node| {
-                let reward = *rewards.get(&node.id()).unwrap_or(&0.0);
-                let hashrate = node.hashrate() as f64;
+            .copied()
+            .filter(|id| !main_chain.contains(id))
+            .collect();
+        assert_eq!(ghostdag.blue_blocks + ghostdag.red_blocks, completed.len());
+        // Δ/T = 0.5 なので並行ブロックが多いが、selected chain 外のブロックも（終了間際の分を除いて）
+        // 後続のブロックにマージされ、k = 18 の範囲に収まるので赤にはならない。
+        assert!(off_chain.len() > 50, "{} off-chain blocks", off_chain.len());
+        let unmerged = off_chain.iter().filter(|id| !merged.contains(id)).count();
+        assert!(unmerged * 20 < off_chain.len(), "{unmerged} unmerged");
+        assert_eq!(ghostdag.red_blocks, 0);
+    }
+
+    #[test]
+    fn tracing_an_orphaned_block_reports_its_abandonment() {
+        let run = |traced: Option<BlockId>| {
+            let mut sim = BlockchainSimulator::new_with_profile(
+                NetworkProfile::from_hashrates(&[1_000; 6]).unwrap(),
+                7,
+                200,
+                60_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.set_end_main_chain_height(Some(200));
+            sim.set_trace_block(traced);
+            sim.simulation().unwrap();
+            sim
+        };
+
+        // 追跡しない実行で、告知されたのにメインチェーンに残らなかったブロックを選ぶ（追跡は乱数を使わない）。
+        let sim = run(None);
+        assert!(sim.block_trace().is_empty());
+        let main_chain: HashSet<BlockId> =
+            sim.env.blockchain.get_main_chain().into_iter().collect();
+        let orphan = sim
+            .env
+            .blockchain
+            .blocks()
+            .find(|b| b.is_announced() && !main_chain.contains(&b.id()))
+            .expect("Δ/T = 0.1 leaves stale blocks")
+            .id();
+
+        let sim = run(Some(orphan));
+        let trace = sim.block_trace();
+        assert!(
+            matches!(trace[0], BlockTraceEvent::Generated { block_id, .. } if block_id == orphan)
+        );
+        assert!(
+            trace
+                .iter()
+                .any(|e| matches!(e, BlockTraceEvent::Received { .. }))
+        );
+        let abandoned = trace
+            .iter()
+            .filter(|e| matches!(e, BlockTraceEvent::Abandoned { .. }))
+            .count();
+        assert!(abandoned > 0, "{trace:?}");
+        assert!(
+            trace
+                .iter()
+                .any(|e| e.to_string().contains("abandoned by node"))
+        );
+        let last = trace.last().unwrap();
+        assert_eq!(
+            *last,
+            BlockTraceEvent::Final {
+                block_id: orphan,
+                main_chain: false,
+                depth: None,
+            }
+        );
+        assert!(last.to_string().ends_with("orphaned"));
+    }
+
+    #[test]
+    fn observer_reorgs_follow_the_network_fork_rate() {
+        let run = |delay_ms: i64| {
+            let mut sim = BlockchainSimulator::new_with_profile(
+                NetworkProfile::from_hashrates(&[1_000; 6]).unwrap(),
+                4,
+                500,
+                delay_ms,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.add_observers(2);
+            sim.set_freeze_difficulty(true);
+            sim.set_end_main_chain_height(Some(500));
+            // 最後のブロックが観測ノードに届くまで処理する。
+            sim.set_drain_at_end(true);
+            sim.simulation().unwrap();
+            let report = sim.report();
+            let main_chain: HashSet<BlockId> =
+                sim.env.blockchain.get_main_chain().into_iter().collect();
+            let stale = sim
+                .env
+                .blockchain
+                .blocks()
+                .filter(|b| b.is_announced() && !main_chain.contains(&b.id()))
+                .count() as u64;
+            (report, stale)
+        };
+
+        // 遅延がなければ分岐せず、観測ノードも reorg しない。
+        let (report, stale) = run(0);
+        assert_eq!(stale, 0);
+        assert_eq!(report.observers.len(), 2);
+        for observer in &report.observers {
+            assert_eq!((observer.reorgs, observer.max_reorg_depth), (0, 0));
+            assert!(observer.adopted_height >= 500);
+        }
+
+        // Δ/T = 0.1 なら分岐が起き、観測ノードは先に受け取った側が負けたときだけ reorg する。
+        // 遅延は全ノード共通なので、2 台の観測ノードは同じ順にブロックを受け取り、同じ回数 reorg する。
+        let (report, stale) = run(60_000);
+        let [first, second] = &report.observers[..] else {
+            panic!("expected two observers: {:?}", report.observers);
+        };
+        assert_eq!(first.reorgs, second.reorgs);
+        assert!(
+            first.reorgs > 0 && first.reorgs <= stale,
+            "{first:?}, {stale} stale blocks"
+        );
+        assert!(first.max_reorg_depth >= 1);
+    }
+
+    #[test]
+    fn revealing_to_heavy_miners_raises_effective_gamma() {
+        use crate::mining_strategy::{DEFAULT_REVEAL_LEAD, MiningStrategyEnum};
+
+        // 攻撃者 node 0、ハッシュレートの大きい honest node 1..=4、小さい honest node 5..=8。
+        let run = |reveal_targets: Vec<usize>| {
+            let selfish = MiningStrategyEnum::Selfish {
+                reveal_lead: DEFAULT_REVEAL_LEAD,
+                reveal_targets: Some(reveal_targets.into_iter().map(NodeId::new).collect()),
+            };
+            let nodes = std::iter::once((3_000, selfish))
+                .chain(std::iter::repeat_n((2_000, MiningStrategyEnum::Honest), 4))
+                .chain(std::iter::repeat_n((250, MiningStrategyEnum::Honest), 4));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                2,
+                2_000,
+                1_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.set_gamma(Some(1.0));
+            sim.set_end_main_chain_height(Some(2_000));
+            sim.simulation().unwrap();
+            effective_gamma(&sim)
+        };
+
+        let heavy = run(vec![1, 2, 3, 4]);
+        let light = run(vec![5, 6, 7, 8]);
+        assert!(heavy > 2.0 * light, "heavy {heavy}, light {light}");
+    }
+
+    /// 同じ親を持つ攻撃者ブロックと honest ブロックの競合で、honest が攻撃者側に積んだブロックの割合。
+    fn effective_gamma(sim: &BlockchainSimulator) -> f64 {
+        let blockchain = &sim.env.blockchain;
+        let is_attacker = |b: &Block| b.minter().is_some_and(|m| sim.env.is_attacker(m));
+        let mut children: HashMap<BlockId, Vec<&Block>> = HashMap::new();
+        for block in blockchain.blocks().filter(|b| b.is_announced()) {
+            if let Some(parent) = block.prev_block_id() {
+                children.entry(parent).or_default().push(block);
+            }
+        }
+        let honest_children = |id: BlockId| {
+            children
+                .get(&id)
+                .map_or(0, |c| c.iter().filter(|b| !is_attacker(b)).count())
+        };
+        let (mut on_attacker, mut total) = (0, 0);
+        for siblings in children.values() {
+            if !siblings.iter().any(|b| is_attacker(b)) || siblings.iter().all(|b| is_attacker(b)) {
+                continue;
+            }
+            for block in siblings {
+                let n = honest_children(block.id());
+                total += n;
+                if is_attacker(block) {
+                    on_attacker += n;
+                }
+            }
+        }
+        on_attacker as f64 / total as f64
+    }
+
+    #[test]
+    fn higher_mining_time_shape_narrows_block_intervals() {
+        let intervals = |shape: f64| {
+            // 難易度 1・1 ノードなので、採掘時間の平均は 2^32 / hashrate = 1000 ms。
+            let hashrate = (BitcoinDifficulty::HASHES_PER_DIFFICULTY / 1000.0) as i64;
+            let mut sim = BlockchainSimulator::new_with_profile(
+                NetworkProfile::from_hashrates(&[hashrate]).unwrap(),
+                6,
+                3_000,
+                0,
+                PropagationDelayMode::Uniform,
+                ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Fixed),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.set_mining_time_shape(shape);
+            sim.set_end_main_chain_height(Some(3_000));
+            sim.simulation().unwrap();
+            let times: Vec<i64> = sim
+                .env
+                .blockchain
+                .main_chain_blocks_for_export()
+                .map(|b| b.timestamp())
+                .collect();
+            let intervals: Vec<f64> = times.windows(2).map(|w| (w[1] - w[0]) as f64).collect();
+            let n = intervals.len() as f64;
+            let mean = intervals.iter().sum::<f64>() / n;
+            let variance = intervals.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+            (mean, variance)
+        };
+
+        let (mean1, var1) = intervals(1.0);
+        let (mean4, var4) = intervals(4.0);
+        for mean in [mean1, mean4] {
+            assert!((mean - 1000.0).abs() < 60.0, "mean {mean}");
+        }
+        // 分散は平均² / shape なので、shape 4 ではおよそ 1/4 になる。
+        let ratio = var4 / var1;
+        assert!((0.18..0.35).contains(&ratio), "variance {var1} -> {var4}");
+    }
+
+    #[test]
+    fn dominant_attacker_stops_the_run_on_a_deep_reorg() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        let run = |attacker_hashrate: i64| {
+            let nodes = std::iter::once((attacker_hashrate, MiningStrategyEnum::PrivateAttack))
+                .chain(std::iter::repeat_n((1_000, MiningStrategyEnum::Honest), 4));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                3,
+                300,
+                1_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.set_end_main_chain_height(Some(300));
+            sim.set_attack_success_condition(Some(AttackSuccessCondition { confirmations: 3 }));
+            sim.simulation().unwrap();
+            sim
+        };
+
+        // 60% の攻撃者は非公開鎖のリードを溜めて公開鎖を何ブロックも覆すので、終了高さより前に打ち切られる。
+        let dominant = run(6_000);
+        let attack = dominant.report().attack.unwrap();
+        assert!(attack.succeeded_at_ms.is_some());
+        assert!(attack.max_reorg_depth >= 3);
+        assert!(dominant.env.blockchain.main_chain_height() < 300);
+
+        // 10% の攻撃者は公開に必要なリードに届かず、終了高さまで走る。
+        let weak = run(444);
+        let attack = weak.report().attack.unwrap();
+        assert_eq!(attack.succeeded_at_ms, None);
+        assert!(attack.max_reorg_depth < 3);
+        assert!(weak.env.blockchain.main_chain_height() >= 300);
+    }
+
+    #[test]
+    fn attacker_reorg_depth_is_measured_across_a_pruned_branch() {
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[10_000, 10_000]).unwrap(),
+            0,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        let (attacker, honest) = (NodeId::new(0), NodeId::new(1));
+        sim.env.blockchain.set_attackers([attacker].into());
+        sim.set_attack_success_condition(Some(AttackSuccessCondition { confirmations: 3 }));
+        let mut extend = |minter, length| {
+            let mut tip = GENESIS_BLOCK_ID;
+            for _ in 0..length {
+                let block = sim.mint_block(minter, tip);
+                tip = sim.env.blockchain.add_block(block);
+                sim.env.blockchain.mark_block_generation_completed(tip);
+            }
+            tip
+        };
+        let honest_tip = extend(honest, 3);
+        let attacker_tip = extend(attacker, 4);
+        // 覆された honest の分岐は刈り込まれて tombstone だけが残る。
+        let pruned = sim.env.blockchain.prune_stale_forks(1, &HashSet::new());
+        assert!(pruned.contains(&honest_tip));
+
+        sim.track_attacker_reorg(honest_tip, attacker_tip);
+        assert_eq!(sim.max_attacker_reorg_depth, 3);
+        assert!(sim.attack_succeeded_at.is_some());
+    }
+
+    #[test]
+    fn empty_block_rate_falls_with_transaction_arrival_rate() {
+        let run = |rate_per_ms: Option<f64>| {
+            let mut sim =
+                BlockchainSimulator::new(4, 7, 200, 600, PropagationDelayMode::Uniform, bitcoin());
+            sim.set_tx_arrival_rate(rate_per_ms);
+            sim.simulation().unwrap();
+            sim.empty_block_rate()
+        };
+
+        assert_eq!(run(None), None);
+        // 到着がなければすべて空ブロック。
+        assert_eq!(run(Some(0.0)), Some(1.0));
+        // ブロック間隔（10 分）に数千件届くなら、空になるのはごく短い間隔で掘られたブロックだけ。
+        let busy = run(Some(0.01)).unwrap();
+        assert!(busy < 0.01, "empty block rate {busy}");
+    }
+
+    #[test]
+    fn transaction_arrivals_do_not_change_mining_times() {
+        let run = |rate_per_ms: Option<f64>| {
+            let mut sim =
+                BlockchainSimulator::new(4, 7, 100, 600, PropagationDelayMode::Uniform, bitcoin());
+            sim.set_tx_arrival_rate(rate_per_ms);
+            sim.simulation().unwrap();
+            sim.env
+                .blockchain
+                .blocks()
+                .map(|block| (block.minter(), block.timestamp()))
+                .collect::<Vec<_>>()
+        };
 
-                // reward_share = this node's reward / sum of all rewards
-                let reward_share = if total_reward > 0.0 {
-                    reward / total_reward
-                } else {
-                    0.0
-                };
+        assert_eq!(run(None), run(Some(0.01)));
+    }
 
-                // hashrate_share = this node's hashrate / total hashrate
-                let hashrate_share = if self.total_hashrate > 0 {
-                    hashrate / self.total_hashrate as f64
-                } else {
-                    0.0
-                };
+    #[test]
+    fn deterministic_arrival_replays_the_given_intervals() {
+        use crate::arrival::{ArrivalExhausted, DeterministicArrival};
 
-                // fairness = reward_share / hashrate_share
-                let fairness = if hashrate_share > 0.0 {
-                    reward_share / hashrate_share
+        let intervals_ms = [1000.0, 250.0, 4000.0, 600.0];
+        let mut sim =
+            BlockchainSimulator::new(4, 3, 100, 50, PropagationDelayMode::Uniform, bitcoin());
+        sim.set_deterministic_arrival(Some(DeterministicArrival::new(
+            intervals_ms.to_vec(),
+            ArrivalExhausted::Stop,
+        )));
+        sim.simulation().unwrap();
+
+        // 間隔はどれも伝播遅延より長いので分岐せず、ブロック時刻は間隔の累積和になる。
+        let times: Vec<i64> = sim
+            .env
+            .blockchain
+            .main_chain_blocks()
+            .skip(1)
+            .map(|block| block.timestamp())
+            .collect();
+        assert_eq!(times, [1000, 1250, 5250, 5850]);
+    }
+
+    #[test]
+    fn tie_break_seed_changes_fork_outcomes_but_not_mining_times() {
+        let sim_with = |tie_break_seed: u64| {
+            let mut sim =
+                BlockchainSimulator::new(8, 4, 300, 3000, PropagationDelayMode::Uniform, bitcoin());
+            sim.set_tie_breaking_rule(TieBreakingRule::Random);
+            sim.set_freeze_difficulty(true);
+            sim.set_tie_break_seed(tie_break_seed);
+            sim
+        };
+
+        // 同じ履歴なら採掘時間（タイムスタンプ）は同じで、同着用の `rand` だけが変わる。
+        let (mut a, mut b) = (sim_with(1), sim_with(2));
+        for i in 0..20 {
+            let minter = NodeId::new(i % 8);
+            let block_a = a.mint_block(minter, GENESIS_BLOCK_ID);
+            let block_b = b.mint_block(minter, GENESIS_BLOCK_ID);
+            assert_eq!(block_a.timestamp(), block_b.timestamp());
+            assert_ne!(block_a.rand(), block_b.rand());
+        }
+
+        let (mut a, mut b) = (sim_with(1), sim_with(2));
+        a.simulation().unwrap();
+        b.simulation().unwrap();
+        // 同じ高さのブロックが初めて 2 つ掘られるまでは同着の判断が起きないので、
+        // そこまでのブロックは同じノードが同じ時刻に掘る。
+        let mut heights = HashSet::new();
+        let first_fork = a
+            .env
+            .blockchain
+            .blocks()
+            .find(|block| !heights.insert(block.height()))
+            .unwrap()
+            .id();
+        let generations = |sim: &BlockchainSimulator| {
+            sim.env
+                .blockchain
+                .blocks()
+                .take_while(|block| block.id() <= first_fork)
+                .map(|block| (block.minter(), block.timestamp()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(generations(&a), generations(&b));
+        assert_ne!(
+            a.env.blockchain.get_main_chain(),
+            b.env.blockchain.get_main_chain()
+        );
+    }
+
+    #[test]
+    fn header_first_relay_holds_relays_until_the_body_is_verified() {
+        let mut sim =
+            BlockchainSimulator::new(3, 0, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        sim.set_freeze_difficulty(true);
+        sim.set_verification_delay(250);
+        sim.set_header_first_relay(Some(0.1));
+        let (a, b, c) = (NodeId::new(0), NodeId::new(1), NodeId::new(2));
+        let block = sim.mint_block(a, GENESIS_BLOCK_ID);
+        let parent = sim.env.blockchain.add_block(block);
+        sim.enqueue_actions(
+            a,
+            &[Action::Propagate {
+                block_id: parent,
+                to: b,
+            }],
+        );
+
+        // ヘッダは Δ の 1 割で届き、b は本体を待たずに乗り換える。
+        let header = sim.event_queue.pop().unwrap();
+        assert_eq!(header.time(), 60_000);
+        sim.current_time = header.time();
+        sim.process_event(&header);
+        assert_eq!(sim.current_head(b), parent);
+
+        // 本体が届く前に掘ったブロックは中継を保留する。
+        let block = sim.mint_block(b, parent);
+        let child = sim.env.blockchain.add_block(block);
+        sim.enqueue_actions(
+            b,
+            &[Action::Propagate {
+                block_id: child,
+                to: c,
+            }],
+        );
+        let next_propagation = |sim: &mut BlockchainSimulator| loop {
+            let event = sim.event_queue.pop().unwrap();
+            sim.current_time = event.time();
+            match *event.event_type() {
+                EventType::Propagation { block_id, .. } | EventType::BlockBody { block_id, .. } => {
+                    sim.process_event(&event);
+                    return (event.time(), block_id);
+                }
+                _ => {}
+            }
+        };
+        // 本体は Δ + 検証遅延で届き、そこで初めて子ブロックのヘッダを送り出す。
+        assert_eq!(next_propagation(&mut sim), (850_000, parent));
+        assert_eq!(next_propagation(&mut sim), (910_000, child));
+        assert_eq!(sim.current_head(c), child);
+    }
+
+    #[test]
+    fn mint_block_extends_parent_with_next_id() {
+        let mut sim =
+            BlockchainSimulator::new(3, 2, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        let minter = NodeId::new(1);
+        // ブロック ID はチェーン内の添字と一致する（genesis が 0）。
+        let block = sim.mint_block(minter, GENESIS_BLOCK_ID);
+        assert_eq!(block.height(), 1);
+        assert_eq!(block.prev_block_id(), Some(GENESIS_BLOCK_ID));
+        assert_eq!(block.minter(), Some(minter));
+        assert_eq!(block.id(), BlockId::new(sim.env.blockchain.len()));
+        assert!(block.mining_time > 0.0);
+
+        sim.env.blockchain.add_block(block.clone());
+        let child = sim.mint_block(minter, block.id());
+        assert_eq!(child.height(), block.height() + 1);
+        assert_eq!(child.prev_block_id(), Some(block.id()));
+        assert_eq!(child.id(), BlockId::new(sim.env.blockchain.len()));
+    }
+
+    #[test]
+    fn minted_blocks_left_out_of_the_chain_leave_no_retarget_record() {
+        let ethereum = ProtocolType::Ethereum.to_protocol(GenesisDifficultyMode::Inferred);
+        let mut sim =
+            BlockchainSimulator::new(3, 2, 10, 600, PropagationDelayMode::Uniform, ethereum);
+        let minter = NodeId::new(1);
+        let mut parent = GENESIS_BLOCK_ID;
+        for _ in 0..2 {
+            let block = sim.mint_block(minter, parent);
+            parent = sim.env.blockchain.add_block(block);
+        }
+        // Ethereum はブロックごとに難易度を調整するが、チェーンに追加しないブロックの調整は記録しない。
+        sim.mint_block(minter, parent);
+        assert!(sim.pending_retargets.is_empty());
+        assert!(sim.retarget_records().is_empty());
+    }
+
+    #[test]
+    fn ethereum_difficulty_follows_exact_integer_arithmetic_without_drift() {
+        // 総ハッシュレート 1e12 H/ms で genesis 難易度は 1.2e16 > 2^53。f64 を経由すると下位桁が落ちる。
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[200_000_000_000; 5]).unwrap(),
+            8,
+            500,
+            0,
+            PropagationDelayMode::Uniform,
+            ProtocolType::Ethereum.to_protocol(GenesisDifficultyMode::Inferred),
+        )
+        .unwrap();
+        sim.set_end_main_chain_height(Some(500));
+        sim.simulation().unwrap();
+
+        let chain: Vec<&Block> = sim.env.blockchain.main_chain_blocks().collect();
+        assert!(chain.len() > 500);
+        let as_u128 = |d: Difficulty| match d {
+            Difficulty::Ethereum(d) => d.as_u256().as_u128(),
+            Difficulty::Bitcoin(_) => unreachable!("Ethereum chain"),
+        };
+        // タイムスタンプだけから u128 で難易度を辿り直し、全ブロックで一致することを確かめる。
+        let mut expected = as_u128(chain[1].difficulty());
+        assert!(expected > 1 << 53);
+        for window in chain.windows(3) {
+            let (grand_parent, parent, block) = (window[0], window[1], window[2]);
+            if parent.height() > 1 {
+                let time_diff = (parent.timestamp() - grand_parent.timestamp()) / 1_000;
+                let factor = (1 - time_diff / 10).max(-99);
+                let step = expected / 2048 * factor.unsigned_abs() as u128;
+                expected = if factor >= 0 {
+                    expected + step
                 } else {
-                    0.0
+                    expected - step
                 };
+            }
+            assert_eq!(
+                as_u128(block.difficulty()),
+                expected,
+                "height {}",
+                block.height()
+            );
+        }
+    }
 
-                (
-                    node.id(),
-                    reward,
-                    hashrate,
-                    reward_share,
-                    hashrate_share,
-                    fairness,
+    #[test]
+    fn uncle_only_miner_earns_reward_share_under_ethereum() {
+        let mut sim = BlockchainSimulator::new(
+            2,
+            4,
+            10,
+            0,
+            PropagationDelayMode::Uniform,
+            ProtocolType::Ethereum.to_protocol(GenesisDifficultyMode::Inferred),
+        );
+        // node 0 がメインチェーン genesis -> a -> b を、node 1 は genesis の上の孤立ブロックだけを掘る。
+        let mine = |sim: &mut BlockchainSimulator, minter: usize, prev: BlockId| {
+            let block = sim.mint_block(NodeId::new(minter), prev);
+            let id = sim.env.blockchain.add_block(block);
+            sim.env.blockchain.mark_block_generation_completed(id);
+            sim.env.blockchain.mark_block_announced(id);
+            id
+        };
+        let a = mine(&mut sim, 0, GENESIS_BLOCK_ID);
+        mine(&mut sim, 1, GENESIS_BLOCK_ID);
+        mine(&mut sim, 0, a);
+
+        let fairness = sim.mining_fairness(0);
+        assert_eq!(fairness[1].uncle_reward, 7.0 / 8.0);
+        assert_eq!(fairness[0].nephew_reward, 1.0 / 32.0);
+        assert!(fairness[1].reward_share > 0.0);
+        let total = 2.0 + 7.0 / 8.0 + 1.0 / 32.0;
+        assert!((fairness[1].reward_share - 7.0 / 8.0 / total).abs() < 1e-12);
+    }
+
+    #[test]
+    fn packet_loss_raises_the_stale_rate() {
+        let run = |packet_loss: Option<f64>| {
+            let mut sim = BlockchainSimulator::new_with_profile(
+                NetworkProfile::from_hashrates(&[1_000; 6]).unwrap(),
+                12,
+                300,
+                30_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.set_end_main_chain_height(Some(300));
+            if let Some(p) = packet_loss {
+                sim.set_packet_loss(p);
+            }
+            sim.simulation().unwrap();
+            sim.report()
+        };
+
+        // 0 は設定しないときと全く同じ実行になる。
+        let default = run(None);
+        assert_eq!(
+            serde_json::to_string(&run(Some(0.0))).unwrap(),
+            serde_json::to_string(&default).unwrap()
+        );
+        let lossy = run(Some(0.5));
+        assert!(
+            lossy.stale_rate > default.stale_rate,
+            "stale rate {} -> {}",
+            default.stale_rate,
+            lossy.stale_rate
+        );
+    }
+
+    #[test]
+    fn same_seed_terminates_at_the_same_point() {
+        // 分岐が多く同着の判断が頻繁に起きる設定で、終了時刻・生成ブロック数・高さが毎回一致する。
+        let run = || {
+            let mut sim = BlockchainSimulator::new(
+                8,
+                23,
+                200,
+                120_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            );
+            sim.set_tie_breaking_rule(TieBreakingRule::Random);
+            sim.set_delay_jitter_pct(50.0);
+            sim.simulation().unwrap();
+            (
+                sim.current_time,
+                sim.env.blockchain.len(),
+                sim.current_round(),
+                sim.env.blockchain.get_main_chain(),
+            )
+        };
+        let first = run();
+        assert!(first.1 as i64 > first.2 + 10, "expected forks: {first:?}");
+        for _ in 0..3 {
+            assert_eq!(run(), first);
+        }
+    }
+
+    #[test]
+    fn event_queue_implementations_give_identical_results() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        // selfish の採掘イベントの置き換えやジッタ付きの伝播を含む設定で比べる。
+        let run = |kind: EventQueueKind| {
+            let strategies = [
+                MiningStrategyEnum::Selfish {
+                    reveal_lead: crate::mining_strategy::DEFAULT_REVEAL_LEAD,
+                    reveal_targets: None,
+                },
+                MiningStrategyEnum::Honest,
+                MiningStrategyEnum::Honest,
+                MiningStrategyEnum::Spv,
+            ];
+            let profile = profile(strategies.into_iter().map(|strategy| (10_000, strategy)));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile,
+                17,
+                300,
+                30_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_event_queue(kind);
+            sim.set_delay_jitter_pct(30.0);
+            sim.set_record_tip_changes(true);
+            sim.simulation().unwrap();
+            (
+                serde_json::to_string(&sim.report()).unwrap(),
+                serde_json::to_string(&sim.tip_change_records()).unwrap(),
+                sim.processed_events(),
+            )
+        };
+
+        assert_eq!(run(EventQueueKind::Priority), run(EventQueueKind::Heap));
+    }
+
+    #[test]
+    fn selfish_overrides_grow_with_hashrate_share() {
+        use crate::mining_strategy::{DEFAULT_REVEAL_LEAD, MiningStrategyEnum};
+
+        let run = |selfish_hashrate: i64| {
+            let selfish = MiningStrategyEnum::Selfish {
+                reveal_lead: DEFAULT_REVEAL_LEAD,
+                reveal_targets: None,
+            };
+            let nodes = std::iter::once((selfish_hashrate, selfish))
+                .chain(std::iter::repeat_n((3_000, MiningStrategyEnum::Honest), 6));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                3,
+                500,
+                1_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.simulation().unwrap();
+            let fairness = sim.report().fairness;
+            assert!(fairness[1..].iter().all(|n| n.overrides == 0));
+            fairness[0].overrides
+        };
+
+        // α = 0.25 と α = 0.4。
+        let low = run(6_000);
+        let high = run(12_000);
+        assert!(low > 0);
+        assert!(high > low, "overrides {high} vs {low}");
+    }
+
+    #[test]
+    fn catch_up_takes_longer_against_a_stronger_attacker() {
+        use crate::mining_strategy::{DEFAULT_REVEAL_LEAD, MiningStrategyEnum};
+
+        let run = |selfish_hashrate: i64| {
+            let selfish = MiningStrategyEnum::Selfish {
+                reveal_lead: DEFAULT_REVEAL_LEAD,
+                reveal_targets: None,
+            };
+            let nodes = std::iter::once((selfish_hashrate, selfish))
+                .chain(std::iter::repeat_n((3_000, MiningStrategyEnum::Honest), 6));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                5,
+                500,
+                1_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.simulation().unwrap();
+            sim.report().catch_up
+        };
+
+        // α = 0.25 と α = 0.45。
+        let low = run(6_000);
+        let high = run(15_000);
+        assert!(low.recoveries > 0 && high.recoveries > 0);
+        assert_eq!(high.blocks_histogram.iter().sum::<u64>(), high.recoveries);
+        assert!(
+            high.mean_blocks > low.mean_blocks,
+            "mean blocks {} vs {}",
+            high.mean_blocks,
+            low.mean_blocks
+        );
+        assert!(high.mean_time_ms > low.mean_time_ms);
+    }
+
+    #[test]
+    fn selfish_final_state_is_readable_by_downcasting() {
+        use crate::mining_strategy::{
+            DEFAULT_REVEAL_LEAD, HonestMiningStrategy, MiningStrategyEnum, SelfishMiningStrategy,
+        };
+
+        let mut ended_with_private_chain = 0;
+        for seed in 0..8 {
+            let selfish = MiningStrategyEnum::Selfish {
+                reveal_lead: DEFAULT_REVEAL_LEAD,
+                reveal_targets: None,
+            };
+            let nodes = std::iter::once((12_000, selfish))
+                .chain(std::iter::repeat_n((3_000, MiningStrategyEnum::Honest), 6));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                seed,
+                100,
+                1_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.simulation().unwrap();
+
+            let strategy = |id: usize| sim.nodes.get_node(NodeId::new(id)).mining_strategy();
+            assert!(
+                strategy(1)
+                    .as_any()
+                    .downcast_ref::<SelfishMiningStrategy>()
+                    .is_none()
+            );
+            assert!(
+                strategy(1)
+                    .as_any()
+                    .downcast_ref::<HonestMiningStrategy>()
+                    .is_some()
+            );
+            let selfish = strategy(0)
+                .as_any()
+                .downcast_ref::<SelfishMiningStrategy>()
+                .unwrap();
+            if selfish.has_unpublished_blocks() {
+                assert!(selfish.private_branch_len() > 0);
+                // 未公開分岐はエクスポート用のメインチェーンにだけ現れる。
+                let report = sim.report();
+                assert!(report.main_chain_height_for_export > report.main_chain_height);
+                ended_with_private_chain += 1;
+            }
+        }
+        assert!(ended_with_private_chain > 0);
+    }
+
+    #[test]
+    fn tie_counters_add_up_on_a_high_fork_network() {
+        let run = |rule: TieBreakingRule| {
+            // Δ/T ≒ 0.5 で同じ高さの tip がよく競合する。
+            let mut sim = BlockchainSimulator::new(
+                5,
+                13,
+                200,
+                300_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            );
+            sim.set_freeze_difficulty(true);
+            sim.set_tie_breaking_rule(rule);
+            sim.simulation().unwrap();
+            sim.report().tie_breaks
+        };
+
+        let first_seen = run(TieBreakingRule::FirstSeen);
+        assert!(first_seen.ties > 0);
+        assert_eq!(first_seen.resolved_for_incoming, 0);
+        assert_eq!(first_seen.unchanged, first_seen.ties);
+
+        let random = run(TieBreakingRule::Random);
+        assert!(random.resolved_for_incoming > 0, "{random:?}");
+        assert!(random.unchanged > 0, "{random:?}");
+        assert_eq!(random.resolved_for_incoming + random.unchanged, random.ties);
+    }
+
+    #[test]
+    fn end_main_chain_height_equalizes_main_chain_length_across_stale_rates() {
+        let run = |delay_ms: i64| {
+            let mut sim = BlockchainSimulator::new(
+                5,
+                11,
+                10,
+                delay_ms,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            );
+            sim.set_freeze_difficulty(true);
+            sim.set_end_main_chain_height(Some(60));
+            sim.simulation().unwrap();
+            sim.report()
+        };
+
+        // Δ/T ≒ 0.0002 と ≒ 0.5。
+        let fast = run(100);
+        let slow = run(300_000);
+        assert!(
+            slow.stale_rate > fast.stale_rate + 0.1,
+            "stale rates {} vs {}",
+            slow.stale_rate,
+            fast.stale_rate
+        );
+        assert_eq!(fast.main_chain_height, 60);
+        assert_eq!(slow.main_chain_height, 60);
+    }
+
+    #[test]
+    fn sorting_by_hashrate_only_renumbers_the_sampled_nodes() {
+        let hashrates = |sim: &BlockchainSimulator| -> Vec<i64> {
+            sim.nodes.nodes().iter().map(|n| n.hashrate()).collect()
+        };
+        let mut sampled =
+            BlockchainSimulator::new(50, 8, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        let mut sorted = BlockchainSimulator::new_sorted_by_hashrate(
+            50,
+            8,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        );
+        let (mut sampled_rates, sorted_rates) = (hashrates(&sampled), hashrates(&sorted));
+        assert!(
+            sorted_rates.windows(2).all(|w| w[0] >= w[1]),
+            "{sorted_rates:?}"
+        );
+        // 並び替え前は node 0 が最大とは限らない。
+        assert_ne!(sampled_rates, sorted_rates);
+        sampled_rates.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(sampled_rates, sorted_rates);
+        assert_eq!(sorted.total_hashrate, sampled.total_hashrate);
+        assert_eq!(sorted.env.total_hashrate, sampled.env.total_hashrate);
+        let ids: Vec<usize> = sorted
+            .nodes
+            .nodes()
+            .iter()
+            .map(|n| n.id().into_usize())
+            .collect();
+        assert_eq!(ids, (0..50).collect::<Vec<_>>());
+        // 以降の乱数列も同じ。
+        assert_eq!(sorted.rng.r#gen::<u64>(), sampled.rng.r#gen::<u64>());
+    }
+
+    #[test]
+    fn broadcast_delivers_each_block_after_exactly_delay_in_one_event() {
+        const NODES: usize = 20;
+        const DELAY_MS: i64 = 100;
+        let run = |model: PropagationModel| {
+            let mut sim = BlockchainSimulator::new(
+                NODES,
+                17,
+                30,
+                DELAY_MS,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            );
+            sim.set_freeze_difficulty(true);
+            sim.set_end_main_chain_height(Some(30));
+            sim.set_propagation_model(model);
+            sim.set_record_tip_changes(true);
+            sim.simulation().unwrap();
+            sim
+        };
+
+        let broadcast = run(PropagationModel::Broadcast);
+        let main_chain = broadcast.env.blockchain.get_main_chain();
+        let blocks = main_chain.len() as u64 - 1;
+        // Δ/T が小さく分岐しないので、高さ h への切り替えはすべて主鎖の h 番目のブロック。
+        assert_eq!(broadcast.report().stale_rate, 0.0);
+        // 終了時点の tip はまだ届いていないので除く。
+        for block_id in &main_chain[1..main_chain.len() - 1] {
+            let block = broadcast.env.blockchain.get_block(*block_id).unwrap();
+            let mut times: Vec<f64> = broadcast
+                .tip_changes()
+                .iter()
+                .filter(|c| c.adopted_height == block.height())
+                .map(|c| c.time)
+                .collect();
+            times.sort_by(f64::total_cmp);
+            // 採掘ノードが採掘した時点で採用し、残りの全ノードはちょうど Δ 後に採用する。
+            assert_eq!(times.len(), NODES, "height {}", block.height());
+            assert!(
+                times[1..]
+                    .iter()
+                    .all(|&t| (t - times[0] - DELAY_MS as f64).abs() < 1e-6),
+                "height {}: {times:?}",
+                block.height()
+            );
+        }
+
+        // ブロックごとのイベントは採掘と broadcast の 2 つで、ノード数に依らない。
+        let per_peer = run(PropagationModel::PerPeer);
+        let per_block = |sim: &BlockchainSimulator| sim.processed_events() as f64 / blocks as f64;
+        assert!(per_block(&broadcast) <= 2.5, "{}", per_block(&broadcast));
+        assert!(
+            per_block(&per_peer) >= (NODES - 1) as f64,
+            "{}",
+            per_block(&per_peer)
+        );
+    }
+
+    #[test]
+    fn miners_propagation_target_skips_observers() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        let run = |target: PropagationTarget| {
+            let nodes =
+                [10_000, 0, 10_000, 0].map(|hashrate| (hashrate, MiningStrategyEnum::Honest));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                4,
+                50,
+                600,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_propagation_target(target);
+            sim.set_end_main_chain_height(Some(50));
+            sim.simulation().unwrap();
+            sim
+        };
+
+        let all = run(PropagationTarget::AllNodes);
+        let miners = run(PropagationTarget::Miners);
+        for observer in [NodeId::new(1), NodeId::new(3)] {
+            let head = |sim: &BlockchainSimulator| {
+                sim.nodes
+                    .get_node(observer)
+                    .mining_strategy()
+                    .current_head()
+            };
+            assert_ne!(head(&all), GENESIS_BLOCK_ID);
+            assert_eq!(head(&miners), GENESIS_BLOCK_ID);
+        }
+        // 観測ノードへの伝播がない分だけイベントが減り、チェーン自体は同じように伸びる。
+        assert!(miners.processed_events() < all.processed_events());
+        assert!(miners.env.blockchain.main_chain_height() >= 50);
+    }
+
+    #[test]
+    fn draining_at_end_completes_in_flight_blocks_of_the_last_round() {
+        let run = |seed: u64, drain: bool| {
+            // Δ/T ≒ 0.5 で同じ高さの競合を起こしやすくし、メインチェーン 20 ブロックで打ち切る。
+            let mut sim = BlockchainSimulator::new(
+                5,
+                seed,
+                10,
+                300_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            );
+            sim.set_freeze_difficulty(true);
+            sim.set_end_main_chain_height(Some(20));
+            sim.set_drain_at_end(drain);
+            sim.simulation().unwrap();
+            let mined = sim
+                .env
+                .blockchain
+                .chain_metrics(None, None, None)
+                .mined_blocks;
+            (sim.current_round(), mined, sim.mining_fairness(0))
+        };
+
+        let mut changed = 0;
+        for seed in 0..10 {
+            let (round, mined, fairness) = run(seed, false);
+            let (drained_round, drained_mined, drained_fairness) = run(seed, true);
+            // 終了時点より高いブロックは作らず、採掘途中だった同じ高さ以下のブロックだけが増える。
+            assert_eq!(drained_round, round);
+            assert!(drained_mined >= mined);
+            let shares = |f: &[NodeInfo]| f.iter().map(|n| n.reward_share).collect::<Vec<_>>();
+            if drained_mined > mined || shares(&drained_fairness) != shares(&fairness) {
+                changed += 1;
+            }
+        }
+        assert!(changed > 0);
+    }
+
+    #[test]
+    fn difficulty_groups_mine_at_different_rates() {
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[10_000; 4]).unwrap(),
+            9,
+            300,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        sim.set_freeze_difficulty(true);
+        // 同じハッシュレートで、node 2, 3 だけ 4 倍の難易度で採掘する。
+        let hard = [NodeId::new(2), NodeId::new(3)];
+        sim.set_difficulty_group(&hard, 4.0);
+        sim.simulation().unwrap();
+
+        let blockchain = &sim.env.blockchain;
+        let (mut easy_blocks, mut hard_blocks) = (0, 0);
+        for block in blockchain.main_chain_blocks().skip(1) {
+            let parent = blockchain
+                .get_block(block.prev_block_id().unwrap())
+                .unwrap();
+            let work = block.cumulative_chain_work() - parent.cumulative_chain_work();
+            if hard.contains(&block.minter().unwrap()) {
+                hard_blocks += 1;
+                assert_eq!(work, block.difficulty().scaled(4.0).chain_work_increment());
+            } else {
+                easy_blocks += 1;
+                assert_eq!(work, block.difficulty().chain_work_increment());
+            }
+        }
+        // 同じハッシュレートなら採掘ペースはおよそ 4 : 1。
+        let ratio = easy_blocks as f64 / hard_blocks as f64;
+        assert!(
+            (3.0..5.5).contains(&ratio),
+            "easy {easy_blocks} vs hard {hard_blocks}"
+        );
+    }
+
+    #[test]
+    fn reward_share_is_not_correlated_with_node_index() {
+        const NODES: usize = 8;
+        const SEEDS: u64 = 40;
+
+        // 同じハッシュレートの honest 8 台。Δ/T ≒ 0.5 で中継競争を頻繁に起こす。
+        // seed ごとに取り分を番号へ回帰した傾きを求め、その平均が 0 から標準誤差の 4 倍以内にあることを確かめる。
+        let slopes: Vec<f64> = (0..SEEDS)
+            .map(|seed| {
+                let mut sim = BlockchainSimulator::new_with_profile(
+                    NetworkProfile::from_hashrates(&[10_000; NODES]).unwrap(),
+                    seed,
+                    10,
+                    300_000,
+                    PropagationDelayMode::Uniform,
+                    bitcoin(),
                 )
+                .unwrap();
+                sim.set_freeze_difficulty(true);
+                sim.set_end_main_chain_height(Some(100));
+                sim.simulation().unwrap();
+                let shares: Vec<f64> = sim
+                    .mining_fairness(0)
+                    .iter()
+                    .map(|info| info.reward_share)
+                    .collect();
+                let mean_index = (NODES as f64 - 1.0) / 2.0;
+                let mean_share = shares.iter().sum::<f64>() / NODES as f64;
+                let (mut cov, mut var_index) = (0.0, 0.0);
+                for (i, share) in shares.iter().enumerate() {
+                    let di = i as f64 - mean_index;
+                    cov += di * (share - mean_share);
+                    var_index += di * di;
+                }
+                cov / var_index
             })
             .collect();
+        let n = SEEDS as f64;
+        let mean = slopes.iter().sum::<f64>() / n;
+        let var = slopes.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        let standard_error = (var / n).sqrt();
+        assert!(
+            mean.abs() < 4.0 * standard_error,
+            "slope {mean} ± {standard_error}"
+        );
+    }
 
-        // Sort by fairness descending.
-        fairness_data.sort_by(|a, b| b.5.partial_cmp(&a.5).unwrap_or(std::cmp::Ordering::Equal));
+    #[test]
+    fn first_recipient_of_a_broadcast_is_uniform_over_nodes() {
+        const NODES: usize = 8;
+        const TRIALS: usize = 7_000;
 
-        // Show all nodes if there are at most 30; otherwise cap at 30 rows.
-        let display_count = if self.nodes.nodes().len() <= 30 {
-            self.nodes.nodes().len()
-        } else {
-            30
-        };
+        // 同じハッシュレートでも、伝播イベントを番号順に積むと同時刻の受信は常に若い番号から処理され、
+        // 中継競争で若い番号が有利になる。送り先の並びがシャッフルされていることを確かめる。
+        let mut sim = BlockchainSimulator::new_with_profile(
+            NetworkProfile::from_hashrates(&[10_000; NODES]).unwrap(),
+            0,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        let from = NodeId::new(0);
+        let actions: Vec<Action> = sim
+            .env
+            .propagation_targets(from)
+            .map(|to| Action::Propagate {
+                block_id: GENESIS_BLOCK_ID,
+                to,
+            })
+            .collect();
 
-        if display_count == self.nodes.nodes().len() {
-            log::info!("Mining Fairness Ranking (all nodes):");
-        } else {
-            log::info!("Mining Fairness Ranking (top {}):", display_count);
+        let mut first = [0usize; NODES];
+        for _ in 0..TRIALS {
+            let ordered = sim.shuffle_propagation_targets(&actions);
+            let Action::Propagate { to, .. } = *ordered[0] else {
+                unreachable!("only propagations are shuffled");
+            };
+            first[to.into_usize()] += 1;
         }
-        log::info!(
-            "Rank | Node ID | Reward (%) | Hashrate (%) | Fairness (Reward Share/Hashrate Share) | Strategy"
+        assert_eq!(first[0], 0);
+        // 期待値 1000、標準偏差はおよそ 30。
+        for count in &first[1..] {
+            assert!((850..1150).contains(count), "first recipients {first:?}");
+        }
+    }
+
+    #[test]
+    fn chi_square_flags_selfish_mining_but_not_honest_network() {
+        use crate::mining_strategy::{DEFAULT_REVEAL_LEAD, MiningStrategyEnum};
+
+        let p_value = |attacker: MiningStrategyEnum, seed: u64| {
+            let nodes = std::iter::once((12_000, attacker))
+                .chain(std::iter::repeat_n((3_000, MiningStrategyEnum::Honest), 6));
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile(nodes),
+                seed,
+                500,
+                1_000,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.simulation().unwrap();
+            sim.fairness_chi_square().1
+        };
+
+        // 同じハッシュレート構成で、node 0 が honest か selfish（α = 0.4）か。1% 水準で判定する。
+        // honest の p 値は一様分布なので、8 seed 中 2 つ以上が有意になる確率は 0.3% 程度。
+        let seeds = 0..8;
+        let honest_significant = seeds
+            .clone()
+            .filter(|&seed| p_value(MiningStrategyEnum::Honest, seed) < 0.01)
+            .count();
+        assert!(
+            honest_significant <= 1,
+            "{honest_significant} honest runs significant"
         );
-        log::info!(
-            "-----|---------|------------|--------------|--------------------------|----------"
+        for seed in seeds {
+            let selfish_p = p_value(
+                MiningStrategyEnum::Selfish {
+                    reveal_lead: DEFAULT_REVEAL_LEAD,
+                    reveal_targets: None,
+                },
+                seed,
+            );
+            assert!(selfish_p < 0.01, "seed {seed}: selfish p-value {selfish_p}");
+        }
+    }
+
+    #[test]
+    fn resolved_config_lists_every_key_parameter() {
+        let mut sim =
+            BlockchainSimulator::new(3, 42, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        sim.set_tie_breaking_rule(TieBreakingRule::Random);
+        sim.set_gamma(Some(0.5));
+        let config = sim.resolved_config();
+
+        for expected in [
+            "seed: 42",
+            "num_nodes: 3",
+            "hashrates: [",
+            "protocol: Bitcoin",
+            "target_block_time_ms: 600000",
+            "expected_block_interval_ms: ",
+            "delay_ms: 600",
+            "propagation_delay_mode: Uniform",
+            "tie_breaking_rule: Random",
+            "fork_choice: protocol",
+            "gamma: Some(0.5)",
+            "end_round: 10",
+        ] {
+            assert!(
+                config.lines().any(|line| line.starts_with(expected)),
+                "missing {expected:?} in\n{config}"
+            );
+        }
+    }
+
+    #[test]
+    fn nakamoto_coefficient_counts_nodes_past_half() {
+        let infos = |shares: &[f64]| -> Vec<NodeInfo> {
+            shares
+                .iter()
+                .enumerate()
+                .map(|(node_id, &reward_share)| NodeInfo {
+                    node_id,
+                    strategy: "Honest".to_string(),
+                    reward_share,
+                    hashrate_share: 0.0,
+                    fairness: 0.0,
+                    orphaned_blocks: 0,
+                    uncle_reward: 0.0,
+                    nephew_reward: 0.0,
+                    overrides: 0,
+                })
+                .collect()
+        };
+        // 偏った分布（順不同）: 0.3 + 0.25 > 0.5
+        assert_eq!(
+            nakamoto_coefficient(&infos(&[0.1, 0.3, 0.05, 0.25, 0.2, 0.1])),
+            2
         );
+        assert_eq!(nakamoto_coefficient(&infos(&[0.6, 0.2, 0.2])), 1);
+        // ちょうど 50% は過半数ではない。
+        assert_eq!(nakamoto_coefficient(&infos(&[0.25, 0.25, 0.25, 0.25])), 3);
+        assert_eq!(nakamoto_coefficient(&infos(&[0.5, 0.5])), 2);
+        assert_eq!(nakamoto_coefficient(&infos(&[0.0, 0.0])), 0);
+    }
+
+    #[test]
+    fn equal_hashrate_share_variance_shrinks_with_run_length() {
+        let run = |blocks: i64| {
+            let profile = NetworkProfile::from_hashrates(&[10_000; 10]).unwrap();
+            let mut sim = BlockchainSimulator::new_with_profile(
+                profile,
+                6,
+                blocks,
+                600,
+                PropagationDelayMode::Uniform,
+                bitcoin(),
+            )
+            .unwrap();
+            sim.set_freeze_difficulty(true);
+            sim.set_end_main_chain_height(Some(blocks));
+            sim.run().unwrap().equal_hashrate_share_variance.unwrap()
+        };
+
+        // 二項分布なら分散はおよそ p(1 - p) / n（p = 0.1）。
+        let short = run(50);
+        let long = run(2_000);
+        assert!(long < short / 10.0, "variance {short} -> {long}");
+        assert!(long < 0.09 / 2_000.0 * 3.0, "variance {long}");
+
+        // 同じハッシュレートのノードがなければ計算できない。
+        let sim = BlockchainSimulator::new(5, 1, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        assert_eq!(sim.report().equal_hashrate_share_variance, None);
+    }
+
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+    /// 自分宛ての伝播イベントを数えるキュー。
+    struct SelfSendCounter {
+        inner: Box<dyn EventQueue>,
+        self_sends: Arc<AtomicUsize>,
+    }
+
+    impl EventQueue for SelfSendCounter {
+        fn is_empty(&self) -> bool {
+            self.inner.is_empty()
+        }
 
-        for (rank, (node_id, _reward, _hashrate, reward_share, hashrate_share, fairness)) in
-            fairness_data.iter().take(display_count).enumerate()
+        fn len(&self) -> usize {
+            self.inner.len()
+        }
+
+        fn push(&mut self, event: Event) {
+            if let EventType::Propagation { from, to, .. } = *event.event_type()
+                && from == to
+            {
+                self.self_sends.fetch_add(1, AtomicOrdering::Relaxed);
+            }
+            self.inner.push(event);
+        }
+
+        fn pop(&mut self) -> Option<Event> {
+            self.inner.pop()
+        }
+
+        fn cancel_mining(&mut self, minter: NodeId) -> Option<Event> {
+            self.inner.cancel_mining(minter)
+        }
+
+        fn pending_mining(&self, minter: NodeId) -> Option<&Event> {
+            self.inner.pending_mining(minter)
+        }
+
+        fn referenced_blocks(&self) -> Box<dyn Iterator<Item = BlockId> + '_> {
+            self.inner.referenced_blocks()
+        }
+    }
+
+    #[test]
+    fn event_hook_counts_every_block_generation() {
+        let generations = Arc::new(AtomicUsize::new(0));
+        let events = Arc::new(AtomicUsize::new(0));
+        let mut sim =
+            BlockchainSimulator::new(5, 3, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        sim.set_end_main_chain_height(Some(40));
         {
-            let strategy_name = self.nodes.get_node(*node_id).mining_strategy().name();
-            log::info!(
-                "{:4} | {:7} | {:10.2} | {:12.2} | {:24.6} | {}",
-                rank + 1,
-                node_id,
-                reward_share * 100.0,
-                hashrate_share * 100.0,
-                fairness,
-                strategy_name
-            );
+            let generations = Arc::clone(&generations);
+            sim.add_event_hook(move |event, _| {
+                if event.is_block_generation() {
+                    generations.fetch_add(1, AtomicOrdering::Relaxed);
+                }
+            });
+        }
+        {
+            let events = Arc::clone(&events);
+            sim.add_event_hook(move |event, sim| {
+                // hook はイベントを処理する前の状態を見る。
+                assert!(sim.current_time <= event.time());
+                assert_eq!(
+                    sim.processed_events(),
+                    events.fetch_add(1, AtomicOrdering::Relaxed) as u64
+                );
+            });
         }
+        sim.simulation().unwrap();
+
+        let minted = sim
+            .env
+            .blockchain
+            .chain_metrics(None, None, None)
+            .mined_blocks;
+        assert!(minted >= 40);
+        assert_eq!(generations.load(AtomicOrdering::Relaxed) as u64, minted);
+        assert_eq!(
+            events.load(AtomicOrdering::Relaxed) as u64,
+            sim.processed_events()
+        );
+    }
+
+    #[test]
+    fn no_strategy_enqueues_a_propagation_to_itself() {
+        use crate::mining_strategy::MiningStrategyEnum;
+
+        let strategies = [
+            MiningStrategyEnum::Honest,
+            MiningStrategyEnum::Selfish {
+                reveal_lead: 2,
+                reveal_targets: None,
+            },
+            MiningStrategyEnum::PrivateAttack,
+            MiningStrategyEnum::Spv,
+            MiningStrategyEnum::SelfishTimewarp {
+                mtp_window_size: 11,
+            },
+            MiningStrategyEnum::Timewarp {
+                mtp_window_size: 11,
+            },
+            MiningStrategyEnum::FeeMaximizing,
+        ];
+        let profile = profile(strategies.into_iter().map(|strategy| (10_000, strategy)));
+        let mut sim = BlockchainSimulator::new_with_profile(
+            profile,
+            5,
+            300,
+            60_000,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+        let self_sends = Arc::new(AtomicUsize::new(0));
+        sim.event_queue = Box::new(SelfSendCounter {
+            inner: EventQueueKind::default().to_event_queue(),
+            self_sends: self_sends.clone(),
+        });
+        sim.simulation().unwrap();
+
+        assert!(sim.env.blockchain.get_main_chain().len() > 300);
+        assert_eq!(self_sends.load(AtomicOrdering::Relaxed), 0);
+    }
+
+    #[test]
+    fn exported_profile_reproduces_network() {
+        let sim =
+            BlockchainSimulator::new(7, 37, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        let profile = sim.export_profile();
+        let json = serde_json::to_string(&profile).unwrap();
+        let profile: NetworkProfile = serde_json::from_str(&json).unwrap();
+        let rebuilt = BlockchainSimulator::new_with_profile(
+            profile,
+            37,
+            10,
+            600,
+            PropagationDelayMode::Uniform,
+            bitcoin(),
+        )
+        .unwrap();
+
+        let describe = |sim: &BlockchainSimulator| {
+            sim.nodes
+                .nodes()
+                .iter()
+                .map(|n| (n.hashrate(), n.mining_strategy().to_profile()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(describe(&rebuilt), describe(&sim));
+        assert_eq!(rebuilt.total_hashrate, sim.total_hashrate);
+    }
+
+    #[test]
+    fn verification_delay_adds_to_each_hop() {
+        let mut sim =
+            BlockchainSimulator::new(3, 0, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        sim.set_verification_delay(250);
+        sim.current_time = 1_000_000;
+        let hop = |sim: &mut BlockchainSimulator, from: usize, to: usize| -> i64 {
+            let start = sim.current_time;
+            sim.enqueue_actions(
+                NodeId::new(from),
+                &[Action::Propagate {
+                    block_id: GENESIS_BLOCK_ID,
+                    to: NodeId::new(to),
+                }],
+            );
+            let event = sim.event_queue.pop().unwrap();
+            sim.current_time = event.time();
+            event.time() - start
+        };
+        // 0 → 1 → 2 と中継すると、2 ホップ分の (Δ + 検証遅延) がかかる。
+        let total = hop(&mut sim, 0, 1) + hop(&mut sim, 1, 2);
+        assert_eq!(total, 2 * (600_000 + 250_000));
+    }
+
+    #[test]
+    fn delay_jitter_varies_around_delay() {
+        let mut sim =
+            BlockchainSimulator::new(2, 41, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        let (a, b) = (NodeId::new(0), NodeId::new(1));
+        assert!((0..100).all(|_| sim.propagation_time(a, b) == 600_000));
+
+        sim.set_delay_jitter_pct(20.0);
+        let delays: Vec<i64> = (0..10_000).map(|_| sim.propagation_time(a, b)).collect();
+        assert!(delays.iter().all(|d| (480_000..=720_000).contains(d)));
+        assert!(delays.iter().any(|&d| d != delays[0]));
+        let mean = delays.iter().sum::<i64>() as f64 / delays.len() as f64;
+        assert!((mean - 600_000.0).abs() < 600_000.0 * 0.01, "mean {mean}");
+        // 自分自身への伝播は常に 0。
+        assert_eq!(sim.propagation_time(a, a), 0);
+    }
+
+    // 不変条件は debug_assert! なので、リリースビルドでは検査されない。
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "reward shares must sum to 1")]
+    fn fairness_check_trips_on_non_node_minter_reward() {
+        let sim = BlockchainSimulator::new(3, 0, 10, 0, PropagationDelayMode::Uniform, bitcoin());
+        // ノードに属さない minter（かつての genesis のダミー minter 相当）への報酬を混ぜる。
+        let rewards = HashMap::from([(NodeId::new(0), 3.0), (NodeId::new(usize::MAX), 1.0)]);
+        sim.fairness_from_rewards(&rewards, &HashMap::new(), &HashMap::new());
+    }
+
+    #[test]
+    fn default_event_budget_does_not_trip_sane_config() {
+        let mut sim =
+            BlockchainSimulator::new(5, 7, 10, 600, PropagationDelayMode::Uniform, bitcoin());
+        assert!(sim.simulation().is_ok());
+        assert!(sim.processed_events() < default_max_events(10, 5));
     }
 }
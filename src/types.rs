@@ -1,15 +1,127 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
+use crate::blockchain::BlockId;
 use crate::node::NodeId;
 
+/// mining fairness の報酬の数え方（`--reward-weighting`）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RewardWeighting {
+    /// メインチェーンのブロック 1 つを報酬 1 と数える。
+    #[default]
+    Blocks,
+    /// ブロックの難易度（仕事量に比例）を報酬とする。難易度が大きく変わる実行で、採掘した時期による偏りを均す。
+    Work,
+}
+
+/// CSV / ログに出す時間の単位（`--time-unit`）。シミュレーション内部の時刻には影響しない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TimeUnit {
+    #[default]
+    Ms,
+    S,
+    Min,
+}
+
+impl TimeUnit {
+    /// ミリ秒の値をこの単位に換算する。
+    pub fn from_ms(self, ms: f64) -> f64 {
+        match self {
+            TimeUnit::Ms => ms,
+            TimeUnit::S => ms / 1_000.0,
+            TimeUnit::Min => ms / 60_000.0,
+        }
+    }
+
+    pub fn suffix(self) -> &'static str {
+        match self {
+            TimeUnit::Ms => "ms",
+            TimeUnit::S => "s",
+            TimeUnit::Min => "min",
+        }
+    }
+}
+
+/// 出力の書式（`--time-unit`, `--precision`）。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputFormat {
+    pub time_unit: TimeUnit,
+    /// 小数点以下の桁数。`None` なら丸めない。
+    pub precision: Option<usize>,
+}
+
+impl OutputFormat {
+    /// 浮動小数を `precision` 桁に丸める。
+    pub fn round(&self, value: f64) -> f64 {
+        match self.precision {
+            Some(p) => {
+                let scale = 10f64.powi(p as i32);
+                (value * scale).round() / scale
+            }
+            None => value,
+        }
+    }
+
+    /// ミリ秒の時間を出力単位に換算して丸める。
+    pub fn time(&self, ms: f64) -> f64 {
+        self.round(self.time_unit.from_ms(ms))
+    }
+
+    /// ログ用: ミリ秒の時間を出力単位・桁数で文字列にする。
+    pub fn format_time(&self, ms: f64) -> String {
+        let value = self.time_unit.from_ms(ms);
+        match self.precision {
+            Some(p) => format!("{value:.p$}"),
+            None => format!("{value}"),
+        }
+    }
+}
+
+/// 時刻の列を書き出す。整数値（既定の ms で `--precision` なし）は `600000.0` ではなく `600000` と書く。
+fn serialize_time<S: serde::Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        serializer.serialize_i64(*value as i64)
+    } else {
+        serializer.serialize_f64(*value)
+    }
+}
+
+/// CSV 各行の先頭に付ける実際に使った seed 列。`(SeedColumn, 行)` のタプルで書き出す。
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SeedColumn {
+    pub seed: u64,
+}
+
 #[derive(Serialize)]
 pub struct Record {
     pub round: u32,
-    pub timestamp: i64,
+    /// ブロックのタイムスタンプ（`--time-unit`、既定はミリ秒）
+    #[serde(serialize_with = "serialize_time")]
+    pub timestamp: f64,
     pub difficulty: f64,
-    /// 実際のブロック生成時間（ミリ秒、内部は μs から換算）
+    /// 実際のブロック生成時間（`--time-unit`、既定はミリ秒。内部は μs から換算）
     pub mining_time: f64,
-    pub minter: NodeId,
+    /// ジェネシスは空欄
+    pub minter: Option<NodeId>,
+    /// 実際に採掘された時刻（`--time-unit`）。timestamp を偽る戦略のブロックでだけ `timestamp` と異なる
+    #[serde(serialize_with = "serialize_time")]
+    pub created_at: f64,
+}
+
+/// ブロックツリー JSON（`--tree-json`）の 1 要素。分岐も含む全ブロック。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeNode {
+    pub id: usize,
+    pub height: i64,
+    /// ジェネシスは `null`
+    pub prev_id: Option<usize>,
+    /// ジェネシスは `null`
+    pub minter: Option<usize>,
+    /// ブロックのタイムスタンプ（ms、採掘者の申告）
+    pub time: i64,
+    /// 実際に採掘された時刻（ms）
+    pub created_at: i64,
+    pub difficulty: f64,
+    pub on_main_chain: bool,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -32,11 +144,451 @@ pub struct ChainMetrics {
     pub private_attack_reorg_success: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct NodeInfo {
     pub node_id: usize,
     pub strategy: String,
     pub reward_share: f64,
     pub hashrate_share: f64,
     pub fairness: f64,
+    /// 採掘したがメインチェーンに乗らなかったブロック数
+    pub orphaned_blocks: u64,
+    /// uncle として得た報酬（ブロック報酬 1 を単位とする。GHOST のプロトコルのみ）
+    pub uncle_reward: f64,
+    /// uncle を取り込んで得た nephew 報酬（同上）
+    pub nephew_reward: f64,
+    /// 非公開チェーンを公開して公開チェーンを上書きした回数（selfish 系のみ）
+    pub overrides: u64,
+}
+
+/// メインチェーンと競合し続けた分岐（[`Blockchain::longest_fork`](crate::Blockchain::longest_fork)）。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ForkEvent {
+    /// 競合が始まった高さ（分岐側の最初のブロックの高さ）
+    pub start_height: i64,
+    /// 2 つのブロックが並存した連続する高さの数
+    pub depth: i64,
+    /// 競合区間のブロックを採掘したノード（両側、昇順）
+    pub nodes: Vec<usize>,
+}
+
+/// `--trace-block` で追跡する 1 ブロックに起きたこと。info レベルで 1 行ずつ出す。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BlockTraceEvent {
+    /// 採掘が完了した。
+    Generated {
+        time_ms: i64,
+        block_id: BlockId,
+        minter: usize,
+        height: i64,
+    },
+    /// ノード `to` が受け取った。
+    Received {
+        time_ms: i64,
+        block_id: BlockId,
+        from: usize,
+        to: usize,
+    },
+    /// ノードの採用チェーンに入った（自身か子孫を tip にした）。
+    Adopted {
+        time_ms: i64,
+        block_id: BlockId,
+        node: usize,
+    },
+    /// ノードがこのブロックを含まないチェーン（tip `new_head`）に乗り換えた。
+    Abandoned {
+        time_ms: i64,
+        block_id: BlockId,
+        node: usize,
+        new_head: BlockId,
+    },
+    /// 実行終了時の状態。告知済みメインチェーン上なら `depth` は確認数（自身を含む）。
+    Final {
+        block_id: BlockId,
+        main_chain: bool,
+        depth: Option<i64>,
+    },
+}
+
+impl std::fmt::Display for BlockTraceEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockTraceEvent::Generated {
+                time_ms,
+                block_id,
+                minter,
+                height,
+            } => write!(
+                f,
+                "🔎 time (ms): {time_ms}, block {block_id}: generated by node {minter} at height {height}"
+            ),
+            BlockTraceEvent::Received {
+                time_ms,
+                block_id,
+                from,
+                to,
+            } => write!(
+                f,
+                "🔎 time (ms): {time_ms}, block {block_id}: received {from}->{to}"
+            ),
+            BlockTraceEvent::Adopted {
+                time_ms,
+                block_id,
+                node,
+            } => write!(
+                f,
+                "🔎 time (ms): {time_ms}, block {block_id}: adopted by node {node}"
+            ),
+            BlockTraceEvent::Abandoned {
+                time_ms,
+                block_id,
+                node,
+                new_head,
+            } => write!(
+                f,
+                "🔎 time (ms): {time_ms}, block {block_id}: abandoned by node {node} for block {new_head}"
+            ),
+            BlockTraceEvent::Final {
+                block_id,
+                depth: Some(depth),
+                ..
+            } => write!(f, "🔎 block {block_id}: main chain, depth {depth}"),
+            BlockTraceEvent::Final { block_id, .. } => {
+                write!(f, "🔎 block {block_id}: orphaned")
+            }
+        }
+    }
+}
+
+/// ノードが採用する tip を切り替えた記録（`--tip-history` の 1 行）。
+#[derive(Debug, Clone, Serialize)]
+pub struct TipChange {
+    /// 切り替えた時刻（ms。CSV 出力時は `--time-unit`）
+    #[serde(serialize_with = "serialize_time")]
+    pub time: f64,
+    pub node_id: usize,
+    pub adopted_height: i64,
+}
+
+/// イベントキューの長さの標本（`--queue-csv` の 1 行）。
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSample {
+    /// 標本を取ったイベントの時刻（ms。CSV 出力時は `--time-unit`）
+    #[serde(serialize_with = "serialize_time")]
+    pub time: f64,
+    /// それまでに処理したイベント数
+    pub processed_events: u64,
+    /// そのイベントを処理した直後にキューに残っているイベント数
+    pub queue_len: usize,
+}
+
+/// trace レベルで出すシミュレーションイベント。`--log-json` なら 1 行 1 JSON（`event` がイベント種別）、
+/// そうでなければ従来どおりの自由書式で出す。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    /// ブロックの採掘が完了した。
+    BlockGeneration {
+        time_ms: i64,
+        block_id: BlockId,
+        height: i64,
+        minter: usize,
+        difficulty: f64,
+    },
+    /// ノード `to` がブロックを受け取った。
+    Propagation {
+        time_ms: i64,
+        block_id: BlockId,
+        height: i64,
+        minter: Option<usize>,
+        from: usize,
+        to: usize,
+    },
+    /// header-first relay で、ノード `to` にブロック本体が届いた。
+    BlockBody {
+        time_ms: i64,
+        block_id: BlockId,
+        height: i64,
+        from: usize,
+        to: usize,
+    },
+}
+
+impl std::fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceEvent::BlockGeneration {
+                time_ms,
+                height,
+                minter,
+                difficulty,
+                ..
+            } => write!(
+                f,
+                "📦 time (ms): {time_ms}, minter: {minter}, difficulty: {difficulty:.4}, height: {height}"
+            ),
+            TraceEvent::Propagation {
+                time_ms,
+                height,
+                from,
+                to,
+                ..
+            } => write!(f, "🚚 time (ms): {time_ms}, {from}->{to}, height: {height}"),
+            TraceEvent::BlockBody {
+                time_ms,
+                height,
+                from,
+                to,
+                ..
+            } => write!(
+                f,
+                "📄 time (ms): {time_ms}, {from}->{to}, height: {height} (body)"
+            ),
+        }
+    }
+}
+
+/// 難易度調整で実測の間隔をクランプしたか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetargetClamp {
+    None,
+    /// 間隔が短すぎて下限に切り上げた（難易度の上げ幅が頭打ち）
+    MinTimespan,
+    /// 間隔が長すぎて上限に切り下げた（難易度の下げ幅が頭打ち）
+    MaxTimespan,
+}
+
+/// 難易度調整 1 回分の記録（`--retargets` の 1 行）。
+#[derive(Debug, Clone, Serialize)]
+pub struct RetargetEvent {
+    /// 新しい難易度が適用されるブロックの高さ
+    pub height: i64,
+    /// 調整に使った実測の平均ブロック間隔（クランプ前。ms。CSV 出力時は `--time-unit`）
+    pub avg_block_time: f64,
+    pub clamp: RetargetClamp,
+    pub old_difficulty: f64,
+    pub new_difficulty: f64,
+}
+
+/// 攻撃者のブロックがメインチェーン tip になってから honest が取り返すまでの分布
+/// （[`Blockchain::catch_up_intervals`](crate::Blockchain::catch_up_intervals)）。
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CatchUpStats {
+    /// honest が取り返した回数
+    pub recoveries: u64,
+    /// 取り返すまでに続いた攻撃者ブロック数の平均
+    pub mean_blocks: f64,
+    pub max_blocks: i64,
+    /// 取り返すまでの時間の平均（ms）
+    pub mean_time_ms: f64,
+    pub max_time_ms: i64,
+    /// `i` 番目は攻撃者ブロックが `i + 1` 個続いた区間の数
+    pub blocks_histogram: Vec<u64>,
+}
+
+impl CatchUpStats {
+    /// `(攻撃者ブロック数, 回復時間 ms)` の列から集計する。
+    pub fn from_intervals(intervals: &[(i64, i64)]) -> Self {
+        if intervals.is_empty() {
+            return Self::default();
+        }
+        let n = intervals.len() as f64;
+        let max_blocks = intervals.iter().map(|&(len, _)| len).max().unwrap();
+        let mut blocks_histogram = vec![0; max_blocks as usize];
+        for &(len, _) in intervals {
+            blocks_histogram[len as usize - 1] += 1;
+        }
+        CatchUpStats {
+            recoveries: intervals.len() as u64,
+            mean_blocks: intervals.iter().map(|&(len, _)| len as f64).sum::<f64>() / n,
+            max_blocks,
+            mean_time_ms: intervals.iter().map(|&(_, t)| t as f64).sum::<f64>() / n,
+            max_time_ms: intervals.iter().map(|&(_, t)| t).max().unwrap(),
+            blocks_histogram,
+        }
+    }
+}
+
+/// フォーク選択で chainwork の等しい tip が競合した回数と、その解決のされ方。
+/// 採掘ノードの受信ごとに数えるので、同じ競合でもノードの数だけ数える。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TieBreakStats {
+    /// 同着の回数（`resolved_for_incoming + unchanged`）
+    pub ties: u64,
+    /// 受信した tip に切り替えた回数
+    pub resolved_for_incoming: u64,
+    /// 現在の tip を維持した回数
+    pub unchanged: u64,
+}
+
+impl TieBreakStats {
+    /// 同着を 1 回数える。`switched` は受信した tip を選んだか。
+    pub fn record(&mut self, switched: bool) {
+        self.ties += 1;
+        if switched {
+            self.resolved_for_incoming += 1;
+        } else {
+            self.unchanged += 1;
+        }
+    }
+}
+
+/// 戦略ごとに集計したハッシュレートとメインチェーンのブロックの取り分。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StrategyShare {
+    /// 戦略名（`MiningStrategy::name`）
+    pub strategy: String,
+    /// この戦略で動くノード数
+    pub nodes: usize,
+    /// 合計ハッシュレートの割合
+    pub hashrate_share: f64,
+    /// メインチェーンのブロック（burn-in 後、`set_confirmations` の確認数で数える）の割合
+    pub block_share: f64,
+    /// block_share / hashrate_share（ハッシュレートがなければ 0）
+    pub fairness: f64,
+}
+
+/// 観測ノード（ハッシュレート 0 の honest ノード）が見たチェーン。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ObserverStats {
+    pub node_id: usize,
+    /// 採用 tip を祖先でないブロックに切り替えた（reorg した）回数
+    pub reorgs: u64,
+    /// reorg で捨てたブロック数の最大値
+    pub max_reorg_depth: i64,
+    /// 終了時に採用している tip の高さ
+    pub adopted_height: i64,
+}
+
+/// [`BlockchainSimulator::run_until_converged`](crate::BlockchainSimulator::run_until_converged) の結果。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Convergence {
+    /// 上限に届く前に指標が許容幅に収まったか
+    pub converged: bool,
+    /// 終了時の生成高さ（`current_round`）
+    pub rounds: i64,
+    /// 最後に評価した指標の値（一度も評価していなければ `None`）
+    pub value: Option<f64>,
+}
+
+/// 攻撃成功とみなす条件：honest ノードが攻撃者のチェーンへ reorg して、`confirmations` 個以上の
+/// ブロック（つまり `confirmations` 確認を得たブロック）を捨てたとき。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttackSuccessCondition {
+    pub confirmations: i64,
+}
+
+/// [`AttackSuccessCondition`] に対する結果。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttackSuccessReport {
+    /// 成功とみなす確認数
+    pub confirmations: i64,
+    /// honest ノードが攻撃者のチェーンへ reorg したときに捨てたブロック数の最大値
+    pub max_reorg_depth: i64,
+    /// 条件を満たして打ち切った時刻（ms）。成功しなければ `None`
+    pub succeeded_at_ms: Option<f64>,
+}
+
+/// 採掘完了済みのブロックを GHOSTDAG で順序付けた結果（ジェネシスを除く）。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct GhostDagReport {
+    pub k: usize,
+    pub blue_blocks: usize,
+    pub red_blocks: usize,
+}
+
+/// 二重支払いの対象トランザクション（`set_double_spend_height`）の結末。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DoubleSpendReport {
+    /// 対象トランザクションを含める高さ
+    pub height: i64,
+    /// その高さで最初に honest ノードが採掘した、対象トランザクションを含むブロック（まだなければ `None`）
+    pub block_id: Option<BlockId>,
+    /// honest ノードが対象ブロックを含むチェーンから含まないチェーンへ reorg した回数
+    pub reversals: u64,
+    /// reorg で覆されたときに対象ブロックが得ていた確認数の最大値（自身を含む）
+    pub max_reversed_confirmations: i64,
+    /// 終了時のメインチェーンに対象ブロックがない（二重支払いが成功した）
+    pub succeeded: bool,
+}
+
+/// シミュレーション終了後のサマリ（`print_summary` と `print_mining_fairness` が出す内容をすべて含む）。
+/// ロガーを入れなくても、結果はこの構造体だけから読める。
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationReport {
+    /// 実際に使った乱数 seed（同じ設定でこの seed を渡せば再現できる）
+    pub seed: u64,
+    /// 終了時点のシミュレーション時刻（ms）
+    pub current_time_ms: i64,
+    pub end_round: i64,
+    /// いずれかの分岐上で生成された最大高さ
+    pub max_generated_height: i64,
+    pub total_blocks: usize,
+    /// 告知済みメインチェーンの高さ
+    pub main_chain_height: i64,
+    /// 未告知ブロックも含めたメインチェーンの高さ
+    pub main_chain_height_for_export: i64,
+    /// 採掘中のものも含めた、いずれかの分岐上のブロックの最大高さ
+    pub max_block_height: i64,
+    /// 最後に追加されたブロックの難易度
+    pub last_difficulty: f64,
+    /// この高さ未満のブロックは以下の指標から除外される（シミュレーション自体には含まれる）
+    pub burn_in_rounds: i64,
+    /// 指標の対象となったメインチェーンブロック数（burn-in 後）
+    pub measured_main_chain_blocks: i64,
+    /// メインチェーン 1 ブロックあたりの平均時間（ms）
+    pub avg_block_time_ms: f64,
+    /// メインチェーンのブロック間隔の lag 1 自己相関（[`Blockchain::inter_block_autocorrelation`](crate::blockchain::Blockchain::inter_block_autocorrelation)）
+    pub block_time_autocorrelation: f64,
+    /// メインチェーン長 / 理想ブロック数（`current_time / 目標生成間隔`）。stale が増えると 1 を下回る。
+    pub effective_block_rate: f64,
+    /// 告知済み・採掘完了ブロックのうちメインチェーンに乗らなかった割合
+    pub stale_rate: f64,
+    /// メインチェーン tip の難易度・総ハッシュレートでのプロトコル上の期待ブロック間隔 T（ms）
+    pub expected_block_interval_ms: i64,
+    /// 伝播遅延 Δ / 期待ブロック間隔 T
+    pub delay_over_block_interval: f64,
+    /// Δ/T から見積もった理論上の孤立率 `1 - e^(-Δ/T)`（[`theoretical_orphan_rate`](crate::theoretical_orphan_rate)）
+    pub theoretical_stale_rate: f64,
+    /// 最長のフォーク（分岐がなければ `None`）
+    pub longest_fork: Option<ForkEvent>,
+    /// 孤立ブロックの採掘に費やされたハッシュレート×時間 / 計測区間の総ハッシュレート×時間
+    pub wasted_hashrate_fraction: f64,
+    /// メインチェーンのブロックが採掘されてから、全ノードが採用した子を得るまでの平均時間（ms、burn-in 後）。
+    /// 確認されたブロックがなければ `None`。
+    pub avg_confirmation_time_ms: Option<f64>,
+    /// トランザクションを含まないメインチェーンのブロックの割合（`set_tx_arrival_rate` を設定したときだけ）
+    pub empty_block_rate: Option<f64>,
+    /// ノードごとの mining fairness（`set_confirmations` の確認数で数えた報酬）
+    pub fairness: Vec<NodeInfo>,
+    /// `fairness` の報酬の過半を占めるのに必要な最小ノード数（[`nakamoto_coefficient`](crate::nakamoto_coefficient)）
+    pub nakamoto_coefficient: usize,
+    /// ハッシュレートが等しいノード同士の reward share の分散
+    /// （[`equal_hashrate_share_variance`](crate::equal_hashrate_share_variance)、該当ノードがなければ `None`）
+    pub equal_hashrate_share_variance: Option<f64>,
+    /// ハッシュレート比に対するメインチェーンのブロック数のカイ二乗統計量と p 値
+    /// （[`BlockchainSimulator::fairness_chi_square`](crate::BlockchainSimulator::fairness_chi_square)）
+    pub fairness_chi_square: f64,
+    pub fairness_p_value: f64,
+    /// 戦略ごとのハッシュレートとブロックの取り分（戦略名順）
+    pub strategies: Vec<StrategyShare>,
+    /// 観測ノードごとの reorg と採用高さ（ノード番号順、採掘ノードの指標とは別）
+    pub observers: Vec<ObserverStats>,
+    /// 攻撃者ブロックに追い越されたメインチェーンを honest が取り返すまでの分布（burn-in 後）
+    pub catch_up: CatchUpStats,
+    /// 攻撃者の分岐に覆されて孤立した honest ブロック数（burn-in 後、
+    /// [`Blockchain::honest_orphans_by_cause`](crate::Blockchain::honest_orphans_by_cause)）
+    pub honest_orphans_by_attacker: u64,
+    /// honest 同士の自然な分岐で孤立した honest ブロック数（burn-in 後）
+    pub honest_orphans_by_fork: u64,
+    /// フォーク選択で同着になった回数とその解決（`fork_choice` 指定時は数えない）
+    pub tie_breaks: TieBreakStats,
+    /// 二重支払いの対象トランザクションの結末（`set_double_spend_height` を設定したときだけ）
+    pub double_spend: Option<DoubleSpendReport>,
+    /// 攻撃成功の条件に対する結果（`set_attack_success_condition` を設定したときだけ）
+    pub attack: Option<AttackSuccessReport>,
+    /// GHOSTDAG で青・赤に分けたブロック数（blockDAG のプロトコルのときだけ）
+    pub ghostdag: Option<GhostDagReport>,
 }
@@ -0,0 +1,67 @@
+//! `--log-json` の trace 行の検査。グローバルなロガーと max level を書き換えるので、
+//! 他のテストと並行しないよう独立したテストバイナリに置く。
+
+use std::sync::Mutex;
+
+use blockchain_sim::{
+    BlockchainSimulator, GenesisDifficultyMode, PropagationDelayMode, ProtocolType,
+};
+
+/// trace 行だけを集めるロガー。
+struct CaptureLogger {
+    lines: Mutex<Vec<String>>,
+}
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() == log::Level::Trace {
+            self.lines.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn json_trace_lines_parse_as_json() {
+    static LOGGER: CaptureLogger = CaptureLogger {
+        lines: Mutex::new(Vec::new()),
+    };
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let protocol = ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Inferred);
+    let mut sim = BlockchainSimulator::new(3, 7, 5, 600, PropagationDelayMode::Uniform, protocol);
+    sim.set_json_trace(true);
+    sim.simulation().unwrap();
+    log::set_max_level(log::LevelFilter::Off);
+
+    let lines = std::mem::take(&mut *LOGGER.lines.lock().unwrap());
+    let events: Vec<serde_json::Value> = lines
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let count = |kind: &str| events.iter().filter(|e| e["event"] == kind).count();
+    assert_eq!(
+        count("block_generation") + count("propagation"),
+        events.len()
+    );
+    // 採掘されたブロックは自分以外の 2 ノードに送られる。終了時に届いていない分がある。
+    assert!(count("block_generation") > 0);
+    assert!(count("propagation") > 0);
+    assert!(count("propagation") <= 2 * count("block_generation"));
+    for event in &events {
+        assert!(event["time_ms"].is_i64());
+        assert!(event["height"].as_i64().unwrap() > 0);
+    }
+    assert!(
+        events
+            .iter()
+            .filter(|e| e["event"] == "block_generation")
+            .all(|e| e["minter"].as_u64().unwrap() < 3)
+    );
+}
@@ -0,0 +1,39 @@
+//! ロガーを入れずに `run` だけで結果を受け取れることの検査。グローバルな max level を見るので、
+//! ロガーを設定するテストとは別のテストバイナリに置く。
+
+use blockchain_sim::{
+    BlockchainSimulator, GenesisDifficultyMode, PropagationDelayMode, ProtocolType,
+    nakamoto_coefficient,
+};
+
+#[test]
+fn run_returns_every_metric_without_a_logger() {
+    // このテストバイナリはロガーを入れないので、log のマクロは何も出さない。
+    assert_eq!(log::max_level(), log::LevelFilter::Off);
+
+    let protocol = ProtocolType::Bitcoin.to_protocol(GenesisDifficultyMode::Inferred);
+    let mut sim =
+        BlockchainSimulator::new(6, 11, 200, 600, PropagationDelayMode::Uniform, protocol);
+    sim.set_freeze_difficulty(true);
+    let report = sim.run().unwrap();
+
+    assert_eq!(report.seed, 11);
+    assert!(report.main_chain_height >= 200);
+    assert!(report.max_block_height >= report.main_chain_height);
+    assert!(report.avg_block_time_ms > 0.0);
+    assert_eq!(report.fairness.len(), 6);
+    assert_eq!(
+        report.nakamoto_coefficient,
+        nakamoto_coefficient(&report.fairness)
+    );
+    assert_eq!(
+        (report.fairness_chi_square, report.fairness_p_value),
+        sim.fairness_chi_square()
+    );
+    assert_eq!(report.strategies.len(), 1);
+    // run の戻り値は終了後に report() で取り直したものと同じ。
+    assert_eq!(
+        serde_json::to_string(&report).unwrap(),
+        serde_json::to_string(&sim.report()).unwrap()
+    );
+}